@@ -1,12 +1,9 @@
-use std::fs::OpenOptions;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
 use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 
+use extcap::pcap_file::DataLink;
 use extcap::*;
-use log::{debug, LevelFilter};
-use pcap_file::{pcap::PcapHeader, DataLink, PcapWriter};
+use log::debug;
 use rand::Rng;
 use simplelog::{Config, SimpleLogger, WriteLogger};
 
@@ -31,62 +28,42 @@ const OPT_DLT_DEFAULT: u32 = 147;
 struct RRPktDump {}
 
 impl ExtcapListener for RRPktDump {
-    fn init_log(&mut self, _extcap: &Extcap, debug: bool, debug_file: Option<&str>) {
-        let lvl = if debug {
-            LevelFilter::Debug
+    fn init_logging(&mut self, _extcap: &Extcap, mut config: LogConfig) {
+        if let Some(file) = config.opened_debug_file.take() {
+            let _ = WriteLogger::init(config.level, Config::default(), file);
         } else {
-            LevelFilter::Warn
-        };
-        if let Some(file) = debug_file {
-            let _ = WriteLogger::init(
-                lvl,
-                Config::default(),
-                OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(file)
-                    .unwrap(),
-            );
-        } else {
-            let _ = SimpleLogger::init(lvl, Config::default());
+            let _ = SimpleLogger::init(config.level, Config::default());
         }
     }
 
-    fn capture_header(&mut self, extcap: &Extcap, _ifc: &IFace) -> PcapHeader {
+    fn capture_header2(&mut self, extcap: &Extcap, _ifc: &IFace) -> CaptureHeader {
         let dlt = extcap
-            .get_matches()
-            .value_of(OPT_DLT)
-            .and_then(|s| s.parse::<u32>().ok())
+            .matches()
+            .value_parsed::<u32>(OPT_DLT)
             .unwrap_or(OPT_DLT_DEFAULT);
 
-        debug!("capture_header() dlt={}", dlt);
+        debug!("capture_header2() dlt={}", dlt);
 
-        PcapHeader {
-            datalink: DataLink::from(dlt),
-            ..Default::default()
-        }
+        CaptureHeader::builder().link_type(dlt).build()
     }
 
-    fn capture(
+    fn capture_dump(
         &mut self,
         extcap: &Extcap,
         _ifc: &IFace,
-        mut pcap_writer: PcapWriter<ExtcapWriter>,
+        dumper: &mut ExtcapDumper,
     ) -> ExtcapResult<()> {
         let maxbytes = extcap
-            .get_matches()
-            .value_of(OPT_MAXBYTES)
-            .and_then(|s| s.parse::<usize>().ok())
+            .matches()
+            .value_parsed::<usize>(OPT_MAXBYTES)
             .unwrap_or(OPT_MAXBYTES_DEFAULT);
         let count = extcap
-            .get_matches()
-            .value_of(OPT_COUNT)
-            .and_then(|s| s.parse::<i32>().ok())
+            .matches()
+            .value_parsed::<i32>(OPT_COUNT)
             .unwrap_or(OPT_COUNT_DEFAULT);
         let delay = extcap
-            .get_matches()
-            .value_of(OPT_DELAY)
-            .and_then(|s| s.parse::<i32>().ok())
+            .matches()
+            .value_parsed::<i32>(OPT_DELAY)
             .unwrap_or(OPT_DELAY_DEFAULT);
 
         debug!(
@@ -96,18 +73,8 @@ impl ExtcapListener for RRPktDump {
 
         let mut rng = rand::thread_rng();
         let delay = Duration::from_millis(if delay > 0 { delay as u64 } else { 0 });
-        let running = Arc::new(AtomicBool::new(true));
-        let r = running.clone();
-        ctrlc::set_handler(move || {
-            r.store(false, Ordering::SeqCst);
-        })
-        .expect("Error setting Ctrl-C handler");
         let mut cnt = 0u32;
-        loop {
-            if !running.load(Ordering::SeqCst) {
-                debug!("Ctrl+C or SIGINT signal received");
-                break;
-            }
+        while !dumper.is_stopped() {
             if (count >= 0) && (cnt == (count as u32)) {
                 debug!("count {} reached", cnt);
                 break;
@@ -116,21 +83,13 @@ impl ExtcapListener for RRPktDump {
             let len = rng.gen_range(1..=maxbytes);
             let mut data = vec![0u8; len];
             rng.fill(data.as_mut_slice());
-            let ts = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("SystemTime before UNIX EPOCH");
-            let _ = pcap_writer.write(
-                ts.as_secs() as u32,
-                ts.subsec_micros(),
-                &data,
-                data.len() as u32,
-            );
+            dumper.write_now(&data)?;
 
             cnt += 1;
             thread::sleep(delay);
         }
 
-        debug!("capture() finished");
+        debug!("capture_dump() finished");
         Ok(())
     }
 }
@@ -142,6 +101,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     ex.help("http://abcd");
     ex.usage(USAGE_STR);
     ex.after_help(AFTER_HELP_STR);
+    ex.stop_on_signal(true);
+    ex.on_write_progress(ProgressInterval::Bytes(1 << 20), |stats| {
+        debug!(
+            "written {} packets, {} bytes in {:?}",
+            stats.packets, stats.bytes, stats.elapsed
+        );
+    });
 
     // Interface
     let mut rrpkt = IFace::new("rrpkt")
@@ -180,7 +146,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     rrpkt.add_arg(arg_dlt);
 
     rrpkt.config_debug();
-    ex.add_interface(rrpkt);
+    ex.add_interface(rrpkt)?;
 
     let user = RRPktDump {};
     ex.run(user)?;
@@ -1,18 +1,14 @@
-use std::fs::OpenOptions;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
-
+use extcap::pcap_file::DataLink;
 use extcap::*;
-use log::{debug, warn, LevelFilter};
-use pcap_file::{pcap::PcapHeader, DataLink, PcapWriter};
+use log::{debug, warn};
 use simplelog::{Config, SimpleLogger, WriteLogger};
-use std::net::{SocketAddr, UdpSocket};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
 
 const USAGE_STR: &str = r#"rudump --extcap-interfaces
     rudump --extcap-interface=randpkt --extcap-dlts
     rudump --extcap-interface=randpkt --extcap-config
-    rudump --extcap-interface=randpkt --dlt 150 --port 5566 --fifo=FILENAME --capture"#;
+    rudump --extcap-interface=randpkt --dlt 150 --port 5566 --fifo=FILENAME --capture
+    rudump --extcap-interface=randpkt --dlt 252 --payload json --fifo=FILENAME --capture"#;
 
 const AFTER_HELP_STR: &str = r#"Notes:
   just example"#;
@@ -22,96 +18,83 @@ const OPT_PORT_DEFAULT: u16 = 5555;
 const OPT_PORT_RANGE: &str = "1,65535";
 const OPT_DLT: &str = "dlt";
 const OPT_DLT_DEFAULT: u32 = 147;
+const OPT_PAYLOAD: &str = "payload";
+const OPT_PAYLOAD_DEFAULT: &str = "data";
 
 const BUF_LEN: usize = 4096;
 
 struct RUdpDump {}
 
 impl ExtcapListener for RUdpDump {
-    fn init_log(&mut self, _extcap: &Extcap, debug: bool, debug_file: Option<&str>) {
-        let lvl = if debug {
-            LevelFilter::Debug
-        } else {
-            LevelFilter::Warn
-        };
-        if let Some(file) = debug_file {
-            let _ = WriteLogger::init(
-                lvl,
-                Config::default(),
-                OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(file)
-                    .unwrap(),
-            );
+    fn init_logging(&mut self, _extcap: &Extcap, mut config: LogConfig) {
+        if let Some(file) = config.opened_debug_file.take() {
+            let _ = WriteLogger::init(config.level, Config::default(), file);
         } else {
-            let _ = SimpleLogger::init(lvl, Config::default());
+            let _ = SimpleLogger::init(config.level, Config::default());
         }
     }
 
-    fn capture_header(&mut self, extcap: &Extcap, _ifc: &IFace) -> PcapHeader {
+    fn capture_header2(&mut self, extcap: &Extcap, _ifc: &IFace) -> CaptureHeader {
         let dlt = extcap
-            .get_matches()
-            .value_of(OPT_DLT)
-            .and_then(|s| s.parse::<u32>().ok())
+            .matches()
+            .value_parsed::<u32>(OPT_DLT)
             .unwrap_or(OPT_DLT_DEFAULT);
 
-        debug!("capture_header() dlt={}", dlt);
+        debug!("capture_header2() dlt={}", dlt);
 
-        PcapHeader {
-            datalink: DataLink::from(dlt),
-            ..Default::default()
-        }
+        CaptureHeader::builder().link_type(dlt).build()
     }
 
-    fn capture(
+    fn capture_dump(
         &mut self,
         extcap: &Extcap,
         _ifc: &IFace,
-        mut pcap_writer: PcapWriter<ExtcapWriter>,
+        dumper: &mut ExtcapDumper,
     ) -> ExtcapResult<()> {
         let port = extcap
-            .get_matches()
-            .value_of(OPT_PORT)
-            .and_then(|s| s.parse::<u16>().ok())
+            .matches()
+            .value_parsed::<u16>(OPT_PORT)
             .unwrap_or(OPT_PORT_DEFAULT);
 
-        debug!("capture() port={}", port);
+        let exported =
+            extcap.matches().value_parsed::<u32>(OPT_DLT) == Some(DLT_WIRESHARK_UPPER_PDU);
+        let payload = extcap
+            .matches()
+            .value(OPT_PAYLOAD)
+            .unwrap_or(OPT_PAYLOAD_DEFAULT);
+
+        debug!("capture_dump() port={} exported={}", port, exported);
 
         let sa = SocketAddr::from(([0, 0, 0, 0], port));
         let socket = UdpSocket::bind(sa).expect("couldn't bind to address");
         let mut buf = [0; BUF_LEN];
-        let running = Arc::new(AtomicBool::new(true));
-        let r = running.clone();
-        ctrlc::set_handler(move || {
-            r.store(false, Ordering::SeqCst);
-        })
-        .expect("Error setting Ctrl-C handler");
-        loop {
-            if !running.load(Ordering::SeqCst) {
-                debug!("Ctrl+C or SIGINT signal received");
-                break;
-            }
-
-            let rcv_len = match socket.recv_from(&mut buf) {
-                Ok((len, _)) => len,
+        while !dumper.is_stopped() {
+            let (rcv_len, src) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
                 Err(e) => {
                     warn!("recv_from() failed {}", e);
                     continue;
                 }
             };
-            let ts = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("SystemTime before UNIX EPOCH");
-            let _ = pcap_writer.write(
-                ts.as_secs() as u32,
-                ts.subsec_micros(),
-                &buf[..rcv_len],
-                rcv_len as u32,
-            );
+            if !exported {
+                dumper.write_now(&buf[..rcv_len])?;
+                continue;
+            }
+            // Like udpdump, hand the datagram to the payload dissector
+            let pdu = ExportedPdu::new().dissector(payload);
+            let pdu = match src.ip() {
+                IpAddr::V4(ip) => pdu.src_ipv4(ip),
+                IpAddr::V6(ip) => pdu.src_ipv6(ip),
+            };
+            let pdu = pdu
+                .port_type(PortType::Udp)
+                .src_port(src.port())
+                .dst_port(port)
+                .payload(&buf[..rcv_len]);
+            dumper.write_now(&pdu.to_vec())?;
         }
 
-        debug!("capture() finished");
+        debug!("capture_dump() finished");
         Ok(())
     }
 }
@@ -123,6 +106,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     ex.help("http://abcd");
     ex.usage(USAGE_STR);
     ex.after_help(AFTER_HELP_STR);
+    ex.stop_on_signal(true);
 
     // Interface
     let mut rudump = IFace::new("rudump")
@@ -140,16 +124,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut arg_dlt = IfArg::new_selector(OPT_DLT)
         .display("DLT number")
         .default(&OPT_DLT_DEFAULT)
-        .tooltip("DLT_USER0-DLT_USER15");
+        .tooltip("DLT_USER0-DLT_USER15 or Exported PDU");
     for dlt in u32::from(DataLink::USER0)..=u32::from(DataLink::USER15) {
         arg_dlt.add_val(
             IfArgVal::new(dlt).display(&format!("DLT_USER{}", dlt - u32::from(DataLink::USER0))),
         );
     }
+    arg_dlt.add_val(IfArgVal::new(DLT_WIRESHARK_UPPER_PDU).display("Exported PDU"));
     rudump.add_arg(arg_dlt);
+    rudump.add_arg(
+        IfArg::new_string(OPT_PAYLOAD)
+            .display("Payload type")
+            .default(&OPT_PAYLOAD_DEFAULT)
+            .tooltip("The dissector used for the payload with the Exported PDU DLT"),
+    );
 
     rudump.config_debug();
-    ex.add_interface(rudump);
+    ex.add_interface(rudump)?;
 
     let user = RUdpDump {};
     ex.run(user)?;
@@ -1,9 +1,6 @@
-use std::fs::OpenOptions;
-use std::time::{SystemTime, UNIX_EPOCH};
-
+use extcap::pcap_file::DataLink;
 use extcap::*;
-use log::{debug, LevelFilter};
-use pcap_file::{pcap::PcapHeader, DataLink, PcapWriter};
+use log::debug;
 use simplelog::{Config, SimpleLogger, WriteLogger};
 
 const OPT_SERVER: &str = "server";
@@ -16,88 +13,64 @@ const OPT_DLT_DEFAULT: u32 = 147;
 struct TestArgDump {}
 
 impl ExtcapListener for TestArgDump {
-    fn init_log(&mut self, _extcap: &Extcap, debug: bool, debug_file: Option<&str>) {
-        let lvl = if debug {
-            LevelFilter::Debug
-        } else {
-            LevelFilter::Warn
-        };
-        if let Some(file) = debug_file {
-            let _ = WriteLogger::init(
-                lvl,
-                Config::default(),
-                OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(file)
-                    .unwrap(),
-            );
+    fn init_logging(&mut self, _extcap: &Extcap, mut config: LogConfig) {
+        if let Some(file) = config.opened_debug_file.take() {
+            let _ = WriteLogger::init(config.level, Config::default(), file);
         } else {
-            let _ = SimpleLogger::init(lvl, Config::default());
+            let _ = SimpleLogger::init(config.level, Config::default());
         }
     }
 
-    fn reload_option(
+    fn try_reload_option(
         &mut self,
         extcap: &Extcap,
         _ifc: &IFace,
         _arg: &IfArg,
-    ) -> Option<Vec<IfArgVal>> {
+    ) -> ExtcapResult<Option<Vec<IfArgVal>>> {
         let dlt_max = extcap
-            .get_matches()
-            .value_of(OPT_DLT_MAX)
-            .and_then(|s| s.parse::<u32>().ok())
+            .matches()
+            .value_parsed::<u32>(OPT_DLT_MAX)
             .unwrap_or(OPT_DLT_MAX_DEFAULT);
-        debug!("reload_option() dlt_max={}", dlt_max);
+        debug!("try_reload_option() dlt_max={}", dlt_max);
         let vals = (u32::from(DataLink::USER0)..=dlt_max)
             .map(|dlt| {
                 IfArgVal::new(dlt).display(&format!("DLT_USER{}", dlt - u32::from(DataLink::USER0)))
             })
             .collect::<Vec<_>>();
-        Some(vals)
+        Ok(Some(vals))
     }
 
-    fn capture_header(&mut self, extcap: &Extcap, _ifc: &IFace) -> PcapHeader {
+    fn capture_header2(&mut self, extcap: &Extcap, _ifc: &IFace) -> CaptureHeader {
         let dlt = extcap
-            .get_matches()
-            .value_of(OPT_DLT)
-            .and_then(|s| s.parse::<u32>().ok())
+            .matches()
+            .value_parsed::<u32>(OPT_DLT)
             .unwrap_or(OPT_DLT_DEFAULT);
 
-        debug!("capture_header() dlt={}", dlt);
+        debug!("capture_header2() dlt={}", dlt);
 
-        PcapHeader {
-            datalink: DataLink::from(dlt),
-            ..Default::default()
-        }
+        CaptureHeader::builder().link_type(dlt).build()
     }
 
-    fn capture(
+    fn capture2(
         &mut self,
         extcap: &Extcap,
         _ifc: &IFace,
-        mut pcap_writer: PcapWriter<ExtcapWriter>,
+        mut sink: PacketSink,
     ) -> ExtcapResult<()> {
         let mut msg = "Test arguments:\n".to_string();
         for a in &[OPT_SERVER, OPT_DLT_MAX, OPT_DLT] {
-            if let Some(v) = extcap.get_matches().value_of(a) {
+            if let Some(v) = extcap.matches().value(a) {
                 msg += &format!("{}={}\n", a, v);
             }
         }
 
-        debug!("capture() {}", msg);
+        debug!("capture2() {}", msg);
 
-        let ts = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("SystemTime before UNIX EPOCH");
-        let _ = pcap_writer.write(
-            ts.as_secs() as u32,
-            ts.subsec_micros(),
-            msg.as_bytes(),
-            msg.as_bytes().len() as u32,
-        );
+        let _ = PacketBuilder::new()
+            .data(msg.as_bytes())
+            .write_to(&mut sink);
 
-        debug!("capture() finished");
+        debug!("capture2() finished");
         Ok(())
     }
 }
@@ -106,6 +79,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut ex = Extcap::new("test_arg_dump");
     ex.version("0.0.1");
     ex.about("Test extcap arguments (Rust extcap example)");
+    #[cfg(feature = "standalone")]
+    ex.enable_standalone_mode()?;
 
     // Interface
     let mut tadump = IFace::new("tadump")
@@ -137,7 +112,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     tadump.add_arg(arg_dlt);
 
     tadump.config_debug();
-    ex.add_interface(tadump);
+    ex.add_interface(tadump)?;
 
     let user = TestArgDump {};
     ex.run(user)?;
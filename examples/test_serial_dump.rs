@@ -1,11 +1,10 @@
-use std::fs::OpenOptions;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use extcap::pcap_file::DataLink;
 use extcap::*;
 use futures::channel::mpsc::{self, Sender};
 use futures::prelude::*;
-use log::{debug, warn, LevelFilter};
-use pcap_file::{pcap::Packet, pcap::PcapHeader, DataLink};
+use log::{debug, warn};
 use serialport::available_ports;
 use simplelog::{Config, SimpleLogger, WriteLogger};
 use tokio_serial::{SerialPortBuilderExt, SerialStream};
@@ -19,34 +18,18 @@ const BAUD_RATES: &[u32] = &[9_600, 14_400, 115_200];
 struct TestSerialDump {}
 
 impl ExtcapListener for TestSerialDump {
-    fn init_log(&mut self, _extcap: &Extcap, debug: bool, debug_file: Option<&str>) {
-        let lvl = if debug {
-            LevelFilter::Debug
+    fn init_logging(&mut self, _extcap: &Extcap, mut config: LogConfig) {
+        if let Some(file) = config.opened_debug_file.take() {
+            let _ = WriteLogger::init(config.level, Config::default(), file);
         } else {
-            LevelFilter::Warn
-        };
-        if let Some(file) = debug_file {
-            let _ = WriteLogger::init(
-                lvl,
-                Config::default(),
-                OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(file)
-                    .unwrap(),
-            );
-        } else {
-            let _ = SimpleLogger::init(lvl, Config::default());
+            let _ = SimpleLogger::init(config.level, Config::default());
         }
     }
 
-    fn capture_header(&mut self, _extcap: &Extcap, _ifc: &IFace) -> PcapHeader {
-        debug!("capture_header()");
+    fn capture_header2(&mut self, _extcap: &Extcap, _ifc: &IFace) -> CaptureHeader {
+        debug!("capture_header2()");
 
-        PcapHeader {
-            datalink: DataLink::USER10,
-            ..Default::default()
-        }
+        CaptureHeader::builder().link(DataLink::USER10).build()
     }
 
     fn capture_async(&mut self, extcap: &Extcap, _ifc: &IFace) -> ExtcapResult<ExtcapReceiver> {
@@ -58,14 +41,10 @@ impl ExtcapListener for TestSerialDump {
             Err(err) => warn!("available_ports retrieving failed: {:?}", err),
         }
 
-        let port = extcap.get_matches().value_of(OPT_PORT).unwrap();
+        let port = extcap.matches().value(OPT_PORT).unwrap();
         debug!("port={}", port);
         let mut builder = tokio_serial::new(port, 9_600);
-        if let Some(baud) = extcap
-            .get_matches()
-            .value_of(OPT_BAUD)
-            .and_then(|s| s.parse::<u32>().ok())
-        {
+        if let Some(baud) = extcap.matches().value_parsed::<u32>(OPT_BAUD) {
             debug!("baud={}", baud);
             builder = builder.baud_rate(baud);
         }
@@ -82,13 +61,13 @@ impl ExtcapListener for TestSerialDump {
     }
 }
 
-async fn task(port: SerialStream, mut sender: Sender<Packet<'static>>) {
+async fn task(port: SerialStream, mut sender: Sender<ExtcapPacket>) {
     let mut reader = FramedRead::new(port, LinesCodec::new());
     while let Some(res) = reader.next().await {
         match res {
             Ok(msg) => {
                 debug!("line received: '{:?}'", msg);
-                write_pkt(&mut sender, &msg).await;
+                write_pkt(&mut sender, msg).await;
             }
             Err(err) => {
                 debug!("error during receiving: {:?}", err);
@@ -98,21 +77,19 @@ async fn task(port: SerialStream, mut sender: Sender<Packet<'static>>) {
     }
 }
 
-async fn write_pkt(snd: &mut Sender<Packet<'static>>, msg: &str) {
+async fn write_pkt(snd: &mut Sender<ExtcapPacket>, msg: String) {
     debug!("write_msg() {}", msg);
     let ts = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("SystemTime before UNIX EPOCH");
-    let pkt = Packet::new_owned(
-        ts.as_secs() as u32,
-        ts.subsec_micros(),
-        msg.as_bytes().to_vec(),
-        msg.as_bytes().len() as u32,
-    );
+    let len = msg.len() as u32;
+    // the line is moved into the packet, not copied
+    let pkt = ExtcapPacket::new(ts, len, msg);
     let _ = snd.send(pkt).await;
 }
 
-#[tokio::main]
+// a single serial port doesn't need the worker threads of the multi-threaded runtime
+#[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut ex = Extcap::new("test_serial_dump");
     ex.version("0.0.1");
@@ -121,7 +98,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Interface
     let mut tser1 = IFace::new("tser1")
         .description("Test serial input")
-        .dlt(DataLink::USER10.into());
+        .dlt_data_link(DataLink::USER10);
 
     // Interface arguments
     let mut arg_port = IfArg::new_selector(OPT_PORT)
@@ -145,7 +122,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tser1.add_arg(arg_baud);
 
     tser1.config_debug();
-    ex.add_interface(tser1);
+    ex.add_interface(tser1)?;
 
     let user = TestSerialDump {};
     ex.run_async(user).await?;
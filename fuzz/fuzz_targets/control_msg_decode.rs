@@ -0,0 +1,20 @@
+//! Decodes arbitrary bytes as the control pipe, run with
+//! `cargo +nightly fuzz run control_msg_decode`
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &[u8]| {
+    // the first byte chooses the size of the reads from the pipe
+    let Some((chunk, data)) = input.split_first() else {
+        return;
+    };
+    // resynchronizing never fails and doesn't depend on how the data arrive
+    let msgs = extcap::fuzz_decode(data, *chunk as usize, false).unwrap();
+    let whole = extcap::fuzz_decode(data, data.len(), false).unwrap();
+    assert_eq!(format!("{:?}", msgs), format!("{:?}", whole));
+    // without a framing error the strict decoder gets the same messages
+    if let Ok(strict) = extcap::fuzz_decode(data, *chunk as usize, true) {
+        assert_eq!(format!("{:?}", strict), format!("{:?}", msgs));
+    }
+});
@@ -1,9 +1,119 @@
-use crate::print_opt_value;
+use crate::opts::is_reserved_option;
+use crate::self_check::{check_text, Issue};
+use crate::text::{TextKind, Texts};
+use crate::{write_opt_value, ExtcapError, ExtcapResult};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Normalizes boolean-ish values to `true` or `false` as expected by Wireshark
+pub(crate) fn normalize_bool(val: &str) -> Option<&'static str> {
+    match val.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some("true"),
+        "false" | "0" | "no" | "off" => Some("false"),
+        _ => None,
+    }
+}
+
+/// Makes a runtime string safe to be used as a display string,
+/// braces and control characters would break the sentence
+pub(crate) fn sanitize_display(display: &str) -> String {
+    display
+        .chars()
+        .map(|c| match c {
+            '{' => '(',
+            '}' => ')',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+/// Checks a machine-facing identifier (interface or argument name),
+/// only `[A-Za-z0-9._-]` without a leading dash is allowed
+pub(crate) fn is_valid_ident(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Checks a group name, it is shown to the user so only characters
+/// breaking the sentence syntax (`{`, `}`, `=` and control characters) are rejected
+pub(crate) fn is_valid_group(group: &str) -> bool {
+    !group.is_empty()
+        && !group
+            .chars()
+            .any(|c| c.is_control() || matches!(c, '{' | '}' | '='))
+}
+
+/// Formats a `Double` value for Wireshark, always with a decimal dot and without an exponent
+pub(crate) fn format_f64(val: f64) -> String {
+    // `Display` of f64 prints the shortest exact representation, never in the scientific notation
+    val.to_string()
+}
+
+/// Parses a `Double` value, accepts the decimal comma sent by Wireshark in some locales
+pub(crate) fn parse_f64(val: &str) -> Option<f64> {
+    let val = val.trim();
+    let val = if val.contains('.') {
+        val.to_owned()
+    } else {
+        val.replacen(',', ".", 1)
+    };
+    val.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+/// Checks that `default` parses as `T` and lies within the "min,max" `range`
+fn check_range<T: FromStr + PartialOrd + Display>(
+    default: &str,
+    range: Option<&str>,
+) -> Result<(), String> {
+    let val: T = default
+        .trim()
+        .parse()
+        .map_err(|_| format!("default '{}' is not a valid number", default))?;
+    let range = match range {
+        Some(range) => range,
+        None => return Ok(()),
+    };
+    let (min, max) = range
+        .split_once(',')
+        .and_then(|(min, max)| Some((min.trim().parse::<T>().ok()?, max.trim().parse::<T>().ok()?)))
+        .ok_or_else(|| format!("invalid range '{}', expected 'min,max'", range))?;
+    if val < min || val > max {
+        return Err(format!("default '{}' is out of range '{}'", default, range));
+    }
+    Ok(())
+}
+
+/// Checks that `default` fully matches the `validation` regular expression
+#[cfg(feature = "regex")]
+fn check_validation(default: &str, validation: &str) -> Result<(), String> {
+    let re = compile_validation(validation)?;
+    if !re.is_match(default) {
+        return Err(format!(
+            "default '{}' does not match validation '{}'",
+            default, validation
+        ));
+    }
+    Ok(())
+}
+
+/// Compiles the `validation` regular expression matching the whole value
+#[cfg(feature = "regex")]
+fn compile_validation(validation: &str) -> Result<regex::Regex, String> {
+    let pattern = format!("^(?:{})$", validation.replace("\\\\", "\\"));
+    regex::Regex::new(&pattern).map_err(|e| format!("invalid validation '{}': {}", validation, e))
+}
 
 /// Extcap Argument types
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 pub enum IfArgType {
     /// None or unknown
+    #[default]
     None,
     /// EXTCAP_ARG_INTEGER
     Integer,
@@ -33,12 +143,6 @@ pub enum IfArgType {
     Timestamp,
 }
 
-impl Default for IfArgType {
-    fn default() -> Self {
-        IfArgType::None
-    }
-}
-
 impl IfArgType {
     fn type_str(&self) -> &'static str {
         match self {
@@ -60,7 +164,13 @@ impl IfArgType {
     }
 }
 
+/// Values of a dependent argument for the value of the argument it depends on
+type DependentVals = Arc<dyn Fn(&str) -> Vec<IfArgVal> + Send + Sync>;
+
 /// Argument representation
+///
+/// The name is used as the command line option and must consist of `[A-Za-z0-9._-]`
+/// without a leading dash, it is checked when the interface is added.
 #[derive(Default, Clone)]
 pub struct IfArg<'a> {
     number: usize,
@@ -72,16 +182,20 @@ pub struct IfArg<'a> {
     validation: Option<String>,
     mustexist: Option<bool>,
     reload: Option<bool>,
+    reload_cache: Option<Duration>,
+    reload_timeout: Option<Duration>,
     placeholder: Option<String>,
     tooltip: Option<String>,
     group: Option<String>,
     vals: Vec<IfArgVal>,
+    unchecked_default: bool,
+    depends_on: Option<(&'a str, DependentVals)>,
 }
 
 impl<'a> IfArg<'a> {
     fn new(atype: IfArgType, name: &'a str) -> Self {
         Self {
-            number: usize::max_value(),
+            number: usize::MAX,
             name,
             atype,
             ..Default::default()
@@ -95,18 +209,86 @@ impl<'a> IfArg<'a> {
         }
     }
 
-    pub(crate) fn get_name(&self) -> &'a str {
+    /// Gets the argument number
+    pub fn get_number(&self) -> usize {
+        self.number
+    }
+
+    /// Gets the argument name
+    pub fn get_name(&self) -> &'a str {
         self.name
     }
 
-    pub(crate) fn get_display(&self) -> Option<&'a str> {
+    /// Gets the display string
+    pub fn get_display(&self) -> Option<&'a str> {
         self.display
     }
 
-    pub(crate) fn get_type(&self) -> &IfArgType {
+    /// Gets the argument type
+    pub fn get_type(&self) -> &IfArgType {
         &self.atype
     }
 
+    /// Gets the default value
+    pub fn get_default(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    /// Gets the range string
+    pub fn get_range(&self) -> Option<&str> {
+        self.range.as_deref()
+    }
+
+    /// Gets the validation regular expression string
+    pub fn get_validation(&self) -> Option<&str> {
+        self.validation.as_deref()
+    }
+
+    /// Gets the `mustexist` flag
+    pub fn get_mustexist(&self) -> Option<bool> {
+        self.mustexist
+    }
+
+    /// Gets the `reload` flag
+    pub fn get_reload(&self) -> Option<bool> {
+        self.reload
+    }
+
+    /// Gets the time-to-live of the cached reload values
+    pub fn get_reload_cache(&self) -> Option<Duration> {
+        self.reload_cache
+    }
+
+    /// Gets the timeout of the reload callback
+    pub fn get_reload_timeout(&self) -> Option<Duration> {
+        self.reload_timeout
+    }
+
+    /// Gets the placeholder string
+    pub fn get_placeholder(&self) -> Option<&str> {
+        self.placeholder.as_deref()
+    }
+
+    /// Gets the tooltip string
+    pub fn get_tooltip(&self) -> Option<&str> {
+        self.tooltip.as_deref()
+    }
+
+    /// Gets the group
+    pub fn get_group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Gets the values
+    pub fn get_vals(&self) -> &[IfArgVal] {
+        &self.vals
+    }
+
+    /// Gets the name of the argument this one depends on, see `depends_on()`
+    pub fn get_depends_on(&self) -> Option<&'a str> {
+        self.depends_on.as_ref().map(|(other, _)| *other)
+    }
+
     /// Creates a new instance of `IfArg` with 'IfArgType::Integer' type using a string name
     pub fn new_integer(name: &'a str) -> Self {
         IfArg::new(IfArgType::Integer, name)
@@ -179,8 +361,17 @@ impl<'a> IfArg<'a> {
     }
 
     /// Sets the default value
+    ///
+    /// For `Boolean` and `Boolflag` arguments boolean-ish values (`1`, `False`, ...)
+    /// are normalized to `true` or `false`, anything else is rejected when the interface is added.
     pub fn default<T: ToString>(mut self, default: &T) -> Self {
-        self.default = Some(default.to_string());
+        let default = default.to_string();
+        self.default = match self.atype {
+            IfArgType::Boolean | IfArgType::Boolflag => {
+                normalize_bool(&default).map(String::from).or(Some(default))
+            }
+            _ => Some(default),
+        };
         self
     }
 
@@ -190,6 +381,20 @@ impl<'a> IfArg<'a> {
         self
     }
 
+    /// Sets the default value of a `Double` argument
+    ///
+    /// Formatted with a decimal dot regardless of the locale and without an exponent.
+    pub fn default_f64(mut self, default: f64) -> Self {
+        self.default = Some(format_f64(default));
+        self
+    }
+
+    /// Sets the range of a `Double` argument
+    pub fn range_f(mut self, min: f64, max: f64) -> Self {
+        self.range = Some(format!("{},{}", format_f64(min), format_f64(max)));
+        self
+    }
+
     /// Sets the validation regular expression string
     pub fn validation<T: ToString>(mut self, validation: &T) -> Self {
         self.validation = Some(validation.to_string());
@@ -208,6 +413,52 @@ impl<'a> IfArg<'a> {
         self
     }
 
+    /// Caches the values returned by `try_reload_option` for `ttl`
+    ///
+    /// Wireshark runs a new process for every press of the Reload button, a reload within
+    /// `ttl` after the last successful one is served from a state file without calling
+    /// the listener. See `Extcap::reload_cache_dir()` and `ExtcapListener::force_reload()`.
+    pub fn reload_cache(mut self, ttl: Duration) -> Self {
+        self.reload_cache = Some(ttl);
+        self
+    }
+
+    /// Limits the time the reload of this argument may take
+    ///
+    /// The task returned by `ExtcapListener::reload_task` runs on a worker thread. When it
+    /// does not return in time the argument is printed with its previous values plus
+    /// a disabled "timed out, try again" value and the run returns normally. The task
+    /// can't be cancelled, it is left running on its detached thread.
+    pub fn reload_timeout(mut self, timeout: Duration) -> Self {
+        self.reload_timeout = Some(timeout);
+        self
+    }
+
+    /// Makes the values depend on the value of the `other` argument of the interface
+    ///
+    /// Extcap can't show or hide an argument based on another one. The argument becomes
+    /// reloadable instead and its reload returns `vals` called with the value of `other`
+    /// passed by Wireshark, or its default when not passed. Return e.g. a single disabled
+    /// "(not applicable)" value when the argument makes no sense for the value.
+    /// Values returned by `try_reload_option` take precedence over `vals`.
+    ///
+    /// Limitations: Wireshark does not reload the argument when `other` changes, the user
+    /// has to press its Reload button, and the argument stays visible. The reload cache
+    /// is not used for the argument as the values depend on the passed options.
+    pub fn depends_on<F>(mut self, other: &'a str, vals: F) -> Self
+    where
+        F: Fn(&str) -> Vec<IfArgVal> + Send + Sync + 'static,
+    {
+        self.depends_on = Some((other, Arc::new(vals)));
+        self.reload = Some(true);
+        self
+    }
+
+    /// The values for the `other` value if the argument depends on another one
+    pub(crate) fn dependent_vals(&self, other: &str) -> Option<Vec<IfArgVal>> {
+        self.depends_on.as_ref().map(|(_, vals)| vals(other))
+    }
+
     pub(crate) fn has_reload(&self) -> bool {
         self.reload.unwrap_or_default()
     }
@@ -225,16 +476,147 @@ impl<'a> IfArg<'a> {
     }
 
     /// Sets the group
+    ///
+    /// Group names may contain spaces, but `{`, `}`, `=` and control characters
+    /// are rejected when the interface is added.
     pub fn group(mut self, group: &str) -> Self {
         self.group = Some(group.to_owned());
         self
     }
 
+    /// Skips the check of the default value against the range and validation,
+    /// useful for intentionally out-of-range sentinel defaults
+    pub fn unchecked_default(mut self, unchecked: bool) -> Self {
+        self.unchecked_default = unchecked;
+        self
+    }
+
     /// Adds a value
     pub fn add_val(&mut self, val: IfArgVal) {
         self.vals.push(val);
     }
 
+    pub(crate) fn validate(&self, ifc: &str) -> ExtcapResult<()> {
+        if !is_valid_ident(self.name) {
+            return Err(ExtcapError::invalid_config(format!(
+                "Interface '{}': invalid arg name '{}', only [A-Za-z0-9._-] without a leading dash is allowed",
+                ifc, self.name
+            )));
+        }
+        if is_reserved_option(self.name) {
+            return Err(ExtcapError::invalid_config(format!(
+                "Interface '{}': arg name '{}' collides with an option reserved by Wireshark",
+                ifc, self.name
+            )));
+        }
+        if let Some(group) = self.group.as_deref().filter(|g| !is_valid_group(g)) {
+            return Err(ExtcapError::invalid_config(format!(
+                "Interface '{}' arg '{}': invalid group '{}', '{{', '}}', '=' and control characters are not allowed",
+                ifc, self.name, group
+            )));
+        }
+        if let (IfArgType::Boolean | IfArgType::Boolflag, Some(default)) =
+            (self.atype, &self.default)
+        {
+            if normalize_bool(default) != Some(default.as_str()) {
+                return Err(ExtcapError::invalid_config(format!(
+                    "Interface '{}' arg '{}': invalid boolean default '{}', expected true or false",
+                    ifc, self.name, default
+                )));
+            }
+        }
+        if self.unchecked_default {
+            return Ok(());
+        }
+        if let Some(default) = &self.default {
+            let range = self.range.as_deref();
+            let res = match self.atype {
+                IfArgType::Integer | IfArgType::Long => check_range::<i64>(default, range),
+                IfArgType::Unsigned => check_range::<u64>(default, range),
+                IfArgType::Double => check_range::<f64>(default, range),
+                #[cfg(feature = "regex")]
+                IfArgType::String | IfArgType::Password => match &self.validation {
+                    Some(validation) => check_validation(default, validation),
+                    None => Ok(()),
+                },
+                _ => Ok(()),
+            };
+            res.map_err(|e| {
+                ExtcapError::invalid_config(format!(
+                    "Interface '{}' arg '{}': {}",
+                    ifc, self.name, e
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Collects the issues, including the ones rejected by `validate`
+    pub(crate) fn self_check(&self, ifc: &str, issues: &mut Vec<Issue>) {
+        let what = format!("Interface '{}' arg '{}'", ifc, self.name);
+        if let Err(e) = self.validate(ifc) {
+            issues.push(Issue::error(e.get_message()));
+        }
+        if self.display.is_none() {
+            issues.push(Issue::warning(format!("{}: no display string", what)));
+        }
+        check_text(issues, &format!("{} display", what), self.display);
+        check_text(
+            issues,
+            &format!("{} tooltip", what),
+            self.tooltip.as_deref(),
+        );
+        check_text(
+            issues,
+            &format!("{} placeholder", what),
+            self.placeholder.as_deref(),
+        );
+        #[cfg(feature = "regex")]
+        if let Some(Err(e)) = self.validation.as_deref().map(compile_validation) {
+            issues.push(Issue::error(format!("{}: {}", what, e)));
+        }
+        match self.atype {
+            IfArgType::Selector | IfArgType::Radio | IfArgType::Multicheck => {
+                if self.vals.is_empty() && self.reload != Some(true) {
+                    issues.push(Issue::warning(format!("{}: no values", what)));
+                }
+                if let Some(default) = self
+                    .default
+                    .as_deref()
+                    .filter(|d| !self.vals.is_empty() && !self.vals.iter().any(|v| v.value == *d))
+                {
+                    issues.push(Issue::warning(format!(
+                        "{}: default '{}' is not among the values",
+                        what, default
+                    )));
+                }
+                let defaults = self.vals.iter().filter(|v| v.default == Some(true)).count();
+                if defaults > 1 && !matches!(self.atype, IfArgType::Multicheck) {
+                    issues.push(Issue::warning(format!(
+                        "{}: {} values marked as default, only one is used",
+                        what, defaults
+                    )));
+                }
+            }
+            IfArgType::Integer | IfArgType::Unsigned | IfArgType::Long | IfArgType::Double => {}
+            _ => {
+                if self.range.is_some() {
+                    issues.push(Issue::warning(format!(
+                        "{}: range is ignored for the {} type",
+                        what,
+                        self.atype.type_str()
+                    )));
+                }
+            }
+        }
+        for val in &self.vals {
+            let what = format!("{} value '{}'", what, val.value);
+            check_text(issues, &what, Some(&val.value));
+            check_text(issues, &format!("{} display", what), val.display.as_deref());
+        }
+    }
+
+    /// Replaces the values, the argument number stays untouched
     pub(crate) fn reload_option(&mut self, vals: Vec<IfArgVal>) {
         self.vals.clear();
         let anum = self.number;
@@ -242,27 +624,34 @@ impl<'a> IfArg<'a> {
             v.set_arg(anum);
             v
         }));
+        debug_assert!(self.vals.iter().all(|v| v.arg == anum));
     }
 
-    pub(crate) fn print_arg(&self) {
-        print!(
+    /// Writes the argument, `reload_placeholder` is used for a reloadable argument
+    /// without values and placeholder
+    pub(crate) fn write_arg(&self, out: &mut String, reload_placeholder: &str, texts: &Texts) {
+        out.push_str(&format!(
             "arg {{number={}}}{{call=--{}}}{{display={}}}{{type={}}}",
             self.number,
             self.name,
-            self.display.unwrap_or(self.name),
+            texts.resolve(TextKind::Display, self.display.unwrap_or(self.name)),
             self.atype.type_str()
-        );
-        print_opt_value("default", &self.default);
-        print_opt_value("range", &self.range);
-        print_opt_value("validation", &self.validation);
-        print_opt_value("mustexist", &self.mustexist);
-        print_opt_value("reload", &self.reload);
-        print_opt_value("placeholder", &self.placeholder);
-        print_opt_value("tooltip", &self.tooltip);
-        print_opt_value("group", &self.group);
-        println!();
-
-        self.vals.iter().for_each(IfArgVal::print_value);
+        ));
+        write_opt_value(out, "default", &self.default);
+        write_opt_value(out, "range", &self.range);
+        write_opt_value(out, "validation", &self.validation);
+        write_opt_value(out, "mustexist", &self.mustexist);
+        write_opt_value(out, "reload", &self.reload);
+        let placeholder = match self.placeholder.as_deref() {
+            None if self.has_reload() && self.vals.is_empty() => Some(reload_placeholder),
+            placeholder => placeholder,
+        };
+        texts.write_opt(out, "placeholder", TextKind::Placeholder, placeholder);
+        texts.write_opt(out, "tooltip", TextKind::Tooltip, self.tooltip.as_deref());
+        texts.write_opt(out, "group", TextKind::Group, self.group.as_deref());
+        out.push('\n');
+
+        self.vals.iter().for_each(|val| val.write_value(out, texts));
     }
 }
 
@@ -273,13 +662,14 @@ pub struct IfArgVal {
     value: String,
     display: Option<String>,
     default: Option<bool>,
+    enabled: Option<bool>,
 }
 
 impl IfArgVal {
     /// Creates a new instance of `IfArgVal` using a string value
     pub fn new<T: ToString>(value: T) -> Self {
         IfArgVal {
-            arg: usize::max_value(),
+            arg: usize::MAX,
             value: value.to_string(),
             ..Default::default()
         }
@@ -289,6 +679,26 @@ impl IfArgVal {
         self.arg = arg;
     }
 
+    /// Gets the value
+    pub fn get_value(&self) -> &str {
+        &self.value
+    }
+
+    /// Gets the display string
+    pub fn get_display(&self) -> Option<&str> {
+        self.display.as_deref()
+    }
+
+    /// Gets the default flag
+    pub fn get_default(&self) -> Option<bool> {
+        self.default
+    }
+
+    /// Gets the enabled flag
+    pub fn get_enabled(&self) -> Option<bool> {
+        self.enabled
+    }
+
     /// Sets the display string
     pub fn display(mut self, display: &str) -> Self {
         self.display = Some(display.to_owned());
@@ -301,14 +711,133 @@ impl IfArgVal {
         self
     }
 
-    fn print_value(&self) {
-        print!(
+    /// Sets the enabled flag
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = Some(enabled);
+        self
+    }
+
+    fn write_value(&self, out: &mut String, texts: &Texts) {
+        out.push_str(&format!(
             "value {{arg={}}}{{value={}}}{{display={}}}",
             self.arg,
             self.value,
-            self.display.as_ref().unwrap_or(&self.value)
-        );
-        print_opt_value("default", &self.default);
-        println!();
+            texts.resolve(
+                TextKind::Display,
+                self.display.as_ref().unwrap_or(&self.value)
+            )
+        ));
+        write_opt_value(out, "default", &self.default);
+        write_opt_value(out, "enabled", &self.enabled);
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_support::{self, TempPath};
+    use crate::{Extcap, ExtcapListener, IFace, PacketSink};
+
+    #[test]
+    fn f64_formatted_without_exponent() {
+        let cases = [
+            (0.0, "0"),
+            (1.0, "1"),
+            (-1.5, "-1.5"),
+            (0.1, "0.1"),
+            (1e-7, "0.0000001"),
+            (1.25e-10, "0.000000000125"),
+            (1e21, "1000000000000000000000"),
+            (123456789.125, "123456789.125"),
+        ];
+        for (val, expected) in cases {
+            assert_eq!(format_f64(val), expected);
+        }
+        assert!(!format_f64(f64::MAX).contains('e'));
+        assert!(!format_f64(f64::MIN_POSITIVE).contains('e'));
+    }
+
+    #[test]
+    fn f64_round_trips() {
+        let values = [0.0, 1.5, -2.75, 1e-300, 5e-324, 1e300, f64::MAX, 0.1 + 0.2];
+        for val in values {
+            assert_eq!(parse_f64(&format_f64(val)), Some(val), "{}", val);
+        }
+    }
+
+    #[test]
+    fn f64_parsed_with_dot_or_comma() {
+        let cases = [
+            ("1.5", Some(1.5)),
+            ("1,5", Some(1.5)),
+            (" -0,25 ", Some(-0.25)),
+            ("42", Some(42.0)),
+            ("1e3", Some(1000.0)),
+            ("0,000001", Some(0.000001)),
+            ("1.000,5", None),
+            ("1,2,3", None),
+            ("abc", None),
+            ("", None),
+            ("inf", None),
+            ("NaN", None),
+        ];
+        for (val, expected) in cases {
+            assert_eq!(parse_f64(val), expected, "{}", val);
+        }
+    }
+
+    #[test]
+    fn f64_default_and_range() {
+        let arg = IfArg::new_double("gain")
+            .default_f64(0.000001)
+            .range_f(-1e-9, 1e9);
+        assert_eq!(arg.get_default(), Some("0.000001"));
+        assert_eq!(arg.get_range(), Some("-0.000000001,1000000000"));
+    }
+
+    struct Gain(Mutex<Vec<ExtcapResult<Option<f64>>>>);
+
+    impl ExtcapListener for &Gain {
+        fn capture2(&mut self, extcap: &Extcap, _: &IFace, _: PacketSink) -> ExtcapResult<()> {
+            self.0.lock().unwrap().push(extcap.arg_f64("gain"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn f64_argument_value() {
+        let cases: [(Option<&str>, Option<Option<f64>>); 4] = [
+            (Some("2,5"), Some(Some(2.5))),
+            (Some("-0.125"), Some(Some(-0.125))),
+            (None, Some(None)),
+            (Some("high"), None),
+        ];
+        for (value, expected) in cases {
+            let fifo = TempPath::new("gain.pcap");
+            let mut extcap = Extcap::new("doubles");
+            let mut ifc = IFace::new("if1");
+            ifc.add_arg(IfArg::new_double("gain"));
+            extcap.add_interface(ifc).unwrap();
+            let mut args = vec![
+                "--capture",
+                "--extcap-interface",
+                "if1",
+                "--fifo",
+                fifo.as_str(),
+            ];
+            let option = value.map(|v| format!("--gain={}", v));
+            args.extend(option.as_deref());
+            let gain = Gain(Mutex::new(Vec::new()));
+            let (res, _) = test_support::run(extcap, &gain, &args);
+            assert!(res.is_ok());
+            let seen = gain.0.lock().unwrap().pop().unwrap();
+            match expected {
+                Some(expected) => assert_eq!(seen.unwrap(), expected),
+                None => assert!(seen.is_err(), "{:?}", value),
+            }
+        }
     }
 }
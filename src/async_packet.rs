@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use pcap_file::pcap::PcapPacket;
+
+use crate::{ExtcapResult, PacketSink};
+
+/// Packet sent through the `ExtcapReceiver`
+///
+/// The data are reference counted, a packet is moved to the writer without copying
+/// them. Fill them from a `PacketPool`, or hand over a `Vec` or `String` already owned.
+/// A `pcap_file` packet converts with `into()`, copying borrowed data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtcapPacket {
+    /// Time since the UNIX epoch, written in the header timestamp resolution
+    pub timestamp: Duration,
+    /// Original length of the packet, at least the length of the data
+    pub orig_len: u32,
+    /// Captured data
+    pub data: Bytes,
+}
+
+impl ExtcapPacket {
+    /// Creates the packet, the arguments in the order of `PcapPacket::new`
+    pub fn new(timestamp: Duration, orig_len: u32, data: impl Into<Bytes>) -> Self {
+        Self {
+            timestamp,
+            orig_len,
+            data: data.into(),
+        }
+    }
+
+    pub(crate) fn write_to(&self, sink: &mut PacketSink) -> ExtcapResult<()> {
+        let (ts_sec, ts_frac) = sink.get_header().timestamp(self.timestamp)?;
+        sink.write(ts_sec, ts_frac, &self.data, self.orig_len)
+    }
+}
+
+impl From<PcapPacket<'_>> for ExtcapPacket {
+    fn from(pkt: PcapPacket<'_>) -> Self {
+        let data = match pkt.data {
+            Cow::Owned(data) => Bytes::from(data),
+            Cow::Borrowed(data) => Bytes::copy_from_slice(data),
+        };
+        Self::new(pkt.timestamp, pkt.orig_len, data)
+    }
+}
+
+/// Pool of the packet buffers for the async senders
+///
+/// The buffers are slices of a shared allocation of the given capacity. Once the
+/// packets checked out before have been written and dropped, the allocation is reused,
+/// so a steady flow of packets is captured without an allocation per packet.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use extcap::{ExtcapPacket, PacketPool};
+/// let mut pool = PacketPool::new(64 * 1024);
+/// let mut buf = pool.checkout(1500);
+/// // read the packet into buf
+/// buf.truncate(60);
+/// let pkt = ExtcapPacket::new(Duration::ZERO, 60, buf);
+/// ```
+#[derive(Debug)]
+pub struct PacketPool {
+    buf: BytesMut,
+    capacity: usize,
+}
+
+impl PacketPool {
+    /// Creates the pool allocating `capacity` bytes at once
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buf: BytesMut::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Checks out a zeroed buffer of `len` bytes
+    ///
+    /// Truncate it to the data read and pass it as the packet data.
+    pub fn checkout(&mut self, len: usize) -> BytesMut {
+        self.reserve(len);
+        self.buf.resize(len, 0);
+        self.buf.split_to(len)
+    }
+
+    /// Copies the data to a pooled buffer
+    pub fn copy(&mut self, data: &[u8]) -> Bytes {
+        self.reserve(data.len());
+        self.buf.extend_from_slice(data);
+        self.buf.split().freeze()
+    }
+
+    /// Reclaims the allocation when no checked out buffer is alive, allocates
+    /// another one otherwise
+    fn reserve(&mut self, len: usize) {
+        if self.buf.capacity() < len {
+            self.buf.reserve(self.capacity.max(len));
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use log::warn;
+use pcap_file::pcap::PcapPacket;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::capture::{
+    encode_pcap_header, open_file, record_header, SharedStats, SinkOptions, PCAP_HEADER_LEN,
+    PCAP_RECORD_HEADER_LEN,
+};
+use crate::stop::is_pipe_closed;
+use crate::{CaptureHeader, CaptureStats, Clock, ExtcapError, ExtcapResult, PauseState, StopToken};
+
+/// Possible writers for `AsyncPcapWriter`
+pub enum AsyncExtcapWriter {
+    /// Writer to stdout
+    EWStdout(tokio::io::Stdout),
+    /// Writer to file
+    EWFile(tokio::fs::File),
+    /// Writer supplied by the plugin, e.g. a buffer in tests
+    EWCustom(Pin<Box<dyn AsyncWrite + Send>>),
+}
+
+impl AsyncWrite for AsyncExtcapWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            AsyncExtcapWriter::EWStdout(sout) => Pin::new(sout).poll_write(cx, buf),
+            AsyncExtcapWriter::EWFile(file) => Pin::new(file).poll_write(cx, buf),
+            AsyncExtcapWriter::EWCustom(writer) => writer.as_mut().poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncExtcapWriter::EWStdout(sout) => Pin::new(sout).poll_flush(cx),
+            AsyncExtcapWriter::EWFile(file) => Pin::new(file).poll_flush(cx),
+            AsyncExtcapWriter::EWCustom(writer) => writer.as_mut().poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncExtcapWriter::EWStdout(sout) => Pin::new(sout).poll_shutdown(cx),
+            AsyncExtcapWriter::EWFile(file) => Pin::new(file).poll_shutdown(cx),
+            AsyncExtcapWriter::EWCustom(writer) => writer.as_mut().poll_shutdown(cx),
+        }
+    }
+}
+
+/// Writer of the pcap records to the fifo from the listener's task, see
+/// `ExtcapListener::capture_async_writer`
+///
+/// The records are encoded as by `PacketSink`, the pcap header has been written when
+/// the writer is handed out. The fifo closed by Wireshark trips the stop token and
+/// the packets are suppressed while the capture is paused, the statistics are shared
+/// with the `CaptureContext`.
+/// A file is written in the background by tokio, call `flush` before returning
+/// from the task so no packet is lost.
+pub struct AsyncPcapWriter {
+    writer: AsyncExtcapWriter,
+    header: CaptureHeader,
+    encoded: Vec<u8>,
+    stats: CaptureStats,
+    clock: Clock,
+    shared_stats: Option<SharedStats>,
+    pause: PauseState,
+    stop: Option<StopToken>,
+    closed: bool,
+    name: String,
+}
+
+impl AsyncPcapWriter {
+    /// Creates the writer, writes the pcap header
+    pub async fn new(writer: AsyncExtcapWriter, header: CaptureHeader) -> ExtcapResult<Self> {
+        let encoded = encode_pcap_header(&header)?;
+        let clock = Clock::default();
+        let mut apw = Self {
+            writer,
+            header,
+            encoded,
+            stats: CaptureStats {
+                bytes: PCAP_HEADER_LEN,
+                files: 1,
+                file_bytes: PCAP_HEADER_LEN,
+                started_at: clock.now(),
+                ..Default::default()
+            },
+            clock,
+            shared_stats: None,
+            pause: PauseState::default(),
+            stop: None,
+            closed: false,
+            name: env!("CARGO_PKG_NAME").to_owned(),
+        };
+        apw.write_encoded().await?;
+        apw.flush().await?;
+        Ok(apw)
+    }
+
+    /// Creates the writer for the fifo, "-" stands for stdout
+    ///
+    /// The file rotation, tee and compression of the sink can't be combined with it.
+    pub(crate) async fn create(
+        fifo: &Path,
+        header: CaptureHeader,
+        options: SinkOptions,
+    ) -> ExtcapResult<Self> {
+        if options.rotation.is_some() || options.tee.is_some() || options.gzip {
+            return Err(ExtcapError::invalid_config(
+                "file rotation, tee and gzip are not supported by the async writer",
+            ));
+        }
+        let writer = if fifo == Path::new("-") {
+            AsyncExtcapWriter::EWStdout(tokio::io::stdout())
+        } else {
+            AsyncExtcapWriter::EWFile(tokio::fs::File::from_std(open_file(fifo)?))
+        };
+        let mut apw = Self::new(writer, header).await?;
+        apw.stats.started_at = options.clock.now();
+        apw.clock = options.clock;
+        apw.shared_stats = options.stats;
+        apw.pause = options.pause;
+        apw.stop = options.stop;
+        apw.name = options.name;
+        apw.share_stats();
+        Ok(apw)
+    }
+
+    /// Returns the capture header
+    pub fn get_header(&self) -> &CaptureHeader {
+        &self.header
+    }
+
+    /// Returns the statistics of the data written so far
+    pub fn get_stats(&self) -> CaptureStats {
+        CaptureStats {
+            elapsed: self.clock.elapsed(self.stats.started_at),
+            ..self.stats
+        }
+    }
+
+    /// Writes a packet
+    ///
+    /// The `ts_frac` is in units of the header timestamp resolution, a fraction of
+    /// a whole second or more fails. Data longer than the header snaplen is truncated.
+    pub async fn write(
+        &mut self,
+        ts_sec: u32,
+        ts_frac: u32,
+        data: &[u8],
+        orig_len: u32,
+    ) -> ExtcapResult<()> {
+        if self.pause.is_paused() {
+            self.stats.suppressed += 1;
+            self.share_stats();
+            return Ok(());
+        }
+        self.header.ts_resolution.check_fraction(ts_frac)?;
+        let (data, orig_len) = self.header.truncate(data, orig_len);
+        let record = record_header(
+            self.header.endianness,
+            [ts_sec, ts_frac, data.len() as u32, orig_len],
+        );
+        self.encoded.extend_from_slice(&record);
+        self.encoded.extend_from_slice(data);
+        self.write_encoded().await?;
+        let len = PCAP_RECORD_HEADER_LEN + data.len() as u64;
+        self.stats.packets += 1;
+        self.stats.bytes += len;
+        self.stats.file_packets += 1;
+        self.stats.file_bytes += len;
+        self.share_stats();
+        Ok(())
+    }
+
+    /// Writes a packet, e.g. built by `PacketBuilder`
+    pub async fn write_packet(&mut self, packet: &PcapPacket<'_>) -> ExtcapResult<()> {
+        let (ts_sec, ts_frac) = self.header.timestamp(packet.timestamp)?;
+        self.write(ts_sec, ts_frac, &packet.data, packet.orig_len)
+            .await
+    }
+
+    /// Flushes the written packets to the fifo
+    pub async fn flush(&mut self) -> ExtcapResult<()> {
+        let res = self.writer.flush().await;
+        Ok(self.checked(res)?)
+    }
+
+    /// Gets the underlying writer
+    pub fn get_mut(&mut self) -> &mut AsyncExtcapWriter {
+        &mut self.writer
+    }
+
+    fn share_stats(&self) {
+        if let Some(Ok(mut shared)) = self.shared_stats.as_ref().map(|s| s.lock()) {
+            *shared = self.get_stats();
+        }
+    }
+
+    async fn write_encoded(&mut self) -> io::Result<()> {
+        let buf = std::mem::take(&mut self.encoded);
+        let res = self.writer.write_all(&buf).await;
+        self.checked(res)
+    }
+
+    /// Trips the stop token once the fifo is closed by Wireshark
+    fn checked(&mut self, res: io::Result<()>) -> io::Result<()> {
+        if let Err(e) = &res {
+            if !self.closed && is_pipe_closed(e) {
+                self.closed = true;
+                warn!(
+                    "[{}] fifo closed by the reader, stopping the capture",
+                    self.name
+                );
+                if let Some(stop) = &self.stop {
+                    stop.stop();
+                }
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::sync::{Arc, Mutex};
+
+    use pcap_file::pcap::PcapReader;
+
+    use super::*;
+    use crate::test_support::{self, TempPath};
+    use crate::{CaptureTask, CtrlPipes, Extcap, ExtcapListener, IFace, RotationPolicy};
+
+    /// Writes three packets from its task, keeps the shared statistics
+    #[derive(Default)]
+    struct Writing(Arc<Mutex<Option<SharedStats>>>);
+
+    impl ExtcapListener for Writing {
+        fn capture_async_writer(
+            &mut self,
+            extcap: &Extcap,
+            _: &IFace,
+            _: Option<CtrlPipes>,
+            mut writer: AsyncPcapWriter,
+        ) -> ExtcapResult<CaptureTask> {
+            let stats = extcap.capture_context().unwrap().stats.clone();
+            *self.0.lock().unwrap() = Some(stats);
+            Ok(CaptureTask::new(async move {
+                for i in 0..3u8 {
+                    writer.write(i.into(), 0, &[i; 6], 6).await?;
+                }
+                writer.flush().await
+            }))
+        }
+    }
+
+    fn args(fifo: &TempPath) -> [&str; 5] {
+        [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.as_str(),
+        ]
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fifo_gets_valid_pcap() {
+        let fifo = TempPath::new("async.pcap");
+        let mut extcap = Extcap::new("asyncdump");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        extcap.async_writer(true);
+        let listener = Writing::default();
+        let stats = listener.0.clone();
+        let (res, _) = test_support::run_async(extcap, listener, &args(&fifo)).await;
+        assert!(res.is_ok(), "{:?}", res.err());
+
+        let mut reader = PcapReader::new(File::open(&fifo.0).unwrap()).unwrap();
+        let mut packets = Vec::new();
+        while let Some(pkt) = reader.next_packet() {
+            let pkt = pkt.unwrap();
+            packets.push((pkt.timestamp.as_secs(), pkt.data.to_vec(), pkt.orig_len));
+        }
+        let expected: Vec<_> = (0..3u8).map(|i| (i.into(), vec![i; 6], 6)).collect();
+        assert_eq!(packets, expected);
+
+        let stats = stats.lock().unwrap().take().unwrap();
+        let stats = *stats.lock().unwrap();
+        assert_eq!(stats.packets, 3);
+        assert_eq!(
+            stats.bytes,
+            PCAP_HEADER_LEN + 3 * (PCAP_RECORD_HEADER_LEN + 6)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sink_layers_refused() {
+        let fifo = TempPath::new("async.pcap");
+        let mut extcap = Extcap::new("asyncdump");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        extcap.async_writer(true);
+        extcap.file_rotation(RotationPolicy::new().max_bytes(100));
+        let (res, _) = test_support::run_async(extcap, Writing::default(), &args(&fifo)).await;
+        assert!(res.is_err());
+    }
+}
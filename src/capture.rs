@@ -0,0 +1,1600 @@
+use std::fs::File;
+#[cfg(windows)]
+use std::fs::OpenOptions;
+use std::io::{self, IoSlice, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{debug, warn};
+use pcap_file::pcap::{PcapHeader, PcapPacket, PcapWriter};
+use pcap_file::{DataLink, Endianness};
+
+#[cfg(feature = "gzip")]
+use crate::gzip::{is_gzip_path, GzFile};
+use crate::pacing::Pacer;
+#[cfg(windows)]
+use crate::rotation::is_named_pipe;
+use crate::rotation::{is_regular_file_target, Rotation};
+use crate::stop::is_pipe_closed;
+use crate::tee::Tee;
+use crate::{
+    Clock, ExtcapResult, ExtcapWriter, InterfaceDescription, Pacing, PauseState, PcapNgWriter,
+    RotationPolicy, SectionMetadata, StopToken,
+};
+
+pub(crate) const PCAP_HEADER_LEN: u64 = 24;
+pub(crate) const PCAP_RECORD_HEADER_LEN: u64 = 16;
+const LINK_TYPE_RESERVED: u32 = 0x03ff_0000; // bits between the link type and the FCS info
+#[cfg(windows)]
+const ERROR_PIPE_BUSY: i32 = 231;
+#[cfg(windows)]
+const PIPE_BUSY_RETRIES: u32 = 50;
+#[cfg(windows)]
+const PIPE_BUSY_DELAY: Duration = Duration::from_millis(100);
+
+/// Builder of `CaptureHeader`, see `CaptureHeader::builder`
+///
+/// The header is checked when the capture starts, the capture fails for a zero snaplen
+/// or a link type with the reserved bits set.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CaptureHeaderBuilder {
+    header: CaptureHeader,
+}
+
+impl CaptureHeaderBuilder {
+    /// Sets the link type, e.g. `DataLink::USER10`
+    pub fn link(self, data_link: DataLink) -> Self {
+        self.link_type(data_link.into())
+    }
+
+    /// Sets the link type (DLT) number
+    pub fn link_type(mut self, link_type: u32) -> Self {
+        self.header.link_type = link_type;
+        self
+    }
+
+    /// Sets the maximum number of bytes stored per packet, longer packets are truncated
+    pub fn snaplen(mut self, snaplen: u32) -> Self {
+        self.header.snaplen = snaplen;
+        self
+    }
+
+    /// Uses nanosecond timestamps instead of microsecond ones, the magic number follows
+    ///
+    /// `PacketSink::write_at` and `PacketBuilder` convert the packet time to the resolution.
+    pub fn nanos(mut self, nanos: bool) -> Self {
+        self.header.ts_resolution = if nanos {
+            TsResolution::NanoSecond
+        } else {
+            TsResolution::MicroSecond
+        };
+        self
+    }
+
+    /// Sets the byte order of the pcap header and the packet records
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.header.endianness = endianness;
+        self
+    }
+
+    /// Creates the header
+    pub fn build(self) -> CaptureHeader {
+        self.header
+    }
+}
+
+/// Timestamp resolution of the captured packets
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TsResolution {
+    /// Timestamp fraction in microseconds
+    #[default]
+    MicroSecond,
+    /// Timestamp fraction in nanoseconds
+    NanoSecond,
+}
+
+impl TsResolution {
+    /// Gets the number of fraction units per second
+    pub fn units_per_second(self) -> u32 {
+        match self {
+            TsResolution::MicroSecond => 1_000_000,
+            TsResolution::NanoSecond => 1_000_000_000,
+        }
+    }
+
+    /// Gets the sub-second part of the time in the fraction units
+    pub fn fraction(self, time: Duration) -> u32 {
+        match self {
+            TsResolution::MicroSecond => time.subsec_micros(),
+            TsResolution::NanoSecond => time.subsec_nanos(),
+        }
+    }
+
+    /// Rejects a fraction of a whole second or more, e.g. nanoseconds with
+    /// the microsecond resolution
+    pub(crate) fn check_fraction(self, ts_frac: u32) -> io::Result<()> {
+        if ts_frac < self.units_per_second() {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "timestamp fraction {} out of range of the {:?} resolution",
+                ts_frac, self
+            ),
+        ))
+    }
+}
+
+/// Capture header returned by the listener
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureHeader {
+    /// Link type (DLT) of the captured packets
+    pub link_type: u32,
+    /// Maximum number of bytes stored per packet
+    pub snaplen: u32,
+    /// Resolution of the packet timestamps
+    pub ts_resolution: TsResolution,
+    /// Byte order of the pcap header and the packet records
+    pub endianness: Endianness,
+}
+
+impl Default for CaptureHeader {
+    fn default() -> Self {
+        Self {
+            link_type: DataLink::USER0.into(), // DLT_USER0 default
+            snaplen: 65535,
+            ts_resolution: TsResolution::default(),
+            endianness: Endianness::Big, // as written before, not the native pcap_file default
+        }
+    }
+}
+
+impl CaptureHeader {
+    /// Creates a header for the given link type (DLT)
+    pub fn new(link_type: u32) -> Self {
+        Self {
+            link_type,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a header for the given `DataLink`, e.g. `DataLink::USER10`
+    pub fn from_data_link(data_link: DataLink) -> Self {
+        Self::new(data_link.into())
+    }
+
+    /// Creates a builder starting from the defaults: `DataLink::USER0`, snaplen 65535,
+    /// microsecond timestamps and big-endian byte order
+    ///
+    /// ```
+    /// # use extcap::{CaptureHeader, TsResolution};
+    /// # use pcap_file::DataLink;
+    /// let header = CaptureHeader::builder()
+    ///     .link(DataLink::USER10)
+    ///     .snaplen(262144)
+    ///     .nanos(true)
+    ///     .build();
+    /// assert_eq!(header.ts_resolution, TsResolution::NanoSecond);
+    /// ```
+    pub fn builder() -> CaptureHeaderBuilder {
+        CaptureHeaderBuilder::default()
+    }
+
+    /// Gets the link type as `DataLink`
+    pub fn get_data_link(&self) -> DataLink {
+        DataLink::from(self.link_type)
+    }
+
+    /// Checks the snaplen is not zero and the link type has no reserved bits set
+    pub(crate) fn check(&self) -> io::Result<()> {
+        if self.snaplen == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "capture header snaplen is 0",
+            ));
+        }
+        if self.link_type & LINK_TYPE_RESERVED != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("capture header link type {:#x} invalid", self.link_type),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Truncates the data to the snaplen, the original length is at least the data length
+    pub(crate) fn truncate<'d>(&self, data: &'d [u8], orig_len: u32) -> (&'d [u8], u32) {
+        let orig_len = orig_len.max(data.len() as u32);
+        match self.snaplen as usize {
+            0 => (data, orig_len),
+            snaplen => (&data[..data.len().min(snaplen)], orig_len),
+        }
+    }
+
+    /// Splits the time since the epoch to the seconds and the fraction in the resolution,
+    /// a time past the 32-bit pcap seconds fails
+    pub(crate) fn timestamp(&self, since_epoch: Duration) -> io::Result<(u32, u32)> {
+        Ok((
+            pcap_seconds(since_epoch.as_secs())?,
+            self.ts_resolution.fraction(since_epoch),
+        ))
+    }
+
+    pub(crate) fn from_pcap_header(ph: &PcapHeader) -> Self {
+        Self {
+            link_type: ph.datalink.into(),
+            snaplen: ph.snaplen,
+            ts_resolution: match ph.ts_resolution {
+                pcap_file::TsResolution::MicroSecond => TsResolution::MicroSecond,
+                pcap_file::TsResolution::NanoSecond => TsResolution::NanoSecond,
+            },
+            endianness: ph.endianness,
+        }
+    }
+
+    pub(crate) fn to_pcap_header(self) -> PcapHeader {
+        PcapHeader {
+            snaplen: self.snaplen,
+            datalink: self.get_data_link(),
+            ts_resolution: match self.ts_resolution {
+                TsResolution::MicroSecond => pcap_file::TsResolution::MicroSecond,
+                TsResolution::NanoSecond => pcap_file::TsResolution::NanoSecond,
+            },
+            endianness: self.endianness,
+            ..Default::default()
+        }
+    }
+}
+
+/// Statistics of the data written to the fifo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureStats {
+    /// Number of packets written
+    pub packets: u64,
+    /// Number of packets suppressed while the capture was paused
+    pub suppressed: u64,
+    /// Number of bytes written including the pcap headers, before compression
+    pub bytes: u64,
+    /// Number of compressed bytes written to the files, 0 unless compressed
+    pub compressed_bytes: u64,
+    /// Number of files written, more than one with file rotation
+    pub files: u32,
+    /// Number of packets written to the current file
+    pub file_packets: u64,
+    /// Number of bytes written to the current file
+    pub file_bytes: u64,
+    /// Time the capture started
+    pub started_at: SystemTime,
+    /// Time since the capture started
+    pub elapsed: Duration,
+    /// Wireshark closed the control channel, the control messages can't be sent anymore
+    pub control_closed: bool,
+}
+
+impl Default for CaptureStats {
+    fn default() -> Self {
+        Self {
+            packets: 0,
+            suppressed: 0,
+            bytes: 0,
+            compressed_bytes: 0,
+            files: 0,
+            file_packets: 0,
+            file_bytes: 0,
+            started_at: UNIX_EPOCH,
+            elapsed: Duration::ZERO,
+            control_closed: false,
+        }
+    }
+}
+
+/// Threshold for the write progress callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressInterval {
+    /// Report each time the given number of bytes has been written
+    Bytes(u64),
+    /// Report on the first write after the given time has passed
+    Time(Duration),
+}
+
+/// Write progress callback registered in `Extcap`
+#[derive(Clone)]
+pub(crate) struct WriteProgress {
+    interval: ProgressInterval,
+    callback: Arc<dyn Fn(&CaptureStats) + Send + Sync>,
+}
+
+impl WriteProgress {
+    pub(crate) fn new<F>(interval: ProgressInterval, callback: F) -> Self
+    where
+        F: Fn(&CaptureStats) + Send + Sync + 'static,
+    {
+        Self {
+            interval,
+            callback: Arc::new(callback),
+        }
+    }
+}
+
+/// When the buffered packets are written out to the fifo
+///
+/// The buffered policies trade the latency for fewer writes, Wireshark shows the packets
+/// once they are flushed. The async capture flushes whenever no packet is queued,
+/// the blocking capture should call `PacketSink::flush` before waiting for the source.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Each packet is written through at once, no buffering
+    #[default]
+    EveryPacket,
+    /// Flushed when the given number of bytes has been buffered
+    AfterBytes(u64),
+    /// Flushed by the first write after the given time since the last flush
+    AfterDuration(Duration),
+}
+
+/// Buffer of the sink writer with a buffered `FlushPolicy`
+const FLUSH_BUFFER_LEN: usize = 64 * 1024;
+
+/// Statistics shared with the sink owned by the listener
+pub(crate) type SharedStats = Arc<Mutex<CaptureStats>>;
+
+/// Writer layer options configured in `Extcap`
+#[derive(Clone, Default)]
+pub(crate) struct SinkOptions {
+    pub(crate) progress: Option<WriteProgress>,
+    pub(crate) rotation: Option<RotationPolicy>,
+    pub(crate) stats: Option<SharedStats>,
+    pub(crate) clock: Clock,
+    pub(crate) stop: Option<StopToken>, // tripped when the fifo is closed by Wireshark
+    pub(crate) control_closed: Arc<AtomicBool>, // set when the control out pipe is closed
+    pub(crate) sync_on_close: bool,
+    pub(crate) tee: Option<PathBuf>,
+    pub(crate) gzip: bool,
+    pub(crate) pause: PauseState,
+    pub(crate) flush: FlushPolicy,
+    pub(crate) name: String, // of the extcap, prefixing the log lines
+}
+
+/// Progress callback with the next threshold
+struct ProgressState {
+    progress: WriteProgress,
+    next_bytes: u64,
+    next_time: Duration,
+}
+
+impl ProgressState {
+    fn new(progress: WriteProgress) -> Self {
+        let (next_bytes, next_time) = match progress.interval {
+            ProgressInterval::Bytes(interval) => (interval.max(1), Duration::ZERO),
+            ProgressInterval::Time(interval) => (0, interval),
+        };
+        Self {
+            progress,
+            next_bytes,
+            next_time,
+        }
+    }
+
+    /// Invokes the callback if the threshold has been passed
+    fn update(&mut self, stats: &CaptureStats) {
+        match self.progress.interval {
+            ProgressInterval::Bytes(interval) => {
+                if stats.bytes < self.next_bytes {
+                    return;
+                }
+                let interval = interval.max(1);
+                self.next_bytes = (stats.bytes / interval + 1) * interval;
+            }
+            ProgressInterval::Time(interval) => {
+                if stats.elapsed < self.next_time {
+                    return;
+                }
+                self.next_time = stats.elapsed + interval;
+            }
+        }
+        (self.progress.callback)(stats);
+    }
+}
+
+/// Creator of the `PacketSink` for the fifo, see `ExtcapListener::capture_lazy`
+///
+/// The fifo is opened and the pcap header written only by `create`, the listener can
+/// connect to its source first and fail without touching the fifo.
+pub struct SinkFactory {
+    fifo: PathBuf,
+    options: SinkOptions,
+}
+
+impl SinkFactory {
+    pub(crate) fn new(fifo: &Path, options: SinkOptions) -> Self {
+        Self {
+            fifo: fifo.to_path_buf(),
+            options,
+        }
+    }
+
+    /// Opens the fifo and writes the header, the sink is configured as by `Extcap`
+    pub fn create(self, header: CaptureHeader) -> ExtcapResult<PacketSink> {
+        debug!(
+            "[{}] deferred capture header: {:?}",
+            self.options.name, header
+        );
+        Ok(PacketSink::create(&self.fifo, header, self.options)?)
+    }
+
+    /// Returns the fifo given by Wireshark
+    pub fn get_fifo(&self) -> &Path {
+        &self.fifo
+    }
+}
+
+/// Destination of the captured packets
+///
+/// Writes the packets in the pcap format to the fifo given by Wireshark. The sink is
+/// flushed when dropped, also while unwinding from a panic in the listener, so the
+/// packets written before are not lost when the fifo is a regular file. There is no
+/// panic hook flushing it: the sink is owned by the listener, the unwinding drops it
+/// the same way a return does. With `panic = "abort"` nothing is dropped and buffered
+/// packets are lost, use `FlushPolicy::EveryPacket` then.
+///
+/// It bundles the writer with the `write`, `write_at` and `write_now` helpers, the
+/// packet and byte counters of `get_stats`, and `is_stopped` set by the signals of
+/// `Extcap::stop_on_signal` and by the fifo closed by Wireshark.
+pub struct PacketSink {
+    header: CaptureHeader,
+    writer: Option<ExtcapWriter>, // taken by the deprecated capture only
+    clock: Clock,
+    stats: CaptureStats,
+    progress: Option<ProgressState>,
+    rotation: Option<Rotation>,
+    shared_stats: Option<SharedStats>,
+    stop: Option<StopToken>,
+    closed: bool,
+    control_closed: Arc<AtomicBool>,
+    sync: bool, // sync the files to the disk when closed
+    tee: Option<Tee>,
+    compressed: Option<Arc<AtomicU64>>, // compressed bytes written if gzip is used
+    pause: PauseState,
+    flush: FlushPolicy,
+    unflushed: u64,
+    flushed_at: Instant,
+    pacer: Option<Pacer>,
+    name: String,
+}
+
+/// Dumper borrowed by `ExtcapListener::capture_dump`, the sink of the capture
+pub type ExtcapDumper = PacketSink;
+
+/// Encodes the pcap record header as `PcapWriter` does
+pub(crate) fn record_header(endianness: Endianness, fields: [u32; 4]) -> [u8; 16] {
+    let mut header = [0; 16];
+    for (bytes, field) in header.chunks_exact_mut(4).zip(fields) {
+        bytes.copy_from_slice(&match endianness {
+            Endianness::Big => field.to_be_bytes(),
+            Endianness::Little => field.to_le_bytes(),
+        });
+    }
+    header
+}
+
+/// Writes the record header and the data by a single vectored write if possible
+///
+/// A writer without vectored IO writes the header only, the data follow by another write.
+pub(crate) fn write_record(
+    writer: &mut ExtcapWriter,
+    mut header: &[u8],
+    data: &[u8],
+) -> io::Result<()> {
+    loop {
+        match writer.write_vectored(&[IoSlice::new(header), IoSlice::new(data)]) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) if n < header.len() => header = &header[n..],
+            Ok(n) => return writer.write_all(&data[n - header.len()..]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Opens the fifo for writing, created if it's a regular file
+///
+/// A Windows named pipe is opened, not created, and retried for a while when all its
+/// instances are busy.
+pub(crate) fn open_file(path: &Path) -> io::Result<File> {
+    #[cfg(windows)]
+    if is_named_pipe(path) {
+        let mut retries = PIPE_BUSY_RETRIES;
+        loop {
+            match OpenOptions::new().write(true).open(path) {
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) && retries > 0 => {
+                    debug!("pipe {} busy, retrying", path.display());
+                    retries -= 1;
+                    thread::sleep(PIPE_BUSY_DELAY);
+                }
+                res => return res,
+            }
+        }
+    }
+    File::create(path)
+}
+
+/// Creates the capture file, gzip compressed if `compressed` counts the written bytes
+#[cfg_attr(not(feature = "gzip"), allow(unused_variables))]
+pub(crate) fn create_file(
+    path: &Path,
+    compressed: Option<&Arc<AtomicU64>>,
+    sync: bool,
+) -> io::Result<ExtcapWriter> {
+    let file = open_file(path)?;
+    #[cfg(feature = "gzip")]
+    if let Some(compressed) = compressed {
+        let gz = GzFile::new(file, compressed.clone(), sync);
+        return Ok(ExtcapWriter::EWCustom(Box::new(gz)));
+    }
+    Ok(ExtcapWriter::EWFile(file))
+}
+
+/// Checks whether the file is to be gzip compressed, never a pipe or stdout
+#[cfg(feature = "gzip")]
+pub(crate) fn is_gzip_target(path: &Path, gzip: bool) -> bool {
+    (gzip || is_gzip_path(path)) && is_regular_file_target(path)
+}
+
+/// Checks whether the file is to be gzip compressed, never without the `gzip` feature
+#[cfg(not(feature = "gzip"))]
+pub(crate) fn is_gzip_target(_path: &Path, _gzip: bool) -> bool {
+    false
+}
+
+/// Encodes the pcap header in the byte order of the capture header
+pub(crate) fn encode_pcap_header(header: &CaptureHeader) -> io::Result<Vec<u8>> {
+    header.check()?;
+    let mut encoded = Vec::with_capacity(PCAP_HEADER_LEN as usize);
+    header
+        .to_pcap_header()
+        .write_to(&mut encoded)
+        .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+    Ok(encoded)
+}
+
+/// Writes the pcap header by a single write, flushed at once
+pub(crate) fn write_pcap_header(
+    mut writer: ExtcapWriter,
+    header: &CaptureHeader,
+) -> io::Result<ExtcapWriter> {
+    writer.write_all(&encode_pcap_header(header)?)?;
+    // Wireshark waits for the header before showing the capture
+    writer.flush()?;
+    Ok(writer)
+}
+
+pub(crate) fn create_pcap_writer(
+    writer: ExtcapWriter,
+    header: &CaptureHeader,
+) -> io::Result<PcapWriter<ExtcapWriter>> {
+    header.check()?;
+    PcapWriter::with_header(writer, header.to_pcap_header())
+        .map_err(|e| io::Error::other(format!("{:?}", e)))
+}
+
+/// Writer of the deprecated `capture()` skipping the pcap header already written
+/// by the sink, `PcapWriter` writes it again when created
+struct HeaderWritten {
+    writer: ExtcapWriter,
+    skip: usize,
+}
+
+impl Write for HeaderWritten {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.skip > 0 {
+            let skipped = buf.len().min(self.skip);
+            self.skip -= skipped;
+            return Ok(skipped);
+        }
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Buffers the writer unless every packet is to be written through
+fn buffered(writer: ExtcapWriter, flush: FlushPolicy) -> ExtcapWriter {
+    match flush {
+        FlushPolicy::EveryPacket => writer,
+        _ => ExtcapWriter::EWBuffered(io::BufWriter::with_capacity(
+            FLUSH_BUFFER_LEN,
+            Box::new(writer),
+        )),
+    }
+}
+
+pub(crate) fn create_pcapng_writer(
+    writer: ExtcapWriter,
+    interfaces: &[InterfaceDescription],
+    section: &SectionMetadata,
+    default_userappl: &str,
+    clock: Clock,
+) -> io::Result<PcapNgWriter<ExtcapWriter>> {
+    let (first, rest) = interfaces.split_first().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "no pcapng interface described")
+    })?;
+    let mut writer = PcapNgWriter::with_clock(
+        writer,
+        first,
+        section.get_os(),
+        section.get_userappl(default_userappl),
+        clock,
+    )?;
+    for interface in rest {
+        writer.add_interface(interface)?;
+    }
+    Ok(writer)
+}
+
+/// Converts the seconds since the epoch to the 32-bit pcap field, failing past
+/// 2106-02-07 06:28:15 UTC rather than wrapping around
+pub(crate) fn pcap_seconds(ts_sec: u64) -> io::Result<u32> {
+    u32::try_from(ts_sec).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "timestamp {}s out of range of the 32-bit pcap seconds, use pcapng",
+                ts_sec
+            ),
+        )
+    })
+}
+
+/// Creates the fifo writer without the sink layers, "-" stands for stdout
+pub(crate) fn create_fifo_writer(fifo: &Path, gzip: bool) -> io::Result<ExtcapWriter> {
+    if fifo == Path::new("-") {
+        return Ok(ExtcapWriter::EWStdout(io::stdout()));
+    }
+    let compressed = is_gzip_target(fifo, gzip).then(Arc::default);
+    create_file(fifo, compressed.as_ref(), false)
+}
+
+impl PacketSink {
+    /// Creates the sink for the fifo, "-" stands for stdout
+    pub(crate) fn create(
+        fifo: &Path,
+        header: CaptureHeader,
+        options: SinkOptions,
+    ) -> io::Result<Self> {
+        // with the copy only the copy is rotated
+        let regular = is_regular_file_target(fifo);
+        if options.rotation.is_some() && options.tee.is_some() {
+            debug!("[{}] file rotation applied to the copy only", options.name);
+        } else if options.rotation.is_some() && !regular {
+            warn!(
+                "[{}] file rotation ignored, {} is not a regular file",
+                options.name,
+                fifo.display()
+            );
+        }
+        let mut rotation = options
+            .rotation
+            .filter(|_| options.tee.is_none() && regular)
+            .map(|policy| Rotation::new(policy, fifo, options.clock.clone(), &options.name));
+        let sync = options.sync_on_close && regular;
+        let compressed = is_gzip_target(fifo, options.gzip).then(Arc::default);
+        let writer = if let Some(rot) = rotation.as_mut() {
+            let path = rot.next_path()?;
+            debug!("[{}] rotation writing {}", options.name, path.display());
+            create_file(&path, compressed.as_ref(), sync)?
+        } else if fifo == Path::new("-") {
+            ExtcapWriter::EWStdout(io::stdout())
+        } else {
+            create_file(fifo, compressed.as_ref(), sync)?
+        };
+        let writer = buffered(writer, options.flush);
+        let options = SinkOptions {
+            sync_on_close: sync,
+            ..options
+        };
+        let mut sink = Self::with_writer(writer, header, options, rotation)?;
+        sink.compressed = compressed;
+        Ok(sink)
+    }
+
+    /// Creates the sink writing to any writer, e.g. a compressing adapter or a buffer in tests
+    ///
+    /// The sink uses the default options, the progress, rotation and clock configured
+    /// in `Extcap` apply only to the sink created for the fifo.
+    pub fn from_writer<W>(writer: W, header: CaptureHeader) -> ExtcapResult<Self>
+    where
+        W: Write + Send + 'static,
+    {
+        let writer = ExtcapWriter::EWCustom(Box::new(writer));
+        let options = SinkOptions {
+            name: env!("CARGO_PKG_NAME").to_owned(),
+            ..Default::default()
+        };
+        Ok(Self::with_writer(writer, header, options, None)?)
+    }
+
+    fn with_writer(
+        writer: ExtcapWriter,
+        header: CaptureHeader,
+        options: SinkOptions,
+        rotation: Option<Rotation>,
+    ) -> io::Result<Self> {
+        let writer = write_pcap_header(writer, &header)?;
+        let tee = options.tee.as_deref().and_then(|path| {
+            Tee::create(
+                path,
+                &header,
+                options.rotation,
+                options.clock.clone(),
+                options.sync_on_close,
+                &options.name,
+            )
+        });
+        Ok(Self {
+            header,
+            writer: Some(writer),
+            stats: CaptureStats {
+                bytes: PCAP_HEADER_LEN,
+                files: 1,
+                file_bytes: PCAP_HEADER_LEN,
+                started_at: options.clock.now(),
+                ..Default::default()
+            },
+            clock: options.clock,
+            progress: options.progress.map(ProgressState::new),
+            rotation,
+            shared_stats: options.stats,
+            stop: options.stop,
+            closed: false,
+            control_closed: options.control_closed,
+            sync: options.sync_on_close,
+            tee,
+            compressed: None,
+            pause: options.pause,
+            flush: options.flush,
+            unflushed: 0,
+            flushed_at: Instant::now(),
+            pacer: None,
+            name: options.name,
+        })
+    }
+
+    fn pcap_writer(&mut self) -> &mut ExtcapWriter {
+        self.writer
+            .as_mut()
+            .expect("writer taken by take_pcap_writer")
+    }
+
+    /// Flushes the current file, syncs it to the disk if configured
+    fn close_file(&mut self) -> io::Result<()> {
+        let sync = self.sync;
+        let writer = self.pcap_writer();
+        writer.flush()?;
+        if sync {
+            writer.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Switches to the next file if the rotation is due
+    fn rotate(&mut self) -> ExtcapResult<()> {
+        let path = match self.rotation.as_mut() {
+            Some(rot) if rot.is_due(self.stats.file_bytes) => rot.next_path()?,
+            _ => return Ok(()),
+        };
+        debug!("[{}] rotation writing {}", self.name, path.display());
+        self.close_file()?;
+        let writer = create_file(&path, self.compressed.as_ref(), self.sync)?;
+        let writer = buffered(writer, self.flush);
+        self.writer = Some(write_pcap_header(writer, &self.header)?);
+        self.stats.files += 1;
+        self.stats.bytes += PCAP_HEADER_LEN;
+        self.stats.file_packets = 0;
+        self.stats.file_bytes = PCAP_HEADER_LEN;
+        Ok(())
+    }
+
+    /// Returns the capture header
+    pub fn get_header(&self) -> &CaptureHeader {
+        &self.header
+    }
+
+    /// Returns the clock configured by `Extcap::clock`
+    pub fn get_clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Returns `true` once the capture is to stop: the fifo has been closed by Wireshark,
+    /// a signal has been received with `Extcap::stop_on_signal`, or the stop token of
+    /// `CaptureContext` has been tripped otherwise
+    pub fn is_stopped(&self) -> bool {
+        self.closed || self.stop.as_ref().is_some_and(StopToken::is_stopped)
+    }
+
+    /// Returns the statistics of the data written so far
+    pub fn get_stats(&self) -> CaptureStats {
+        CaptureStats {
+            elapsed: self.clock.elapsed(self.stats.started_at),
+            control_closed: self.control_closed.load(Ordering::SeqCst),
+            compressed_bytes: self
+                .compressed
+                .as_ref()
+                .map(|c| c.load(Ordering::Relaxed))
+                .unwrap_or_default(),
+            ..self.stats
+        }
+    }
+
+    /// Sets the pace of the written packets, unpaced by default
+    ///
+    /// The writes block the thread until the packet is due, see `Pacing`. The wait
+    /// ends at once when the capture is stopped and the packet is written.
+    pub fn set_pacing(&mut self, pacing: Pacing) -> ExtcapResult<()> {
+        pacing.check()?;
+        self.pacer = (pacing != Pacing::Unpaced).then(|| Pacer::new(pacing));
+        Ok(())
+    }
+
+    /// Waits until the packet with the timestamp is due
+    fn pace(&mut self, ts_sec: u32, ts_frac: u32) {
+        let Some(pacer) = self.pacer.as_mut() else {
+            return;
+        };
+        let nanos_per_unit = 1_000_000_000 / self.header.ts_resolution.units_per_second();
+        let ts = Duration::new(u64::from(ts_sec), ts_frac * nanos_per_unit);
+        let delay = pacer.delay(ts);
+        if delay.is_zero() || self.is_stopped() {
+            return;
+        }
+        match &self.stop {
+            Some(stop) => {
+                stop.wait_timeout(delay);
+            }
+            None => thread::sleep(delay),
+        }
+    }
+
+    /// Checks whether the fifo has been closed by Wireshark
+    #[cfg(feature = "async-api")]
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    fn io_failed(&mut self, e: &io::Error) {
+        if self.closed || !is_pipe_closed(e) {
+            return;
+        }
+        self.closed = true;
+        warn!(
+            "[{}] fifo closed by the reader, stopping the capture",
+            self.name
+        );
+        if let Some(stop) = &self.stop {
+            stop.stop();
+        }
+    }
+
+    /// Counts the packet suppressed while paused
+    fn suppressed(&mut self) {
+        self.stats.suppressed += 1;
+        if let Some(Ok(mut shared)) = self.shared_stats.as_ref().map(|s| s.lock()) {
+            shared.suppressed = self.stats.suppressed;
+        }
+    }
+
+    fn written(&mut self, len: usize) {
+        let len = PCAP_RECORD_HEADER_LEN + len as u64;
+        self.stats.packets += 1;
+        self.stats.bytes += len;
+        self.stats.file_packets += 1;
+        self.stats.file_bytes += len;
+        if self.progress.is_none() && self.shared_stats.is_none() {
+            return;
+        }
+        let stats = self.get_stats();
+        if let Some(ps) = self.progress.as_mut() {
+            ps.update(&stats);
+        }
+        if let Some(Ok(mut shared)) = self.shared_stats.as_ref().map(|s| s.lock()) {
+            *shared = stats;
+        }
+    }
+
+    /// Writes a packet
+    ///
+    /// The `ts_frac` is in units of the header timestamp resolution, a fraction of
+    /// a whole second or more fails. Data longer than the header snaplen is truncated,
+    /// `orig_len` is at least the length of the passed data. See
+    /// [`PacketBuilder`](crate::PacketBuilder) for the defaults, or `write_at`
+    /// converting the time. Use `write_ts64` with the seconds as `u64`, casting them
+    /// to `u32` wraps around silently.
+    /// The packet is suppressed while the capture is paused, see `PauseState`, it still
+    /// waits for its turn with `set_pacing`.
+    pub fn write(
+        &mut self,
+        ts_sec: u32,
+        ts_frac: u32,
+        data: &[u8],
+        orig_len: u32,
+    ) -> ExtcapResult<()> {
+        self.header.ts_resolution.check_fraction(ts_frac)?;
+        self.pace(ts_sec, ts_frac);
+        if self.pause.is_paused() {
+            self.suppressed();
+            return Ok(());
+        }
+        let (data, orig_len) = self.header.truncate(data, orig_len);
+        self.rotate()?;
+        let fields = [ts_sec, ts_frac, data.len() as u32, orig_len];
+        let record = record_header(self.header.endianness, fields);
+        if let Err(e) = write_record(self.pcap_writer(), &record, data) {
+            self.io_failed(&e);
+            return Err(e.into());
+        }
+        self.written(data.len());
+        self.tee_write(ts_sec, ts_frac, data, orig_len);
+        self.flush_due(data.len())
+    }
+
+    /// Flushes the buffered packets if the flush policy says so
+    fn flush_due(&mut self, len: usize) -> ExtcapResult<()> {
+        self.unflushed += PCAP_RECORD_HEADER_LEN + len as u64;
+        let due = match self.flush {
+            FlushPolicy::EveryPacket => false,
+            FlushPolicy::AfterBytes(bytes) => self.unflushed >= bytes,
+            FlushPolicy::AfterDuration(period) => self.flushed_at.elapsed() >= period,
+        };
+        if due {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the buffered packets before waiting for more, a failure is left
+    /// to the next write
+    #[cfg(feature = "async-api")]
+    pub(crate) fn flush_idle(&mut self) {
+        if self.unflushed == 0 || self.closed || self.writer.is_none() {
+            return;
+        }
+        if let Err(e) = self.flush() {
+            debug!("[{}] idle flush failed: {}", self.name, e);
+        }
+    }
+
+    /// Writes a packet with the seconds since the epoch as `u64`
+    ///
+    /// Plain pcap holds the seconds in 32 bits, i.e. until 2106-02-07 06:28:15 UTC,
+    /// a later timestamp fails rather than being wrapped around. See `write` for
+    /// the other arguments.
+    pub fn write_ts64(
+        &mut self,
+        ts_sec: u64,
+        ts_frac: u32,
+        data: &[u8],
+        orig_len: u32,
+    ) -> ExtcapResult<()> {
+        let ts_sec = pcap_seconds(ts_sec)?;
+        self.write(ts_sec, ts_frac, data, orig_len)
+    }
+
+    /// Writes a packet captured at the time, in the header timestamp resolution
+    ///
+    /// Data longer than the header snaplen is truncated. A time before the epoch
+    /// is written as the epoch, a time past the 32-bit pcap seconds fails, see
+    /// `write_ts64`.
+    pub fn write_at(&mut self, time: SystemTime, data: &[u8]) -> ExtcapResult<()> {
+        crate::PacketBuilder::new()
+            .time(time)
+            .data(data)
+            .write_to(self)
+    }
+
+    /// Writes a packet captured now, by the clock configured by `Extcap::clock`
+    ///
+    /// See `write_at`.
+    pub fn write_now(&mut self, data: &[u8]) -> ExtcapResult<()> {
+        crate::PacketBuilder::new().data(data).write_to(self)
+    }
+
+    /// Flushes the written packets to the fifo
+    ///
+    /// Forces the buffered packets out with a buffered `FlushPolicy`.
+    pub fn flush(&mut self) -> ExtcapResult<()> {
+        self.unflushed = 0;
+        self.flushed_at = Instant::now();
+        if self.tee.as_mut().is_some_and(|tee| !tee.flush()) {
+            self.tee = None;
+        }
+        if let Err(e) = self.pcap_writer().flush() {
+            self.io_failed(&e);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_packet(&mut self, pkt: &PcapPacket) -> ExtcapResult<()> {
+        let (ts_sec, ts_frac) = self.header.timestamp(pkt.timestamp)?;
+        self.write(ts_sec, ts_frac, &pkt.data, pkt.orig_len)
+    }
+
+    /// Writes the packet to the copy, a failure disables it
+    fn tee_write(&mut self, ts_sec: u32, ts_frac: u32, data: &[u8], orig_len: u32) {
+        if let Some(tee) = self.tee.as_mut() {
+            if !tee.write(ts_sec, ts_frac, data, orig_len) {
+                self.tee = None;
+            }
+        }
+    }
+
+    /// Takes the writer for the deprecated capture, nothing is written by the sink then
+    pub(crate) fn take_pcap_writer(&mut self) -> ExtcapResult<PcapWriter<ExtcapWriter>> {
+        if self.rotation.is_some() {
+            warn!(
+                "[{}] file rotation is not supported by the deprecated capture()",
+                self.name
+            );
+        }
+        if self.tee.is_some() {
+            warn!(
+                "[{}] capture copy is not supported by the deprecated capture()",
+                self.name
+            );
+        }
+        if self.flush != FlushPolicy::EveryPacket {
+            warn!(
+                "[{}] flush policy is not applied by the deprecated capture(), flushed every 64 KiB",
+                self.name
+            );
+        }
+        let writer = self
+            .writer
+            .take()
+            .expect("writer taken by take_pcap_writer");
+        let writer = HeaderWritten {
+            writer,
+            skip: PCAP_HEADER_LEN as usize,
+        };
+        let writer = ExtcapWriter::EWCustom(Box::new(writer));
+        Ok(create_pcap_writer(writer, &self.header)?)
+    }
+}
+
+impl Drop for PacketSink {
+    fn drop(&mut self) {
+        if let Some(Err(e)) = self.tee.as_mut().map(Tee::close) {
+            debug!("[{}] capture copy not flushed on drop: {}", self.name, e);
+        }
+        // nothing to flush to a fifo closed by Wireshark
+        if self.writer.is_none() || self.closed {
+            return;
+        }
+        if let Err(e) = self.close_file() {
+            debug!("[{}] sink not flushed on drop: {}", self.name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::AtomicUsize;
+
+    use pcap_file::pcap::PcapReader;
+
+    use super::*;
+    use crate::test_support::{self, TempPath};
+    use crate::{Extcap, ExtcapListener, IFace};
+
+    struct Panicking;
+
+    impl ExtcapListener for Panicking {
+        fn capture2(&mut self, _: &Extcap, _: &IFace, mut sink: PacketSink) -> ExtcapResult<()> {
+            for i in 0..3u8 {
+                sink.write_now(&[i; 4])?;
+            }
+            panic!("listener failed");
+        }
+    }
+
+    #[test]
+    fn packets_before_panic_are_flushed() {
+        let fifo = TempPath::new("panic.pcap");
+        let mut extcap = Extcap::new("panicdump");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        extcap.flush_policy(FlushPolicy::AfterBytes(1 << 20));
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            test_support::run(extcap, Panicking, &args)
+        }));
+        assert!(res.is_err());
+        let mut reader = PcapReader::new(File::open(&fifo.0).unwrap()).unwrap();
+        for i in 0..3u8 {
+            let pkt = reader.next_packet().unwrap().unwrap();
+            assert_eq!(&pkt.data[..], &[i; 4]);
+        }
+        assert!(reader.next_packet().is_none());
+    }
+
+    /// Writes through the `PcapWriter` of the deprecated capture
+    struct Deprecated;
+
+    impl ExtcapListener for Deprecated {
+        fn capture(
+            &mut self,
+            _: &Extcap,
+            _: &IFace,
+            mut writer: PcapWriter<ExtcapWriter>,
+        ) -> ExtcapResult<()> {
+            let packet = PcapPacket::new(Duration::from_secs(2), 3, &[7; 3]);
+            writer.write_packet(&packet)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn deprecated_capture_writes_header_once() {
+        let fifo = TempPath::new("deprecated.pcap");
+        let mut extcap = Extcap::new("olddump");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        let (res, _) = test_support::run(extcap, Deprecated, &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+        let data = std::fs::read(&fifo.0).unwrap();
+        assert_eq!(
+            data.len() as u64,
+            PCAP_HEADER_LEN + PCAP_RECORD_HEADER_LEN + 3
+        );
+        let mut reader = PcapReader::new(&data[..]).unwrap();
+        let packet = reader.next_packet().unwrap().unwrap();
+        assert_eq!(packet.timestamp, Duration::from_secs(2));
+        assert_eq!(&packet.data[..], [7; 3]);
+        assert!(reader.next_packet().is_none());
+    }
+
+    /// Writes through the dumper borrowed by `capture_dump`
+    struct Dumping;
+
+    impl ExtcapListener for Dumping {
+        fn capture_dump(
+            &mut self,
+            _: &Extcap,
+            _: &IFace,
+            dumper: &mut ExtcapDumper,
+        ) -> ExtcapResult<()> {
+            for i in 1..=3u8 {
+                assert!(!dumper.is_stopped());
+                dumper.write(i.into(), 0, &[i; 4], 4)?;
+            }
+            assert_eq!(dumper.get_stats().packets, 3);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dumper_hook_writes_packets() {
+        let fifo = TempPath::new("dump.pcap");
+        let mut extcap = Extcap::new("dumpdump");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        let (res, _) = test_support::run(extcap, Dumping, &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+        let mut reader = PcapReader::new(File::open(&fifo.0).unwrap()).unwrap();
+        let mut packets = Vec::new();
+        while let Some(pkt) = reader.next_packet() {
+            let pkt = pkt.unwrap();
+            packets.push((pkt.timestamp.as_secs(), pkt.data.to_vec()));
+        }
+        assert_eq!(packets, [(1, vec![1; 4]), (2, vec![2; 4]), (3, vec![3; 4])]);
+    }
+
+    struct LongPackets;
+
+    impl ExtcapListener for LongPackets {
+        fn capture2(&mut self, _: &Extcap, _: &IFace, mut sink: PacketSink) -> ExtcapResult<()> {
+            for len in [4, 8, 20] {
+                sink.write_now(&vec![len as u8; len])?;
+            }
+            // the original length kept when longer than the data
+            sink.write(0, 0, &[1; 12], 1500)
+        }
+    }
+
+    #[test]
+    fn packets_truncated_to_snaplen() {
+        let fifo = TempPath::new("snaplen.pcap");
+        let mut extcap = Extcap::new("snapdump");
+        extcap.add_interface(IFace::new("if1").snaplen(8)).unwrap();
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        let (res, _) = test_support::run(extcap, LongPackets, &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+        let mut reader = PcapReader::new(File::open(&fifo.0).unwrap()).unwrap();
+        assert_eq!(reader.header().snaplen, 8);
+        // raw records, pcap_file 2 rejects the original length over the snaplen
+        let mut packets = Vec::new();
+        while let Some(pkt) = reader.next_raw_packet() {
+            let pkt = pkt.unwrap();
+            packets.push((pkt.data.to_vec(), pkt.orig_len));
+        }
+        let expected = [
+            (vec![4; 4], 4),
+            (vec![8; 8], 8),
+            (vec![20; 8], 20),
+            (vec![1; 8], 1500),
+        ];
+        assert_eq!(packets, expected);
+    }
+
+    /// Counts the writes reaching the fifo
+    struct CountingWriter(Arc<AtomicUsize>);
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Writes the packets of 64 bytes, returns the number of writes reaching the fifo
+    fn writes_with(flush: FlushPolicy, packets: usize) -> usize {
+        let writes = Arc::new(AtomicUsize::new(0));
+        let writer = ExtcapWriter::EWCustom(Box::new(CountingWriter(writes.clone())));
+        let options = SinkOptions {
+            flush,
+            ..Default::default()
+        };
+        let header = CaptureHeader::from_data_link(DataLink::ETHERNET);
+        let mut sink =
+            PacketSink::with_writer(buffered(writer, flush), header, options, None).unwrap();
+        for _ in 0..packets {
+            sink.write(0, 0, &[0; 64], 64).unwrap();
+        }
+        writes.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn buffered_policies_cut_the_writes() {
+        let every_packet = writes_with(FlushPolicy::EveryPacket, 10_000);
+        assert!(every_packet > 10_000, "{}", every_packet);
+        // 80 bytes a packet, about two writes per 64 KiB
+        let after_bytes = writes_with(FlushPolicy::AfterBytes(64 * 1024), 10_000);
+        assert!(after_bytes * 100 < every_packet, "{}", after_bytes);
+        // only the header until the period passes
+        let after_duration =
+            writes_with(FlushPolicy::AfterDuration(Duration::from_secs(3600)), 100);
+        assert_eq!(after_duration, 1);
+    }
+
+    #[test]
+    fn buffered_throughput_higher() {
+        let packets_per_sec = |flush| {
+            let path = TempPath::new("throughput.pcap");
+            let header = CaptureHeader::from_data_link(DataLink::ETHERNET);
+            let options = SinkOptions {
+                flush,
+                ..Default::default()
+            };
+            let mut sink = PacketSink::create(&path.0, header, options).unwrap();
+            let started = Instant::now();
+            for _ in 0..50_000 {
+                sink.write(0, 0, &[0; 64], 64).unwrap();
+            }
+            sink.flush().unwrap();
+            50_000.0 / started.elapsed().as_secs_f64()
+        };
+        let unbuffered = packets_per_sec(FlushPolicy::EveryPacket);
+        let buffered = packets_per_sec(FlushPolicy::AfterBytes(64 * 1024));
+        // several times faster in a release build, the margin is left for debug builds
+        assert!(
+            buffered > unbuffered * 1.2,
+            "{:.0} vs {:.0} pps",
+            buffered,
+            unbuffered
+        );
+    }
+
+    /// Checks the fifo size while writing with a buffered policy
+    struct HeaderFirst(PathBuf);
+
+    impl ExtcapListener for HeaderFirst {
+        fn capture2(&mut self, _: &Extcap, _: &IFace, mut sink: PacketSink) -> ExtcapResult<()> {
+            let len = || std::fs::metadata(&self.0).unwrap().len();
+            assert_eq!(len(), PCAP_HEADER_LEN);
+            sink.write_now(&[0; 4])?;
+            assert_eq!(len(), PCAP_HEADER_LEN);
+            // the listener forces the packets out
+            sink.flush()?;
+            assert_eq!(len(), PCAP_HEADER_LEN + PCAP_RECORD_HEADER_LEN + 4);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn header_written_at_once_when_buffered() {
+        let fifo = TempPath::new("header.pcap");
+        let mut extcap = Extcap::new("bufdump");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        extcap.flush_policy(FlushPolicy::AfterBytes(1 << 20));
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        let (res, _) = test_support::run(extcap, HeaderFirst(fifo.0.clone()), &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+    }
+
+    #[test]
+    fn snaplen_zero_refused() {
+        let mut extcap = Extcap::new("snapdump");
+        assert!(extcap.add_interface(IFace::new("if1").snaplen(0)).is_err());
+    }
+
+    /// Counts the write calls per packet, with or without vectored IO
+    struct SyscallWriter {
+        vectored: bool,
+        writes: Arc<AtomicUsize>,
+        out: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Write for SyscallWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            self.out.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+            if !self.vectored {
+                // the default of the writers without vectored IO, the first buffer only
+                let buf = bufs.iter().find(|b| !b.is_empty()).map_or(&[][..], |b| b);
+                return self.write(buf);
+            }
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            let mut out = self.out.lock().unwrap();
+            bufs.iter().for_each(|buf| out.extend_from_slice(buf));
+            Ok(bufs.iter().map(|buf| buf.len()).sum())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Writes the packets, returns the write calls after the header and the output
+    fn record_writes(vectored: bool) -> (usize, Vec<u8>) {
+        let writes = Arc::new(AtomicUsize::new(0));
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = SyscallWriter {
+            vectored,
+            writes: writes.clone(),
+            out: out.clone(),
+        };
+        let header = CaptureHeader::from_data_link(DataLink::ETHERNET);
+        let mut sink = PacketSink::from_writer(writer, header).unwrap();
+        let after_header = writes.load(Ordering::Relaxed);
+        for i in 0..100u8 {
+            sink.write(i.into(), 0, &[i; 60], 60).unwrap();
+        }
+        drop(sink);
+        let out = out.lock().unwrap().clone();
+        (writes.load(Ordering::Relaxed) - after_header, out)
+    }
+
+    #[test]
+    fn single_write_per_packet() {
+        let (writes, vectored_out) = record_writes(true);
+        assert_eq!(writes, 100);
+        // without vectored IO the header and the data are written one after the other
+        let (writes, out) = record_writes(false);
+        assert_eq!(writes, 200);
+        assert_eq!(out, vectored_out);
+        let mut reader = PcapReader::new(&out[..]).unwrap();
+        for i in 0..100u8 {
+            assert_eq!(*reader.next_packet().unwrap().unwrap().data, [i; 60]);
+        }
+        assert!(reader.next_packet().is_none());
+    }
+
+    #[test]
+    fn byte_order_round_trip() {
+        let cases = [
+            (
+                Endianness::Big,
+                TsResolution::MicroSecond,
+                [0xa1, 0xb2, 0xc3, 0xd4],
+            ),
+            (
+                Endianness::Little,
+                TsResolution::MicroSecond,
+                [0xd4, 0xc3, 0xb2, 0xa1],
+            ),
+            (
+                Endianness::Big,
+                TsResolution::NanoSecond,
+                [0xa1, 0xb2, 0x3c, 0x4d],
+            ),
+            (
+                Endianness::Little,
+                TsResolution::NanoSecond,
+                [0x4d, 0x3c, 0xb2, 0xa1],
+            ),
+        ];
+        for (endianness, ts_resolution, magic) in cases {
+            let file = TempPath::new("order.pcap");
+            let header = CaptureHeader::builder()
+                .endianness(endianness)
+                .nanos(ts_resolution == TsResolution::NanoSecond)
+                .build();
+            let mut sink = PacketSink::create(&file.0, header, SinkOptions::default()).unwrap();
+            sink.write(0x0102_0304, 5, &[1, 2, 3], 0x0a0b).unwrap();
+            drop(sink);
+
+            let bytes = std::fs::read(&file.0).unwrap();
+            assert_eq!(bytes[..4], magic, "{:?}", endianness);
+            // the record header follows the byte order of the file header
+            let ts_sec = &bytes[24..28];
+            match endianness {
+                Endianness::Big => assert_eq!(ts_sec, [1, 2, 3, 4]),
+                Endianness::Little => assert_eq!(ts_sec, [4, 3, 2, 1]),
+            }
+            let mut reader = PcapReader::new(&bytes[..]).unwrap();
+            assert_eq!(CaptureHeader::from_pcap_header(&reader.header()), header);
+            let pkt = reader.next_packet().unwrap().unwrap();
+            let nanos = 5 * (1_000_000_000 / ts_resolution.units_per_second());
+            assert_eq!(pkt.timestamp, Duration::new(0x0102_0304, nanos));
+            assert_eq!((&pkt.data[..], pkt.orig_len), (&[1, 2, 3][..], 0x0a0b));
+            assert!(reader.next_packet().is_none());
+        }
+    }
+
+    #[test]
+    fn timestamps_past_2106_refused() {
+        let file = TempPath::new("y2106.pcap");
+        let header = CaptureHeader::default();
+        let mut sink = PacketSink::create(&file.0, header, SinkOptions::default()).unwrap();
+        let last = u64::from(u32::MAX);
+        sink.write_ts64(last, 999_999, &[1], 1).unwrap();
+        assert!(sink.write_ts64(last + 1, 0, &[2], 1).is_err());
+        assert!(sink.write_ts64(u64::MAX, 0, &[3], 1).is_err());
+        let past = UNIX_EPOCH + Duration::from_secs(last + 1);
+        assert!(sink.write_at(past, &[4]).is_err());
+        sink.write_at(past - Duration::from_micros(1), &[5])
+            .unwrap();
+        drop(sink);
+
+        let mut reader = PcapReader::new(File::open(&file.0).unwrap()).unwrap();
+        let mut packets = Vec::new();
+        while let Some(pkt) = reader.next_packet() {
+            let pkt = pkt.unwrap();
+            packets.push((pkt.timestamp, pkt.data[0]));
+        }
+        let max = Duration::new(last, 999_999_000);
+        assert_eq!(packets, [(max, 1), (max, 5)]);
+    }
+
+    #[test]
+    fn pcap_seconds_boundaries() {
+        assert_eq!(pcap_seconds(0).unwrap(), 0);
+        assert_eq!(pcap_seconds(u32::MAX.into()).unwrap(), u32::MAX);
+        let e = pcap_seconds(u64::from(u32::MAX) + 1).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert!(e.to_string().contains("4294967296s"), "{}", e);
+        let header = CaptureHeader::default();
+        let since_epoch = Duration::new(u64::from(u32::MAX) + 1, 0);
+        assert!(header.timestamp(since_epoch).is_err());
+    }
+
+    /// Creates the inbound end of a named pipe allowing `instances` clients
+    #[cfg(windows)]
+    fn pipe_server(path: &str, instances: u32) -> File {
+        use std::iter;
+        use std::os::windows::ffi::OsStrExt;
+        use std::os::windows::io::{FromRawHandle, RawHandle};
+        use std::ptr;
+
+        use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+        use windows_sys::Win32::Storage::FileSystem::PIPE_ACCESS_INBOUND;
+        use windows_sys::Win32::System::Pipes::{CreateNamedPipeW, PIPE_TYPE_BYTE, PIPE_WAIT};
+
+        let wide: Vec<u16> = std::ffi::OsStr::new(path)
+            .encode_wide()
+            .chain(iter::once(0))
+            .collect();
+        // SAFETY: the name is a valid wide C string
+        let handle = unsafe {
+            CreateNamedPipeW(
+                wide.as_ptr(),
+                PIPE_ACCESS_INBOUND,
+                PIPE_TYPE_BYTE | PIPE_WAIT,
+                instances,
+                65536,
+                65536,
+                0,
+                ptr::null(),
+            )
+        };
+        assert_ne!(handle, INVALID_HANDLE_VALUE);
+        // SAFETY: the handle is valid and owned from now on
+        unsafe { File::from_raw_handle(handle as RawHandle) }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn named_pipe_fifo_opened() {
+        use std::io::Read;
+
+        let path = format!(r"\\.\pipe\extcap-capture-test-{}", std::process::id());
+        let mut server = pipe_server(&path, 1);
+        let mut extcap = Extcap::new("pipedump");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        let args = ["--capture", "--extcap-interface", "if1", "--fifo", &path];
+        // the records fit in the pipe buffer, read once the capture has closed it
+        let (res, _) = test_support::run(extcap, LongPackets, &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+        let mut buf = Vec::new();
+        server.read_to_end(&mut buf).unwrap();
+        let mut reader = PcapReader::new(&buf[..]).unwrap();
+        let mut lens = Vec::new();
+        while let Some(pkt) = reader.next_packet() {
+            lens.push(pkt.unwrap().data.len());
+        }
+        assert_eq!(lens, [4, 8, 20, 12]);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn busy_named_pipe_retried() {
+        let path = format!(r"\\.\pipe\extcap-busy-test-{}", std::process::id());
+        let _first_server = pipe_server(&path, 2);
+        let _first = open_file(Path::new(&path)).unwrap();
+        // the only instance is taken until the second one is created
+        let second_path = path.clone();
+        let creator = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            pipe_server(&second_path, 2)
+        });
+        let started = Instant::now();
+        let second = open_file(Path::new(&path));
+        let _second_server = creator.join().unwrap();
+        assert!(second.is_ok(), "{:?}", second.err());
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+}
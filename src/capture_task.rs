@@ -0,0 +1,151 @@
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::Duration;
+
+use futures::channel::oneshot;
+use futures::future::{self, AbortHandle, Abortable, BoxFuture, Either, FutureExt, Shared};
+use log::debug;
+
+use crate::ExtcapResult;
+
+/// Producer of the async capture, returned along with the packet receiver
+///
+/// The crate polls the task together with the packet loop and returns its result
+/// from `run_async`. The packets queued before the task finishes or is aborted
+/// are still written.
+pub struct CaptureTask {
+    future: Abortable<BoxFuture<'static, ExtcapResult<()>>>,
+    done: Option<oneshot::Sender<()>>,
+    handle: CaptureTaskHandle,
+}
+
+/// Handle of the `CaptureTask`, can be cloned and used from other tasks
+#[derive(Clone)]
+pub struct CaptureTaskHandle {
+    abort: AbortHandle,
+    finished: Arc<AtomicBool>,
+    done: Shared<oneshot::Receiver<()>>,
+}
+
+impl CaptureTask {
+    /// Creates the task from the producer future
+    pub fn new<F>(future: F) -> Self
+    where
+        F: Future<Output = ExtcapResult<()>> + Send + 'static,
+    {
+        let (abort, registration) = AbortHandle::new_pair();
+        let (done_tx, done_rx) = oneshot::channel();
+        Self {
+            future: Abortable::new(future.boxed(), registration),
+            done: Some(done_tx),
+            handle: CaptureTaskHandle {
+                abort,
+                finished: Arc::default(),
+                done: done_rx.shared(),
+            },
+        }
+    }
+
+    /// Gets the handle of the task
+    pub fn handle(&self) -> CaptureTaskHandle {
+        self.handle.clone()
+    }
+}
+
+impl Future for CaptureTask {
+    type Output = ExtcapResult<()>;
+
+    /// Completes with the result of the producer, `Ok` if it has been aborted
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = match self.future.poll_unpin(cx) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.handle.finished.store(true, Ordering::SeqCst);
+        if let Some(done) = self.done.take() {
+            let _ = done.send(());
+        }
+        Poll::Ready(res.unwrap_or_else(|_| {
+            debug!("capture task aborted");
+            Ok(())
+        }))
+    }
+}
+
+impl CaptureTaskHandle {
+    /// Aborts the task, the packets already queued are still written
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+
+    /// Returns `true` if `abort` has been called
+    pub fn is_aborted(&self) -> bool {
+        self.abort.is_aborted()
+    }
+
+    /// Returns `true` if the task has finished
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the task finishes, its result is returned from `run_async`
+    pub async fn finished(&self) {
+        let _ = self.done.clone().await;
+    }
+}
+
+/// Runs the capture loop along with the task, the task is aborted if the loop fails
+pub(crate) async fn capture_with_task<F>(capture: F, task: Option<CaptureTask>) -> ExtcapResult<()>
+where
+    F: Future<Output = ExtcapResult<()>>,
+{
+    let task = match task {
+        Some(task) => task,
+        None => return capture.await,
+    };
+    let handle = task.handle();
+    let capture = async {
+        let res = capture.await;
+        if res.is_err() {
+            handle.abort();
+        }
+        res
+    };
+    let (res, task_res) = future::join(capture, task).await;
+    res.and(task_res)
+}
+
+/// Runs the capture until it finishes or `timeout` passes after `stopped`, `None` then
+pub(crate) async fn with_shutdown_timeout<F, S>(
+    capture: F,
+    stopped: S,
+    timeout: Duration,
+) -> Option<ExtcapResult<()>>
+where
+    F: Future<Output = ExtcapResult<()>>,
+    S: Future<Output = ()>,
+{
+    let deadline = async {
+        stopped.await;
+        debug!("capture stop requested, waiting up to {:?}", timeout);
+        delay(timeout).await;
+    };
+    match future::select(pin!(capture), pin!(deadline)).await {
+        Either::Left((res, _)) => Some(res),
+        Either::Right(_) => None,
+    }
+}
+
+/// Runtime independent delay, a thread is used as the timer
+fn delay(timeout: Duration) -> impl Future<Output = ()> {
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        let _ = tx.send(());
+    });
+    rx.map(|_| ())
+}
@@ -0,0 +1,544 @@
+#[cfg(not(feature = "clap"))]
+use std::collections::HashMap;
+use std::ffi::OsString;
+
+#[cfg(feature = "clap")]
+use clap::{builder::ValueParser, error::ErrorKind, Arg, ArgAction, ArgGroup, Command};
+
+use crate::opts::{
+    OPT_CAPTURE, OPT_EXTCAP_CONFIG, OPT_EXTCAP_DTLS, OPT_EXTCAP_INTERFACE, OPT_EXTCAP_INTERFACES,
+};
+#[cfg(not(feature = "clap"))]
+use crate::ExtcapError;
+use crate::{ExtcapMatches, ExtcapResult};
+
+/// Interface actions, at most one is allowed and each requires the interface
+const IF_ACTION: [&str; 3] = [OPT_EXTCAP_DTLS, OPT_EXTCAP_CONFIG, OPT_CAPTURE];
+
+/// Command line option specification, independent of the parser
+#[derive(Debug, Clone)]
+pub(crate) struct OptSpec {
+    name: String,
+    help: Option<String>,
+    value_name: Option<&'static str>,
+    takes_value: bool,
+    allow_negative: bool,
+    path: bool,
+    requires: Vec<&'static str>,
+    conflicts_with: Vec<&'static str>,
+    multiple: bool,
+}
+
+impl OptSpec {
+    /// Creates an option without a value
+    pub(crate) fn flag(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            help: None,
+            value_name: None,
+            takes_value: false,
+            allow_negative: false,
+            path: false,
+            requires: Vec::new(),
+            conflicts_with: Vec::new(),
+            multiple: false,
+        }
+    }
+
+    /// Creates an option taking a value
+    pub(crate) fn value(name: &str) -> Self {
+        Self {
+            takes_value: true,
+            ..Self::flag(name)
+        }
+    }
+
+    pub(crate) fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_owned());
+        self
+    }
+
+    pub(crate) fn value_name(mut self, value_name: &'static str) -> Self {
+        self.value_name = Some(value_name);
+        self
+    }
+
+    pub(crate) fn allow_negative_numbers(mut self) -> Self {
+        self.allow_negative = true;
+        self
+    }
+
+    /// The value is a path, kept as passed even if not valid UTF-8
+    pub(crate) fn path(mut self) -> Self {
+        self.path = true;
+        self
+    }
+
+    pub(crate) fn requires(mut self, name: &'static str) -> Self {
+        self.requires.push(name);
+        self
+    }
+
+    pub(crate) fn conflicts_with(mut self, name: &'static str) -> Self {
+        self.conflicts_with.push(name);
+        self
+    }
+
+    /// The option may be passed multiple times, all the values are kept in order
+    pub(crate) fn multiple(mut self) -> Self {
+        self.multiple = true;
+        self
+    }
+}
+
+/// Checks whether the interfaces are listed, their arguments aren't parsed then
+///
+/// Checked before the parsing, the help lists all the options.
+pub(crate) fn lists_interfaces(args: &[OsString]) -> bool {
+    let listing = args.iter().skip(1).any(|arg| {
+        arg.to_str()
+            .and_then(|arg| arg.strip_prefix("--"))
+            .is_some_and(|name| name == OPT_EXTCAP_INTERFACES)
+    });
+    let help = args
+        .iter()
+        .skip(1)
+        .any(|arg| arg == "-h" || arg == "--help");
+    listing && !help
+}
+
+/// Command line specification, independent of the parser
+#[derive(Debug, Default)]
+pub(crate) struct CliSpec {
+    pub(crate) name: String,
+    pub(crate) version: Option<String>,
+    pub(crate) author: Option<String>,
+    pub(crate) about: Option<String>,
+    pub(crate) usage: Option<String>,
+    pub(crate) after_help: Option<String>,
+    pub(crate) opts: Vec<OptSpec>,
+}
+
+/// Outcome of the command line parsing
+pub(crate) enum Parsed {
+    /// Options to process
+    Matches(ExtcapMatches),
+    /// Help or version text to print, nothing else to do
+    Display(String),
+}
+
+impl CliSpec {
+    pub(crate) fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn opt(mut self, opt: OptSpec) -> Self {
+        self.opts.push(opt);
+        self
+    }
+
+    #[cfg(feature = "clap")]
+    pub(crate) fn parse<I: IntoIterator<Item = OsString>>(self, args: I) -> ExtcapResult<Parsed> {
+        let args: Vec<OsString> = args.into_iter().collect();
+        let mut cmd = Command::new(self.name);
+        if let Some(version) = self.version {
+            cmd = cmd.version(version);
+        }
+        if let Some(author) = self.author {
+            cmd = cmd.author(author);
+        }
+        if let Some(about) = self.about {
+            cmd = cmd.about(about);
+        }
+        if let Some(usage) = self.usage {
+            cmd = cmd.override_usage(usage);
+        }
+        if let Some(after_help) = self.after_help {
+            cmd = cmd.after_help(after_help);
+        }
+        let names = self
+            .opts
+            .iter()
+            .map(|opt| (opt.name.clone(), opt.takes_value))
+            .collect::<Vec<_>>();
+        for opt in self.opts {
+            let mut arg = Arg::new(opt.name.clone()).long(opt.name);
+            if let Some(help) = opt.help {
+                arg = arg.help(help);
+            }
+            if let Some(value_name) = opt.value_name {
+                arg = arg.value_name(value_name);
+            }
+            arg = if opt.takes_value {
+                let action = if opt.multiple {
+                    ArgAction::Append
+                } else {
+                    ArgAction::Set
+                };
+                let arg = arg
+                    .action(action)
+                    .allow_negative_numbers(opt.allow_negative);
+                if opt.path {
+                    arg.value_parser(ValueParser::os_string())
+                } else {
+                    arg
+                }
+            } else if opt.multiple {
+                arg.action(ArgAction::Count)
+            } else {
+                arg.action(ArgAction::SetTrue)
+            };
+            for name in opt.requires {
+                arg = arg.requires(name);
+            }
+            for name in opt.conflicts_with {
+                arg = arg.conflicts_with(name);
+            }
+            cmd = cmd.arg(arg);
+        }
+        cmd = cmd.group(
+            ArgGroup::new("if_action")
+                .args(IF_ACTION)
+                .multiple(false)
+                .requires(OPT_EXTCAP_INTERFACE),
+        );
+        match cmd.try_get_matches_from(args) {
+            Ok(matches) => Ok(Parsed::Matches(ExtcapMatches::from_clap(matches, &names))),
+            Err(e) => match e.kind() {
+                ErrorKind::DisplayHelp | ErrorKind::DisplayVersion => {
+                    Ok(Parsed::Display(e.to_string()))
+                }
+                _ => Err(e.into()),
+            },
+        }
+    }
+
+    /// Minimal parser understanding `--name value`, `--name=value` and bare flags
+    #[cfg(not(feature = "clap"))]
+    pub(crate) fn parse<I: IntoIterator<Item = OsString>>(self, args: I) -> ExtcapResult<Parsed> {
+        let args: Vec<OsString> = args.into_iter().collect();
+        let mut values: HashMap<String, Vec<OsString>> = HashMap::new();
+        let mut args = args.into_iter().skip(1);
+        while let Some(arg) = args.next() {
+            // option names must be UTF-8, separate values are kept as passed
+            let arg = arg.into_string().map_err(|arg| {
+                ExtcapError::invalid_args(format!("invalid UTF-8 in argument {:?}", arg))
+            })?;
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(Parsed::Display(self.help_text())),
+                "-V" | "--version" if self.version.is_some() => {
+                    return Ok(Parsed::Display(format!(
+                        "{} {}\n",
+                        self.name,
+                        self.version.as_deref().unwrap_or_default()
+                    )))
+                }
+                _ => {}
+            }
+            let body = arg.strip_prefix("--").ok_or_else(|| {
+                ExtcapError::invalid_args(format!("unexpected argument '{}'", arg))
+            })?;
+            let (name, inline) = match body.split_once('=') {
+                Some((name, value)) => (name, Some(OsString::from(value))),
+                None => (body, None),
+            };
+            let opt = self
+                .opts
+                .iter()
+                .find(|opt| opt.name == name)
+                .ok_or_else(|| {
+                    ExtcapError::invalid_args(format!("unexpected argument '--{}'", name))
+                })?;
+            if values.contains_key(name) && !opt.multiple {
+                return Err(ExtcapError::invalid_args(format!(
+                    "the argument '--{}' cannot be used multiple times",
+                    name
+                )));
+            }
+            let vals = match (opt.takes_value, inline) {
+                (true, Some(value)) => vec![value],
+                (true, None) => {
+                    let value = args.next().filter(|v| {
+                        let v = v.to_string_lossy();
+                        !v.starts_with('-') || (opt.allow_negative && v.parse::<f64>().is_ok())
+                    });
+                    match value {
+                        Some(value) => vec![value],
+                        None => {
+                            return Err(ExtcapError::invalid_args(format!(
+                                "a value is required for '--{}'",
+                                name
+                            )))
+                        }
+                    }
+                }
+                (false, Some(_)) => {
+                    return Err(ExtcapError::invalid_args(format!(
+                        "unexpected value for '--{}'",
+                        name
+                    )))
+                }
+                (false, None) => Vec::new(),
+            };
+            values.entry(name.to_owned()).or_default().extend(vals);
+        }
+        self.check_relations(&values)?;
+        Ok(Parsed::Matches(ExtcapMatches::from_values(values)))
+    }
+
+    #[cfg(not(feature = "clap"))]
+    fn check_relations(&self, values: &HashMap<String, Vec<OsString>>) -> ExtcapResult<()> {
+        for opt in self
+            .opts
+            .iter()
+            .filter(|opt| values.contains_key(&opt.name))
+        {
+            if let Some(name) = opt.requires.iter().find(|n| !values.contains_key(**n)) {
+                return Err(ExtcapError::invalid_args(format!(
+                    "the argument '--{}' requires '--{}'",
+                    opt.name, name
+                )));
+            }
+            if let Some(name) = opt.conflicts_with.iter().find(|n| values.contains_key(**n)) {
+                return Err(ExtcapError::invalid_args(format!(
+                    "the argument '--{}' cannot be used with '--{}'",
+                    opt.name, name
+                )));
+            }
+        }
+        let actions = IF_ACTION
+            .iter()
+            .filter(|n| values.contains_key(**n))
+            .collect::<Vec<_>>();
+        if actions.len() > 1 {
+            return Err(ExtcapError::invalid_args(format!(
+                "the argument '--{}' cannot be used with '--{}'",
+                actions[0], actions[1]
+            )));
+        }
+        if !actions.is_empty() && !values.contains_key(OPT_EXTCAP_INTERFACE) {
+            return Err(ExtcapError::invalid_args(format!(
+                "the argument '--{}' requires '--{}'",
+                actions[0], OPT_EXTCAP_INTERFACE
+            )));
+        }
+        Ok(())
+    }
+
+    /// Generates a simple usage listing
+    #[cfg(not(feature = "clap"))]
+    fn help_text(&self) -> String {
+        let mut out = String::new();
+        if let Some(about) = &self.about {
+            out.push_str(&format!("{}\n\n", about));
+        }
+        match &self.usage {
+            Some(usage) => out.push_str(&format!("Usage: {}\n\nOptions:\n", usage)),
+            None => out.push_str(&format!("Usage: {} [OPTIONS]\n\nOptions:\n", self.name)),
+        }
+        for opt in &self.opts {
+            let call = match (opt.takes_value, opt.value_name) {
+                (true, Some(value_name)) => format!("--{} <{}>", opt.name, value_name),
+                (true, None) => format!("--{} <{}>", opt.name, opt.name),
+                (false, _) => format!("--{}", opt.name),
+            };
+            out.push_str(&format!(
+                "  {:<32}  {}\n",
+                call,
+                opt.help.as_deref().unwrap_or_default()
+            ));
+        }
+        if let Some(after_help) = &self.after_help {
+            out.push_str(&format!("\n{}\n", after_help));
+        }
+        out
+    }
+}
+
+/// Shared behavior of both parsers, run by the clap and the `--no-default-features` builds
+#[cfg(test)]
+mod tests {
+    use std::process::ExitCode;
+    use std::sync::{Arc, Mutex};
+
+    use crate::test_support::{self, TempPath};
+    use crate::{Extcap, ExtcapListener, ExtcapResult, IFace, IfArg, PacketSink};
+
+    const NAMES: [&str; 5] = ["port", "offset", "verbose", "channels", "filter"];
+
+    /// Value and all the values of an interface argument seen by the capture
+    type Seen = (&'static str, Option<String>, Vec<String>);
+
+    /// Records the values of the interface arguments seen by the capture
+    #[derive(Default)]
+    struct Recorder(Arc<Mutex<Vec<Seen>>>);
+
+    impl ExtcapListener for Recorder {
+        fn capture2(&mut self, extcap: &Extcap, _: &IFace, _: PacketSink) -> ExtcapResult<()> {
+            let matches = extcap.matches();
+            let mut seen = self.0.lock().unwrap();
+            for name in NAMES {
+                let value = matches.value(name).map(String::from);
+                let values = matches.values(name).into_iter().map(String::from);
+                seen.push((name, value, values.collect()));
+            }
+            Ok(())
+        }
+    }
+
+    fn extcap() -> Extcap<'static> {
+        let mut extcap = Extcap::new("clidump");
+        let mut ifc = IFace::new("if1");
+        ifc.add_arg(IfArg::new_unsigned("port").display("Port"));
+        ifc.add_arg(IfArg::new_integer("offset").display("Offset"));
+        ifc.add_arg(IfArg::new_boolflag("verbose").display("Verbose"));
+        ifc.add_arg(IfArg::new_multicheck("channels").display("Channels"));
+        ifc.add_arg(IfArg::new_string("filter").display("Filter"));
+        extcap.add_interface(ifc).unwrap();
+        extcap
+    }
+
+    /// Runs a capture with the interface arguments, returns what the listener has seen
+    fn capture_values(args: &[&str]) -> Vec<Seen> {
+        let fifo = TempPath::new("cli.pcap");
+        let mut argv = vec![
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        argv.extend_from_slice(args);
+        let recorder = Recorder::default();
+        let seen = recorder.0.clone();
+        let (res, _) = test_support::run(extcap(), recorder, &argv);
+        assert!(res.is_ok(), "{:?}: {:?}", args, res.err());
+        let seen = seen.lock().unwrap();
+        seen.clone()
+    }
+
+    /// Arguments, name of the interface argument, its value and all its values
+    type ValueCase = (
+        &'static [&'static str],
+        &'static str,
+        Option<&'static str>,
+        &'static [&'static str],
+    );
+
+    #[test]
+    fn interface_argument_values() {
+        let cases: &[ValueCase] = &[
+            (&["--port", "5555"], "port", Some("5555"), &["5555"]),
+            (&["--port=5555"], "port", Some("5555"), &["5555"]),
+            (&["--filter=a=b"], "filter", Some("a=b"), &["a=b"]),
+            (&["--offset", "-5"], "offset", Some("-5"), &["-5"]),
+            (&["--offset=-5"], "offset", Some("-5"), &["-5"]),
+            (&["--verbose"], "verbose", None, &[]),
+            (&[], "port", None, &[]),
+            // repeated by Wireshark, the last value wins
+            (
+                &["--port", "5555", "--port", "6000"],
+                "port",
+                Some("6000"),
+                &["5555", "6000"],
+            ),
+            (
+                &["--channels", "a", "--channels=b"],
+                "channels",
+                Some("b"),
+                &["a", "b"],
+            ),
+        ];
+        for (args, name, value, values) in cases {
+            let seen = capture_values(args);
+            let (_, seen_value, seen_values) = seen.iter().find(|(n, _, _)| n == name).unwrap();
+            assert_eq!(seen_value.as_deref(), *value, "{:?}", args);
+            assert_eq!(seen_values, values, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn exit_codes() {
+        let cases: &[(&[&str], ExitCode)] = &[
+            (&["--extcap-interfaces"], ExitCode::SUCCESS),
+            (
+                &["--extcap-version=4.2.0", "--extcap-interfaces"],
+                ExitCode::SUCCESS,
+            ),
+            (&["--extcap-interface"], ExitCode::FAILURE),
+            (&["--unknown"], ExitCode::FAILURE),
+            (&["plain"], ExitCode::FAILURE),
+            (&["--extcap-config"], ExitCode::FAILURE),
+            (&["--fifo", "out.pcap"], ExitCode::FAILURE),
+            (&["--verbose=yes"], ExitCode::FAILURE),
+            (
+                &["--extcap-config", "--capture", "--extcap-interface", "if1"],
+                ExitCode::FAILURE,
+            ),
+            (
+                &[
+                    "--extcap-dlts",
+                    "--extcap-dlts",
+                    "--extcap-interface",
+                    "if1",
+                ],
+                ExitCode::FAILURE,
+            ),
+        ];
+        for (args, expected) in cases {
+            let (code, out) = test_support::run_to_exit_code(extcap(), Recorder::default(), args);
+            assert_eq!(code, *expected, "{:?}", args);
+            if *expected == ExitCode::FAILURE {
+                assert!(out.is_empty(), "{:?}: {}", args, out);
+            }
+        }
+    }
+
+    #[test]
+    fn help_lists_all_options() {
+        for args in [&["--help"][..], &["--extcap-interfaces", "-h"]] {
+            let (code, out) = test_support::run_to_exit_code(extcap(), Recorder::default(), args);
+            assert_eq!(code, ExitCode::SUCCESS);
+            for name in [
+                "--extcap-interfaces",
+                "--capture",
+                "--fifo",
+                "--port",
+                "--channels",
+            ] {
+                assert!(out.contains(name), "{}: {}", name, out);
+            }
+        }
+    }
+
+    /// The output must not depend on the parser
+    #[test]
+    fn extcap_output() {
+        let cases: &[(&[&str], &str)] = &[
+            (
+                &["--extcap-interfaces"],
+                "extcap {version=unknown}\ninterface {value=if1}\n",
+            ),
+            (
+                &["--extcap-config", "--extcap-interface", "if1"],
+                "arg {number=0}{call=--port}{display=Port}{type=unsigned}\n\
+                 arg {number=1}{call=--offset}{display=Offset}{type=integer}\n\
+                 arg {number=2}{call=--verbose}{display=Verbose}{type=boolflag}\n\
+                 arg {number=3}{call=--channels}{display=Channels}{type=multicheck}\n\
+                 arg {number=4}{call=--filter}{display=Filter}{type=string}\n",
+            ),
+        ];
+        for (args, expected) in cases {
+            let (res, out) = test_support::run(extcap(), Recorder::default(), args);
+            assert!(res.is_ok());
+            assert_eq!(out, *expected, "{:?}", args);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source of the timestamps used by the crate
+///
+/// Defaults to the system clock, tests can freeze or step the time to produce
+/// deterministic captures.
+#[derive(Debug, Clone, Default)]
+pub enum Clock {
+    /// System clock
+    #[default]
+    System,
+    /// Frozen at the given time
+    Fixed(SystemTime),
+    /// Nanoseconds since the UNIX epoch, stepped by the test
+    Manual(Arc<AtomicU64>),
+}
+
+impl Clock {
+    /// Returns the current time
+    pub fn now(&self) -> SystemTime {
+        match self {
+            Clock::System => SystemTime::now(),
+            Clock::Fixed(time) => *time,
+            Clock::Manual(nanos) => UNIX_EPOCH + Duration::from_nanos(nanos.load(Ordering::SeqCst)),
+        }
+    }
+
+    /// Returns the current time as a duration since the UNIX epoch, zero before the epoch
+    pub fn since_epoch(&self) -> Duration {
+        self.now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+
+    /// Returns the time passed since `earlier`, zero if the clock went backwards
+    pub fn elapsed(&self, earlier: SystemTime) -> Duration {
+        self.now().duration_since(earlier).unwrap_or_default()
+    }
+}
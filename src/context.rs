@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use pcap_file::DataLink;
+
+use crate::capture::SharedStats;
+#[cfg(feature = "ctrl-pipe")]
+use crate::control_pipe::PeerVersion;
+use crate::stop::StopToken;
+use crate::{CaptureStats, Clock, PauseState};
+
+/// Capture context
+///
+/// Information about the running capture gathered from the command line options
+/// and the selected interface. Available via `Extcap::capture_context()` in the capture step.
+#[derive(Debug)]
+pub struct CaptureContext {
+    pub(crate) interface: String,
+    pub(crate) description: Option<String>,
+    pub(crate) dlt: DataLink,
+    pub(crate) fifo: PathBuf,
+    pub(crate) capture_filter: Option<String>,
+    pub(crate) ctrl_pipe: bool,
+    pub(crate) ws_version: Option<String>,
+    #[cfg(feature = "ctrl-pipe")]
+    pub(crate) ctrl_peer_version: PeerVersion,
+    pub(crate) stop: StopToken,
+    pub(crate) pause: PauseState,
+    pub(crate) clock: Clock,
+    pub(crate) stats: SharedStats,
+}
+
+impl CaptureContext {
+    /// Gets interface name
+    pub fn get_interface(&self) -> &str {
+        &self.interface
+    }
+
+    /// Gets interface description
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Gets the DLT of the interface
+    pub fn get_dlt(&self) -> u32 {
+        self.dlt.into()
+    }
+
+    /// Gets the DLT of the interface as `DataLink`
+    pub fn get_data_link(&self) -> DataLink {
+        self.dlt
+    }
+
+    /// Gets the fifo path, `-` stands for stdout
+    pub fn get_fifo(&self) -> &Path {
+        &self.fifo
+    }
+
+    /// Checks whether the capture is written to stdout
+    pub fn is_stdout(&self) -> bool {
+        self.fifo == Path::new("-")
+    }
+
+    /// Gets the capture filter
+    pub fn get_capture_filter(&self) -> Option<&str> {
+        self.capture_filter.as_deref()
+    }
+
+    /// Checks whether control pipes have been passed by Wireshark
+    pub fn has_ctrl_pipe(&self) -> bool {
+        self.ctrl_pipe
+    }
+
+    /// Gets Wireshark version passed by `--extcap-version`
+    pub fn get_ws_version(&self) -> Option<&str> {
+        self.ws_version.as_deref()
+    }
+
+    /// Gets Wireshark version received within the control pipe `Initialized` message
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn get_ctrl_peer_version(&self) -> Option<String> {
+        self.ctrl_peer_version.lock().ok().and_then(|pv| pv.clone())
+    }
+
+    /// Gets the clock configured by `Extcap::clock`, use it to timestamp the packets
+    pub fn get_clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Gets the capture stop token
+    pub fn get_stop_token(&self) -> &StopToken {
+        &self.stop
+    }
+
+    /// Gets the capture pause state, see `Extcap::pause_control`
+    pub fn get_pause_state(&self) -> &PauseState {
+        &self.pause
+    }
+
+    /// Gets the statistics of the packets written to the fifo so far
+    ///
+    /// Counted by `PacketSink`, the packets written by the deprecated `capture` or
+    /// to the pcapng output are not. A packet is counted once the write succeeds,
+    /// so the numbers hold also when the capture ends by an error or a closed fifo.
+    pub fn get_capture_stats(&self) -> CaptureStats {
+        *self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -1,4 +1,7 @@
-use crate::print_opt_value;
+use crate::arg::normalize_bool;
+use crate::self_check::{check_text, Issue};
+use crate::text::{TextKind, Texts};
+use crate::{write_opt_value, ExtcapError, ExtcapResult, IFace};
 
 /// Button roles
 pub enum ButtonRole {
@@ -7,6 +10,9 @@ pub enum ButtonRole {
     /// INTERFACE_ROLE_LOGGER
     Logger,
     /// INTERFACE_ROLE_HELP
+    ///
+    /// Wireshark opens the help URI set by `Extcap::help()`, the button does nothing
+    /// without it. `Extcap::self_check()` reports the missing URI as an error.
     Help,
     /// INTERFACE_ROLE_RESTORE
     Restore,
@@ -24,8 +30,10 @@ impl ButtonRole {
 }
 
 /// Interface toolbar Control types
+#[derive(Default)]
 pub enum ControlType {
     /// None or unknown
+    #[default]
     None,
     /// INTERFACE_TYPE_BOOLEAN
     Boolean,
@@ -37,12 +45,6 @@ pub enum ControlType {
     String,
 }
 
-impl Default for ControlType {
-    fn default() -> Self {
-        ControlType::None
-    }
-}
-
 impl ControlType {
     fn type_str(&self) -> &'static str {
         match self {
@@ -67,6 +69,9 @@ pub struct Control {
     tooltip: Option<String>,
     placeholder: Option<String>,
     vals: Vec<ControlVal>,
+    interfaces: Option<Vec<String>>, // None for all the interfaces
+    #[cfg(feature = "ctrl-pipe")]
+    no_persist: bool,
 }
 
 impl Control {
@@ -112,8 +117,15 @@ impl Control {
     }
 
     /// Sets the default value
+    ///
+    /// For `Boolean` controls boolean-ish values (`1`, `False`, ...) are normalized
+    /// to `true` or `false`, anything else is rejected when the control is added.
     pub fn default<T: ToString>(mut self, default: &T) -> Self {
-        self.default = Some(default.to_string());
+        let default = default.to_string();
+        self.default = match self.ctype {
+            ControlType::Boolean => normalize_bool(&default).map(String::from).or(Some(default)),
+            _ => Some(default),
+        };
         self
     }
 
@@ -146,24 +158,157 @@ impl Control {
         self.vals.push(val);
     }
 
-    pub(crate) fn print_control(&self) {
-        print!(
+    /// Excludes the control from `Extcap::persist_control_values`, e.g. a password string
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn persist(mut self, persist: bool) -> Self {
+        self.no_persist = !persist;
+        self
+    }
+
+    /// Returns `true` if the control value is kept between the captures when enabled
+    #[cfg(feature = "ctrl-pipe")]
+    pub(crate) fn is_persistent(&self) -> bool {
+        !self.no_persist
+            && matches!(
+                self.ctype,
+                ControlType::Boolean | ControlType::Selector | ControlType::String
+            )
+    }
+
+    /// Gets the control type
+    pub fn get_type(&self) -> &ControlType {
+        &self.ctype
+    }
+
+    /// Gets the default value
+    pub fn get_default(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    /// Gets the selector values
+    pub fn get_vals(&self) -> &[ControlVal] {
+        &self.vals
+    }
+
+    /// Gets the control number, assigned when the control is added to `Extcap`
+    pub fn get_number(&self) -> usize {
+        self.number
+    }
+
+    /// Gets the interfaces the control is relevant to, `None` for all of them
+    pub fn get_interfaces(&self) -> Option<&[String]> {
+        self.interfaces.as_deref()
+    }
+
+    pub(crate) fn set_interfaces(&mut self, interfaces: &[&str]) {
+        self.interfaces = Some(interfaces.iter().map(|i| i.to_string()).collect());
+    }
+
+    /// Returns `true` if the control is relevant to the interface
+    pub(crate) fn applies_to(&self, ifc: &IFace) -> bool {
+        self.interfaces
+            .as_ref()
+            .is_none_or(|names| names.iter().any(|name| ifc.has_name(name)))
+    }
+
+    pub(crate) fn validate(&self) -> ExtcapResult<()> {
+        if let (ControlType::Boolean, Some(default)) = (&self.ctype, &self.default) {
+            if normalize_bool(default) != Some(default.as_str()) {
+                return Err(ExtcapError::invalid_config(format!(
+                    "Control '{}': invalid boolean default '{}', expected true or false",
+                    self.display.as_deref().unwrap_or_default(),
+                    default
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects the issues, including the ones rejected by `validate`
+    pub(crate) fn self_check(&self, issues: &mut Vec<Issue>) {
+        let what = format!("Control {}", self.number);
+        if let Err(e) = self.validate() {
+            issues.push(Issue::error(e.get_message()));
+        }
+        if self.display.is_none() {
+            issues.push(Issue::warning(format!("{}: no display string", what)));
+        }
+        check_text(
+            issues,
+            &format!("{} display", what),
+            self.display.as_deref(),
+        );
+        check_text(
+            issues,
+            &format!("{} tooltip", what),
+            self.tooltip.as_deref(),
+        );
+        check_text(
+            issues,
+            &format!("{} placeholder", what),
+            self.placeholder.as_deref(),
+        );
+        match self.ctype {
+            ControlType::None => {
+                issues.push(Issue::error(format!("{}: no control type", what)));
+            }
+            ControlType::Selector if self.vals.is_empty() => {
+                issues.push(Issue::warning(format!("{}: selector without values", what)));
+            }
+            ControlType::Boolean | ControlType::Button(_) => {
+                let unused = [
+                    ("range", &self.range),
+                    ("validation", &self.validation),
+                    ("placeholder", &self.placeholder),
+                ];
+                for (attr, _) in unused.iter().filter(|(_, v)| v.is_some()) {
+                    issues.push(Issue::warning(format!(
+                        "{}: {} is ignored for the {} type",
+                        what,
+                        attr,
+                        self.ctype.type_str()
+                    )));
+                }
+            }
+            _ => {}
+        }
+        if !self.vals.is_empty() && !matches!(self.ctype, ControlType::Selector) {
+            issues.push(Issue::warning(format!(
+                "{}: values are ignored for the {} type",
+                what,
+                self.ctype.type_str()
+            )));
+        }
+        for val in &self.vals {
+            let what = format!("{} value '{}'", what, val.value);
+            check_text(issues, &what, Some(&val.value));
+            check_text(issues, &format!("{} display", what), val.display.as_deref());
+        }
+    }
+
+    pub(crate) fn write_control(&self, out: &mut String, texts: &Texts) {
+        out.push_str(&format!(
             "control {{number={}}}{{type={}}}",
             self.number,
             self.ctype.type_str()
-        );
+        ));
         if let ControlType::Button(role) = &self.ctype {
-            print!("{{role={}}}", role.role_str());
+            out.push_str(&format!("{{role={}}}", role.role_str()));
         };
-        print_opt_value("display", &self.display);
-        print_opt_value("default", &self.default);
-        print_opt_value("range", &self.range);
-        print_opt_value("validation", &self.validation);
-        print_opt_value("tooltip", &self.tooltip);
-        print_opt_value("placeholder", &self.placeholder);
-        println!();
+        texts.write_opt(out, "display", TextKind::Display, self.display.as_deref());
+        write_opt_value(out, "default", &self.default);
+        write_opt_value(out, "range", &self.range);
+        write_opt_value(out, "validation", &self.validation);
+        texts.write_opt(out, "tooltip", TextKind::Tooltip, self.tooltip.as_deref());
+        texts.write_opt(
+            out,
+            "placeholder",
+            TextKind::Placeholder,
+            self.placeholder.as_deref(),
+        );
+        out.push('\n');
 
-        self.vals.iter().for_each(ControlVal::print_value);
+        self.vals.iter().for_each(|val| val.write_value(out, texts));
     }
 }
 
@@ -186,6 +331,16 @@ impl ControlVal {
         }
     }
 
+    /// Gets the value
+    pub fn get_value(&self) -> &str {
+        &self.value
+    }
+
+    /// Gets the default flag
+    pub fn get_default(&self) -> Option<bool> {
+        self.default
+    }
+
     fn set_control(&mut self, control: usize) {
         self.control = control;
     }
@@ -202,14 +357,17 @@ impl ControlVal {
         self
     }
 
-    fn print_value(&self) {
-        print!(
+    fn write_value(&self, out: &mut String, texts: &Texts) {
+        out.push_str(&format!(
             "value {{control={}}}{{value={}}}{{display={}}}",
             self.control,
             self.value,
-            self.display.as_ref().unwrap_or(&self.value)
-        );
-        print_opt_value("default", &self.default);
-        println!();
+            texts.resolve(
+                TextKind::Display,
+                self.display.as_ref().unwrap_or(&self.value)
+            )
+        ));
+        write_opt_value(out, "default", &self.default);
+        out.push('\n');
     }
 }
@@ -1,9 +1,20 @@
 use std::fs::File;
 use std::future::Future;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
 
-use futures::channel::mpsc::{Receiver, Sender};
+use futures::channel::mpsc::{self, Receiver, Sender};
+use futures::lock::Mutex as AsyncMutex;
+use log::debug;
 
 use crate::control_pipe_runtime::ControlPipeRuntime;
+use crate::control_state::ControlStore;
+use crate::metrics::{send_counted, SharedMetrics};
+use crate::selector_state::{RemovePlan, SelectorStates};
+use crate::{Control, ExtcapError, ExtcapResult, PauseState, StopToken};
+
+/// Maximum data length of a control message (24-bit length minus the header)
+pub const CTRL_MSG_DATA_MAX: usize = 0xFF_FFFF - 2;
 
 /// Interface toolbar Control commands
 #[derive(Debug)]
@@ -100,27 +111,345 @@ impl ControlMsg {
     pub fn get_data(&self) -> &[u8] {
         &self.data
     }
+
+    /// Get the Wireshark version carried by an `Initialized` message
+    ///
+    /// Newer Wireshark versions send their version string as the payload of the
+    /// `Initialized` message on control number 0. Returns `None` for any other
+    /// message or if the payload is empty or not a readable string.
+    pub fn get_initialized_version(&self) -> Option<String> {
+        if !matches!(self.command, ControlCmd::Initialized) || self.ctrl_num != 0 {
+            return None;
+        }
+        let payload = std::str::from_utf8(&self.data).ok()?;
+        let version = payload.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        if version.is_empty() {
+            None
+        } else {
+            Some(version.to_owned())
+        }
+    }
 }
 
-/// Control pipes
-pub type CtrlPipes = (Receiver<ControlMsg>, Sender<ControlMsg>);
+/// Peer (Wireshark) version shared between the control pipe and `Extcap`
+pub(crate) type PeerVersion = Arc<Mutex<Option<String>>>;
+
+/// Sending half of the control pipes
+///
+/// Messages are validated before they are queued so errors are reported to the caller.
+/// Raw `send` checks the data length only, other helpers check the control number
+/// against the registered controls too.
+#[derive(Clone, Debug)]
+pub struct ControlSender {
+    sender: Option<Sender<ControlMsg>>, // None drops the messages
+    controls: usize,
+    ignored: Vec<u8>,
+    batch: Arc<AsyncMutex<()>>, // shared by the clones, keeps a batch together
+    metrics: SharedMetrics,
+    selectors: SelectorStates,
+}
+
+impl ControlSender {
+    pub(crate) fn new(sender: Sender<ControlMsg>, controls: usize) -> Self {
+        Self {
+            sender: Some(sender),
+            controls,
+            ignored: Vec::new(),
+            batch: Arc::default(),
+            metrics: None,
+            selectors: SelectorStates::default(),
+        }
+    }
+
+    /// Sends a raw control message, the control number is not checked
+    pub async fn send(&mut self, msg: ControlMsg) -> ExtcapResult<()> {
+        if msg.get_data().len() > CTRL_MSG_DATA_MAX {
+            return Err(ExtcapError::control_msg_too_long(msg.get_data().len()));
+        }
+        match self.sender.as_mut() {
+            Some(sender) => {
+                let _batch = self.batch.lock().await;
+                let counters = self.metrics.as_deref().map(|m| &m.control_out);
+                self.selectors.record(&msg);
+                send_counted(sender, msg, counters)
+                    .await
+                    .map_err(|_| ExtcapError::control_pipe_closed())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Sends raw control messages as a batch, the control numbers are not checked
+    ///
+    /// The batch is not interleaved with the messages sent by the clones of the sender,
+    /// e.g. a selector rebuild stays together, and it is written to the pipe at once:
+    /// 20 messages take a single pipe write instead of 20. All the messages are checked
+    /// before sending, if the pipe closes meanwhile the error tells how many have been sent.
+    pub async fn send_all<I>(&mut self, msgs: I) -> ExtcapResult<()>
+    where
+        I: IntoIterator<Item = ControlMsg>,
+    {
+        let msgs: Vec<ControlMsg> = msgs.into_iter().collect();
+        if let Some(msg) = msgs
+            .iter()
+            .find(|msg| msg.get_data().len() > CTRL_MSG_DATA_MAX)
+        {
+            return Err(ExtcapError::control_msg_too_long(msg.get_data().len()));
+        }
+        let sender = match self.sender.as_mut() {
+            Some(sender) => sender,
+            None => return Ok(()),
+        };
+        let _batch = self.batch.lock().await;
+        let counters = self.metrics.as_deref().map(|m| &m.control_out);
+        let total = msgs.len();
+        for (sent, msg) in msgs.into_iter().enumerate() {
+            self.selectors.record(&msg);
+            send_counted(sender, msg, counters)
+                .await
+                .map_err(|_| ExtcapError::control_pipe_closed_after(sent, total))?;
+        }
+        Ok(())
+    }
+
+    /// Sends a command to a registered control
+    ///
+    /// Commands to the controls not relevant to the captured interface are dropped.
+    pub async fn send_to(
+        &mut self,
+        ctrl_num: u8,
+        command: ControlCmd,
+        data: &[u8],
+    ) -> ExtcapResult<()> {
+        if usize::from(ctrl_num) >= self.controls {
+            return Err(ExtcapError::invalid_control(ctrl_num, self.controls));
+        }
+        if self.ignored.contains(&ctrl_num) {
+            debug!(
+                "control {} not relevant to the interface, {:?} dropped",
+                ctrl_num, command
+            );
+            return Ok(());
+        }
+        self.send(ControlMsg::new(ctrl_num, command, data)).await
+    }
+
+    /// Sets the value of a registered control
+    pub async fn set(&mut self, ctrl_num: u8, value: &[u8]) -> ExtcapResult<()> {
+        self.send_to(ctrl_num, ControlCmd::Set, value).await
+    }
+
+    /// Adds a value to a registered control (selector entry or logger line)
+    pub async fn add(&mut self, ctrl_num: u8, value: &[u8]) -> ExtcapResult<()> {
+        self.send_to(ctrl_num, ControlCmd::Add, value).await
+    }
+
+    /// Removes a value from a registered control
+    pub async fn remove(&mut self, ctrl_num: u8, value: &[u8]) -> ExtcapResult<()> {
+        self.send_to(ctrl_num, ControlCmd::Remove, value).await
+    }
+
+    /// Removes an entry of a registered selector control, keeping a valid selection
+    ///
+    /// The entries and the selection of the selector controls are tracked. Removing the
+    /// selected entry sets the following one (the preceding one for the last entry) in
+    /// the same batch, Wireshark shows no sensible selection otherwise. The only entry
+    /// left is not removed, an error is returned instead. Values unknown to the tracking
+    /// and other control types are removed as by `remove`.
+    pub async fn remove_value(&mut self, ctrl_num: u8, value: &[u8]) -> ExtcapResult<()> {
+        let plan = if self.ignored.contains(&ctrl_num) {
+            RemovePlan::Untracked
+        } else {
+            self.selectors.plan_remove(ctrl_num, value)
+        };
+        match plan {
+            RemovePlan::Untracked | RemovePlan::Remove => self.remove(ctrl_num, value).await,
+            RemovePlan::RemoveSelected(next) => {
+                debug!(
+                    "control {} selected entry removed, selecting {:?}",
+                    ctrl_num,
+                    String::from_utf8_lossy(&next)
+                );
+                self.send_all([
+                    ControlMsg::new(ctrl_num, ControlCmd::Remove, value),
+                    ControlMsg::new(ctrl_num, ControlCmd::Set, &next),
+                ])
+                .await
+            }
+            RemovePlan::Last => Err(ExtcapError::invalid_state(format!(
+                "Control {}: the only selector entry '{}' can't be removed",
+                ctrl_num,
+                String::from_utf8_lossy(value)
+            ))),
+        }
+    }
+
+    /// Enables a registered control
+    pub async fn enable(&mut self, ctrl_num: u8) -> ExtcapResult<()> {
+        self.send_to(ctrl_num, ControlCmd::Enable, &[]).await
+    }
+
+    /// Disables a registered control
+    pub async fn disable(&mut self, ctrl_num: u8) -> ExtcapResult<()> {
+        self.send_to(ctrl_num, ControlCmd::Disable, &[]).await
+    }
+
+    /// Shows a message in the Wireshark status bar
+    pub async fn statusbar_message(&mut self, msg: &str) -> ExtcapResult<()> {
+        self.send(ControlMsg::new(
+            0,
+            ControlCmd::StatusbarMessage,
+            msg.as_bytes(),
+        ))
+        .await
+    }
+
+    /// Shows an information message dialog
+    pub async fn information_message(&mut self, msg: &str) -> ExtcapResult<()> {
+        self.send(ControlMsg::new(
+            0,
+            ControlCmd::InformationMessage,
+            msg.as_bytes(),
+        ))
+        .await
+    }
+
+    /// Shows a warning message dialog
+    pub async fn warning_message(&mut self, msg: &str) -> ExtcapResult<()> {
+        self.send(ControlMsg::new(
+            0,
+            ControlCmd::WarningMessage,
+            msg.as_bytes(),
+        ))
+        .await
+    }
+
+    /// Shows an error message dialog
+    pub async fn error_message(&mut self, msg: &str) -> ExtcapResult<()> {
+        self.send(ControlMsg::new(0, ControlCmd::ErrorMessage, msg.as_bytes()))
+            .await
+    }
+}
+
+/// Control pipes, the incoming messages from Wireshark and the sender to Wireshark
+///
+/// Created by the crate for the capture, `from_channels` and `disconnected` are
+/// the supported way to unit-test code consuming control pipes.
+#[derive(Debug)]
+pub struct CtrlPipes {
+    receiver: Receiver<ControlMsg>,
+    sender: ControlSender,
+}
+
+impl CtrlPipes {
+    pub(crate) fn new(receiver: Receiver<ControlMsg>, sender: ControlSender) -> Self {
+        Self { receiver, sender }
+    }
+
+    /// Creates the pipes from plain channels
+    ///
+    /// The incoming messages are read from `receiver`, the sent ones are written
+    /// to `sender`. Any control number is accepted as there are no registered controls.
+    pub fn from_channels(receiver: Receiver<ControlMsg>, sender: Sender<ControlMsg>) -> Self {
+        Self::new(
+            receiver,
+            ControlSender::new(sender, usize::from(u8::MAX) + 1),
+        )
+    }
+
+    /// Creates the pipes with the incoming pipe closed and the sent messages dropped
+    pub fn disconnected() -> Self {
+        let (_, receiver) = mpsc::channel(0);
+        Self::new(
+            receiver,
+            ControlSender {
+                sender: None,
+                controls: usize::from(u8::MAX) + 1,
+                ignored: Vec::new(),
+                batch: Arc::default(),
+                metrics: None,
+                selectors: SelectorStates::default(),
+            },
+        )
+    }
+
+    /// Gets the receiver of the incoming messages
+    pub fn receiver(&mut self) -> &mut Receiver<ControlMsg> {
+        &mut self.receiver
+    }
+
+    /// Gets the sender
+    pub fn sender(&mut self) -> &mut ControlSender {
+        &mut self.sender
+    }
+
+    /// Splits the pipes into the receiver and the sender
+    pub fn into_parts(self) -> (Receiver<ControlMsg>, ControlSender) {
+        (self.receiver, self.sender)
+    }
+}
 
 pub(crate) struct ControlPipe {
     runtime: ControlPipeRuntime,
+    metrics: SharedMetrics,
 }
 
 impl ControlPipe {
-    pub(crate) fn new(pipe_in: File, pipe_out: File) -> Self {
+    pub(crate) fn new(
+        pipe_in: File,
+        pipe_out: File,
+        peer_version: PeerVersion,
+        strict: bool,
+        metrics: SharedMetrics,
+        pause: Option<(u8, PauseState)>,
+        name: &str,
+    ) -> Self {
         Self {
-            runtime: ControlPipeRuntime::new(pipe_in, pipe_out),
+            runtime: ControlPipeRuntime::new(
+                pipe_in,
+                pipe_out,
+                peer_version,
+                strict,
+                metrics.clone(),
+                pause,
+                name,
+            ),
+            metrics,
         }
     }
 
-    pub(crate) fn start(&mut self) -> CtrlPipes {
-        self.runtime.start()
+    /// Starts the pipes, the messages of the `ignored` controls are dropped in both directions
+    ///
+    /// `stop` is tripped when Wireshark closes the incoming pipe, and the outgoing one
+    /// if `stop_on_out_closed`. `out_closed` is set when the outgoing pipe is closed.
+    pub(crate) fn start(
+        &mut self,
+        controls: &[Control],
+        ignored: Vec<u8>,
+        store: Option<ControlStore>,
+        stop: StopToken,
+        out_closed: Arc<AtomicBool>,
+        stop_on_out_closed: bool,
+    ) -> ExtcapResult<CtrlPipes> {
+        let selectors = SelectorStates::new(controls);
+        let (rcv_in, snd_out) = self.runtime.start(
+            ignored.clone(),
+            store,
+            selectors.clone(),
+            stop,
+            out_closed,
+            stop_on_out_closed,
+        )?;
+        let sender = ControlSender {
+            ignored,
+            metrics: self.metrics.clone(),
+            selectors,
+            ..ControlSender::new(snd_out, controls.len())
+        };
+        Ok(CtrlPipes::new(rcv_in, sender))
     }
 
-    pub(crate) fn run_task(&mut self) -> impl Future<Output = ()> {
+    pub(crate) fn run_task(&mut self) -> ExtcapResult<impl Future<Output = ()>> {
         self.runtime.run_task()
     }
 
@@ -128,3 +457,108 @@ impl ControlPipe {
         self.runtime.stop()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::{FutureExt, StreamExt};
+
+    use super::*;
+    use crate::ControlVal;
+
+    type Sent = (u8, u8, String);
+
+    /// Sender of a string control 0 and a selector 1 with `a`, `b` (default), `c`
+    fn sender() -> (ControlSender, Receiver<ControlMsg>) {
+        let mut text = Control::new_string();
+        text.set_number(0);
+        let mut selector = Control::new_selector();
+        for val in ["a", "b", "c"] {
+            selector.add_val(ControlVal::new(val).default(val == "b"));
+        }
+        selector.set_number(1);
+        let controls = [text, selector];
+        let (tx, rx) = mpsc::channel(16);
+        let sender = ControlSender {
+            selectors: SelectorStates::new(&controls),
+            ..ControlSender::new(tx, controls.len())
+        };
+        (sender, rx)
+    }
+
+    /// The messages sent to the pipe so far
+    fn sent(rx: &mut Receiver<ControlMsg>) -> Vec<Sent> {
+        let mut msgs = Vec::new();
+        while let Some(Some(msg)) = rx.next().now_or_never() {
+            let data = String::from_utf8_lossy(msg.get_data()).into_owned();
+            msgs.push((msg.get_ctrl_num(), u8::from(msg.get_command()), data));
+        }
+        msgs
+    }
+
+    fn remove(value: &str) -> Sent {
+        (1, u8::from(&ControlCmd::Remove), value.to_owned())
+    }
+
+    fn set(value: &str) -> Sent {
+        (1, u8::from(&ControlCmd::Set), value.to_owned())
+    }
+
+    #[test]
+    fn unselected_entry_only_removed() {
+        let (mut sender, mut rx) = sender();
+        block_on(sender.remove_value(1, b"a")).unwrap();
+        assert_eq!(sent(&mut rx), [remove("a")]);
+    }
+
+    #[test]
+    fn selected_entry_replaced_by_the_next_then_the_previous() {
+        let (mut sender, mut rx) = sender();
+        block_on(sender.remove_value(1, b"b")).unwrap();
+        assert_eq!(sent(&mut rx), [remove("b"), set("c")]);
+        // `c` is selected and the last entry now
+        block_on(sender.remove_value(1, b"c")).unwrap();
+        assert_eq!(sent(&mut rx), [remove("c"), set("a")]);
+    }
+
+    #[test]
+    fn only_entry_kept() {
+        let (mut sender, mut rx) = sender();
+        block_on(sender.remove_value(1, b"a")).unwrap();
+        block_on(sender.remove_value(1, b"c")).unwrap();
+        assert_eq!(sent(&mut rx), [remove("a"), remove("c")]);
+        assert!(block_on(sender.remove_value(1, b"b")).is_err());
+        assert!(sent(&mut rx).is_empty());
+    }
+
+    #[test]
+    fn selection_made_by_the_user_followed() {
+        let (mut sender, mut rx) = sender();
+        // as received from Wireshark
+        sender
+            .selectors
+            .record(&ControlMsg::new(1, ControlCmd::Set, b"a"));
+        block_on(sender.remove_value(1, b"b")).unwrap();
+        block_on(sender.remove_value(1, b"a")).unwrap();
+        assert_eq!(sent(&mut rx), [remove("b"), remove("a"), set("c")]);
+    }
+
+    #[test]
+    fn added_entries_tracked() {
+        let (mut sender, mut rx) = sender();
+        block_on(sender.add(1, b"d\0Device D")).unwrap();
+        block_on(sender.set(1, b"d")).unwrap();
+        block_on(sender.remove_value(1, b"d")).unwrap();
+        let msgs = sent(&mut rx);
+        assert_eq!(msgs[2..], [remove("d"), set("c")]);
+    }
+
+    #[test]
+    fn untracked_values_removed_as_is() {
+        let (mut sender, mut rx) = sender();
+        block_on(sender.remove_value(1, b"x")).unwrap();
+        block_on(sender.remove_value(0, b"a")).unwrap();
+        let text_remove = (0, u8::from(&ControlCmd::Remove), "a".to_owned());
+        assert_eq!(sent(&mut rx), [remove("x"), text_remove]);
+    }
+}
@@ -1,6 +1,8 @@
 use std::fs::File;
 use std::future::Future;
 use std::io::{self, Cursor};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use bytes::buf::BufMut;
 use bytes::{Buf, BytesMut};
@@ -9,10 +11,15 @@ use futures::channel::oneshot;
 use futures::future::{self, lazy, BoxFuture, FutureExt};
 use futures::sink::SinkExt;
 use futures::stream::{StreamExt, TryStreamExt};
-use log::{debug, error};
+use log::{debug, error, warn};
 use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
 
-use crate::control_pipe::{ControlMsg, CtrlPipes};
+use crate::control_pipe::{ControlCmd, ControlMsg, PeerVersion, CTRL_MSG_DATA_MAX};
+use crate::control_state::ControlStore;
+use crate::metrics::{send_counted, SharedMetrics};
+use crate::selector_state::SelectorStates;
+use crate::stop::is_pipe_closed;
+use crate::{ExtcapError, ExtcapResult, PauseState, StopToken};
 
 const PIPE_LEN: usize = 128;
 
@@ -31,25 +38,54 @@ enum State {
 pub(crate) struct ControlPipeRuntime {
     state: Option<State>,
     tsk: Option<BoxFuture<'static, ()>>,
+    peer_version: PeerVersion,
+    strict: bool,
+    metrics: SharedMetrics,
+    pause: Option<(u8, PauseState)>,
+    name: String,
 }
 
 impl ControlPipeRuntime {
-    pub(crate) fn new(pipe_in: File, pipe_out: File) -> Self {
+    pub(crate) fn new(
+        pipe_in: File,
+        pipe_out: File,
+        peer_version: PeerVersion,
+        strict: bool,
+        metrics: SharedMetrics,
+        pause: Option<(u8, PauseState)>,
+        name: &str,
+    ) -> Self {
         Self {
             state: Some(State::New { pipe_in, pipe_out }),
             tsk: None,
+            peer_version,
+            strict,
+            metrics,
+            pause,
+            name: name.to_owned(),
         }
     }
 
-    pub(crate) fn start(&mut self) -> CtrlPipes {
-        debug!("start() state={:?}", self.state);
+    pub(crate) fn start(
+        &mut self,
+        ignored: Vec<u8>,
+        store: Option<ControlStore>,
+        selectors: SelectorStates,
+        capture_stop: StopToken,
+        out_closed: Arc<AtomicBool>,
+        stop_on_out_closed: bool,
+    ) -> ExtcapResult<(Receiver<ControlMsg>, Sender<ControlMsg>)> {
+        debug!("[{}] start() state={:?}", self.name, self.state);
 
-        let (pipe_in, pipe_out) = if let Some(State::New { pipe_in, pipe_out }) = self.state.take()
-        {
-            (pipe_in, pipe_out)
-        } else {
-            error!("start() called in wrong state");
-            panic!("start() called in wrong state");
+        let (pipe_in, pipe_out) = match self.state.take() {
+            Some(State::New { pipe_in, pipe_out }) => (pipe_in, pipe_out),
+            state => {
+                error!("[{}] start() called in wrong state {:?}", self.name, state);
+                self.state = state;
+                return Err(ExtcapError::invalid_state(
+                    "Control pipe runtime already started",
+                ));
+            }
         };
 
         let (snd, rcv_in) = mpsc::channel(PIPE_LEN);
@@ -60,106 +96,410 @@ impl ControlPipeRuntime {
 
         self.state = Some(State::Started { stop_in, stop_out });
 
+        // an unrelated pause control never reaches the listener, nor pauses the capture
+        let pause = self
+            .pause
+            .clone()
+            .filter(|(ctrl_num, _)| !ignored.contains(ctrl_num));
+
         let tsk = futures::future::join(
-            thread_in(stop_in_rx, pipe_in, snd),
-            thread_out(stop_out_rx, pipe_out, rcv),
+            thread_in(
+                stop_in_rx,
+                pipe_in,
+                snd,
+                InContext {
+                    ignored,
+                    store: store.clone(),
+                    selectors,
+                    restore: snd_out.clone(),
+                    peer_version: self.peer_version.clone(),
+                    capture_stop: capture_stop.clone(),
+                    strict: self.strict,
+                    metrics: self.metrics.clone(),
+                    pause,
+                },
+                self.name.clone(),
+            ),
+            thread_out(
+                stop_out_rx,
+                pipe_out,
+                rcv,
+                OutContext {
+                    store,
+                    closed: out_closed,
+                    capture_stop: stop_on_out_closed.then_some(capture_stop),
+                    metrics: self.metrics.clone(),
+                },
+                self.name.clone(),
+            ),
         )
         .map(|_| ());
 
         self.tsk = Some(tsk.boxed::<'static>());
 
-        debug!("start() done state={:?}", self.state);
-        (rcv_in, snd_out)
+        debug!("[{}] start() done state={:?}", self.name, self.state);
+        Ok((rcv_in, snd_out))
     }
 
-    pub(crate) fn run_task(&mut self) -> impl Future<Output = ()> {
-        self.tsk.take().unwrap()
+    pub(crate) fn run_task(&mut self) -> ExtcapResult<impl Future<Output = ()>> {
+        self.tsk.take().ok_or_else(|| {
+            ExtcapError::invalid_state("Control pipe runtime not started or task already taken")
+        })
     }
 
     pub(crate) fn stop(mut self) {
-        debug!("stop() state={:?}", self.state);
+        debug!("[{}] stop() state={:?}", self.name, self.state);
         if let Some(State::Started { stop_in, stop_out }) = self.state.take() {
-            stop_in.send(()).unwrap();
-            stop_out.send(()).unwrap();
+            // the tasks may have already finished, nothing to stop then
+            let _ = stop_in.send(());
+            let _ = stop_out.send(());
         } else {
-            error!("stop() called in wrong state");
+            error!("[{}] stop() called in wrong state", self.name);
             return;
         }
-        debug!("stop() done");
+        debug!("[{}] stop() done", self.name);
     }
 }
 
+/// Incoming messages handling besides forwarding them to the listener
+struct InContext {
+    ignored: Vec<u8>,
+    store: Option<ControlStore>,
+    selectors: SelectorStates,   // follows the selection made by the user
+    restore: Sender<ControlMsg>, // for the restored values sent on Initialized
+    peer_version: PeerVersion,
+    capture_stop: StopToken, // tripped when Wireshark closes the pipe
+    strict: bool,            // fail on a framing error instead of resynchronizing
+    metrics: SharedMetrics,
+    pause: Option<(u8, PauseState)>, // control flipping the pause state
+}
+
 async fn thread_in(
     stop: oneshot::Receiver<()>,
     pipe: File,
     sender: Sender<ControlMsg>,
+    ctx: InContext,
+    name: String,
 ) -> Result<(), ()> {
-    debug!("thread_in starting ...");
+    let InContext {
+        ignored,
+        store,
+        selectors,
+        restore,
+        peer_version,
+        capture_stop,
+        strict,
+        metrics,
+        pause,
+    } = ctx;
+    let counters = metrics.as_deref().map(|m| &m.control_in);
+    let name = name.as_str();
+    debug!("[{}] thread_in starting ...", name);
     lazy::<_, Result<(), ()>>(|_| {
-        debug!("thread_in started");
+        debug!("[{}] thread_in started", name);
         Ok(())
     })
     .await?;
     let tpipe = tokio::fs::File::from_std(pipe);
-    let strm = FramedRead::new(tpipe, ControlMsgCodec);
+    let strm = FramedRead::new(tpipe, ControlMsgCodec::new(name, strict));
     let task = strm
-        .inspect(|msg| debug!("thread_in received {:?}", msg))
-        .map_err(|e| error!("thread_in stream_err {:?}", e))
-        .forward(sender.sink_map_err(|e| error!("thread_in sink_err {:?}", e)));
-    future::select(stop, task).await;
-    debug!("thread_in stopped");
+        .inspect(|msg| debug!("[{}] thread_in received {:?}", name, msg))
+        .inspect_ok(move |msg| store_peer_version(name, &peer_version, msg))
+        .and_then(move |msg| {
+            let mut restore_msgs = match (&store, msg.get_command()) {
+                (Some(store), ControlCmd::Initialized) => store.restore_msgs(),
+                (Some(store), _) => {
+                    store.record(&msg);
+                    Vec::new()
+                }
+                _ => Vec::new(),
+            };
+            restore_msgs
+                .iter()
+                .chain(std::iter::once(&msg))
+                .for_each(|m| selectors.record(m));
+            // the restored value of the pause control applies as well
+            if let Some(pause) = &pause {
+                let statuses: Vec<_> = restore_msgs
+                    .iter()
+                    .chain(std::iter::once(&msg))
+                    .filter_map(|m| apply_pause(name, pause, m))
+                    .collect();
+                restore_msgs.extend(statuses);
+            }
+            let mut restore = restore.clone();
+            async move {
+                for rmsg in restore_msgs {
+                    debug!("[{}] thread_in restoring {:?}", name, rmsg);
+                    if let Err(e) = restore.send(rmsg).await {
+                        error!("[{}] thread_in restore_err {:?}", name, e);
+                    }
+                }
+                Ok(msg)
+            }
+        })
+        .try_filter(move |msg| {
+            let ignore = is_ignored(name, &ignored, msg);
+            if let Some(counters) = counters.filter(|_| ignore) {
+                counters.dropped();
+            }
+            future::ready(!ignore)
+        })
+        .map_err(|e| error!("[{}] thread_in stream_err {:?}", name, e))
+        // keeps reading when the listener has dropped the receiver, Wireshark would block
+        // writing to the pipe otherwise
+        .try_fold(Some(sender), move |sender, msg| async move {
+            match sender {
+                Some(mut sender) => match send_counted(&mut sender, msg, counters).await {
+                    Ok(()) => Ok(Some(sender)),
+                    Err(e) => {
+                        debug!("[{}] thread_in receiver dropped, draining: {:?}", name, e);
+                        Ok(None)
+                    }
+                },
+                None => {
+                    debug!("[{}] thread_in discarded {:?}", name, msg);
+                    if let Some(counters) = counters {
+                        counters.dropped();
+                    }
+                    Ok(None)
+                }
+            }
+        });
+    if let future::Either::Right(_) = future::select(stop, task.boxed()).await {
+        debug!("[{}] thread_in pipe closed, stopping the capture", name);
+        capture_stop.stop();
+    }
+    debug!("[{}] thread_in stopped", name);
     Ok(())
 }
 
+/// Outgoing messages handling besides writing them to the pipe
+struct OutContext {
+    store: Option<ControlStore>,
+    closed: Arc<AtomicBool>,         // set when Wireshark closes the pipe
+    capture_stop: Option<StopToken>, // tripped then if configured
+    metrics: SharedMetrics,
+}
+
 async fn thread_out(
     stop: oneshot::Receiver<()>,
     pipe: File,
     mut receiver: Receiver<ControlMsg>,
+    ctx: OutContext,
+    name: String,
 ) -> Result<(), ()> {
-    debug!("thread_out starting ...");
+    let OutContext {
+        store,
+        closed,
+        capture_stop,
+        metrics,
+    } = ctx;
+    let counters = metrics.as_deref().map(|m| &m.control_out);
+    debug!("[{}] thread_out starting ...", name);
     lazy::<_, Result<(), ()>>(|_| {
-        debug!("thread_out started");
+        debug!("[{}] thread_out started", name);
         Ok(())
     })
     .await?;
     let tpipe = tokio::fs::File::from_std(pipe);
-    let mut strm = FramedWrite::new(tpipe, ControlMsgCodec);
+    let mut strm = FramedWrite::new(tpipe, ControlMsgCodec::new(&name, true));
     let task = async {
         while let Some(msg) = receiver.next().await {
-            debug!("thread_out received {:?}", msg);
-            if let Err(e) = strm.send(msg).await {
-                error!("thread_out strm_err {:?}", e);
+            // the messages queued meanwhile (e.g. a batch) are written at once
+            let mut msgs = vec![msg];
+            while let Some(Some(msg)) = receiver.next().now_or_never() {
+                msgs.push(msg);
+            }
+            if let Some(counters) = counters {
+                counters.depth(msgs.len());
+            }
+            write_msgs(&name, &mut strm, store.as_ref(), msgs).await?;
+        }
+        Ok::<_, io::Error>(())
+    };
+    let res = match future::select(stop, task.boxed()).await {
+        future::Either::Left(_) => None,
+        future::Either::Right((res, _)) => Some(res),
+    };
+    let res = match res {
+        Some(res) => res,
+        None => {
+            // the messages sent before the stop are still written, e.g. a final status
+            let mut msgs = Vec::new();
+            while let Some(Some(msg)) = receiver.next().now_or_never() {
+                msgs.push(msg);
+            }
+            if msgs.is_empty() {
+                Ok(())
+            } else {
+                write_msgs(&name, &mut strm, store.as_ref(), msgs).await
             }
         }
     };
-    future::select(stop, task.boxed()).await;
-    debug!("thread_out stopped");
+    if let Err(e) = res {
+        // the receiver is dropped, the next sends fail at once instead of queueing
+        warn!(
+            "[{}] thread_out Wireshark closed the control channel: {}",
+            name, e
+        );
+        closed.store(true, Ordering::SeqCst);
+        if let Some(capture_stop) = capture_stop {
+            capture_stop.stop();
+        }
+    }
+    debug!("[{}] thread_out stopped", name);
     Ok(())
 }
 
-struct ControlMsgCodec;
+async fn write_msgs(
+    name: &str,
+    strm: &mut FramedWrite<tokio::fs::File, ControlMsgCodec>,
+    store: Option<&ControlStore>,
+    msgs: Vec<ControlMsg>,
+) -> io::Result<()> {
+    debug!("[{}] thread_out writing {} messages", name, msgs.len());
+    for msg in msgs {
+        debug!("[{}] thread_out received {:?}", name, msg);
+        if let Some(store) = store {
+            store.record(&msg);
+        }
+        if let Err(e) = strm.feed(msg).await {
+            if is_pipe_closed(&e) {
+                return Err(e);
+            }
+            error!("[{}] thread_out strm_err {:?}", name, e);
+        }
+    }
+    match strm.flush().await {
+        Err(e) if is_pipe_closed(&e) => Err(e),
+        Err(e) => {
+            error!("[{}] thread_out strm_err {:?}", name, e);
+            Ok(())
+        }
+        Ok(()) => Ok(()),
+    }
+}
 
-impl Decoder for ControlMsgCodec {
-    type Item = ControlMsg;
-    type Error = io::Error;
+/// Returns `true` for the messages of the controls not relevant to the captured interface
+fn is_ignored(name: &str, ignored: &[u8], msg: &ControlMsg) -> bool {
+    let ignore = !matches!(msg.get_command(), ControlCmd::Initialized)
+        && ignored.contains(&msg.get_ctrl_num());
+    if ignore {
+        debug!(
+            "[{}] thread_in control not relevant, {:?} dropped",
+            name, msg
+        );
+    }
+    ignore
+}
 
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+/// Flips the pause state on a `Set` of the pause control, returns the status bar message
+fn apply_pause(name: &str, pause: &(u8, PauseState), msg: &ControlMsg) -> Option<ControlMsg> {
+    let (ctrl_num, state) = pause;
+    if !matches!(msg.get_command(), ControlCmd::Set) || msg.get_ctrl_num() != *ctrl_num {
+        return None;
+    }
+    let paused = msg
+        .get_data()
+        .first()
+        .is_some_and(|b| *b != 0 && *b != b'0');
+    if !state.set_paused(paused) {
+        return None;
+    }
+    let status = if paused { "paused" } else { "resumed" };
+    debug!("[{}] thread_in capture {}", name, status);
+    Some(ControlMsg::new(
+        0,
+        ControlCmd::StatusbarMessage,
+        format!("{}: capture {}", name, status).as_bytes(),
+    ))
+}
+
+fn store_peer_version(name: &str, peer_version: &PeerVersion, msg: &ControlMsg) {
+    if !matches!(msg.get_command(), ControlCmd::Initialized) {
+        return;
+    }
+    match msg.get_initialized_version() {
+        Some(ver) => {
+            debug!("[{}] thread_in peer version {}", name, ver);
+            match peer_version.lock() {
+                Ok(mut pv) => *pv = Some(ver),
+                Err(e) => warn!("[{}] thread_in peer version not stored {:?}", name, e),
+            }
+        }
+        None => debug!("[{}] thread_in initialized without version", name),
+    }
+}
+
+/// Framing of the control messages, shared with `testing::cli`
+pub(crate) struct ControlMsgCodec {
+    name: String,
+    strict: bool,
+    skipped: usize, // bytes dropped by the current resynchronization
+}
+
+impl ControlMsgCodec {
+    pub(crate) fn new(name: &str, strict: bool) -> Self {
+        Self {
+            name: name.to_owned(),
+            strict,
+            skipped: 0,
+        }
+    }
+
+    /// Checks the header at the start of `buf`, `Ok(None)` when it's incomplete
+    fn check_header(buf: &[u8]) -> Result<Option<usize>, &'static str> {
+        if buf.first().is_some_and(|b| *b != b'T') {
+            return Err("Sync Pipe Indication != 'T'");
+        }
         if buf.len() < 4 {
             return Ok(None);
         }
-        let mut hdr = Cursor::new(&buf[0..4]);
-        if hdr.get_u8() != b'T' {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Sync Pipe Indication != 'T'",
-            ));
-        }
+        let mut hdr = Cursor::new(&buf[1..4]);
         let msg_len = hdr.get_uint(3) as usize;
         if msg_len < 2 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Message Length < 2",
-            ));
+            return Err("Message Length < 2");
+        }
+        Ok(Some(msg_len))
+    }
+
+    /// Drops the bytes up to the next header which isn't known to be invalid
+    fn resync(&mut self, buf: &mut BytesMut) {
+        let skip = (1..buf.len())
+            .find(|i| Self::check_header(&buf[*i..]).is_ok())
+            .unwrap_or(buf.len());
+        buf.advance(skip);
+        self.skipped += skip;
+    }
+}
+
+impl Decoder for ControlMsgCodec {
+    type Item = ControlMsg;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let msg_len = loop {
+            match Self::check_header(buf) {
+                Ok(Some(msg_len)) => break msg_len,
+                Ok(None) => return Ok(None),
+                Err(e) if self.strict => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                }
+                Err(e) => {
+                    if self.skipped == 0 {
+                        warn!("[{}] decode() {}, resynchronizing", self.name, e);
+                    }
+                    self.resync(buf);
+                }
+            }
+        };
+        if self.skipped > 0 {
+            warn!(
+                "[{}] decode() resynchronized, skipped {} bytes",
+                self.name, self.skipped
+            );
+            self.skipped = 0;
         }
         if buf.len() < (4 + msg_len) {
             return Ok(None);
@@ -172,17 +512,394 @@ impl Decoder for ControlMsgCodec {
     }
 }
 
+/// Decodes `data` fed in chunks of `chunk` bytes, the entry point of the fuzz target
+///
+/// Fails only in the strict mode. Not a part of the API, built for the tests and
+/// `cargo fuzz` only.
+#[cfg(any(test, fuzzing))]
+pub fn fuzz_decode(data: &[u8], chunk: usize, strict: bool) -> io::Result<Vec<ControlMsg>> {
+    let mut codec = ControlMsgCodec::new("fuzz", strict);
+    let mut buf = BytesMut::new();
+    let mut msgs = Vec::new();
+    for part in data.chunks(chunk.max(1)) {
+        buf.extend_from_slice(part);
+        while let Some(msg) = codec.decode(&mut buf)? {
+            msgs.push(msg);
+        }
+    }
+    Ok(msgs)
+}
+
 impl Encoder<ControlMsg> for ControlMsgCodec {
     type Error = io::Error;
 
     fn encode(&mut self, msg: ControlMsg, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        if msg.get_data().len() > CTRL_MSG_DATA_MAX {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Message Length > 0xFFFFFF",
+            ));
+        }
         buf.reserve(6 + msg.get_data().len());
         buf.put_u8(b'T');
         buf.put_uint(2 + msg.get_data().len() as u64, 3);
         buf.put_u8(msg.get_ctrl_num());
         buf.put_u8(u8::from(msg.get_command()));
         buf.put(msg.get_data());
-        debug!("encode() {:?}", buf);
+        debug!("[{}] encode() {:?}", self.name, buf);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::test_support::{self, TempPath};
+    use crate::{Control, CtrlPipes, Extcap, ExtcapListener, ExtcapReceiver, IFace};
+
+    #[test]
+    fn initialized_version_stored() {
+        let extcap = Extcap::new("peerdump");
+        let peer_version = extcap.ctrl_peer_version.clone();
+        let initialized = |data: &[u8]| ControlMsg::new(0, ControlCmd::Initialized, data);
+        store_peer_version("test", &peer_version, &initialized(b"4.2.5\0"));
+        assert_eq!(extcap.ctrl_peer_version().as_deref(), Some("4.2.5"));
+        // other messages and unknown payloads keep the stored version
+        let others = [
+            initialized(b""),
+            initialized(&[0xff, 0xfe]),
+            ControlMsg::new(1, ControlCmd::Initialized, b"9.9"),
+            ControlMsg::new(0, ControlCmd::StatusbarMessage, b"9.9"),
+        ];
+        for msg in &others {
+            store_peer_version("test", &peer_version, msg);
+        }
+        assert_eq!(extcap.ctrl_peer_version().as_deref(), Some("4.2.5"));
+    }
+
+    #[test]
+    fn initialized_version_round_trip() {
+        let extcap = Extcap::new("peerdump");
+        let mut codec = ControlMsgCodec::new("test", true);
+        let mut buf = BytesMut::new();
+        let sent = ControlMsg::new(0, ControlCmd::Initialized, b"4.2.5\0");
+        codec.encode(sent, &mut buf).unwrap();
+        let received = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(buf.is_empty());
+        store_peer_version("test", &extcap.ctrl_peer_version, &received);
+        assert_eq!(extcap.ctrl_peer_version().as_deref(), Some("4.2.5"));
+    }
+
+    /// Ignores the control pipes, ends the capture once Wireshark has sent everything
+    struct IgnoringControls {
+        sent: Option<std::sync::mpsc::Receiver<io::Result<()>>>,
+        outcome: Arc<Mutex<Option<io::Result<()>>>>,
+    }
+
+    impl ExtcapListener for IgnoringControls {
+        fn capture_async_with_ctrl(
+            &mut self,
+            _: &crate::Extcap,
+            _: &IFace,
+            _: Option<CtrlPipes>,
+        ) -> ExtcapResult<ExtcapReceiver> {
+            let (sender, receiver) = mpsc::channel(1);
+            let sent = self.sent.take().unwrap();
+            let outcome = self.outcome.clone();
+            thread::spawn(move || {
+                let res = sent.recv_timeout(Duration::from_secs(5));
+                *outcome.lock().unwrap() = Some(res.unwrap_or_else(|e| Err(io::Error::other(e))));
+                drop(sender);
+            });
+            Ok(receiver)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ignored_controls_dont_block_wireshark() {
+        use std::os::fd::AsRawFd;
+
+        let out = TempPath::new("out");
+        // a real pipe, its buffer fills up unless the messages are read
+        let (reader, mut writer) = io::pipe().unwrap();
+        let ctrl_in = format!("/proc/self/fd/{}", reader.as_raw_fd());
+        let fifo = out.0.with_file_name("fifo");
+
+        let (sent_tx, sent_rx) = std::sync::mpsc::channel();
+        let wireshark = thread::spawn(move || {
+            let mut codec = ControlMsgCodec::new("test", true);
+            let mut buf = BytesMut::new();
+            codec
+                .encode(ControlMsg::new(0, ControlCmd::Initialized, &[]), &mut buf)
+                .unwrap();
+            for _ in 0..1000 {
+                let msg = ControlMsg::new(0, ControlCmd::Set, &[b'x'; 100]);
+                codec.encode(msg, &mut buf).unwrap();
+            }
+            // over 100 kB, more than the pipe buffer
+            let res = writer.write_all(&buf);
+            let _ = sent_tx.send(res);
+        });
+
+        let mut extcap = Extcap::new("ignoring");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        extcap
+            .add_control(Control::new_string().display("Filter"))
+            .unwrap();
+        let listener = IgnoringControls {
+            sent: Some(sent_rx),
+            outcome: Default::default(),
+        };
+        let outcome = listener.outcome.clone();
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.to_str().unwrap(),
+            "--extcap-control-in",
+            &ctrl_in,
+            "--extcap-control-out",
+            out.as_str(),
+        ];
+        let (res, _) = test_support::run_async(extcap, listener, &args).await;
+        drop(reader);
+        wireshark.join().unwrap();
+        assert!(res.is_ok(), "{:?}", res.err());
+        let sent = outcome
+            .lock()
+            .unwrap()
+            .take()
+            .expect("capture ended by the listener");
+        assert!(sent.is_ok(), "Wireshark blocked: {:?}", sent);
+    }
+
+    /// Sends a status bar message every 20 ms until the capture is stopped
+    struct Ticking {
+        send_err: Arc<Mutex<Option<String>>>,
+        stats: Arc<Mutex<Option<crate::capture::SharedStats>>>,
+    }
+
+    impl ExtcapListener for Ticking {
+        fn capture_async_with_ctrl(
+            &mut self,
+            extcap: &crate::Extcap,
+            _: &IFace,
+            ctrl_pipes: Option<CtrlPipes>,
+        ) -> ExtcapResult<ExtcapReceiver> {
+            let (sender, receiver) = mpsc::channel(1);
+            let mut ctrl = ctrl_pipes.expect("control pipes").sender().clone();
+            let ctx = extcap.capture_context().unwrap();
+            let stop = ctx.get_stop_token().clone();
+            *self.stats.lock().unwrap() = Some(ctx.stats.clone());
+            let send_err = self.send_err.clone();
+            thread::spawn(move || {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while !stop.is_stopped() && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(20));
+                    if let Err(e) = futures::executor::block_on(ctrl.statusbar_message("tick")) {
+                        send_err.lock().unwrap().get_or_insert(e.to_string());
+                    }
+                }
+                drop(sender);
+            });
+            Ok(receiver)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn closed_out_pipe_ends_capture() {
+        use std::os::fd::AsRawFd;
+
+        let fifo = TempPath::new("fifo");
+        let (in_reader, mut in_writer) = io::pipe().unwrap();
+        let (out_reader, out_writer) = io::pipe().unwrap();
+        let ctrl_in = format!("/proc/self/fd/{}", in_reader.as_raw_fd());
+        let ctrl_out = format!("/proc/self/fd/{}", out_writer.as_raw_fd());
+        // the incoming pipe stays open, only the outgoing one is closed
+        let mut buf = BytesMut::new();
+        ControlMsgCodec::new("test", true)
+            .encode(ControlMsg::new(0, ControlCmd::Initialized, &[]), &mut buf)
+            .unwrap();
+        in_writer.write_all(&buf).unwrap();
+        let wireshark = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            drop(out_reader);
+            Instant::now()
+        });
+
+        let mut extcap = Extcap::new("closing");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        extcap
+            .add_control(Control::new_string().display("Filter"))
+            .unwrap();
+        let listener = Ticking {
+            send_err: Default::default(),
+            stats: Default::default(),
+        };
+        let (send_err, stats) = (listener.send_err.clone(), listener.stats.clone());
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.as_str(),
+            "--extcap-control-in",
+            &ctrl_in,
+            "--extcap-control-out",
+            &ctrl_out,
+        ];
+        let (res, _) = test_support::run_async(extcap, listener, &args).await;
+        let closed_at = wireshark.join().unwrap();
+        assert!(res.is_ok(), "{:?}", res.err());
+        assert!(closed_at.elapsed() < Duration::from_secs(2));
+        let stats = stats.lock().unwrap().take().unwrap();
+        let stats = stats.lock().map(|s| *s).unwrap();
+        assert!(stats.control_closed);
+        assert!(send_err.lock().unwrap().is_some());
+        drop((in_reader, in_writer, out_writer));
+    }
+
+    type Msg = (u8, u8, Vec<u8>);
+
+    fn simple(msgs: &[ControlMsg]) -> Vec<Msg> {
+        msgs.iter()
+            .map(|m| {
+                (
+                    m.get_ctrl_num(),
+                    u8::from(m.get_command()),
+                    m.get_data().to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    fn encode(msgs: &[Msg]) -> Vec<u8> {
+        let mut codec = ControlMsgCodec::new("test", true);
+        let mut buf = BytesMut::new();
+        for (num, cmd, data) in msgs {
+            let msg = ControlMsg::new(*num, (*cmd).into(), data);
+            codec.encode(msg, &mut buf).unwrap();
+        }
+        buf.to_vec()
+    }
+
+    fn decode(data: &[u8], strict: bool) -> io::Result<Vec<Msg>> {
+        fuzz_decode(data, data.len(), strict).map(|msgs| simple(&msgs))
+    }
+
+    fn random_msgs(rng: &mut StdRng) -> Vec<Msg> {
+        (0..rng.gen_range(0..20))
+            .map(|_| {
+                let len = rng.gen_range(0..300);
+                let data = (0..len).map(|_| rng.gen()).collect();
+                (rng.gen(), rng.gen(), data)
+            })
+            .collect()
+    }
+
+    fn msg(num: u8, data: &[u8]) -> Msg {
+        (num, 1, data.to_vec())
+    }
+
+    #[test]
+    fn arbitrary_messages_round_trip() {
+        let mut rng = StdRng::seed_from_u64(481);
+        for _ in 0..500 {
+            let msgs = random_msgs(&mut rng);
+            let data = encode(&msgs);
+            let chunk = rng.gen_range(1..=data.len().max(1));
+            let decoded = fuzz_decode(&data, chunk, true).unwrap();
+            assert_eq!(simple(&decoded), msgs, "read by {}", chunk);
+        }
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_fail_to_resync() {
+        let mut rng = StdRng::seed_from_u64(482);
+        for _ in 0..500 {
+            let len = rng.gen_range(0..500);
+            let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let whole = decode(&data, false).unwrap();
+            let chunk = rng.gen_range(1..=len.max(1));
+            let chunked = fuzz_decode(&data, chunk, false).unwrap();
+            assert_eq!(simple(&chunked), whole, "read by {}", chunk);
+        }
+    }
+
+    #[test]
+    fn messages_after_garbage_are_recovered() {
+        let mut rng = StdRng::seed_from_u64(483);
+        for _ in 0..500 {
+            let len = rng.gen_range(1..50);
+            // no byte of the garbage looks like a header
+            let mut data: Vec<u8> = (0..len).map(|_| rng.gen_range(b'U'..=u8::MAX)).collect();
+            let msgs = random_msgs(&mut rng);
+            data.extend(encode(&msgs));
+            assert_eq!(decode(&data, false).unwrap(), msgs);
+            assert!(decode(&data, true).is_err());
+        }
+    }
+
+    /// Corrupt bytes and stray headers, the messages around them survive
+    #[test]
+    fn corruption_regressions() {
+        let frames = encode(&[msg(1, b"a"), msg(2, b"bc"), msg(3, b"")]);
+        let corrupt = |at: usize, byte: u8| {
+            let mut data = frames.clone();
+            data[at] = byte;
+            data
+        };
+        let cases: [(&str, Vec<u8>, Vec<Msg>); 6] = [
+            (
+                "sync byte of the first message",
+                corrupt(0, b'X'),
+                vec![msg(2, b"bc"), msg(3, b"")],
+            ),
+            (
+                "length under the minimum",
+                corrupt(3, 1),
+                vec![msg(2, b"bc"), msg(3, b"")],
+            ),
+            (
+                "sync byte of the middle message",
+                corrupt(7, 0),
+                vec![msg(1, b"a"), msg(3, b"")],
+            ),
+            (
+                "byte before the first message",
+                [&[0xFF][..], &frames].concat(),
+                vec![msg(1, b"a"), msg(2, b"bc"), msg(3, b"")],
+            ),
+            (
+                "stray header with a bad length",
+                [&b"T\0\0\x01"[..], &frames].concat(),
+                vec![msg(1, b"a"), msg(2, b"bc"), msg(3, b"")],
+            ),
+            (
+                "trailing garbage",
+                [&frames[..], b"\0\0\0"].concat(),
+                vec![msg(1, b"a"), msg(2, b"bc"), msg(3, b"")],
+            ),
+        ];
+        for (case, data, expected) in cases {
+            assert_eq!(decode(&data, false).unwrap(), expected, "{}", case);
+            for chunk in 1..data.len() {
+                let chunked = fuzz_decode(&data, chunk, false).unwrap();
+                assert_eq!(simple(&chunked), expected, "{} read by {}", case, chunk);
+            }
+        }
+        assert!(decode(&corrupt(0, b'X'), true).is_err());
+        assert!(decode(&corrupt(3, 1), true).is_err());
+    }
+}
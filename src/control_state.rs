@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use log::{debug, warn};
+
+use crate::control_pipe::{ControlCmd, ControlMsg};
+
+/// Last values of the persistent controls, stored in the state file between the captures
+///
+/// One `number=value` line per control, the value is hex encoded.
+#[derive(Clone, Debug)]
+pub(crate) struct ControlStore {
+    path: PathBuf,
+    persistent: Vec<u8>,
+    values: Arc<Mutex<BTreeMap<u8, Vec<u8>>>>,
+}
+
+impl ControlStore {
+    /// Loads the values stored by the previous capture, a missing file means no values
+    pub(crate) fn load(path: &Path, persistent: Vec<u8>) -> Self {
+        let mut values = BTreeMap::new();
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                    match parse_line(line) {
+                        Some((num, val)) if persistent.contains(&num) => {
+                            values.insert(num, val);
+                        }
+                        Some(_) => {}
+                        None => warn!(
+                            "invalid control state line '{}' in {}",
+                            line,
+                            path.display()
+                        ),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("control state {} can't be read: {}", path.display(), e),
+        }
+        Self {
+            path: path.to_path_buf(),
+            persistent,
+            values: Arc::new(Mutex::new(values)),
+        }
+    }
+
+    /// Records the value of a persistent control
+    pub(crate) fn record(&self, msg: &ControlMsg) {
+        if !matches!(msg.get_command(), ControlCmd::Set)
+            || !self.persistent.contains(&msg.get_ctrl_num())
+        {
+            return;
+        }
+        if let Ok(mut values) = self.values.lock() {
+            values.insert(msg.get_ctrl_num(), msg.get_data().to_vec());
+        }
+    }
+
+    /// Returns the `Set` messages restoring the stored values
+    pub(crate) fn restore_msgs(&self) -> Vec<ControlMsg> {
+        match self.values.lock() {
+            Ok(values) => values
+                .iter()
+                .map(|(num, val)| ControlMsg::new(*num, ControlCmd::Set, val))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Writes the values to the state file
+    pub(crate) fn save(&self) -> io::Result<()> {
+        let mut text = String::new();
+        if let Ok(values) = self.values.lock() {
+            for (num, val) in values.iter() {
+                let _ = write!(text, "{}=", num);
+                val.iter().for_each(|b| {
+                    let _ = write!(text, "{:02x}", b);
+                });
+                text.push('\n');
+            }
+        }
+        debug!("control state saved to {}", self.path.display());
+        fs::write(&self.path, text)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(u8, Vec<u8>)> {
+    let (num, hex) = line.trim().split_once('=')?;
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    let val = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some((num.parse().ok()?, val))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use futures::channel::mpsc;
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::test_support::{self, read_ctrl_msgs, write_ctrl_msgs, TempPath};
+    use crate::{Control, CtrlPipes, Extcap, ExtcapListener, ExtcapReceiver, ExtcapResult, IFace};
+
+    /// Keeps the capture running until Wireshark closes the control pipe
+    struct UntilClosed;
+
+    impl ExtcapListener for UntilClosed {
+        fn capture_async_with_ctrl(
+            &mut self,
+            _: &Extcap,
+            _: &IFace,
+            ctrl_pipes: Option<CtrlPipes>,
+        ) -> ExtcapResult<ExtcapReceiver> {
+            let (sender, receiver) = mpsc::channel(1);
+            let mut pipes = ctrl_pipes.expect("control pipes");
+            tokio::spawn(async move {
+                while pipes.receiver().next().await.is_some() {}
+                drop(sender);
+            });
+            Ok(receiver)
+        }
+    }
+
+    /// Runs a capture with the incoming control messages, returns the sent ones
+    async fn capture(dir: &Path, state: &Path, incoming: Vec<ControlMsg>) -> Vec<ControlMsg> {
+        let (ctrl_in, ctrl_out, fifo) = (dir.join("in"), dir.join("out"), dir.join("fifo"));
+        write_ctrl_msgs(&ctrl_in, incoming);
+        let mut extcap = Extcap::new("persisting");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        let mut verbosity = Control::new_selector().display("Verbosity");
+        verbosity.add_val(crate::ControlVal::new("1"));
+        extcap.add_control(verbosity).unwrap();
+        extcap
+            .add_control(Control::new_string().display("Password").persist(false))
+            .unwrap();
+        extcap.persist_control_values(state);
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.to_str().unwrap(),
+            "--extcap-control-in",
+            ctrl_in.to_str().unwrap(),
+            "--extcap-control-out",
+            ctrl_out.to_str().unwrap(),
+        ];
+        let (res, _) = test_support::run_async(extcap, UntilClosed, &args).await;
+        assert!(res.is_ok(), "{:?}", res.err());
+        read_ctrl_msgs(&ctrl_out)
+    }
+
+    fn sets(msgs: &[ControlMsg]) -> Vec<(u8, Vec<u8>)> {
+        msgs.iter()
+            .filter(|m| matches!(m.get_command(), ControlCmd::Set))
+            .map(|m| (m.get_ctrl_num(), m.get_data().to_vec()))
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn second_capture_restores_the_values() {
+        let state = TempPath::new("controls.state");
+        let dir = state.0.parent().unwrap();
+        let first = capture(
+            dir,
+            &state.0,
+            vec![
+                ControlMsg::new(0, ControlCmd::Initialized, &[]),
+                ControlMsg::new(0, ControlCmd::Set, b"3"),
+                ControlMsg::new(1, ControlCmd::Set, b"secret"),
+            ],
+        )
+        .await;
+        assert!(sets(&first).is_empty(), "{:?}", first);
+        assert_eq!(fs::read_to_string(&state.0).unwrap(), "0=33\n");
+
+        let initialized = || vec![ControlMsg::new(0, ControlCmd::Initialized, &[])];
+        let second = capture(dir, &state.0, initialized()).await;
+        assert_eq!(sets(&second), [(0, b"3".to_vec())]);
+
+        // nothing restored without the state file
+        fs::remove_file(&state.0).unwrap();
+        let third = capture(dir, &state.0, initialized()).await;
+        assert!(sets(&third).is_empty(), "{:?}", third);
+    }
+}
@@ -0,0 +1,237 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Default size limit of the debug file
+const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Size limit of the debug file, see `Extcap::debug_file_limits`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugFileLimits {
+    /// The file grows without a limit
+    Unlimited,
+    /// The file is emptied once it would exceed the size
+    Truncate(u64),
+    /// The file is renamed to `<file>.old` once it would exceed the size,
+    /// replacing the previous one, so at most twice the size is kept
+    KeepOld(u64),
+}
+
+impl Default for DebugFileLimits {
+    fn default() -> Self {
+        DebugFileLimits::KeepOld(DEFAULT_MAX_BYTES)
+    }
+}
+
+/// Debug file handed out by `LogConfig`, appended to and size-capped
+///
+/// Line-buffered: complete lines are written to the file at once, so the warnings
+/// and errors are on the disk when the process gets killed, a partial line waits
+/// for its end or a flush. The limit applies at line boundaries, the most recent
+/// lines are never dropped.
+#[derive(Debug)]
+pub struct DebugFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    limits: DebugFileLimits,
+    pending: Vec<u8>,
+}
+
+impl DebugFile {
+    /// Opens the file for appending
+    pub fn open(path: &Path, limits: DebugFileLimits) -> io::Result<Self> {
+        let file = open_append(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            len,
+            limits,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Gets the file path
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Makes room for `len` more bytes, the current content is dropped or kept as `.old`
+    fn make_room(&mut self, len: u64) -> io::Result<()> {
+        let max = match self.limits {
+            DebugFileLimits::Unlimited => return Ok(()),
+            DebugFileLimits::Truncate(max) | DebugFileLimits::KeepOld(max) => max,
+        };
+        if self.len == 0 || self.len + len <= max {
+            return Ok(());
+        }
+        if let DebugFileLimits::KeepOld(_) = self.limits {
+            let mut old = OsString::from(self.path.as_os_str());
+            old.push(".old");
+            // renaming over an existing file fails on Windows
+            let _ = fs::remove_file(&old);
+            fs::rename(&self.path, &old)?;
+            self.file = open_append(&self.path)?;
+        }
+        self.file.set_len(0)?;
+        self.len = 0;
+        Ok(())
+    }
+
+    fn write_lines(&mut self, lines: &[u8]) -> io::Result<()> {
+        self.make_room(lines.len() as u64)?;
+        self.file.write_all(lines)?;
+        self.len += lines.len() as u64;
+        Ok(())
+    }
+}
+
+impl Write for DebugFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        if let Some(end) = self.pending.iter().rposition(|b| *b == b'\n') {
+            let lines: Vec<u8> = self.pending.drain(..=end).collect();
+            self.write_lines(&lines)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.write_lines(&pending)?;
+        }
+        self.file.flush()
+    }
+}
+
+impl Drop for DebugFile {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, TempPath};
+    use crate::{Extcap, ExtcapListener, IFace, LogConfig};
+
+    fn old_path(path: &Path) -> PathBuf {
+        let mut old = OsString::from(path.as_os_str());
+        old.push(".old");
+        PathBuf::from(old)
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap_or_default()
+    }
+
+    /// Writes the lines `line 0` to `line 99`, 8 or 9 bytes each
+    fn write_lines(path: &Path, limits: DebugFileLimits) {
+        let mut file = DebugFile::open(path, limits).unwrap();
+        for i in 0..100 {
+            writeln!(file, "line {}", i).unwrap();
+        }
+    }
+
+    fn numbers(content: &str) -> Vec<u32> {
+        content
+            .lines()
+            .map(|l| l.strip_prefix("line ").unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn old_generation_kept() {
+        let path = TempPath::new("debug.log");
+        write_lines(&path.0, DebugFileLimits::KeepOld(100));
+        let (old, current) = (read(&old_path(&path.0)), read(&path.0));
+        assert!(current.len() <= 100 && old.len() <= 100);
+        // the most recent lines follow each other across the two files
+        let lines = numbers(&(old + &current));
+        assert_eq!(lines.last(), Some(&99));
+        assert!(lines.windows(2).all(|w| w[1] == w[0] + 1), "{:?}", lines);
+        assert!(lines.len() > 11, "{:?}", lines);
+    }
+
+    #[test]
+    fn truncated_keeps_the_latest_lines() {
+        let path = TempPath::new("debug.log");
+        write_lines(&path.0, DebugFileLimits::Truncate(100));
+        let current = read(&path.0);
+        assert!(current.len() <= 100);
+        let lines = numbers(&current);
+        assert_eq!(lines.last(), Some(&99));
+        assert!(lines.windows(2).all(|w| w[1] == w[0] + 1), "{:?}", lines);
+        assert!(!old_path(&path.0).exists());
+    }
+
+    #[test]
+    fn unlimited_grows() {
+        let path = TempPath::new("debug.log");
+        write_lines(&path.0, DebugFileLimits::Unlimited);
+        assert_eq!(numbers(&read(&path.0)), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn appended_content_counts_to_the_limit() {
+        let path = TempPath::new("debug.log");
+        fs::write(&path.0, "x".repeat(95) + "\n").unwrap();
+        let mut file = DebugFile::open(&path.0, DebugFileLimits::KeepOld(100)).unwrap();
+        writeln!(file, "line 0").unwrap();
+        assert_eq!(read(&path.0), "line 0\n");
+        assert_eq!(read(&old_path(&path.0)).len(), 96);
+    }
+
+    #[test]
+    fn partial_line_waits_for_its_end() {
+        let path = TempPath::new("debug.log");
+        let mut file = DebugFile::open(&path.0, DebugFileLimits::default()).unwrap();
+        write!(file, "line 0\nline").unwrap();
+        assert_eq!(read(&path.0), "line 0\n");
+        write!(file, " 1").unwrap();
+        file.flush().unwrap();
+        assert_eq!(read(&path.0), "line 0\nline 1");
+    }
+
+    /// Logs through the handed out file like a logger would
+    struct Logging;
+
+    impl ExtcapListener for Logging {
+        fn init_logging(&mut self, _: &Extcap, config: LogConfig) {
+            let mut file = config.opened_debug_file.unwrap();
+            for i in 0..100 {
+                writeln!(file, "line {}", i).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn handed_out_file_limited() {
+        let path = TempPath::new("debug.log");
+        let mut extcap = Extcap::new("logdump");
+        let mut ifc = IFace::new("if1");
+        ifc.config_debug();
+        extcap.add_interface(ifc).unwrap();
+        extcap.debug_file_limits(DebugFileLimits::Truncate(100));
+        let args = [
+            "--extcap-dlts",
+            "--extcap-interface",
+            "if1",
+            "--debug-file",
+            path.as_str(),
+        ];
+        let (res, _) = test_support::run(extcap, Logging, &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+        let current = read(&path.0);
+        assert!(current.len() <= 100);
+        assert_eq!(numbers(&current).last(), Some(&99));
+    }
+}
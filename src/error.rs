@@ -2,14 +2,34 @@ use std::error::Error;
 use std::fmt;
 use std::io;
 
+use pcap_file::PcapError;
+
+use crate::stop::is_pipe_closed;
+
 #[derive(Debug)]
 enum ExtcapErrorKind {
     Io,
+    #[cfg(feature = "clap")]
     Clap,
+    #[cfg(not(feature = "clap"))]
+    InvalidArgs,
     Pcap,
     MissingInterface,
+    MissingFifo,
     InvalidInterface,
     UnknownStepRequested,
+    InvalidConfig,
+    InvalidState,
+    UnsupportedWsVersion,
+    #[cfg(feature = "async-api")]
+    ShutdownTimeout,
+    InvalidSentence,
+    #[cfg(feature = "ctrl-pipe")]
+    InvalidControl,
+    #[cfg(feature = "ctrl-pipe")]
+    ControlMsgTooLong,
+    #[cfg(feature = "ctrl-pipe")]
+    ControlPipeClosed,
     UserError,
 }
 
@@ -18,6 +38,7 @@ enum ExtcapErrorKind {
 pub struct ExtcapError {
     kind: ExtcapErrorKind,
     message: String,
+    source: Option<Box<dyn Error + Send + Sync>>,
 }
 
 impl ExtcapError {
@@ -25,6 +46,15 @@ impl ExtcapError {
         ExtcapError {
             kind: ExtcapErrorKind::MissingInterface,
             message: "Missing interface".to_string(),
+            source: None,
+        }
+    }
+
+    pub(crate) fn missing_fifo() -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::MissingFifo,
+            message: "Missing fifo".to_string(),
+            source: None,
         }
     }
 
@@ -32,6 +62,7 @@ impl ExtcapError {
         ExtcapError {
             kind: ExtcapErrorKind::InvalidInterface,
             message: format!("Invalid interface: {}", interface),
+            source: None,
         }
     }
 
@@ -39,6 +70,99 @@ impl ExtcapError {
         ExtcapError {
             kind: ExtcapErrorKind::UnknownStepRequested,
             message: "Unknown step requested".to_string(),
+            source: None,
+        }
+    }
+
+    pub(crate) fn invalid_config<T: ToString>(msg: T) -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::InvalidConfig,
+            message: msg.to_string(),
+            source: None,
+        }
+    }
+
+    #[cfg(not(feature = "clap"))]
+    pub(crate) fn invalid_args<T: ToString>(msg: T) -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::InvalidArgs,
+            message: msg.to_string(),
+            source: None,
+        }
+    }
+
+    pub(crate) fn invalid_state<T: ToString>(msg: T) -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::InvalidState,
+            message: msg.to_string(),
+            source: None,
+        }
+    }
+
+    pub(crate) fn unsupported_ws_version<T: ToString>(msg: T) -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::UnsupportedWsVersion,
+            message: msg.to_string(),
+            source: None,
+        }
+    }
+
+    #[cfg(feature = "async-api")]
+    pub(crate) fn shutdown_timeout(timeout: std::time::Duration) -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::ShutdownTimeout,
+            message: format!(
+                "Capture not finished within {:?} after stop, aborted",
+                timeout
+            ),
+            source: None,
+        }
+    }
+
+    pub(crate) fn invalid_sentence<T: ToString>(msg: T) -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::InvalidSentence,
+            message: msg.to_string(),
+            source: None,
+        }
+    }
+
+    #[cfg(feature = "ctrl-pipe")]
+    pub(crate) fn invalid_control(ctrl_num: u8, controls: usize) -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::InvalidControl,
+            message: format!(
+                "Invalid control number: {} ({} controls registered)",
+                ctrl_num, controls
+            ),
+            source: None,
+        }
+    }
+
+    #[cfg(feature = "ctrl-pipe")]
+    pub(crate) fn control_msg_too_long(len: usize) -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::ControlMsgTooLong,
+            message: format!("Control message data too long: {} bytes", len),
+            source: None,
+        }
+    }
+
+    #[cfg(feature = "ctrl-pipe")]
+    pub(crate) fn control_pipe_closed_after(sent: usize, total: usize) -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::ControlPipeClosed,
+            message: format!("Control pipe closed, {} of {} messages sent", sent, total),
+            source: None,
+        }
+    }
+
+    #[cfg(feature = "ctrl-pipe")]
+    pub(crate) fn control_pipe_closed() -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::ControlPipeClosed,
+            message: "Control pipe closed".to_string(),
+            source: None,
         }
     }
 
@@ -47,11 +171,62 @@ impl ExtcapError {
         ExtcapError {
             kind: ExtcapErrorKind::UserError,
             message: msg.to_string(),
+            source: None,
+        }
+    }
+
+    /// Create user error keeping the error as its source
+    pub fn user_source<E: Error + Send + Sync + 'static>(error: E) -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::UserError,
+            message: error.to_string(),
+            source: Some(Box::new(error)),
         }
     }
+
+    /// Returns `true` if the capture has been aborted after `Extcap::shutdown_timeout`
+    pub fn is_shutdown_timeout(&self) -> bool {
+        #[cfg(feature = "async-api")]
+        if let ExtcapErrorKind::ShutdownTimeout = self.kind {
+            return true;
+        }
+        false
+    }
+
+    /// Returns `true` if the capture has failed because Wireshark has closed the fifo
+    pub fn is_fifo_closed(&self) -> bool {
+        let mut source = Error::source(self);
+        while let Some(e) = source {
+            let closed = match (e.downcast_ref::<io::Error>(), e.downcast_ref::<PcapError>()) {
+                (Some(e), _) | (_, Some(PcapError::IoError(e))) => is_pipe_closed(e),
+                _ => false,
+            };
+            if closed {
+                return true;
+            }
+            source = e.source();
+        }
+        false
+    }
+
+    /// Returns `true` if Wireshark has stopped the capture by closing the fifo
+    ///
+    /// The capture run by `Extcap` finishes normally then, the check is for listeners
+    /// polling the result of their own writes.
+    pub fn is_capture_stopped(&self) -> bool {
+        self.is_fifo_closed()
+    }
+
+    pub(crate) fn get_message(&self) -> &str {
+        &self.message
+    }
 }
 
-impl Error for ExtcapError {}
+impl Error for ExtcapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|e| e as &(dyn Error + 'static))
+    }
+}
 
 impl fmt::Display for ExtcapError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -64,15 +239,18 @@ impl From<io::Error> for ExtcapError {
         ExtcapError {
             kind: ExtcapErrorKind::Io,
             message: error.to_string(),
+            source: Some(Box::new(error)),
         }
     }
 }
 
+#[cfg(feature = "clap")]
 impl From<clap::Error> for ExtcapError {
     fn from(error: clap::Error) -> Self {
         ExtcapError {
             kind: ExtcapErrorKind::Clap,
             message: format!("{:?}: {}", error.kind(), error),
+            source: Some(Box::new(error)),
         }
     }
 }
@@ -82,6 +260,17 @@ impl From<pcap_file::PcapError> for ExtcapError {
         ExtcapError {
             kind: ExtcapErrorKind::Pcap,
             message: error.to_string(),
+            source: Some(Box::new(error)),
+        }
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for ExtcapError {
+    fn from(error: Box<dyn Error + Send + Sync>) -> Self {
+        ExtcapError {
+            kind: ExtcapErrorKind::UserError,
+            message: error.to_string(),
+            source: Some(error),
         }
     }
 }
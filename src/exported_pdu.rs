@@ -0,0 +1,339 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Link type of the exported PDUs, `DataLink::WIRESHARK_UPPER_PDU` as a number
+pub const DLT_WIRESHARK_UPPER_PDU: u32 = 252;
+
+const TAG_END_OF_OPT: u16 = 0;
+const TAG_DISSECTOR_NAME: u16 = 12;
+const TAG_HEUR_DISSECTOR_NAME: u16 = 13;
+const TAG_DISSECTOR_TABLE_NAME: u16 = 14;
+const TAG_IPV4_SRC: u16 = 20;
+const TAG_IPV4_DST: u16 = 21;
+const TAG_IPV6_SRC: u16 = 22;
+const TAG_IPV6_DST: u16 = 23;
+const TAG_PORT_TYPE: u16 = 24;
+const TAG_SRC_PORT: u16 = 25;
+const TAG_DST_PORT: u16 = 26;
+const TAG_ORIG_FNO: u16 = 30;
+const TAG_DISSECTOR_TABLE_NAME_NUM_VAL: u16 = 32;
+const TAG_COL_PROT_TEXT: u16 = 33;
+const TAG_COL_INFO_TEXT: u16 = 36;
+
+/// Longest string tag value, padded to 4 bytes it still fits the 16-bit length
+const TAG_STR_MAX: usize = u16::MAX as usize & !3;
+
+/// Transport of the source and destination ports, `EXP_PDU_TAG_PORT_TYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortType {
+    /// SCTP ports
+    Sctp,
+    /// TCP ports
+    Tcp,
+    /// UDP ports
+    Udp,
+    /// DCCP ports
+    Dccp,
+}
+
+impl PortType {
+    fn value(self) -> u32 {
+        match self {
+            PortType::Sctp => 1,
+            PortType::Tcp => 2,
+            PortType::Udp => 3,
+            PortType::Dccp => 4,
+        }
+    }
+}
+
+/// Builder of an exported PDU, the packet data of `DLT_WIRESHARK_UPPER_PDU`
+///
+/// The tags tell Wireshark which dissector to hand the payload to and fill in the
+/// addresses and ports the payload was carried by, the way udpdump does. The tags
+/// are written in the order they were set, followed by the end of options tag and
+/// the payload.
+///
+/// ```
+/// # use extcap::{ExportedPdu, PortType};
+/// # use std::net::Ipv4Addr;
+/// let data = ExportedPdu::new()
+///     .dissector("json")
+///     .src_ipv4(Ipv4Addr::new(10, 0, 0, 1))
+///     .port_type(PortType::Udp)
+///     .src_port(5555)
+///     .payload(b"{}")
+///     .to_vec();
+/// assert_eq!(data.len(), 38);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ExportedPdu<'a> {
+    tags: Vec<u8>,
+    payload: &'a [u8],
+}
+
+impl<'a> ExportedPdu<'a> {
+    /// Creates the builder of a PDU without tags and payload
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the name of the dissector decoding the payload, `EXP_PDU_TAG_DISSECTOR_NAME`
+    pub fn dissector(self, name: &str) -> Self {
+        self.str_tag(TAG_DISSECTOR_NAME, name)
+    }
+
+    /// Sets the name of the heuristic dissector, `EXP_PDU_TAG_HEUR_DISSECTOR_NAME`
+    pub fn heur_dissector(self, name: &str) -> Self {
+        self.str_tag(TAG_HEUR_DISSECTOR_NAME, name)
+    }
+
+    /// Sets the name of the dissector table, `EXP_PDU_TAG_DISSECTOR_TABLE_NAME`
+    ///
+    /// Used together with `dissector_table_value`.
+    pub fn dissector_table(self, name: &str) -> Self {
+        self.str_tag(TAG_DISSECTOR_TABLE_NAME, name)
+    }
+
+    /// Sets the value looked up in the dissector table,
+    /// `EXP_PDU_TAG_DISSECTOR_TABLE_NAME_NUM_VAL`
+    pub fn dissector_table_value(self, value: u32) -> Self {
+        self.tag(TAG_DISSECTOR_TABLE_NAME_NUM_VAL, &value.to_be_bytes())
+    }
+
+    /// Sets the source IPv4 address, `EXP_PDU_TAG_IPV4_SRC`
+    pub fn src_ipv4(self, addr: Ipv4Addr) -> Self {
+        self.tag(TAG_IPV4_SRC, &addr.octets())
+    }
+
+    /// Sets the destination IPv4 address, `EXP_PDU_TAG_IPV4_DST`
+    pub fn dst_ipv4(self, addr: Ipv4Addr) -> Self {
+        self.tag(TAG_IPV4_DST, &addr.octets())
+    }
+
+    /// Sets the source IPv6 address, `EXP_PDU_TAG_IPV6_SRC`
+    pub fn src_ipv6(self, addr: Ipv6Addr) -> Self {
+        self.tag(TAG_IPV6_SRC, &addr.octets())
+    }
+
+    /// Sets the destination IPv6 address, `EXP_PDU_TAG_IPV6_DST`
+    pub fn dst_ipv6(self, addr: Ipv6Addr) -> Self {
+        self.tag(TAG_IPV6_DST, &addr.octets())
+    }
+
+    /// Sets the transport of the ports, `EXP_PDU_TAG_PORT_TYPE`
+    pub fn port_type(self, port_type: PortType) -> Self {
+        self.tag(TAG_PORT_TYPE, &port_type.value().to_be_bytes())
+    }
+
+    /// Sets the source port, `EXP_PDU_TAG_SRC_PORT`
+    pub fn src_port(self, port: u16) -> Self {
+        self.tag(TAG_SRC_PORT, &u32::from(port).to_be_bytes())
+    }
+
+    /// Sets the destination port, `EXP_PDU_TAG_DST_PORT`
+    pub fn dst_port(self, port: u16) -> Self {
+        self.tag(TAG_DST_PORT, &u32::from(port).to_be_bytes())
+    }
+
+    /// Sets the number of the frame the PDU was exported from, `EXP_PDU_TAG_ORIG_FNO`
+    pub fn orig_frame_number(self, number: u32) -> Self {
+        self.tag(TAG_ORIG_FNO, &number.to_be_bytes())
+    }
+
+    /// Sets the text of the protocol column, `EXP_PDU_TAG_COL_PROT_TEXT`
+    pub fn col_protocol(self, text: &str) -> Self {
+        self.str_tag(TAG_COL_PROT_TEXT, text)
+    }
+
+    /// Sets the text of the info column, `EXP_PDU_TAG_COL_INFO_TEXT`
+    pub fn col_info(self, text: &str) -> Self {
+        self.str_tag(TAG_COL_INFO_TEXT, text)
+    }
+
+    /// Sets the payload following the tags
+    pub fn payload(mut self, payload: &'a [u8]) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Builds the packet data: the tags, the end of options tag and the payload
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.tags.len() + 4 + self.payload.len());
+        data.extend_from_slice(&self.tags);
+        data.extend_from_slice(&TAG_END_OF_OPT.to_be_bytes());
+        data.extend_from_slice(&0u16.to_be_bytes());
+        data.extend_from_slice(self.payload);
+        data
+    }
+
+    /// Appends the string padded with zeros to 4 bytes, a string too long for the
+    /// tag is cut at a character boundary
+    fn str_tag(self, tag: u16, value: &str) -> Self {
+        let mut len = value.len().min(TAG_STR_MAX);
+        while !value.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut padded = value.as_bytes()[..len].to_vec();
+        padded.resize((len + 3) & !3, 0);
+        self.tag(tag, &padded)
+    }
+
+    fn tag(mut self, tag: u16, value: &[u8]) -> Self {
+        self.tags.extend_from_slice(&tag.to_be_bytes());
+        self.tags
+            .extend_from_slice(&(value.len() as u16).to_be_bytes());
+        self.tags.extend_from_slice(value);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const END: [u8; 4] = [0, 0, 0, 0];
+
+    /// Sets a tag of the builder
+    type Setter = fn(ExportedPdu<'static>) -> ExportedPdu<'static>;
+
+    /// Tag stream of the single tag followed by the end of options
+    fn single(tag: u16, value: &[u8]) -> Vec<u8> {
+        let mut data = tag.to_be_bytes().to_vec();
+        data.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        data.extend_from_slice(value);
+        data.extend_from_slice(&END);
+        data
+    }
+
+    #[test]
+    fn end_of_options_only() {
+        assert_eq!(ExportedPdu::new().to_vec(), END);
+        let data = ExportedPdu::new().payload(b"abc").to_vec();
+        assert_eq!(data, [0, 0, 0, 0, b'a', b'b', b'c']);
+    }
+
+    #[test]
+    fn string_tags() {
+        let cases: [(Setter, u16); 5] = [
+            (|p| p.dissector("json"), 12),
+            (|p| p.heur_dissector("json"), 13),
+            (|p| p.dissector_table("json"), 14),
+            (|p| p.col_protocol("json"), 33),
+            (|p| p.col_info("json"), 36),
+        ];
+        for (set, tag) in cases {
+            assert_eq!(set(ExportedPdu::new()).to_vec(), single(tag, b"json"));
+        }
+    }
+
+    #[test]
+    fn string_padded_to_4_bytes() {
+        assert_eq!(
+            ExportedPdu::new().dissector("http").to_vec(),
+            [0, 12, 0, 4, b'h', b't', b't', b'p', 0, 0, 0, 0]
+        );
+        assert_eq!(
+            ExportedPdu::new().dissector("dns").to_vec(),
+            [0, 12, 0, 4, b'd', b'n', b's', 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            ExportedPdu::new().dissector("sip.x").to_vec(),
+            [0, 12, 0, 8, b's', b'i', b'p', b'.', b'x', 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(ExportedPdu::new().dissector("").to_vec(), single(12, b""));
+    }
+
+    #[test]
+    fn long_string_truncated() {
+        let long = "a".repeat(70000);
+        let data = ExportedPdu::new().col_info(&long).to_vec();
+        assert_eq!(&data[..4], [0, 36, 0xff, 0xfc]);
+        assert_eq!(data.len(), 4 + 65532 + 4);
+        assert!(data[4..4 + 65532].iter().all(|b| *b == b'a'));
+        assert_eq!(data[4 + 65532..], END);
+    }
+
+    #[test]
+    fn long_string_cut_at_char_boundary() {
+        // 'é' is 2 bytes, the limit of 65532 falls inside the last one
+        let long = format!("a{}", "é".repeat(40000));
+        let data = ExportedPdu::new().col_info(&long).to_vec();
+        assert_eq!(&data[..4], [0, 36, 0xff, 0xfc]);
+        let value = &data[4..4 + 65532];
+        assert_eq!(value[65531], 0);
+        assert!(std::str::from_utf8(&value[..65531]).is_ok());
+    }
+
+    #[test]
+    fn address_tags() {
+        let v4 = Ipv4Addr::new(10, 0, 0, 1);
+        assert_eq!(
+            ExportedPdu::new().src_ipv4(v4).to_vec(),
+            [0, 20, 0, 4, 10, 0, 0, 1, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            ExportedPdu::new().dst_ipv4(v4).to_vec(),
+            [0, 21, 0, 4, 10, 0, 0, 1, 0, 0, 0, 0]
+        );
+        let v6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let octets = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        assert_eq!(
+            ExportedPdu::new().src_ipv6(v6).to_vec(),
+            single(22, &octets)
+        );
+        assert_eq!(
+            ExportedPdu::new().dst_ipv6(v6).to_vec(),
+            single(23, &octets)
+        );
+    }
+
+    #[test]
+    fn number_tags() {
+        let pdu = ExportedPdu::new;
+        assert_eq!(
+            pdu().port_type(PortType::Sctp).to_vec(),
+            single(24, &[0, 0, 0, 1])
+        );
+        assert_eq!(
+            pdu().port_type(PortType::Tcp).to_vec(),
+            single(24, &[0, 0, 0, 2])
+        );
+        assert_eq!(
+            pdu().port_type(PortType::Udp).to_vec(),
+            single(24, &[0, 0, 0, 3])
+        );
+        assert_eq!(
+            pdu().port_type(PortType::Dccp).to_vec(),
+            single(24, &[0, 0, 0, 4])
+        );
+        assert_eq!(
+            pdu().src_port(5555).to_vec(),
+            single(25, &[0, 0, 0x15, 0xb3])
+        );
+        assert_eq!(pdu().dst_port(53).to_vec(), single(26, &[0, 0, 0, 53]));
+        let fno = pdu().orig_frame_number(0x01020304).to_vec();
+        assert_eq!(fno, single(30, &[1, 2, 3, 4]));
+        let table = pdu().dissector_table_value(0xdeadbeef).to_vec();
+        assert_eq!(table, single(32, &[0xde, 0xad, 0xbe, 0xef]));
+    }
+
+    #[test]
+    fn tags_in_order_before_payload() {
+        let data = ExportedPdu::new()
+            .dissector("json")
+            .src_ipv4(Ipv4Addr::new(10, 0, 0, 1))
+            .port_type(PortType::Udp)
+            .src_port(5555)
+            .payload(b"{}")
+            .to_vec();
+        let expected = [
+            0, 12, 0, 4, b'j', b's', b'o', b'n', // dissector
+            0, 20, 0, 4, 10, 0, 0, 1, // source address
+            0, 24, 0, 4, 0, 0, 0, 3, // port type
+            0, 25, 0, 4, 0, 0, 0x15, 0xb3, // source port
+            0, 0, 0, 0, // end of options
+            b'{', b'}',
+        ];
+        assert_eq!(data, expected);
+    }
+}
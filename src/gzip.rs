@@ -0,0 +1,198 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::debug;
+
+/// Returns `true` for the `.gz` files
+pub(crate) fn is_gzip_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+}
+
+/// File counting the bytes written
+struct CountingFile {
+    file: File,
+    written: Arc<AtomicU64>,
+}
+
+impl Write for CountingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.file.write(buf)?;
+        self.written.fetch_add(len as u64, Ordering::Relaxed);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Gzip compressed capture file
+///
+/// The gzip stream is finished when dropped, the file is synced to the disk then
+/// if requested.
+pub(crate) struct GzFile {
+    encoder: GzEncoder<CountingFile>,
+    sync: bool,
+}
+
+impl GzFile {
+    /// `compressed` counts the bytes written to the file
+    pub(crate) fn new(file: File, compressed: Arc<AtomicU64>, sync: bool) -> Self {
+        let file = CountingFile {
+            file,
+            written: compressed,
+        };
+        Self {
+            encoder: GzEncoder::new(file, Compression::default()),
+            sync,
+        }
+    }
+}
+
+impl Write for GzFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+impl Drop for GzFile {
+    fn drop(&mut self) {
+        if let Err(e) = self.encoder.try_finish() {
+            debug!("gzip stream not finished: {}", e);
+            return;
+        }
+        if self.sync {
+            if let Err(e) = self.encoder.get_ref().file.sync_all() {
+                debug!("gzip file not synced: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Mutex;
+
+    use flate2::read::GzDecoder;
+    use pcap_file::pcap::PcapReader;
+
+    use super::*;
+    use crate::capture::is_gzip_target;
+    use crate::test_support::{self, TempPath};
+    use crate::{
+        CaptureStats, Extcap, ExtcapListener, ExtcapResult, IFace, PacketSink, RotationPolicy,
+    };
+
+    /// Writes three packets, keeps the statistics of the flushed sink
+    #[derive(Default)]
+    struct ThreePackets(Arc<Mutex<Option<CaptureStats>>>);
+
+    impl ExtcapListener for ThreePackets {
+        fn capture2(&mut self, _: &Extcap, _: &IFace, mut sink: PacketSink) -> ExtcapResult<()> {
+            for i in 0..3u8 {
+                sink.write_now(&[i; 4])?;
+            }
+            sink.flush()?;
+            *self.0.lock().unwrap() = Some(sink.get_stats());
+            Ok(())
+        }
+    }
+
+    fn capture(fifo: &Path, gzip: bool, rotation: Option<RotationPolicy>) -> CaptureStats {
+        let mut extcap = Extcap::new("gzdump");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        extcap.gzip(gzip);
+        if let Some(policy) = rotation {
+            extcap.file_rotation(policy);
+        }
+        let listener = ThreePackets::default();
+        let stats = listener.0.clone();
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.to_str().unwrap(),
+        ];
+        let (res, _) = test_support::run(extcap, listener, &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+        let stats = stats.lock().unwrap().take();
+        stats.unwrap()
+    }
+
+    /// Decompresses the file, returns the packet data
+    fn read_gz(path: &Path) -> Vec<Vec<u8>> {
+        let file = fs::read(path).unwrap();
+        assert_eq!(&file[..2], [0x1f, 0x8b], "{} not gzip", path.display());
+        let mut reader = PcapReader::new(GzDecoder::new(&file[..])).unwrap();
+        let mut packets = Vec::new();
+        while let Some(pkt) = reader.next_packet() {
+            packets.push(pkt.unwrap().data.to_vec());
+        }
+        packets
+    }
+
+    fn three_packets() -> Vec<Vec<u8>> {
+        (0..3u8).map(|i| vec![i; 4]).collect()
+    }
+
+    #[test]
+    fn gz_file_decompresses_to_the_capture() {
+        let fifo = TempPath::new("capture.pcap.gz");
+        let stats = capture(&fifo.0, false, None);
+        assert_eq!(read_gz(&fifo.0), three_packets());
+        // pcap header 24 B, each packet 16 B record header and 4 B data
+        assert_eq!(stats.bytes, 84);
+        assert!(stats.compressed_bytes > 0);
+        assert!(stats.compressed_bytes <= fs::metadata(&fifo.0).unwrap().len());
+    }
+
+    #[test]
+    fn gzip_requested_without_extension() {
+        let fifo = TempPath::new("capture.pcap");
+        capture(&fifo.0, true, None);
+        assert_eq!(read_gz(&fifo.0), three_packets());
+    }
+
+    #[test]
+    fn plain_file_not_compressed() {
+        let fifo = TempPath::new("capture.pcap");
+        let stats = capture(&fifo.0, false, None);
+        assert_eq!(stats.compressed_bytes, 0);
+        let mut reader = PcapReader::new(fs::File::open(&fifo.0).unwrap()).unwrap();
+        assert!(reader.next_packet().is_some());
+    }
+
+    #[test]
+    fn rotated_files_compressed_each() {
+        let fifo = TempPath::new("capture.pcap.gz");
+        capture(&fifo.0, false, Some(RotationPolicy::new().max_bytes(40)));
+        let mut files: Vec<_> = fs::read_dir(fifo.0.parent().unwrap())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        files.sort();
+        assert_eq!(files.len(), 3, "{:?}", files);
+        assert!(files.iter().all(|f| is_gzip_path(f)), "{:?}", files);
+        let packets: Vec<_> = files.iter().flat_map(|f| read_gz(f)).collect();
+        assert_eq!(packets, three_packets());
+    }
+
+    #[test]
+    fn stdout_never_compressed() {
+        assert!(!is_gzip_target(Path::new("-"), true));
+        assert!(is_gzip_target(Path::new("capture.pcap.GZ"), false));
+        assert!(!is_gzip_target(Path::new("capture.pcap"), false));
+    }
+}
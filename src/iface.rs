@@ -1,26 +1,53 @@
 use pcap_file::DataLink;
 
-use crate::arg::IfArg;
-use crate::print_opt_value;
+use crate::arg::{is_valid_ident, IfArg};
+use crate::opts::{OPT_ALSO_SAVE, OPT_DEBUG, OPT_DEBUG_FILE};
+use crate::self_check::{check_text, Issue};
+use crate::text::{TextKind, Texts};
+use crate::{ExtcapError, ExtcapResult};
 
 /// Interface representation
-#[derive(Default)]
+///
+/// Arguments are numbered by their position within the interface. Wireshark correlates
+/// the values printed by `--extcap-reload-option` with the `{number=}` printed by
+/// `--extcap-config` in an earlier process invocation, so the set and order of arguments
+/// must be the same for every invocation (do not make them depend on the passed options).
 pub struct IFace<'a> {
     interface: String,
     descr: Option<String>,
-    dlt: u32,
+    dlt: DataLink,
     dltname: Option<String>,
     dltdescr: Option<String>,
+    snaplen: Option<u32>,
     args: Vec<IfArg<'a>>,
     debug: bool,
+    aliases: Vec<String>,
+}
+
+impl Default for IFace<'_> {
+    fn default() -> Self {
+        Self {
+            interface: String::new(),
+            descr: None,
+            dlt: DataLink::USER0, // DLT_USER0 default
+            dltname: None,
+            dltdescr: None,
+            snaplen: None,
+            args: Vec::new(),
+            debug: false,
+            aliases: Vec::new(),
+        }
+    }
 }
 
 impl<'a> IFace<'a> {
     /// Creates a new instance of `IFace` using a string name
+    ///
+    /// The name must consist of `[A-Za-z0-9._-]` without a leading dash,
+    /// it is checked when the interface is added.
     pub fn new(interface: &str) -> Self {
         Self {
             interface: interface.to_owned(),
-            dlt: DataLink::USER0.into(), // DLT_USER0 default
             ..Default::default()
         }
     }
@@ -30,18 +57,70 @@ impl<'a> IFace<'a> {
         &self.interface
     }
 
+    /// Gets the aliases
+    pub fn get_aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    /// Returns `true` if the name is the interface name or one of its aliases
+    pub(crate) fn has_name(&self, name: &str) -> bool {
+        self.interface == name || self.aliases.iter().any(|a| a == name)
+    }
+
+    /// Gets the description
+    pub fn get_description(&self) -> Option<&str> {
+        self.descr.as_deref()
+    }
+
+    /// Gets the DLT
+    pub fn get_dlt(&self) -> u32 {
+        self.dlt.into()
+    }
+
+    /// Gets the DLT as `DataLink`
+    pub fn get_data_link(&self) -> DataLink {
+        self.dlt
+    }
+
     /// Sets the description
     pub fn description(mut self, descr: &str) -> Self {
         self.descr = Some(descr.to_owned());
         self
     }
 
-    /// Sets the DLT
+    /// Adds an alias, e.g. a former interface name
+    ///
+    /// Aliases are accepted by `--extcap-interface` but not listed by `--extcap-interfaces`.
+    pub fn alias(mut self, alias: &str) -> Self {
+        self.aliases.push(alias.to_owned());
+        self
+    }
+
+    /// Sets the DLT number
     pub fn dlt(mut self, dlt: u32) -> Self {
+        self.dlt = DataLink::from(dlt);
+        self
+    }
+
+    /// Sets the DLT using a `DataLink` constant, e.g. `DataLink::USER10`
+    pub fn dlt_data_link(mut self, dlt: DataLink) -> Self {
         self.dlt = dlt;
         self
     }
 
+    /// Sets the snaplen of the default capture header, 65535 if not set
+    ///
+    /// The packets written to `PacketSink` are truncated to it.
+    pub fn snaplen(mut self, snaplen: u32) -> Self {
+        self.snaplen = Some(snaplen);
+        self
+    }
+
+    /// Gets the snaplen set by `snaplen`
+    pub fn get_snaplen(&self) -> Option<u32> {
+        self.snaplen
+    }
+
     /// Sets the DLT name
     pub fn dltname(mut self, dltname: &str) -> Self {
         self.dltname = Some(dltname.to_owned());
@@ -55,20 +134,70 @@ impl<'a> IFace<'a> {
     }
 
     /// Adds argument
-    pub fn add_arg(&mut self, mut arg: IfArg<'a>) {
-        arg.set_number(self.args.len());
-        self.args.push(arg);
+    ///
+    /// Arguments added after `config_debug()` are placed before the debug arguments
+    /// so the debug arguments stay last.
+    pub fn add_arg(&mut self, arg: IfArg<'a>) {
+        let index = if self.debug {
+            self.get_arg_idx(OPT_DEBUG).unwrap_or(self.args.len())
+        } else {
+            self.args.len()
+        };
+        self.insert_arg(index, arg);
+    }
+
+    /// Inserts argument at the given position, an index past the end appends
+    ///
+    /// All arguments are renumbered including references within their values.
+    pub fn insert_arg(&mut self, index: usize, arg: IfArg<'a>) {
+        let index = index.min(self.args.len());
+        self.args.insert(index, arg);
+        self.renumber_args(index);
+    }
+
+    /// Moves argument to the given position, an index past the end moves it last
+    ///
+    /// All arguments are renumbered including references within their values.
+    /// Returns `false` if there is no argument with the given name.
+    pub fn move_arg(&mut self, name: &str, new_index: usize) -> bool {
+        let index = match self.get_arg_idx(name) {
+            Some(index) => index,
+            None => return false,
+        };
+        let arg = self.args.remove(index);
+        let new_index = new_index.min(self.args.len());
+        self.args.insert(new_index, arg);
+        self.renumber_args(index.min(new_index));
+        true
+    }
+
+    fn renumber_args(&mut self, from: usize) {
+        for (number, arg) in self.args.iter_mut().enumerate().skip(from) {
+            arg.set_number(number);
+        }
     }
 
     pub(crate) fn get_args(&self) -> &[IfArg<'a>] {
         &self.args
     }
 
+    /// Iterates over the arguments
+    ///
+    /// The order matches the argument numbering and the order in which the arguments are emitted.
+    pub fn args(&self) -> impl Iterator<Item = &IfArg<'a>> {
+        self.args.iter()
+    }
+
+    /// Gets the argument by its name
+    pub fn arg(&self, name: &str) -> Option<&IfArg<'a>> {
+        self.args.iter().find(|x| x.get_name() == name)
+    }
+
     pub(crate) fn get_arg_idx(&self, arg: &str) -> Option<usize> {
         self.args.iter().position(|x| x.get_name() == arg)
     }
 
-    pub(crate) fn get_arg(&self, aidx: usize) -> &IfArg {
+    pub(crate) fn get_arg(&self, aidx: usize) -> &IfArg<'_> {
         &self.args[aidx]
     }
 
@@ -76,6 +205,88 @@ impl<'a> IFace<'a> {
         &mut self.args[aidx]
     }
 
+    pub(crate) fn validate(&self) -> ExtcapResult<()> {
+        if !is_valid_ident(&self.interface) {
+            return Err(ExtcapError::invalid_config(format!(
+                "Invalid interface name '{}', only [A-Za-z0-9._-] without a leading dash is allowed",
+                self.interface
+            )));
+        }
+        if self.snaplen == Some(0) {
+            return Err(ExtcapError::invalid_config(format!(
+                "Interface '{}': snaplen 0",
+                self.interface
+            )));
+        }
+        if let Some(alias) = self.aliases.iter().find(|a| !is_valid_ident(a)) {
+            return Err(ExtcapError::invalid_config(format!(
+                "Interface '{}': invalid alias '{}', only [A-Za-z0-9._-] without a leading dash is allowed",
+                self.interface, alias
+            )));
+        }
+        for arg in &self.args {
+            let other = match arg.get_depends_on() {
+                Some(other) => other,
+                None => continue,
+            };
+            if other == arg.get_name() || self.arg(other).is_none() {
+                return Err(ExtcapError::invalid_config(format!(
+                    "Interface '{}' arg '{}': depends on unknown arg '{}'",
+                    self.interface,
+                    arg.get_name(),
+                    other
+                )));
+            }
+        }
+        self.args
+            .iter()
+            .try_for_each(|arg| arg.validate(&self.interface))
+    }
+
+    /// Collects the issues of the interface and its arguments
+    pub(crate) fn self_check(&self, issues: &mut Vec<Issue>) {
+        let what = format!("Interface '{}'", self.interface);
+        for name in std::iter::once(&self.interface).chain(self.aliases.iter()) {
+            if !is_valid_ident(name) {
+                issues.push(Issue::error(format!(
+                    "{}: invalid name '{}', only [A-Za-z0-9._-] without a leading dash is allowed",
+                    what, name
+                )));
+            }
+        }
+        if self.descr.is_none() {
+            issues.push(Issue::warning(format!("{}: no description", what)));
+        }
+        check_text(
+            issues,
+            &format!("{} description", what),
+            self.descr.as_deref(),
+        );
+        check_text(
+            issues,
+            &format!("{} DLT name", what),
+            self.dltname.as_deref(),
+        );
+        check_text(
+            issues,
+            &format!("{} DLT description", what),
+            self.dltdescr.as_deref(),
+        );
+        for (idx, arg) in self.args.iter().enumerate() {
+            if self.args[..idx]
+                .iter()
+                .any(|a| a.get_name() == arg.get_name())
+            {
+                issues.push(Issue::error(format!(
+                    "{}: duplicate arg '{}'",
+                    what,
+                    arg.get_name()
+                )));
+            }
+            arg.self_check(&self.interface, issues);
+        }
+    }
+
     pub(crate) fn has_reloadable_arg(&self) -> bool {
         self.args.iter().any(IfArg::has_reload)
     }
@@ -84,44 +295,149 @@ impl<'a> IFace<'a> {
         self.debug
     }
 
+    /// Configures the `also-save` argument, a file the capture is copied to
+    ///
+    /// Users can send the exact capture seen by Wireshark, see `Extcap::tee_to`.
+    pub fn config_also_save(&mut self) {
+        if self.get_arg_idx(OPT_ALSO_SAVE).is_some() {
+            return;
+        }
+        self.add_arg(
+            IfArg::new_fileselect(OPT_ALSO_SAVE)
+                .display("Also save to file")
+                .tooltip("Set a file where a copy of the capture is written")
+                .mustexist(false),
+        );
+    }
+
     /// Configures debug arguments `debug` and `debug-file`
+    ///
+    /// Can be called at any time, the debug arguments are kept last.
     pub fn config_debug(&mut self) {
         if self.debug {
             return;
         }
-        self.debug = true;
         self.add_arg(
-            IfArg::new_boolflag("debug")
+            IfArg::new_boolflag(OPT_DEBUG)
                 .display("Run in debug mode")
                 .default(&"false")
                 .tooltip("Print debug messages")
                 .group("Debug"),
         );
         self.add_arg(
-            IfArg::new_string("debug-file")
+            IfArg::new_string(OPT_DEBUG_FILE)
                 .display("Use a file for debug")
                 .tooltip("Set a file where the debug messages are written")
                 .group("Debug"),
         );
+        self.debug = true;
     }
 
-    pub(crate) fn print_iface(&self) {
-        print!("interface {{value={}}}", self.interface);
-        print_opt_value("display", &self.descr);
-        println!();
+    pub(crate) fn write_iface(&self, out: &mut String, texts: &Texts) {
+        out.push_str(&format!("interface {{value={}}}", self.interface));
+        texts.write_opt(out, "display", TextKind::Display, self.descr.as_deref());
+        out.push('\n');
     }
 
-    pub(crate) fn print_dlt_list(&self) {
-        print!(
+    pub(crate) fn write_dlt_list(&self, out: &mut String, texts: &Texts) {
+        out.push_str(&format!(
             "dlt {{number={}}}{{name={}}}",
-            self.dlt,
+            self.get_dlt(),
             self.dltname.as_ref().unwrap_or(&self.interface)
+        ));
+        texts.write_opt(out, "display", TextKind::Display, self.dltdescr.as_deref());
+        out.push('\n');
+    }
+
+    pub(crate) fn write_arg_list(&self, out: &mut String, reload_placeholder: &str, texts: &Texts) {
+        self.args
+            .iter()
+            .for_each(|arg| arg.write_arg(out, reload_placeholder, texts));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IfArgVal;
+
+    fn numbers(ifc: &IFace) -> Vec<(String, usize)> {
+        ifc.args()
+            .map(|arg| (arg.get_name().to_owned(), arg.get_number()))
+            .collect()
+    }
+
+    fn names<'a>(ifc: &'a IFace) -> Vec<&'a str> {
+        ifc.args().map(IfArg::get_name).collect()
+    }
+
+    fn selector(name: &str) -> IfArg<'_> {
+        let mut arg = IfArg::new_selector(name);
+        arg.add_val(IfArgVal::new("v"));
+        arg
+    }
+
+    fn emitted(ifc: &IFace) -> String {
+        let mut out = String::new();
+        ifc.write_arg_list(&mut out, "", &Texts::default());
+        out
+    }
+
+    #[test]
+    fn insert_renumbers_following_args() {
+        let mut ifc = IFace::new("if1");
+        ifc.add_arg(selector("a"));
+        ifc.add_arg(selector("c"));
+        ifc.insert_arg(1, selector("b"));
+        ifc.insert_arg(99, selector("d"));
+        assert_eq!(names(&ifc), ["a", "b", "c", "d"]);
+        for (number, (_, got)) in numbers(&ifc).into_iter().enumerate() {
+            assert_eq!(got, number);
+        }
+        assert_eq!(
+            emitted(&ifc),
+            "arg {number=0}{call=--a}{display=a}{type=selector}\n\
+             value {arg=0}{value=v}{display=v}\n\
+             arg {number=1}{call=--b}{display=b}{type=selector}\n\
+             value {arg=1}{value=v}{display=v}\n\
+             arg {number=2}{call=--c}{display=c}{type=selector}\n\
+             value {arg=2}{value=v}{display=v}\n\
+             arg {number=3}{call=--d}{display=d}{type=selector}\n\
+             value {arg=3}{value=v}{display=v}\n"
         );
-        print_opt_value("display", &self.dltdescr);
-        println!();
     }
 
-    pub(crate) fn print_arg_list(&self) {
-        self.args.iter().for_each(IfArg::print_arg);
+    #[test]
+    fn move_renumbers_affected_args() {
+        let mut ifc = IFace::new("if1");
+        for name in ["a", "b", "c", "d"] {
+            ifc.add_arg(selector(name));
+        }
+        assert!(ifc.move_arg("d", 1));
+        assert_eq!(names(&ifc), ["a", "d", "b", "c"]);
+        assert!(ifc.move_arg("a", 99));
+        assert_eq!(names(&ifc), ["d", "b", "c", "a"]);
+        assert!(!ifc.move_arg("missing", 0));
+        let out = emitted(&ifc);
+        for (number, name) in names(&ifc).into_iter().enumerate() {
+            let arg = format!("arg {{number={}}}{{call=--{}}}", number, name);
+            let val = format!(
+                "{}{{display={}}}{{type=selector}}\nvalue {{arg={}}}",
+                arg, name, number
+            );
+            assert!(out.contains(&val), "{}\n{}", val, out);
+        }
+    }
+
+    #[test]
+    fn debug_args_stay_last() {
+        let mut ifc = IFace::new("if1");
+        ifc.add_arg(selector("a"));
+        ifc.config_debug();
+        ifc.add_arg(selector("b"));
+        ifc.config_debug();
+        assert_eq!(names(&ifc), ["a", "b", OPT_DEBUG, OPT_DEBUG_FILE]);
+        assert_eq!(ifc.arg("b").unwrap().get_number(), 1);
+        assert_eq!(ifc.arg(OPT_DEBUG_FILE).unwrap().get_number(), 3);
     }
 }
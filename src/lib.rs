@@ -9,151 +9,383 @@
 //!
 //! ## Quick Example
 //! ```
-//! use extcap::{Extcap, ExtcapListener, ExtcapResult, ExtcapWriter, IFace};
-//! use pcap_file::{pcap::PcapHeader, DataLink, PcapWriter};
+//! use extcap::pcap_file::DataLink;
+//! use extcap::{Extcap, ExtcapListener, ExtcapResult, IFace, PacketSink};
 //!
 //! struct HelloDump {}
 //!
 //! impl ExtcapListener for HelloDump {
-//!     fn capture_header(&mut self, extcap: &Extcap, ifc: &IFace) -> PcapHeader {
-//!         PcapHeader { datalink: DataLink::USER10, ..Default::default() }
-//!     }
-//!
-//!     fn capture(&mut self, extcap: &Extcap, ifc: &IFace, mut pcap_writer: PcapWriter<ExtcapWriter>) -> ExtcapResult<()> {
+//!     fn capture2(&mut self, extcap: &Extcap, ifc: &IFace, mut sink: PacketSink) -> ExtcapResult<()> {
 //!         let pkt = b"Hello Extcap!";
-//!         pcap_writer.write(0, 0, pkt, pkt.len() as u32);
+//!         sink.write_now(pkt)?;
 //!         Ok(())
 //!     }
 //! }
 //!
 //! fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!     let mut ex = Extcap::new("hellodump");
-//!     ex.add_interface(IFace::new("helloif"));
+//!     ex.add_interface(IFace::new("helloif").dlt_data_link(DataLink::USER10))?;
 //!     ex.run(HelloDump {});
 //!     Ok(())
 //! }
 //! ```
 //! More examples can be found in the `examples` directory
 //!
+//! ## Async runtime
+//! [`Extcap::run_async`] doesn't spawn any tasks and works on the tokio current-thread
+//! runtime, `#[tokio::main(flavor = "current_thread")]` avoids the worker threads for
+//! a small extcap, see the `test_serial_dump` example. The control pipes are read and
+//! written through `tokio::fs` which runs the blocking pipe I/O on the blocking pool.
+//! The packets are written to the fifo by the task polling `run_async`, a write blocks
+//! only while Wireshark isn't reading, stalling the listener's tasks on the
+//! current-thread runtime until it catches up.
+//!
+//! ## Migrating from 0.4
+//! The capture API no longer exposes `pcap_file` types, the old methods keep working
+//! until the next release:
+//! - implement [`ExtcapListener::capture_header2`] returning [`CaptureHeader`] instead of
+//!   `capture_header` returning `PcapHeader`, the `datalink` becomes `link_type` and
+//!   the magic number becomes `ts_resolution`
+//! - implement [`ExtcapListener::capture2`] receiving [`PacketSink`], or
+//!   [`ExtcapListener::capture_dump`] borrowing it as [`ExtcapDumper`], instead of
+//!   `capture` receiving `PcapWriter<ExtcapWriter>`, `write` takes the same arguments
+//! - `CtrlPipes` is a struct instead of a tuple, use `CtrlPipes::into_parts` to destructure it
+//! - `ExtcapWriter` has the `EWCustom` variant used by [`PacketSink::from_writer`]
+//! - the path options (`--fifo`, `--debug-file`, `--extcap-control-in/out`) are kept as passed,
+//!   use [`ExtcapMatches::value_path`] to get them, `CaptureContext::get_fifo` returns a `Path`
+//! - `pcap_file` 2 is re-exported as [`pcap_file`], use it instead of a dependency of
+//!   its own to keep the versions in sync; the deprecated `capture_header` and `capture`
+//!   take its `PcapHeader` and `PcapWriter`, packets are written as `PcapPacket`
+//! - [`PacketBuilder::build`] returns a `PcapPacket`, [`ExtcapPacket`] carries the
+//!   timestamp as a `Duration` like it
+//!
 
 #![deny(missing_docs)]
 #![deny(warnings)]
 
 use std::collections::HashSet;
+#[cfg(feature = "standalone")]
+use std::ffi::OsStr;
+use std::ffi::OsString;
 use std::fmt::Display;
 use std::fs::File;
-use std::io::{self, Stdout, Write};
+use std::io::{self, BufWriter, IoSlice, Stdout, Write};
+use std::panic::Location;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+#[cfg(feature = "ctrl-pipe")]
+use std::sync::atomic::Ordering;
+#[cfg(feature = "async-api")]
+use std::time::{Duration, Instant};
 
-use clap::{Arg, ArgGroup, ArgMatches, Command};
+#[cfg(feature = "clap")]
+use clap::ArgMatches;
 #[cfg(feature = "ctrl-pipe")]
 use futures::future;
 #[cfg(feature = "async-api")]
-use futures::{channel::mpsc::Receiver, stream::StreamExt};
-use log::{debug, warn};
-#[cfg(feature = "async-api")]
-use pcap_file::pcap::Packet;
+use futures::{channel::mpsc::Receiver, future::FutureExt, stream::StreamExt};
+use log::{debug, info, warn};
 use pcap_file::pcap::{PcapHeader, PcapWriter};
 
+/// The `pcap_file` version the capture API is built on
+pub use pcap_file;
+
+mod capture;
+use crate::capture::{
+    create_fifo_writer, create_pcap_writer, create_pcapng_writer, SinkOptions, WriteProgress,
+};
+
+#[cfg(feature = "gzip")]
+mod gzip;
+mod rotation;
+mod tee;
+pub use crate::capture::{
+    CaptureHeader, CaptureHeaderBuilder, CaptureStats, ExtcapDumper, FlushPolicy, PacketSink,
+    ProgressInterval, SinkFactory, TsResolution,
+};
+pub use crate::rotation::RotationPolicy;
+use crate::tee::TeeWriter;
+
 mod error;
 pub use crate::error::ExtcapError;
 
+mod stop;
+use crate::stop::broken_pipe;
+pub use crate::stop::StopToken;
+
+#[cfg(feature = "async-api")]
+mod async_packet;
+#[cfg(feature = "async-api")]
+pub use crate::async_packet::{ExtcapPacket, PacketPool};
+
+mod pacing;
+pub use crate::pacing::Pacing;
+
+mod pause;
+pub use crate::pause::PauseState;
+
+mod clock;
+pub use crate::clock::Clock;
+
+mod packet;
+pub use crate::packet::{Direction, PacketBuilder};
+
+mod exported_pdu;
+pub use crate::exported_pdu::{ExportedPdu, PortType, DLT_WIRESHARK_UPPER_PDU};
+
+mod pcapng;
+pub use crate::pcapng::{
+    InterfaceDescription, OutputFormat, PcapNgWriter, SectionMetadata, SECRETS_TLS_KEY_LOG,
+    SECRETS_WIREGUARD_KEY_LOG,
+};
+
+mod listener;
+pub use crate::listener::ExtcapListenerWithError;
+
+#[cfg(feature = "async-api")]
+mod capture_task;
+#[cfg(feature = "async-api")]
+use crate::capture_task::{capture_with_task, with_shutdown_timeout};
+#[cfg(feature = "async-api")]
+pub use crate::capture_task::{CaptureTask, CaptureTaskHandle};
+
+#[cfg(feature = "async-api")]
+mod metrics;
+#[cfg(feature = "async-api")]
+pub use crate::metrics::{CaptureChannelMetrics, ChannelMetrics};
+#[cfg(feature = "async-api")]
+use crate::metrics::{ChannelCounters, MetricsCounters, SharedMetrics};
+
+mod context;
+pub use crate::context::CaptureContext;
+
+mod log_config;
+pub use crate::log_config::LogConfig;
+
+mod debug_file;
+pub use crate::debug_file::{DebugFile, DebugFileLimits};
+
+mod matches;
+pub use crate::matches::ExtcapMatches;
+
+mod cli;
+use crate::cli::{CliSpec, OptSpec, Parsed};
+
+mod ws_version;
+pub use crate::ws_version::MissingWsVersion;
+
+mod self_check;
+use crate::self_check::is_help_uri;
+pub use crate::self_check::{Issue, Severity};
+
+pub mod testing;
+
 mod iface;
 pub use crate::iface::IFace;
 
 mod arg;
+use crate::arg::{parse_f64, sanitize_display};
 pub use crate::arg::{IfArg, IfArgType, IfArgVal};
 
+#[cfg(feature = "process-guard")]
+mod process_guard;
+#[cfg(feature = "process-guard")]
+pub use crate::process_guard::ProcessGuard;
+
+#[cfg(feature = "ssh")]
+pub mod sources;
+
+mod reload_cache;
+mod reload_timeout;
+pub use crate::reload_timeout::ReloadTask;
+
+#[cfg(test)]
+mod test_support;
+
 mod control;
 pub use crate::control::{ButtonRole, Control, ControlType, ControlVal};
 
+mod text;
+use crate::text::Texts;
+pub use crate::text::{TextKey, TextKind};
+
 #[cfg(feature = "ctrl-pipe")]
 mod control_pipe;
 #[cfg(feature = "ctrl-pipe")]
-use crate::control_pipe::ControlPipe;
+pub use crate::control_pipe::{
+    ControlCmd, ControlMsg, ControlSender, CtrlPipes, CTRL_MSG_DATA_MAX,
+};
 #[cfg(feature = "ctrl-pipe")]
-pub use crate::control_pipe::{ControlCmd, ControlMsg, CtrlPipes};
+use crate::control_pipe::{ControlPipe, PeerVersion};
 
 #[cfg(feature = "ctrl-pipe")]
 mod control_pipe_runtime;
+#[cfg(all(fuzzing, feature = "ctrl-pipe"))]
+#[doc(hidden)]
+pub use crate::control_pipe_runtime::fuzz_decode;
+
+#[cfg(feature = "ctrl-pipe")]
+mod statusbar;
+#[cfg(feature = "ctrl-pipe")]
+use crate::statusbar::with_statusbar_stats;
+
+#[cfg(feature = "ctrl-pipe")]
+mod control_state;
+
+#[cfg(feature = "ctrl-pipe")]
+mod selector_state;
+#[cfg(feature = "ctrl-pipe")]
+use crate::control_state::ControlStore;
 
-const OPT_EXTCAP_VERSION: &str = "extcap-version";
-const OPT_EXTCAP_INTERFACES: &str = "extcap-interfaces";
-const OPT_EXTCAP_INTERFACE: &str = "extcap-interface";
-const OPT_EXTCAP_DTLS: &str = "extcap-dlts";
-const OPT_EXTCAP_CONFIG: &str = "extcap-config";
-const OPT_EXTCAP_RELOAD_OPTION: &str = "extcap-reload-option";
-const OPT_CAPTURE: &str = "capture";
-const OPT_EXTCAP_CAPTURE_FILTER: &str = "extcap-capture-filter";
-const OPT_FIFO: &str = "fifo";
-const OPT_EXTCAP_CONTROL_IN: &str = "extcap-control-in";
-const OPT_EXTCAP_CONTROL_OUT: &str = "extcap-control-out";
-const OPT_DEBUG: &str = "debug";
-const OPT_DEBUG_FILE: &str = "debug-file";
-
-fn print_opt_value<T: Display>(name: &str, value: &Option<T>) {
+#[cfg(feature = "async-writer")]
+mod async_writer;
+#[cfg(feature = "async-writer")]
+pub use crate::async_writer::{AsyncExtcapWriter, AsyncPcapWriter};
+
+pub mod opts;
+use crate::opts::*;
+
+const DEFAULT_RELOAD_PLACEHOLDER: &str = "Press Reload to fetch values";
+
+fn write_opt_value<T: Display>(out: &mut String, name: &str, value: &Option<T>) {
     if let Some(val) = value {
-        print!("{{{}={}}}", name, val);
+        out.push_str(&format!("{{{}={}}}", name, val));
     }
 }
 
 /// Possible writers for `PcapWriter`
+///
+/// Each write writes the whole buffer, retried on short writes and `EINTR`. A vectored
+/// write may write a part of the buffers, as `Write::write_vectored` does.
+/// The fifo closed by Wireshark fails the writes with `io::ErrorKind::BrokenPipe`
+/// on all platforms, Rust programs ignore `SIGPIPE` so the process is not killed.
+/// The capture failed by it finishes normally, see `ExtcapError::is_capture_stopped`.
 pub enum ExtcapWriter {
     /// Writer to stdout
     EWStdout(Stdout),
     /// Writer to file
     EWFile(File),
+    /// Writer supplied by the plugin, see [`PacketSink::from_writer`]
+    EWCustom(Box<dyn Write + Send>),
+    /// Buffered writer, see [`FlushPolicy`]
+    EWBuffered(BufWriter<Box<ExtcapWriter>>),
+}
+
+impl ExtcapWriter {
+    /// Syncs the file to the disk, nothing to do for the other writers
+    pub(crate) fn sync_all(&mut self) -> io::Result<()> {
+        match self {
+            ExtcapWriter::EWFile(file) => file.sync_all(),
+            ExtcapWriter::EWBuffered(writer) => {
+                writer.flush()?;
+                writer.get_mut().sync_all()
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 impl Write for ExtcapWriter {
+    /// Writes the whole buffer, a record is never cut by a short write to a full fifo
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         match self {
-            ExtcapWriter::EWStdout(sout) => sout.write(buf),
-            ExtcapWriter::EWFile(file) => file.write(buf),
+            ExtcapWriter::EWStdout(sout) => sout.write_all(buf),
+            ExtcapWriter::EWFile(file) => file.write_all(buf),
+            ExtcapWriter::EWCustom(writer) => writer.write_all(buf),
+            ExtcapWriter::EWBuffered(writer) => writer.write_all(buf),
         }
+        .map_err(broken_pipe)?;
+        Ok(buf.len())
     }
 
-    fn flush(&mut self) -> io::Result<()> {
+    /// Writes the buffers by a single call if the writer supports vectored IO, the first
+    /// one only otherwise
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
         match self {
-            ExtcapWriter::EWStdout(sout) => sout.flush(),
-            ExtcapWriter::EWFile(file) => file.flush(),
+            ExtcapWriter::EWStdout(sout) => sout.write_vectored(bufs),
+            ExtcapWriter::EWFile(file) => file.write_vectored(bufs),
+            ExtcapWriter::EWCustom(writer) => writer.write_vectored(bufs),
+            ExtcapWriter::EWBuffered(writer) => writer.write_vectored(bufs),
+        }
+        .map_err(broken_pipe)
+    }
+
+    /// Flushes, retried when interrupted
+    fn flush(&mut self) -> io::Result<()> {
+        loop {
+            let res = match self {
+                ExtcapWriter::EWStdout(sout) => sout.flush(),
+                ExtcapWriter::EWFile(file) => file.flush(),
+                ExtcapWriter::EWCustom(writer) => writer.flush(),
+                ExtcapWriter::EWBuffered(writer) => writer.flush(),
+            };
+            match res {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                res => return res.map_err(broken_pipe),
+            }
         }
     }
 }
 
 #[cfg(feature = "ctrl-pipe")]
-fn create_control_pipe(ctrl_in: &str, ctrl_out: &str) -> io::Result<ControlPipe> {
+fn create_control_pipe(
+    ctrl_in: &Path,
+    ctrl_out: &Path,
+    peer_version: PeerVersion,
+    strict: bool,
+    metrics: SharedMetrics,
+    pause: Option<(u8, PauseState)>,
+    name: &str,
+) -> io::Result<ControlPipe> {
     Ok(ControlPipe::new(
         File::open(ctrl_in)?,
         File::create(ctrl_out)?,
+        peer_version,
+        strict,
+        metrics,
+        pause,
+        name,
     ))
 }
 
-fn create_pcap_writer(fifo: &str, pcap_header: PcapHeader) -> io::Result<PcapWriter<ExtcapWriter>> {
-    let writer = if fifo == "-" {
-        ExtcapWriter::EWStdout(io::stdout())
-    } else {
-        ExtcapWriter::EWFile(File::create(fifo)?)
-    };
-    PcapWriter::with_header(pcap_header, writer)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))
-}
-
 /// Extcap specific result
 pub type ExtcapResult<T> = Result<T, ExtcapError>;
 
 /// Packet receiver for async-api
 #[cfg(feature = "async-api")]
-pub type ExtcapReceiver = Receiver<Packet<'static>>;
+pub type ExtcapReceiver = Receiver<ExtcapPacket>;
 
 /// A trait for Extcap callbacks
 pub trait ExtcapListener {
     /// Log initialization
+    #[deprecated(since = "0.5.0", note = "use `init_logging` instead")]
     fn init_log(&mut self, _extcap: &Extcap, _debug: bool, _debug_file: Option<&str>) {}
 
+    /// Log initialization
+    ///
+    /// Calls the deprecated `init_log` by default.
+    fn init_logging(&mut self, extcap: &Extcap, config: LogConfig) {
+        let debug_file = config.debug_file.as_deref().and_then(|p| p.to_str());
+        #[allow(deprecated)]
+        self.init_log(extcap, config.debug, debug_file);
+    }
+
     /// Interfaces update if it depends on passed options
+    #[deprecated(since = "0.5.0", note = "use `try_update_interfaces` instead")]
     fn update_interfaces(&mut self, _extcap: &mut Extcap) {}
 
+    /// Interfaces update if it depends on passed options
+    ///
+    /// An error is reported to Wireshark and stops processing, e.g. when the devices
+    /// can't be enumerated. Calls the deprecated `update_interfaces` by default.
+    fn try_update_interfaces(&mut self, extcap: &mut Extcap) -> ExtcapResult<()> {
+        #[allow(deprecated)]
+        self.update_interfaces(extcap);
+        Ok(())
+    }
+
     /// Interface config reload required for some argument(s)
+    #[deprecated(since = "0.5.0", note = "use `try_reload_option` instead")]
     fn reload_option(
         &mut self,
         _extcap: &Extcap,
@@ -163,10 +395,69 @@ pub trait ExtcapListener {
         None
     }
 
+    /// Interface config reload required for some argument(s)
+    ///
+    /// An error is shown to the user as a disabled "error: ..." value after the previous
+    /// values. Calls the deprecated `reload_option` by default.
+    fn try_reload_option(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+        arg: &IfArg,
+    ) -> ExtcapResult<Option<Vec<IfArgVal>>> {
+        #[allow(deprecated)]
+        Ok(self.reload_option(extcap, ifc, arg))
+    }
+
+    /// Creates the reload of an argument with `IfArg::reload_timeout`, run on a worker thread
+    ///
+    /// The task owns what it needs, e.g. a clone of a device handle, it's left running
+    /// detached once the timeout passes. Returns `None` by default, `try_reload_option`
+    /// is called then without the timeout as the borrowed listener can't be moved
+    /// to a worker thread.
+    fn reload_task(&mut self, _extcap: &Extcap, _ifc: &IFace, _arg: &IfArg) -> Option<ReloadTask> {
+        None
+    }
+
+    /// Bypasses the reload cache of the argument, see `IfArg::reload_cache()`
+    ///
+    /// E.g. a utility can force a fresh scan based on a flag of its own.
+    fn force_reload(&mut self, _extcap: &Extcap, _ifc: &IFace, _arg: &IfArg) -> bool {
+        false
+    }
+
+    /// Get capture header from listener
+    #[deprecated(since = "0.5.0", note = "use `capture_header2` instead")]
+    fn capture_header(&mut self, _extcap: &Extcap, ifc: &IFace) -> PcapHeader {
+        let mut ch = CaptureHeader::from_data_link(ifc.get_data_link());
+        if let Some(snaplen) = ifc.get_snaplen() {
+            ch.snaplen = snaplen;
+        }
+        ch.to_pcap_header()
+    }
+
     /// Get capture header from listener
-    fn capture_header(&mut self, extcap: &Extcap, ifc: &IFace) -> PcapHeader;
+    ///
+    /// Calls the deprecated `capture_header` by default, which defaults to the DLT
+    /// and the snaplen of the interface (65535 if not set) and microsecond timestamps. Override it when
+    /// the header depends on the arguments.
+    fn capture_header2(&mut self, extcap: &Extcap, ifc: &IFace) -> CaptureHeader {
+        #[allow(deprecated)]
+        let ph = self.capture_header(extcap, ifc);
+        CaptureHeader::from_pcap_header(&ph)
+    }
+
+    /// Get capture header from listener or fail the capture before the fifo is opened
+    ///
+    /// Used by the pcap output, calls `capture_header2` by default. Override it when
+    /// the header can't be determined, e.g. the remote device is unreachable, the error
+    /// is returned from `run` and reported to Wireshark.
+    fn try_capture_header(&mut self, extcap: &Extcap, ifc: &IFace) -> ExtcapResult<CaptureHeader> {
+        Ok(self.capture_header2(extcap, ifc))
+    }
 
     /// Main capture loop
+    #[deprecated(since = "0.5.0", note = "use `capture2` instead")]
     fn capture(
         &mut self,
         _extcap: &Extcap,
@@ -176,6 +467,96 @@ pub trait ExtcapListener {
         unimplemented!()
     }
 
+    /// Main capture loop
+    ///
+    /// The sink owns the writer, the statistics and the stop signal, see `PacketSink`.
+    /// Calls `capture_dump` by default.
+    fn capture2(&mut self, extcap: &Extcap, ifc: &IFace, mut sink: PacketSink) -> ExtcapResult<()> {
+        self.capture_dump(extcap, ifc, &mut sink)
+    }
+
+    /// Main capture loop borrowing the dumper, flushed once it returns
+    ///
+    /// Calls the deprecated `capture` by default.
+    fn capture_dump(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+        dumper: &mut ExtcapDumper,
+    ) -> ExtcapResult<()> {
+        #[allow(deprecated)]
+        self.capture(extcap, ifc, dumper.take_pcap_writer()?)
+    }
+
+    /// Get the pcapng interface description from listener
+    ///
+    /// Used by the default `capture_interfaces_ng`. Defaults to the header of
+    /// `capture_header2`, named after the interface and described by its display string.
+    fn capture_header_ng(&mut self, extcap: &Extcap, ifc: &IFace) -> InterfaceDescription {
+        let idb = InterfaceDescription::from_header(&self.capture_header2(extcap, ifc))
+            .name(ifc.get_interface());
+        match ifc.get_description() {
+            Some(descr) => idb.description(descr),
+            None => idb,
+        }
+    }
+
+    /// Get the pcapng interfaces written when the capture starts
+    ///
+    /// Used with `OutputFormat::PcapNg`, one per capture stream, e.g. per aggregated
+    /// radio with its own link type and name. The interface ids follow the order,
+    /// see `PcapNgWriter::interface_id`. Defaults to the one of `capture_header_ng`.
+    fn capture_interfaces_ng(&mut self, extcap: &Extcap, ifc: &IFace) -> Vec<InterfaceDescription> {
+        vec![self.capture_header_ng(extcap, ifc)]
+    }
+
+    /// Main capture loop creating the sink once ready, enabled by `Extcap::defer_fifo`
+    ///
+    /// Neither `try_capture_header` is called nor the fifo opened before, the listener
+    /// passes its header to `SinkFactory::create` e.g. after connecting to the remote
+    /// device. An error returned before leaves the fifo untouched, Wireshark waits
+    /// for the header meanwhile.
+    fn capture_lazy(
+        &mut self,
+        _extcap: &Extcap,
+        _ifc: &IFace,
+        _factory: SinkFactory,
+    ) -> ExtcapResult<()> {
+        unimplemented!()
+    }
+
+    /// Main capture loop writing pcapng, used with `OutputFormat::PcapNg`
+    ///
+    /// The section header and the interface descriptions have been written already.
+    /// The interface statistics are written when the writer is dropped, the counts
+    /// can be reported by `PcapNgWriter::set_drop_count` before returning.
+    fn capture_ng(
+        &mut self,
+        _extcap: &Extcap,
+        _ifc: &IFace,
+        _writer: PcapNgWriter<ExtcapWriter>,
+    ) -> ExtcapResult<()> {
+        unimplemented!()
+    }
+
+    /// Main capture loop writing the raw bytes, used with `OutputFormat::Raw`
+    ///
+    /// The fifo has been opened but nothing has been written to it, `capture_header2`
+    /// is not called. The listener is responsible for the whole byte stream, starting
+    /// with a pcap or pcapng header Wireshark understands, e.g. the output of a remote
+    /// dumpcap passed through as is.
+    fn capture_raw(
+        &mut self,
+        _extcap: &Extcap,
+        _ifc: &IFace,
+        _writer: ExtcapWriter,
+    ) -> ExtcapResult<()> {
+        unimplemented!()
+    }
+
+    /// Capture finished, also when it has failed or been aborted
+    fn capture_ended(&mut self, _extcap: &Extcap, _ifc: &IFace, _result: &ExtcapResult<()>) {}
+
     /// Main async capture loop
     #[cfg(feature = "async-api")]
     fn capture_async(&mut self, _extcap: &Extcap, _ifc: &IFace) -> ExtcapResult<ExtcapReceiver> {
@@ -192,13 +573,60 @@ pub trait ExtcapListener {
     ) -> ExtcapResult<ExtcapReceiver> {
         self.capture_async(extcap, ifc)
     }
+
+    /// Main async capture loop returning the producer task along with the receiver
+    ///
+    /// The crate polls the task and returns its result, see [`CaptureTask`].
+    /// Calls `capture_async` by default, without a task. With the `ctrl-pipe` feature
+    /// `capture_async_task_with_ctrl` is called instead.
+    #[cfg(feature = "async-api")]
+    fn capture_async_task(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+    ) -> ExtcapResult<(ExtcapReceiver, Option<CaptureTask>)> {
+        Ok((self.capture_async(extcap, ifc)?, None))
+    }
+
+    /// Main async capture loop with optional `CtrlPipes` returning the producer task
+    ///
+    /// Calls `capture_async_with_ctrl` by default, without a task.
+    #[cfg(feature = "ctrl-pipe")]
+    fn capture_async_task_with_ctrl(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+        ctrl_pipes: Option<CtrlPipes>,
+    ) -> ExtcapResult<(ExtcapReceiver, Option<CaptureTask>)> {
+        Ok((self.capture_async_with_ctrl(extcap, ifc, ctrl_pipes)?, None))
+    }
+
+    /// Main async capture writing the packets directly, enabled by `Extcap::async_writer`
+    ///
+    /// The pcap header has already been written to the fifo. The returned task owns
+    /// the writer and is polled by the crate, its result is returned from `run_async`.
+    /// The task should finish once the stop token is tripped and flush the writer
+    /// before returning.
+    #[cfg(feature = "async-writer")]
+    fn capture_async_writer(
+        &mut self,
+        _extcap: &Extcap,
+        _ifc: &IFace,
+        _ctrl_pipes: Option<CtrlPipes>,
+        _writer: AsyncPcapWriter,
+    ) -> ExtcapResult<CaptureTask> {
+        unimplemented!()
+    }
 }
 
 /// Extcap steps
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum ExtcapStep {
     /// Not determined
+    #[default]
     None,
+    /// Validation of the extcap configuration
+    SelfCheck,
     /// Query for available interfaces
     QueryIfaces,
     /// Ask for DLT’s to each interface
@@ -215,12 +643,6 @@ pub enum ExtcapStep {
     },
 }
 
-impl Default for ExtcapStep {
-    fn default() -> Self {
-        ExtcapStep::None
-    }
-}
-
 enum TillCaptureOutcome<T> {
     Finish(T),
     Capture { ifidx: usize },
@@ -231,10 +653,12 @@ type TillCaptureResult<T> = Result<TillCaptureOutcome<T>, ExtcapError>;
 /// Exctcap representation
 #[derive(Default)]
 pub struct Extcap<'a> {
+    name: &'a str,
     step: ExtcapStep,
-    app: Option<Command<'a>>,
+    cli: Option<CliSpec>,
     app_args: HashSet<String>, // optional user arguments added from interfaces
-    matches: Option<ArgMatches>,
+    if_args_configured: bool,  // options of the interface arguments registered
+    matches: ExtcapMatches,
     version: Option<String>,
     helppage: Option<String>,
     ws_version: Option<String>,
@@ -243,77 +667,103 @@ pub struct Extcap<'a> {
     ifc_debug: bool,
     control: bool,
     controls: Vec<Control>,
+    #[cfg(feature = "ctrl-pipe")]
+    ctrl_peer_version: PeerVersion,
+    #[cfg(feature = "ctrl-pipe")]
+    disable_unrelated_controls: bool,
+    #[cfg(feature = "ctrl-pipe")]
+    control_state: Option<PathBuf>,
+    #[cfg(feature = "ctrl-pipe")]
+    statusbar_stats: Option<Duration>,
+    #[cfg(feature = "ctrl-pipe")]
+    strict_control_framing: bool,
+    #[cfg(feature = "ctrl-pipe")]
+    keep_capture_on_control_close: bool,
+    #[cfg(feature = "ctrl-pipe")]
+    pause_control: Option<u8>,
+    #[cfg(feature = "async-writer")]
+    async_writer: bool,
+    capture_context: Option<CaptureContext>,
+    stop: StopToken,
+    stop_on_stdin_eof: bool,
+    #[cfg(feature = "signals")]
+    stop_on_signal: bool,
+    reload_placeholder: Option<String>,
+    texts: Texts,
+    reload_cache_dir: Option<PathBuf>,
+    run_location: Option<&'static Location<'static>>,
+    debug: bool,
+    debug_file_limits: DebugFileLimits,
+    ws_min_version: Option<(u32, u32)>,
+    ws_missing_version: MissingWsVersion,
+    sink_options: SinkOptions,
+    output_format: OutputFormat,
+    pcapng_section: SectionMetadata,
+    defer_fifo: bool,
+    #[cfg(feature = "async-api")]
+    shutdown_timeout: Option<Duration>,
+    #[cfg(feature = "async-api")]
+    channel_metrics: SharedMetrics,
+    #[cfg(feature = "standalone")]
+    standalone: bool,
+    #[cfg(test)]
+    test_io: test_support::TestIo,
 }
 
 impl<'a> Extcap<'a> {
     /// Creates a new instance of an `Extcap` requiring a name.
     pub fn new(name: &'a str) -> Self {
-        let app = Command::new(name)
-            .allow_negative_numbers(true)
-            //.template(HELP_TEMPLATE)
-            .arg(
-                Arg::new(OPT_EXTCAP_VERSION)
-                    .long(OPT_EXTCAP_VERSION)
+        let cli = CliSpec::new(name)
+            .opt(
+                OptSpec::value(OPT_EXTCAP_VERSION)
                     .help("Wireshark version")
-                    .takes_value(true)
                     .value_name("ver"),
             )
-            .arg(
-                Arg::new(OPT_EXTCAP_INTERFACES)
-                    .long(OPT_EXTCAP_INTERFACES)
-                    .help("List the extcap Interfaces"),
-            )
-            .arg(
-                Arg::new(OPT_EXTCAP_INTERFACE)
-                    .long(OPT_EXTCAP_INTERFACE)
+            .opt(OptSpec::flag(OPT_EXTCAP_INTERFACES).help("List the extcap Interfaces"))
+            .opt(OptSpec::flag(OPT_EXTCAP_SELFCHECK).help("Check the extcap configuration"))
+            .opt(
+                OptSpec::value(OPT_EXTCAP_INTERFACE)
                     .help("Specify the extcap interface")
-                    .takes_value(true)
                     .value_name("iface")
                     .conflicts_with(OPT_EXTCAP_INTERFACES),
             )
-            .arg(
-                Arg::new(OPT_EXTCAP_DTLS)
-                    .long(OPT_EXTCAP_DTLS)
-                    .help("List the DLTs"),
-            )
-            .arg(
-                Arg::new(OPT_EXTCAP_CONFIG)
-                    .long(OPT_EXTCAP_CONFIG)
+            .opt(OptSpec::flag(OPT_EXTCAP_DTLS).help("List the DLTs"))
+            .opt(
+                OptSpec::flag(OPT_EXTCAP_CONFIG)
                     .help("List the additional configuration for an interface"),
             )
-            .arg(
-                Arg::new(OPT_CAPTURE)
-                    .long(OPT_CAPTURE)
+            .opt(
+                OptSpec::flag(OPT_CAPTURE)
                     .help("Run the capture")
                     .requires(OPT_FIFO),
             )
-            .group(
-                ArgGroup::new("if_action")
-                    .args(&[OPT_EXTCAP_DTLS, OPT_EXTCAP_CONFIG, OPT_CAPTURE])
-                    .multiple(false)
-                    .requires(OPT_EXTCAP_INTERFACE),
-            )
-            .arg(
-                Arg::new(OPT_EXTCAP_CAPTURE_FILTER)
-                    .long(OPT_EXTCAP_CAPTURE_FILTER)
+            .opt(
+                OptSpec::value(OPT_EXTCAP_CAPTURE_FILTER)
                     .help("The capture filter")
-                    .takes_value(true)
                     .value_name("filter")
                     .requires(OPT_CAPTURE),
             )
-            .arg(
-                Arg::new(OPT_FIFO)
-                    .long(OPT_FIFO)
+            .opt(
+                OptSpec::value(OPT_FIFO)
+                    .path()
                     .help("Dump data to file or fifo")
-                    .takes_value(true)
                     .value_name("file")
                     .requires(OPT_CAPTURE),
             );
 
-        Self {
-            app: Some(app),
+        let extcap = Self {
+            name,
+            cli: Some(cli),
             ..Default::default()
-        }
+        };
+        #[cfg(windows)]
+        stop::stop_on_console_ctrl(&extcap.stop);
+        extcap
+    }
+
+    /// Gets the utility name
+    pub fn name(&self) -> &str {
+        self.name
     }
 
     /// Get current step for which it has been invoked from Wireshark
@@ -321,334 +771,1526 @@ impl<'a> Extcap<'a> {
         &self.step
     }
 
-    fn take_app(&mut self) -> Command<'a> {
-        self.app.take().expect("Extcap invalid state: already run")
+    fn take_cli(&mut self) -> ExtcapResult<CliSpec> {
+        self.cli.take().ok_or_else(|| {
+            ExtcapError::invalid_state("Command line has already been parsed, Extcap already run")
+        })
     }
 
-    fn update_app<F>(&mut self, f: F)
+    /// Updates the command line specification, ignored once the command line has been parsed
+    fn update_cli<F>(&mut self, f: F)
     where
-        F: FnOnce(Command<'a>) -> Command<'a>,
+        F: FnOnce(&mut CliSpec),
     {
-        self.app = Some(f(self.take_app()));
+        match self.cli.as_mut() {
+            Some(cli) => f(cli),
+            None => warn!(
+                "[{}] command update ignored, the command line has already been parsed",
+                self.name
+            ),
+        }
     }
 
-    fn app_arg(&mut self, arg: Arg<'a>) {
-        self.app = Some(self.take_app().arg(arg));
+    fn cli_opt(&mut self, opt: OptSpec) -> ExtcapResult<()> {
+        let cli = self.take_cli().map_err(|_| {
+            ExtcapError::invalid_state(format!(
+                "Option '--{}' can't be added once the command line has been parsed",
+                opt.get_name()
+            ))
+        })?;
+        self.cli = Some(cli.opt(opt));
+        Ok(())
     }
 
-    /// Get parsed command line arguments. Provided by `clap::App`.
+    /// Get parsed command line arguments. Provided by `clap::Command`.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called before `run()`/`run_async()` has parsed the command line,
+    /// it is meant to be used from `ExtcapListener` callbacks.
+    #[cfg(feature = "clap")]
+    #[deprecated(since = "0.5.0", note = "use `matches` instead")]
     pub fn get_matches(&self) -> &ArgMatches {
+        assert!(
+            self.cli.is_none(),
+            "Extcap::get_matches() called before the command line has been parsed, \
+             use it from ExtcapListener callbacks only"
+        );
+        &self.matches.matches
+    }
+
+    /// Get parsed command line options
+    ///
+    /// Empty before `run()`/`run_async()` has parsed the command line.
+    pub fn matches(&self) -> &ExtcapMatches {
+        &self.matches
+    }
+
+    /// Gets the value of a `Double` argument, `None` if not passed
+    ///
+    /// Both `1.5` and `1,5` are accepted, Wireshark sends the decimal comma in some locales.
+    pub fn arg_f64(&self, name: &str) -> ExtcapResult<Option<f64>> {
+        match self.matches.value(name) {
+            Some(val) => parse_f64(val).map(Some).ok_or_else(|| {
+                ExtcapError::user_error(format!("invalid value '{}' of option --{}", val, name))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    fn get_fifo(&self) -> ExtcapResult<&Path> {
         self.matches
-            .as_ref()
-            .expect("Extcap invalid state: not run yet")
+            .value_path(OPT_FIFO)
+            .ok_or_else(ExtcapError::missing_fifo)
+    }
+
+    /// Creates the pcap writer passed to the deprecated `ExtcapListener::capture`
+    /// writing to any writer, e.g. a buffer in tests
+    ///
+    /// The header is written at once. For `capture2` use [`PacketSink::from_writer`].
+    ///
+    /// ```
+    /// # use std::io::{self, Write};
+    /// # use std::sync::{Arc, Mutex};
+    /// # use extcap::{CaptureHeader, Extcap, ExtcapListener, ExtcapResult, ExtcapWriter, IFace};
+    /// # use std::time::Duration;
+    /// # use extcap::pcap_file::pcap::{PcapPacket, PcapReader, PcapWriter};
+    /// #[derive(Clone, Default)]
+    /// struct Shared(Arc<Mutex<Vec<u8>>>);
+    ///
+    /// impl Write for Shared {
+    ///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    ///         self.0.lock().unwrap().write(buf)
+    ///     }
+    ///     fn flush(&mut self) -> io::Result<()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// struct Dump;
+    ///
+    /// impl ExtcapListener for Dump {
+    ///     fn capture(&mut self, _: &Extcap, _: &IFace, mut pw: PcapWriter<ExtcapWriter>) -> ExtcapResult<()> {
+    ///         pw.write_packet(&PcapPacket::new(Duration::from_secs(1), 5, b"hello"))?;
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let buf = Shared::default();
+    /// let writer = Extcap::pcap_writer_for_test(CaptureHeader::new(147), buf.clone()).unwrap();
+    /// # #[allow(deprecated)]
+    /// Dump.capture(&Extcap::new("dump"), &IFace::new("if1"), writer).unwrap();
+    ///
+    /// let data = buf.0.lock().unwrap().clone();
+    /// let mut reader = PcapReader::new(&data[..]).unwrap();
+    /// let packet = reader.next_packet().unwrap().unwrap();
+    /// assert_eq!(&packet.data[..], b"hello");
+    /// ```
+    pub fn pcap_writer_for_test<W>(
+        header: CaptureHeader,
+        writer: W,
+    ) -> ExtcapResult<PcapWriter<ExtcapWriter>>
+    where
+        W: Write + Send + 'static,
+    {
+        Ok(create_pcap_writer(
+            ExtcapWriter::EWCustom(Box::new(writer)),
+            &header,
+        )?)
+    }
+
+    /// Get capture context, available in the capture step only
+    pub fn capture_context(&self) -> Option<&CaptureContext> {
+        self.capture_context.as_ref()
+    }
+
+    /// Get Wireshark version received within the control pipe `Initialized` message
+    ///
+    /// Available once the message has been received during capture, useful when
+    /// `--extcap-version` has not been passed.
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn ctrl_peer_version(&self) -> Option<String> {
+        self.ctrl_peer_version.lock().ok().and_then(|pv| pv.clone())
     }
 
     /// Sets the version string
     pub fn version(&mut self, ver: &'a str) {
         self.version = Some(ver.to_owned());
-        self.update_app(|a| a.version(ver));
+        self.update_cli(|c| c.version = Some(ver.to_owned()));
     }
 
     /// Sets the help URI
+    ///
+    /// Opened by Wireshark for the `ButtonRole::Help` toolbar button, use an `http://`,
+    /// `https://` or `file://` URI.
     pub fn help(&mut self, helppage: &'a str) {
         self.helppage = Some(String::from(helppage));
     }
 
-    /// Sets the author string
-    pub fn author(&mut self, author: &'a str) {
-        self.update_app(|a| a.author(author));
+    /// Requires a minimum Wireshark version
+    ///
+    /// When `--extcap-version` reports an older version a single placeholder interface
+    /// explaining the requirement is listed and the other steps fail.
+    pub fn require_ws_version(&mut self, major: u32, minor: u32) {
+        self.ws_min_version = Some((major, minor));
     }
 
-    /// Sets the about string
-    pub fn about(&mut self, about: &'a str) {
-        self.update_app(|a| a.about(about));
+    /// Sets the policy applied when the required Wireshark version can't be checked
+    /// because `--extcap-version` has not been passed
+    pub fn missing_ws_version(&mut self, policy: MissingWsVersion) {
+        self.ws_missing_version = policy;
     }
 
-    /// Sets the usage string
-    pub fn usage(&mut self, usage: &'a str) {
-        self.update_app(|a| a.override_usage(usage));
+    /// Returns the unsupported version message if the reported Wireshark version is too old
+    fn check_ws_version(&self) -> Option<String> {
+        let (major, minor) = self.ws_min_version?;
+        let reported = match self.ws_version.as_deref() {
+            Some(ver) => ver,
+            None => {
+                if self.ws_missing_version == MissingWsVersion::Warn {
+                    warn!(
+                        "[{}] Wireshark version not provided, version >= {}.{} required",
+                        self.name, major, minor
+                    );
+                }
+                return None;
+            }
+        };
+        match ws_version::parse_major_minor(reported) {
+            Some(ver) if ver >= (major, minor) => None,
+            Some(_) => Some(format!(
+                "{} requires Wireshark >= {}.{} (running {})",
+                self.name, major, minor, reported
+            )),
+            None => {
+                warn!(
+                    "[{}] Wireshark version '{}' not recognized, version >= {}.{} required",
+                    self.name, reported, major, minor
+                );
+                None
+            }
+        }
     }
 
-    /// Sets the after-help string
-    pub fn after_help(&mut self, help: &'a str) {
-        self.update_app(|a| a.after_help(help));
+    /// Sets how long `run_async` waits for the capture to finish once the stop token is tripped
+    ///
+    /// The producer task is aborted then, the packets already queued are written and
+    /// the capture fails with an error for which `ExtcapError::is_shutdown_timeout` is `true`.
+    /// Waits indefinitely by default.
+    #[cfg(feature = "async-api")]
+    pub fn shutdown_timeout(&mut self, timeout: Duration) {
+        self.shutdown_timeout = Some(timeout);
     }
 
-    /// Adds an interface
-    pub fn add_interface(&mut self, ifc: IFace<'a>) {
-        if ifc.has_reloadable_arg() && !self.reload_opt {
-            self.config_reload_opt();
-        }
-        if ifc.has_debug() && !self.ifc_debug {
-            self.config_debug();
-        }
-        for ifa in ifc.get_args() {
-            self.config_arg(ifa)
-        }
-        self.interfaces.push(ifc);
+    /// Collects the metrics of the packet and control channels in `run_async`
+    ///
+    /// Tells whether the producer overran the packet channel or the fifo writer fell
+    /// behind, the metrics are logged when the capture finishes. Nothing is measured
+    /// by default.
+    #[cfg(feature = "async-api")]
+    pub fn collect_channel_metrics(&mut self, enable: bool) {
+        self.channel_metrics = enable.then(Default::default);
     }
 
-    fn get_if_idx(&self, ifc: &str) -> Option<usize> {
-        self.interfaces
-            .iter()
-            .position(|x| x.get_interface() == ifc)
+    /// Gets the channel metrics collected so far, `None` unless enabled by
+    /// `collect_channel_metrics`
+    #[cfg(feature = "async-api")]
+    pub fn channel_metrics(&self) -> Option<CaptureChannelMetrics> {
+        self.channel_metrics.as_deref().map(MetricsCounters::get)
     }
 
-    fn get_if(&self, ifidx: usize) -> &IFace {
-        &self.interfaces[ifidx]
+    /// Sets a callback reporting the capture data written to the fifo
+    ///
+    /// The callback is invoked from the writer whenever the interval passes,
+    /// so it must be cheap.
+    pub fn on_write_progress<F>(&mut self, interval: ProgressInterval, callback: F)
+    where
+        F: Fn(&CaptureStats) + Send + Sync + 'static,
+    {
+        self.sink_options.progress = Some(WriteProgress::new(interval, callback));
     }
 
-    fn get_if_mut(&mut self, ifidx: usize) -> &mut IFace<'a> {
-        &mut self.interfaces[ifidx]
+    /// Sets the clock used for the timestamps provided by the crate, the system clock by default
+    pub fn clock(&mut self, clock: Clock) {
+        self.sink_options.clock = clock;
     }
 
-    pub(crate) fn config_arg(&mut self, ifa: &IfArg<'a>) {
-        if self.app_args.contains(ifa.get_name()) {
-            return;
-        }
-
-        let mut arg = Arg::new(ifa.get_name()).long(ifa.get_name());
-        if let Some(hlp) = ifa.get_display() {
-            arg = arg.help(hlp);
-        }
-        arg = arg.takes_value(!matches!(ifa.get_type(), IfArgType::Boolflag));
-        self.app_arg(arg);
+    /// Gets the clock used for the timestamps provided by the crate
+    pub fn get_clock(&self) -> &Clock {
+        &self.sink_options.clock
+    }
 
-        self.app_args.insert(ifa.get_name().to_owned());
+    /// Enables the capture file rotation
+    ///
+    /// Ignored with a warning unless the fifo argument is a regular file, i.e. when run
+    /// without Wireshark. With the copy of `tee_to` the copy is rotated instead.
+    pub fn file_rotation(&mut self, policy: RotationPolicy) {
+        self.sink_options.rotation = Some(policy);
     }
 
-    pub(crate) fn config_reload_opt(&mut self) {
-        if self.reload_opt {
-            return;
-        }
-        self.reload_opt = true;
-        self.app_arg(
-            Arg::new(OPT_EXTCAP_RELOAD_OPTION)
-                .long(OPT_EXTCAP_RELOAD_OPTION)
-                .help("Reload values for the given argument")
-                .takes_value(true)
-                .value_name("option")
-                .requires(OPT_EXTCAP_INTERFACE)
-                .requires(OPT_EXTCAP_CONFIG),
+    /// Rolls the capture file over once it reaches `max_bytes`, keeping `max_files`
+    ///
+    /// The fifo file is renamed with an index suffix, e.g. `capture_00001.pcap`, and
+    /// a new one with a fresh header is started under the original name. A shorthand
+    /// for [`Extcap::file_rotation`] with [`RotationPolicy::rollover`], it replaces
+    /// the policy set before.
+    pub fn set_file_rollover(&mut self, max_bytes: u64, max_files: usize) {
+        self.file_rotation(
+            RotationPolicy::new()
+                .max_bytes(max_bytes)
+                .max_files(max_files)
+                .rollover(),
         );
-        self.app_args.insert(OPT_EXTCAP_RELOAD_OPTION.to_owned());
     }
 
-    pub(crate) fn config_control(&mut self) {
-        if self.control {
-            return;
-        }
-        self.control = true;
-        self.app_arg(
-            Arg::new(OPT_EXTCAP_CONTROL_IN)
-                .long(OPT_EXTCAP_CONTROL_IN)
-                .help("The pipe for control messages from toolbar")
-                .takes_value(true)
-                .value_name("in-pipe")
-                .requires(OPT_CAPTURE),
-        );
-        self.app_arg(
-            Arg::new(OPT_EXTCAP_CONTROL_OUT)
-                .long(OPT_EXTCAP_CONTROL_OUT)
-                .help("The pipe for control messages to toolbar")
-                .takes_value(true)
-                .value_name("out-pipe")
-                .requires(OPT_CAPTURE),
-        );
-        self.app_args.insert(OPT_EXTCAP_CONTROL_IN.to_owned());
-        self.app_args.insert(OPT_EXTCAP_CONTROL_OUT.to_owned());
+    /// Writes a copy of the capture to a local file besides the fifo
+    ///
+    /// The file is overwritten. The `also-save` argument added by `IFace::config_also_save`
+    /// takes precedence. A failure of the copy is logged and disables it, the capture
+    /// continues.
+    ///
+    /// The file rotation of `file_rotation` or `set_file_rollover` applies only to the
+    /// copy while it is configured, the fifo target is never rotated then, even if it
+    /// is a regular file. The pcapng and raw output are copied byte by byte, neither
+    /// the copy nor the fifo is rotated, the rotation is ignored with a warning.
+    pub fn tee_to(&mut self, path: &Path) {
+        self.sink_options.tee = Some(path.to_path_buf());
     }
 
-    fn config_debug(&mut self) {
-        if self.ifc_debug {
-            return;
-        }
-        self.ifc_debug = true;
-        self.app_arg(
-            Arg::new(OPT_DEBUG)
-                .long(OPT_DEBUG)
-                .help("Print additional messages"),
-        );
-        self.app_arg(
-            Arg::new(OPT_DEBUG_FILE)
-                .long(OPT_DEBUG_FILE)
-                .help("Print debug messages to file")
-                .takes_value(true)
-                .value_name("file"),
-        );
+    /// Compresses the capture file by gzip even without the `.gz` extension
+    ///
+    /// The files ending with `.gz` are always compressed, the fifo, pipes and stdout never
+    /// as Wireshark reads them live. The rotation limits apply to the uncompressed size,
+    /// `CaptureStats::compressed_bytes` tells the size of the files.
+    #[cfg(feature = "gzip")]
+    pub fn gzip(&mut self, gzip: bool) {
+        self.sink_options.gzip = gzip;
+    }
+
+    /// Syncs the capture file to the disk when the capture finishes, disabled by default
+    ///
+    /// The buffered data are always flushed when the sink is dropped, the sync additionally
+    /// waits for the disk. Ignored unless the fifo argument is a regular file, the rotated
+    /// files are synced when switched.
+    pub fn sync_on_close(&mut self, sync: bool) {
+        self.sink_options.sync_on_close = sync;
+    }
+
+    /// Sets when the packets are written out to the fifo, each one at once by default
+    ///
+    /// A buffered policy raises the throughput of small packets, see [`FlushPolicy`]
+    /// for the latency it adds. The pcap header is always written out at once.
+    pub fn flush_policy(&mut self, policy: FlushPolicy) {
+        self.sink_options.flush = policy;
+    }
+
+    /// Sets the format of the capture written to the fifo, pcap by default
+    ///
+    /// With `OutputFormat::PcapNg` the blocking capture calls `ExtcapListener::capture_ng`
+    /// instead of `capture2`, with `OutputFormat::Raw` `capture_raw`. These writers have
+    /// none of the `PacketSink` layers but the copy of `tee_to`, the file rotation,
+    /// pause and progress don't apply. The async capture writes pcap only and fails with the others.
+    pub fn set_output_format(&mut self, format: OutputFormat) {
+        self.output_format = format;
+    }
+
+    /// Gets the format of the capture written to the fifo
+    pub fn get_output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+
+    /// Sets the metadata of the pcapng section header
+    ///
+    /// By default `shb_os` is the OS the extcap is built for and `shb_userappl` the name
+    /// followed by the version, if set. Used by `OutputFormat::PcapNg` only.
+    pub fn set_pcapng_section(&mut self, section: SectionMetadata) {
+        self.pcapng_section = section;
+    }
+
+    /// Gets the metadata of the pcapng section header
+    pub fn get_pcapng_section(&self) -> &SectionMetadata {
+        &self.pcapng_section
+    }
+
+    /// Defers opening the fifo until the listener is ready, disabled by default
+    ///
+    /// The blocking pcap capture calls `ExtcapListener::capture_lazy` instead of
+    /// `capture2`, the listener creates the sink itself once its source is up.
+    pub fn defer_fifo(&mut self, defer: bool) {
+        self.defer_fifo = defer;
+    }
+
+    /// Lets the listener write the packets to the fifo itself in the async capture,
+    /// disabled by default
+    ///
+    /// The crate writes the pcap header and calls `ExtcapListener::capture_async_writer`
+    /// instead of `capture_async_task_with_ctrl`, no packet channel is involved.
+    /// The file rotation, tee and gzip can't be combined with it.
+    #[cfg(feature = "async-writer")]
+    pub fn async_writer(&mut self, enable: bool) {
+        self.async_writer = enable;
+    }
+
+    /// Enables the standalone mode to run the capture without Wireshark
+    ///
+    /// Adds `--standalone --iface <iface> --out <file>` options which run the same capture
+    /// path as `--extcap-interface <iface> --capture --fifo <file>`, along with the interface
+    /// arguments. Ctrl-C stops the capture and a summary is printed to stderr at the end.
+    #[cfg(feature = "standalone")]
+    pub fn enable_standalone_mode(&mut self) -> ExtcapResult<()> {
+        if self.standalone {
+            return Ok(());
+        }
+        let mut names = self
+            .interfaces
+            .iter()
+            .flat_map(IFace::get_args)
+            .map(IfArg::get_name)
+            .chain(self.app_args.iter().map(String::as_str));
+        if let Some(name) = names.find(|name| STANDALONE_OPTIONS.contains(name)) {
+            return Err(ExtcapError::invalid_config(format!(
+                "Standalone option '--{}' already used by an interface argument",
+                name
+            )));
+        }
+        self.cli_opt(
+            OptSpec::flag(OPT_STANDALONE)
+                .help("Run the capture without Wireshark")
+                .requires(OPT_STANDALONE_IFACE)
+                .requires(OPT_STANDALONE_OUT)
+                .conflicts_with(OPT_EXTCAP_INTERFACES)
+                .conflicts_with(OPT_EXTCAP_INTERFACE)
+                .conflicts_with(OPT_CAPTURE)
+                .conflicts_with(OPT_FIFO),
+        )?;
+        self.cli_opt(
+            OptSpec::value(OPT_STANDALONE_IFACE)
+                .help("The interface captured in the standalone mode")
+                .value_name("iface")
+                .requires(OPT_STANDALONE),
+        )?;
+        self.cli_opt(
+            OptSpec::value(OPT_STANDALONE_OUT)
+                .path()
+                .help("The file the standalone capture is written to")
+                .value_name("file")
+                .requires(OPT_STANDALONE),
+        )?;
+        self.standalone = true;
+        Ok(())
+    }
+
+    #[cfg(feature = "standalone")]
+    fn is_standalone(&self) -> bool {
+        self.standalone && self.matches.is_present(OPT_STANDALONE)
+    }
+
+    /// Maps the standalone options to the ones passed by Wireshark for the capture
+    #[cfg(feature = "standalone")]
+    fn init_standalone(&mut self) {
+        for (opt, standalone_opt) in [
+            (OPT_EXTCAP_INTERFACE, OPT_STANDALONE_IFACE),
+            (OPT_FIFO, OPT_STANDALONE_OUT),
+        ] {
+            let values = self
+                .matches
+                .values_os(standalone_opt)
+                .into_iter()
+                .map(OsStr::to_os_string)
+                .collect();
+            self.matches.insert(opt, values);
+        }
+        self.matches.insert(OPT_CAPTURE, Vec::new());
+    }
+
+    /// Stops the capture on the first Ctrl-C, exits on the second one
+    #[cfg(feature = "standalone")]
+    fn set_standalone_ctrlc_handler(&self) {
+        let stop = self.stop.clone();
+        let name = self.name.to_owned();
+        if let Err(e) = ctrlc::set_handler(move || {
+            if stop.is_stopped() {
+                std::process::exit(130);
+            }
+            eprintln!("[{}] stopping the capture, Ctrl-C again to exit", name);
+            stop.stop();
+        }) {
+            warn!("[{}] Ctrl-C handler can't be set: {}", self.name, e);
+        }
+    }
+
+    #[cfg(feature = "standalone")]
+    fn print_standalone_summary(&self) {
+        if !self.is_standalone() {
+            return;
+        }
+        let stats = match self.sink_options.stats.as_ref().map(|s| s.lock()) {
+            Some(Ok(stats)) => *stats,
+            _ => return,
+        };
+        let mut extra = String::new();
+        if stats.files > 1 {
+            extra.push_str(&format!(", {} files", stats.files));
+        }
+        if stats.compressed_bytes > 0 {
+            extra.push_str(&format!(", {} bytes compressed", stats.compressed_bytes));
+        }
+        eprintln!(
+            "[{}] {} packets, {} bytes written to {} in {:.1?}{}",
+            self.name,
+            stats.packets,
+            stats.bytes,
+            self.matches.value(OPT_FIFO).unwrap_or_default(),
+            stats.elapsed,
+            extra
+        );
+    }
+
+    /// Limits the size of the `--debug-file`, kept with one `.old` generation of 10 MiB by default
+    ///
+    /// Applies to the file handed out as `LogConfig::opened_debug_file`.
+    pub fn debug_file_limits(&mut self, limits: DebugFileLimits) {
+        self.debug_file_limits = limits;
+    }
+
+    /// Sets the placeholder used for reloadable arguments without values and placeholder
+    ///
+    /// Defaults to "Press Reload to fetch values". The `IfArg` placeholder takes precedence.
+    pub fn reload_placeholder(&mut self, placeholder: &str) {
+        self.reload_placeholder = Some(placeholder.to_owned());
+    }
+
+    /// Sets the resolver of the user-facing strings, e.g. to translate them
+    ///
+    /// Consulted whenever the sentences are printed for the display strings, tooltips,
+    /// placeholders and groups of the interfaces, DLTs, arguments, values and controls.
+    /// `None` keeps the text as set, braces and control characters of a resolved one are
+    /// replaced. The language can be picked at runtime, e.g. from an environment variable
+    /// read by the resolver. The command line help is not resolved.
+    pub fn set_text_resolver<F>(&mut self, resolver: F)
+    where
+        F: Fn(&TextKey) -> Option<String> + Send + Sync + 'static,
+    {
+        self.texts = Texts::new(resolver);
+    }
+
+    /// Sets the directory of the reload cache files, see `IfArg::reload_cache()`
+    ///
+    /// Defaults to the temporary directory.
+    pub fn reload_cache_dir(&mut self, dir: &Path) {
+        self.reload_cache_dir = Some(dir.to_path_buf());
+    }
+
+    fn get_reload_placeholder(&self) -> &str {
+        self.reload_placeholder
+            .as_deref()
+            .unwrap_or(DEFAULT_RELOAD_PLACEHOLDER)
+    }
+
+    /// Sets the author string
+    pub fn author(&mut self, author: &'a str) {
+        self.update_cli(|c| c.author = Some(author.to_owned()));
+    }
+
+    /// Sets the about string
+    pub fn about(&mut self, about: &'a str) {
+        self.update_cli(|c| c.about = Some(about.to_owned()));
+    }
+
+    /// Sets the usage string
+    pub fn usage(&mut self, usage: &'a str) {
+        self.update_cli(|c| c.usage = Some(usage.to_owned()));
+    }
+
+    /// Sets the after-help string
+    pub fn after_help(&mut self, help: &'a str) {
+        self.update_cli(|c| c.after_help = Some(help.to_owned()));
+    }
+
+    /// Adds an interface
+    ///
+    /// Fails if the interface or any of its arguments is invalid, or if its name
+    /// or any of its aliases is already used by another interface.
+    pub fn add_interface(&mut self, ifc: IFace<'a>) -> ExtcapResult<()> {
+        ifc.validate()?;
+        let names = std::iter::once(ifc.get_interface())
+            .chain(ifc.get_aliases().iter().map(String::as_str));
+        for name in names {
+            if let Some(other) = self.interfaces.iter().find(|x| x.has_name(name)) {
+                return Err(ExtcapError::invalid_config(format!(
+                    "Interface '{}': name '{}' already used by interface '{}'",
+                    ifc.get_interface(),
+                    name,
+                    other.get_interface()
+                )));
+            }
+        }
+        if ifc.has_reloadable_arg() && !self.reload_opt {
+            self.config_reload_opt()?;
+        }
+        if ifc.has_debug() && !self.ifc_debug {
+            self.config_debug()?;
+        }
+        for ifa in ifc.get_args() {
+            self.check_arg(ifa)?;
+        }
+        self.interfaces.push(ifc);
+        Ok(())
+    }
+
+    fn get_if_idx(&self, ifc: &str) -> Option<usize> {
+        if let Some(ifidx) = self
+            .interfaces
+            .iter()
+            .position(|x| x.get_interface() == ifc)
+        {
+            return Some(ifidx);
+        }
+        let ifidx = self.interfaces.iter().position(|x| x.has_name(ifc))?;
+        debug!(
+            "[{}] interface alias '{}' used for '{}'",
+            self.name,
+            ifc,
+            self.get_if(ifidx).get_interface()
+        );
+        Some(ifidx)
+    }
+
+    fn get_if(&self, ifidx: usize) -> &IFace<'_> {
+        &self.interfaces[ifidx]
+    }
+
+    fn get_if_mut(&mut self, ifidx: usize) -> &mut IFace<'a> {
+        &mut self.interfaces[ifidx]
+    }
+
+    /// Checks the argument of an added interface, its option is registered before parsing
+    fn check_arg(&self, ifa: &IfArg<'a>) -> ExtcapResult<()> {
+        #[cfg(feature = "standalone")]
+        if self.standalone && STANDALONE_OPTIONS.contains(&ifa.get_name()) {
+            return Err(ExtcapError::invalid_config(format!(
+                "Argument '{}' is used by the standalone mode",
+                ifa.get_name()
+            )));
+        }
+        if self.if_args_configured && !self.app_args.contains(ifa.get_name()) {
+            return Err(ExtcapError::invalid_state(format!(
+                "Option '--{}' can't be added once the command line has been parsed",
+                ifa.get_name()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Registers the options of the interface arguments
+    ///
+    /// Wireshark lists the interfaces of every extcap on each refresh of its interface
+    /// list, the options of possibly hundreds of arguments are built only for the steps
+    /// which parse them.
+    fn config_interface_args(&mut self) -> ExtcapResult<()> {
+        let interfaces = std::mem::take(&mut self.interfaces);
+        let res = interfaces
+            .iter()
+            .flat_map(IFace::get_args)
+            .try_for_each(|ifa| self.config_arg(ifa));
+        self.interfaces = interfaces;
+        self.if_args_configured = true;
+        res
+    }
+
+    fn config_arg(&mut self, ifa: &IfArg<'a>) -> ExtcapResult<()> {
+        if self.app_args.contains(ifa.get_name()) {
+            return Ok(());
+        }
+
+        let mut opt = if matches!(ifa.get_type(), IfArgType::Boolflag) {
+            OptSpec::flag(ifa.get_name())
+        } else {
+            OptSpec::value(ifa.get_name()).allow_negative_numbers()
+        }
+        .multiple();
+        if let Some(hlp) = ifa.get_display() {
+            opt = opt.help(hlp);
+        }
+        self.cli_opt(opt)?;
+
+        self.app_args.insert(ifa.get_name().to_owned());
+        Ok(())
+    }
+
+    /// Warns about the single-valued interface arguments passed multiple times
+    fn warn_repeated_args(&self) {
+        let mut warned: Vec<&str> = Vec::new();
+        let args = self.interfaces.iter().flat_map(|ifc| ifc.get_args());
+        for arg in args.filter(|a| !matches!(a.get_type(), IfArgType::Multicheck)) {
+            let values = self.matches.values(arg.get_name());
+            if values.len() < 2 || warned.contains(&arg.get_name()) {
+                continue;
+            }
+            warn!(
+                "[{}] option --{} passed {} times, the last value '{}' is used",
+                self.name,
+                arg.get_name(),
+                values.len(),
+                values[values.len() - 1]
+            );
+            warned.push(arg.get_name());
+        }
+    }
+
+    pub(crate) fn config_reload_opt(&mut self) -> ExtcapResult<()> {
+        if self.reload_opt {
+            return Ok(());
+        }
+        self.cli_opt(
+            OptSpec::value(OPT_EXTCAP_RELOAD_OPTION)
+                .help("Reload values for the given argument")
+                .value_name("option")
+                .requires(OPT_EXTCAP_INTERFACE)
+                .requires(OPT_EXTCAP_CONFIG),
+        )?;
+        self.reload_opt = true;
+        self.app_args.insert(OPT_EXTCAP_RELOAD_OPTION.to_owned());
+        Ok(())
+    }
+
+    pub(crate) fn config_control(&mut self) -> ExtcapResult<()> {
+        if self.control {
+            return Ok(());
+        }
+        self.cli_opt(
+            OptSpec::value(OPT_EXTCAP_CONTROL_IN)
+                .path()
+                .help("The pipe for control messages from toolbar")
+                .value_name("in-pipe")
+                .requires(OPT_CAPTURE),
+        )?;
+        self.cli_opt(
+            OptSpec::value(OPT_EXTCAP_CONTROL_OUT)
+                .path()
+                .help("The pipe for control messages to toolbar")
+                .value_name("out-pipe")
+                .requires(OPT_CAPTURE),
+        )?;
+        self.control = true;
+        self.app_args.insert(OPT_EXTCAP_CONTROL_IN.to_owned());
+        self.app_args.insert(OPT_EXTCAP_CONTROL_OUT.to_owned());
+        Ok(())
+    }
+
+    fn config_debug(&mut self) -> ExtcapResult<()> {
+        if self.ifc_debug {
+            return Ok(());
+        }
+        self.cli_opt(OptSpec::flag(OPT_DEBUG).help("Print additional messages"))?;
+        self.cli_opt(
+            OptSpec::value(OPT_DEBUG_FILE)
+                .path()
+                .help("Print debug messages to file")
+                .value_name("file"),
+        )?;
+        self.ifc_debug = true;
         self.app_args.insert(OPT_DEBUG.to_owned());
         self.app_args.insert(OPT_DEBUG_FILE.to_owned());
+        Ok(())
+    }
+
+    /// Runs all the static validations of the interfaces, arguments and controls
+    ///
+    /// Unlike `add_interface` and `add_control` it doesn't stop at the first problem
+    /// and reports likely mistakes as warnings too. Also run by `--extcap-selfcheck`.
+    pub fn self_check(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        if self.interfaces.is_empty() {
+            issues.push(Issue::warning("No interfaces"));
+        }
+        // interface arguments share the command line options by name
+        let mut opts: Vec<(&str, &str, bool)> = Vec::new();
+        for (idx, ifc) in self.interfaces.iter().enumerate() {
+            let names = std::iter::once(ifc.get_interface())
+                .chain(ifc.get_aliases().iter().map(String::as_str));
+            for name in names {
+                if let Some(other) = self.interfaces[..idx].iter().find(|x| x.has_name(name)) {
+                    issues.push(Issue::error(format!(
+                        "Interface '{}': name '{}' already used by interface '{}'",
+                        ifc.get_interface(),
+                        name,
+                        other.get_interface()
+                    )));
+                }
+            }
+            ifc.self_check(&mut issues);
+            for arg in ifc.get_args() {
+                let flag = matches!(arg.get_type(), IfArgType::Boolflag);
+                match opts.iter().find(|(name, ..)| *name == arg.get_name()) {
+                    Some((_, other, other_flag)) if *other_flag != flag => {
+                        issues.push(Issue::error(format!(
+                            "Interface '{}' arg '{}': {} a value unlike in interface '{}'",
+                            ifc.get_interface(),
+                            arg.get_name(),
+                            if flag { "doesn't take" } else { "takes" },
+                            other
+                        )));
+                    }
+                    Some(_) => {}
+                    None => opts.push((arg.get_name(), ifc.get_interface(), flag)),
+                }
+            }
+        }
+        for control in &self.controls {
+            control.self_check(&mut issues);
+            for name in control.get_interfaces().unwrap_or_default() {
+                if !self.interfaces.iter().any(|ifc| ifc.has_name(name)) {
+                    issues.push(Issue::warning(format!(
+                        "Control {}: unknown interface '{}'",
+                        control.get_number(),
+                        name
+                    )));
+                }
+            }
+            if matches!(control.get_type(), ControlType::Button(ButtonRole::Help))
+                && self.helppage.is_none()
+            {
+                issues.push(Issue::error(format!(
+                    "Control {}: Help button without a help URI, see Extcap::help()",
+                    control.get_number()
+                )));
+            }
+        }
+        if let Some(help) = self.helppage.as_deref().filter(|h| !is_help_uri(h)) {
+            issues.push(Issue::warning(format!(
+                "Help URI '{}' is not an http://, https:// or file:// URI, Wireshark may not open it",
+                help
+            )));
+        }
+        issues
     }
 
     /// Adds a control
-    pub fn add_control(&mut self, mut control: Control) {
+    ///
+    /// Fails if the control is invalid.
+    pub fn add_control(&mut self, mut control: Control) -> ExtcapResult<()> {
+        control.validate()?;
         if !self.control {
-            self.config_control();
+            self.config_control()?;
         }
         control.set_number(self.controls.len());
         self.controls.push(control);
+        Ok(())
+    }
+
+    /// Adds a control relevant to the given interfaces (or their aliases) only
+    ///
+    /// Wireshark lists the toolbar controls globally, so the control is still shown
+    /// for every interface. During the capture of another interface the messages of the
+    /// control are dropped in both directions, see also `disable_unrelated_controls`.
+    pub fn add_control_for(
+        &mut self,
+        interfaces: &[&str],
+        mut control: Control,
+    ) -> ExtcapResult<()> {
+        control.set_interfaces(interfaces);
+        self.add_control(control)
+    }
+
+    /// Disables the controls not relevant to the captured interface when the capture starts
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn disable_unrelated_controls(&mut self, disable: bool) {
+        self.disable_unrelated_controls = disable;
     }
 
-    fn print_version(&self) {
-        print!(
+    /// Keeps the values of the toolbar controls between the captures in the state file
+    ///
+    /// The last values of the boolean, selector and string controls are stored when
+    /// the capture ends and restored by `Set` messages once Wireshark sends `Initialized`.
+    /// Exclude a control, e.g. a password, by `Control::persist(false)`.
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn persist_control_values(&mut self, state_file: &Path) {
+        self.control_state = Some(state_file.to_path_buf());
+    }
+
+    /// Sends the capture statistics to the Wireshark status bar every second
+    ///
+    /// Only when Wireshark passes the control pipes, the listener doesn't need to
+    /// send anything. The final summary is sent when the capture finishes.
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn auto_statusbar_stats(&mut self, enable: bool) {
+        self.statusbar_stats = enable.then_some(Duration::from_secs(1));
+        if enable && self.sink_options.stats.is_none() {
+            self.sink_options.stats = Some(Default::default());
+        }
+    }
+
+    /// Sets the interval of `auto_statusbar_stats`, enables it too
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn auto_statusbar_stats_interval(&mut self, interval: Duration) {
+        self.auto_statusbar_stats(true);
+        self.statusbar_stats = Some(interval);
+    }
+
+    /// Stops reading the control pipe on the first malformed message
+    ///
+    /// By default the decoder skips the corrupt bytes up to the next plausible
+    /// message header and logs how many were dropped, so a single bad byte
+    /// doesn't kill the control channel for the rest of the capture.
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn strict_control_framing(&mut self, strict: bool) {
+        self.strict_control_framing = strict;
+    }
+
+    /// Pauses the capture while the given boolean control is checked
+    ///
+    /// The `Set` messages of the control flip the `PauseState` of the capture context,
+    /// the status bar shows the state. While paused the packets written to the sink are
+    /// suppressed, the listener just keeps reading its source. Fails if the control
+    /// is not a registered boolean control.
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn pause_control(&mut self, ctrl_num: usize) -> ExtcapResult<()> {
+        match self.controls.get(ctrl_num).map(Control::get_type) {
+            Some(ControlType::Boolean) => {
+                self.pause_control = Some(ctrl_num as u8);
+                Ok(())
+            }
+            _ => Err(ExtcapError::invalid_config(format!(
+                "Pause control {} is not a registered boolean control",
+                ctrl_num
+            ))),
+        }
+    }
+
+    /// Stops the capture when Wireshark closes the control channel, enabled by default
+    ///
+    /// A failed write to the outgoing control pipe means Wireshark is gone. Either way the
+    /// control messages sent afterwards fail with an error and `CaptureStats::control_closed`
+    /// is set.
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn stop_on_control_close(&mut self, stop: bool) {
+        self.keep_capture_on_control_close = !stop;
+    }
+
+    /// Stops the capture once stdin reaches EOF, disabled by default
+    ///
+    /// Wireshark keeps the stdin of the extcap open while it runs, so EOF is the only
+    /// signal left when Wireshark or dumpcap dies abruptly: there is no `SIGTERM` and the
+    /// broken fifo is noticed only by the next write, which a quiet source may never do.
+    /// A thread reads and discards
+    /// stdin during the capture. Not started when stdin is a terminal or in the standalone
+    /// mode. stdout, where `--fifo -` writes, is not touched.
+    pub fn stop_on_stdin_eof(&mut self, stop: bool) {
+        self.stop_on_stdin_eof = stop;
+    }
+
+    /// Stops the capture on `SIGINT`, `SIGTERM` and `SIGHUP`, disabled by default
+    ///
+    /// Wireshark sends `SIGTERM` on Unix when the capture is stopped, the stop token is
+    /// tripped at once rather than by the next write to the closed fifo. On Windows
+    /// Ctrl-C, Ctrl-Break and closing the console are covered. The handler is process
+    /// wide, the listener can't set its own then. The standalone mode has its own one.
+    #[cfg(feature = "signals")]
+    pub fn stop_on_signal(&mut self, stop: bool) {
+        self.stop_on_signal = stop;
+    }
+
+    /// Gets the controls relevant to the interface
+    pub fn get_controls_for(&self, ifc: &IFace) -> Vec<&Control> {
+        self.controls.iter().filter(|c| c.applies_to(ifc)).collect()
+    }
+
+    fn write_version(&self, out: &mut String) {
+        out.push_str(&format!(
             "extcap {{version={}}}",
             self.version.as_ref().map_or("unknown", String::as_ref)
-        );
-        print_opt_value("help", &self.helppage);
-        println!();
+        ));
+        write_opt_value(out, "help", &self.helppage);
+        out.push('\n');
     }
 
-    fn print_iface_list(&self) {
-        self.interfaces.iter().for_each(IFace::print_iface);
+    fn write_iface_list(&self, out: &mut String) {
+        self.interfaces
+            .iter()
+            .for_each(|ifc| ifc.write_iface(out, &self.texts));
     }
 
-    fn print_control_list(&self) {
-        self.controls.iter().for_each(Control::print_control);
+    fn write_control_list(&self, out: &mut String) {
+        if self.helppage.is_none()
+            && self
+                .controls
+                .iter()
+                .any(|c| matches!(c.get_type(), ControlType::Button(ButtonRole::Help)))
+        {
+            warn!(
+                "[{}] Help button without a help URI does nothing, see Extcap::help()",
+                self.name
+            );
+        }
+        self.controls
+            .iter()
+            .for_each(|ctrl| ctrl.write_control(out, &self.texts));
+    }
+
+    /// Emits the sentences to stdout, mirrored into the debug log in debug mode
+    ///
+    /// Debug builds verify the sentences first and panic on a malformed one.
+    fn emit(&self, out: &str) {
+        #[cfg(debug_assertions)]
+        if let Err(e) = testing::parse_sentences(out) {
+            log::error!("[{}] malformed sentence {}", self.name, e.get_message());
+            panic!("[{}] malformed sentence {}", self.name, e.get_message());
+        }
+        self.print(out);
+        if self.debug {
+            out.lines()
+                .for_each(|line| debug!("[{}] >> {}", self.name, line));
+        }
+    }
+
+    /// Prints to stdout, collected by the unit tests
+    fn print(&self, out: &str) {
+        #[cfg(test)]
+        if self.test_io.capture(out) {
+            return;
+        }
+        print!("{}", out);
+    }
+
+    /// The command line of the run, given by the unit tests
+    fn args(&mut self) -> Vec<OsString> {
+        #[cfg(test)]
+        if let Some(args) = self.test_io.args.take() {
+            return args;
+        }
+        std::env::args_os().collect()
     }
 
     /// Starts main capture loop
+    ///
+    /// Fails with an invalid state error if the instance has already been run.
+    #[track_caller]
     pub fn run<T: ExtcapListener>(mut self, mut listener: T) -> ExtcapResult<()> {
-        match self.run_till_capture(&mut listener)? {
+        self.run_steps(&mut listener)
+    }
+
+    /// Starts main capture loop and maps the result to the process exit code
+    ///
+    /// Wireshark interprets the exit status per step:
+    /// - interfaces, DLTs and config queries: a failure is printed to stderr as one line
+    ///   and the exit code is non-zero, Wireshark hides the utility or shows the error
+    /// - argument reload: always zero, a failure prints the arg with its previous values
+    ///   and an error value
+    /// - capture: zero when it has been stopped by Wireshark closing the fifo
+    ///   or after the shutdown timeout, other failures are printed to stderr
+    ///   and the exit code is non-zero
+    ///
+    /// ```no_run
+    /// # use extcap::{Extcap, ExtcapListener};
+    /// # struct Listener;
+    /// # impl ExtcapListener for Listener {}
+    /// fn main() -> std::process::ExitCode {
+    ///     let extcap = Extcap::new("myextcap");
+    ///     extcap.run_to_exit_code(Listener)
+    /// }
+    /// ```
+    #[track_caller]
+    pub fn run_to_exit_code<T: ExtcapListener>(mut self, mut listener: T) -> ExitCode {
+        let res = self.run_steps(&mut listener);
+        self.exit_code(res)
+    }
+
+    #[track_caller]
+    fn run_steps<T: ExtcapListener>(&mut self, listener: &mut T) -> ExtcapResult<()> {
+        match self.run_till_capture(listener)? {
             TillCaptureOutcome::Finish(_) => Ok(()),
-            TillCaptureOutcome::Capture { ifidx } => {
-                self.capture(&mut listener, self.get_if(ifidx))
-            }
+            TillCaptureOutcome::Capture { ifidx } => self.capture(listener, self.get_if(ifidx)),
         }
     }
 
     /// Main async capture loop
+    ///
+    /// Fails with an invalid state error if the instance has already been run.
     #[cfg(feature = "async-api")]
     pub async fn run_async<T: ExtcapListener>(mut self, mut listener: T) -> ExtcapResult<()> {
-        match self.run_till_capture(&mut listener)? {
+        self.run_async_steps(&mut listener).await
+    }
+
+    /// Main async capture loop mapping the result to the process exit code
+    ///
+    /// See `run_to_exit_code` for the exit codes.
+    #[cfg(feature = "async-api")]
+    pub async fn run_async_to_exit_code<T: ExtcapListener>(mut self, mut listener: T) -> ExitCode {
+        let res = self.run_async_steps(&mut listener).await;
+        self.exit_code(res)
+    }
+
+    #[cfg(feature = "async-api")]
+    async fn run_async_steps<T: ExtcapListener>(&mut self, listener: &mut T) -> ExtcapResult<()> {
+        match self.run_till_capture(listener)? {
             TillCaptureOutcome::Finish(_) => Ok(()),
             TillCaptureOutcome::Capture { ifidx } => {
-                self.capture_async(&mut listener, self.get_if(ifidx)).await
+                self.capture_async(listener, self.get_if(ifidx)).await
+            }
+        }
+    }
+
+    /// Maps the result of the run to the exit code expected by Wireshark in the step
+    fn exit_code(&self, res: ExtcapResult<()>) -> ExitCode {
+        let e = match res {
+            Ok(()) => return ExitCode::SUCCESS,
+            Err(e) => e,
+        };
+        let stopped = matches!(self.get_step(), ExtcapStep::Capture { .. })
+            && (e.is_fifo_closed() || e.is_shutdown_timeout());
+        if stopped {
+            debug!("[{}] capture stopped: {}", self.name, e);
+            return ExitCode::SUCCESS;
+        }
+        let msg = e.to_string();
+        eprintln!("{}: {}", self.name, msg.lines().next().unwrap_or_default());
+        if let ExtcapStep::ConfigIface { reload: true } = self.get_step() {
+            warn!("[{}] reload failed: {}", self.name, e);
+            // Wireshark would clear the selector without the values
+            if let Some(arg) = self.reload_arg() {
+                self.emit(&self.reload_fallback(arg, reload_error_val(&e)));
             }
+            return ExitCode::SUCCESS;
         }
+        ExitCode::FAILURE
     }
 
+    #[track_caller]
     fn run_till_capture<T: ExtcapListener>(&mut self, listener: &mut T) -> TillCaptureResult<()> {
+        if let Some(location) = self.run_location {
+            return Err(already_run_error(location));
+        }
+        self.run_location = Some(Location::caller());
+
         // Save matches for listener
-        self.matches = match self.take_app().try_get_matches() {
-            Ok(m) => Some(m),
-            Err(cerr) => match cerr.kind() {
-                clap::ErrorKind::DisplayHelp | clap::ErrorKind::DisplayVersion => {
-                    print!("{}", cerr);
-                    return Ok(TillCaptureOutcome::Finish(()));
-                }
-                _ => return Err(cerr.into()),
-            },
+        let args = self.args();
+        if !cli::lists_interfaces(&args) {
+            self.config_interface_args()?;
+        }
+        self.matches = match self.take_cli()?.parse(args)? {
+            Parsed::Matches(m) => m,
+            Parsed::Display(text) => {
+                self.print(&text);
+                return Ok(TillCaptureOutcome::Finish(()));
+            }
         };
+        #[cfg(feature = "standalone")]
+        if self.is_standalone() {
+            self.init_standalone();
+        }
 
         // Determine the step
-        self.step = if self.get_matches().is_present(OPT_EXTCAP_INTERFACES) {
+        self.step = if self.matches.is_present(OPT_EXTCAP_SELFCHECK) {
+            ExtcapStep::SelfCheck
+        } else if self.matches.is_present(OPT_EXTCAP_INTERFACES) {
             ExtcapStep::QueryIfaces
-        } else if self.get_matches().is_present(OPT_EXTCAP_DTLS) {
+        } else if self.matches.is_present(OPT_EXTCAP_DTLS) {
             ExtcapStep::QueryDlts
-        } else if self.get_matches().is_present(OPT_EXTCAP_CONFIG) {
-            let reload = self.get_matches().is_present(OPT_EXTCAP_RELOAD_OPTION);
+        } else if self.matches.is_present(OPT_EXTCAP_CONFIG) {
+            let reload = self.reload_opt && self.matches.is_present(OPT_EXTCAP_RELOAD_OPTION);
             ExtcapStep::ConfigIface { reload }
-        } else if self.get_matches().is_present(OPT_CAPTURE) {
-            let ctrl_pipe = self.get_matches().is_present(OPT_EXTCAP_CONTROL_IN)
-                && self.get_matches().is_present(OPT_EXTCAP_CONTROL_OUT);
+        } else if self.matches.is_present(OPT_CAPTURE) {
+            let ctrl_pipe = self.control
+                && self.matches.is_present(OPT_EXTCAP_CONTROL_IN)
+                && self.matches.is_present(OPT_EXTCAP_CONTROL_OUT);
             ExtcapStep::Capture { ctrl_pipe }
         } else {
             ExtcapStep::None
         };
 
         // Log initialization
-        let debug = self.get_matches().is_present(OPT_DEBUG);
-        let debug_file = self.get_matches().value_of(OPT_DEBUG_FILE).and_then(|s| {
-            if s.trim().is_empty() {
-                None
-            } else {
-                Some(s)
-            }
-        });
-        listener.init_log(self, debug, debug_file);
-        debug!("=======================");
+        let debug = self.ifc_debug && self.matches.is_present(OPT_DEBUG);
+        self.debug = debug;
+        let debug_file = if self.ifc_debug {
+            self.matches
+                .value_path(OPT_DEBUG_FILE)
+                .filter(|p| !p.to_string_lossy().trim().is_empty())
+        } else {
+            None
+        };
+        let (config, open_err) =
+            LogConfig::new(debug, debug_file, self.debug_file_limits, &self.step);
+        listener.init_logging(self, config);
+        debug!(
+            "[{}] ======================= version {}",
+            self.name,
+            self.version.as_deref().unwrap_or("unknown")
+        );
         debug!(
-            "Log initialized debug={} debug_file={}",
+            "[{}] Log initialized debug={} debug_file={}",
+            self.name,
             debug,
-            debug_file.unwrap_or_default()
+            debug_file.unwrap_or(Path::new("")).display()
         );
-        debug!("step = {:?}", self.step);
-        debug!("env::args = {:?}", std::env::args());
+        if let Some(e) = open_err {
+            warn!(
+                "[{}] debug file {} can't be opened: {}",
+                self.name,
+                debug_file.unwrap_or(Path::new("")).display(),
+                e
+            );
+        }
+        debug!("[{}] step = {:?}", self.name, self.step);
+        debug!("[{}] env::args = {:?}", self.name, std::env::args());
+        self.warn_repeated_args();
 
         // Save version for listener
-        self.ws_version = self
-            .get_matches()
-            .value_of(OPT_EXTCAP_VERSION)
-            .map(String::from);
+        self.ws_version = self.matches.value(OPT_EXTCAP_VERSION).map(String::from);
         debug!(
-            "Wireshark version {}",
+            "[{}] Wireshark version {}",
+            self.name,
             self.ws_version
                 .as_ref()
                 .map_or("-not provided-", String::as_str)
         );
 
+        if let Some(msg) = self.check_ws_version() {
+            warn!("[{}] {}", self.name, msg);
+            if let ExtcapStep::QueryIfaces = self.get_step() {
+                let mut out = String::new();
+                self.write_version(&mut out);
+                // the message quotes the version string passed by Wireshark
+                out.push_str(&format!(
+                    "interface {{value={}-unsupported}}{{display={}}}\n",
+                    self.name,
+                    sanitize_display(&msg)
+                ));
+                self.emit(&out);
+                return Ok(TillCaptureOutcome::Finish(()));
+            }
+            return Err(ExtcapError::unsupported_ws_version(msg));
+        }
+
         // Call listener interfaces update if it depends on passed options
-        listener.update_interfaces(self);
+        listener.try_update_interfaces(self)?;
+
+        if let ExtcapStep::SelfCheck = self.get_step() {
+            let issues = self.self_check();
+            let errors = issues.iter().filter(|i| i.is_error()).count();
+            let mut out = String::new();
+            issues
+                .iter()
+                .for_each(|issue| out.push_str(&format!("{}\n", issue)));
+            out.push_str(&format!(
+                "{} errors, {} warnings\n",
+                errors,
+                issues.len() - errors
+            ));
+            self.print(&out);
+            if errors > 0 {
+                return Err(ExtcapError::invalid_config(format!(
+                    "Self-check found {} errors",
+                    errors
+                )));
+            }
+            return Ok(TillCaptureOutcome::Finish(()));
+        }
 
         if let ExtcapStep::QueryIfaces = self.get_step() {
-            debug!("list of interfaces required");
-            self.print_version();
-            self.print_iface_list();
-            self.print_control_list();
+            debug!("[{}] list of interfaces required", self.name);
+            let mut out = String::new();
+            self.write_version(&mut out);
+            self.write_iface_list(&mut out);
+            self.write_control_list(&mut out);
+            self.emit(&out);
             return Ok(TillCaptureOutcome::Finish(()));
         }
 
-        let ifidx = self
-            .get_matches()
-            .value_of(OPT_EXTCAP_INTERFACE)
-            .map_or_else(
-                || Err(ExtcapError::missing_interface()),
-                |ifnm| {
-                    self.get_if_idx(ifnm)
-                        .ok_or_else(|| ExtcapError::invalid_interface(ifnm))
-                },
-            )?;
+        let ifidx = self.matches.value(OPT_EXTCAP_INTERFACE).map_or_else(
+            || Err(ExtcapError::missing_interface()),
+            |ifnm| {
+                self.get_if_idx(ifnm)
+                    .ok_or_else(|| ExtcapError::invalid_interface(ifnm))
+            },
+        )?;
 
-        debug!("interface = {}", self.get_if(ifidx).get_interface());
+        debug!(
+            "[{}] interface = {}",
+            self.name,
+            self.get_if(ifidx).get_interface()
+        );
         match self.get_step() {
             ExtcapStep::QueryDlts => {
-                debug!("interface DLTs required");
-                self.get_if(ifidx).print_dlt_list();
+                debug!("[{}] interface DLTs required", self.name);
+                let mut out = String::new();
+                self.get_if(ifidx).write_dlt_list(&mut out, &self.texts);
+                self.emit(&out);
                 Ok(TillCaptureOutcome::Finish(()))
             }
             ExtcapStep::ConfigIface { .. } => {
-                if let Some(arg) = self.get_matches().value_of(OPT_EXTCAP_RELOAD_OPTION) {
+                let reload_arg = if self.reload_opt {
+                    self.matches.value(OPT_EXTCAP_RELOAD_OPTION)
+                } else {
+                    None
+                };
+                if let Some(arg) = reload_arg {
                     let arg = arg.to_owned(); // ends self immutable borrow
-                    debug!("interface config reload required for '{}' argument", arg);
+                    debug!(
+                        "[{}] interface config reload required for '{}' argument",
+                        self.name, arg
+                    );
                     self.reload_option(listener, ifidx, &arg);
                 } else {
-                    debug!("interface config required");
-                    self.get_if(ifidx).print_arg_list();
+                    debug!("[{}] interface config required", self.name);
+                    let mut out = String::new();
+                    self.get_if(ifidx).write_arg_list(
+                        &mut out,
+                        self.get_reload_placeholder(),
+                        &self.texts,
+                    );
+                    self.emit(&out);
                 }
                 Ok(TillCaptureOutcome::Finish(()))
             }
-            ExtcapStep::Capture { .. } => Ok(TillCaptureOutcome::Capture { ifidx }),
+            ExtcapStep::Capture { ctrl_pipe } => {
+                let ctrl_pipe = *ctrl_pipe;
+                self.sink_options.stats.get_or_insert_with(Default::default);
+                self.capture_context = Some(self.new_capture_context(ifidx, ctrl_pipe));
+                #[cfg(feature = "standalone")]
+                let standalone = self.is_standalone();
+                #[cfg(not(feature = "standalone"))]
+                let standalone = false;
+                #[cfg(feature = "standalone")]
+                if standalone {
+                    self.set_standalone_ctrlc_handler();
+                }
+                #[cfg(feature = "signals")]
+                if self.stop_on_signal && !standalone {
+                    stop::stop_on_signal(self.name, self.stop.clone());
+                }
+                if self.stop_on_stdin_eof && !standalone {
+                    stop::stop_on_stdin_eof(self.name, self.stop.clone());
+                }
+                Ok(TillCaptureOutcome::Capture { ifidx })
+            }
             _ => Err(ExtcapError::unknown_step()),
         }
     }
 
+    fn new_capture_context(&self, ifidx: usize, ctrl_pipe: bool) -> CaptureContext {
+        let ifc = self.get_if(ifidx);
+        CaptureContext {
+            interface: ifc.get_interface().to_owned(),
+            description: ifc.get_description().map(String::from),
+            dlt: ifc.get_data_link(),
+            fifo: self
+                .matches
+                .value_path(OPT_FIFO)
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            capture_filter: self
+                .matches
+                .value(OPT_EXTCAP_CAPTURE_FILTER)
+                .map(String::from),
+            ctrl_pipe,
+            ws_version: self.ws_version.clone(),
+            #[cfg(feature = "ctrl-pipe")]
+            ctrl_peer_version: self.ctrl_peer_version.clone(),
+            stop: self.stop.clone(),
+            pause: self.sink_options.pause.clone(),
+            clock: self.sink_options.clock.clone(),
+            stats: self.sink_options.stats.clone().unwrap_or_default(),
+        }
+    }
+
+    /// The capture stopped by Wireshark closing the fifo has finished normally
+    fn stopped_by_wireshark(&self, res: ExtcapResult<()>) -> ExtcapResult<()> {
+        match res {
+            Err(e) if e.is_capture_stopped() => {
+                debug!("[{}] capture stopped by Wireshark: {}", self.name, e);
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
+    /// Logs the final statistics of the packets written by the sink
+    fn log_capture_stats(&self) {
+        if self.output_format != OutputFormat::Pcap {
+            return;
+        }
+        if let Some(Ok(stats)) = self.sink_options.stats.as_ref().map(|s| s.lock()) {
+            debug!(
+                "[{}] capture wrote {} packets, {} bytes, {} suppressed",
+                self.name, stats.packets, stats.bytes, stats.suppressed
+            );
+        }
+    }
+
+    /// The argument of the reload step, if the interface and the argument are known
+    fn reload_arg(&self) -> Option<&IfArg<'a>> {
+        let interface = self.matches.value(OPT_EXTCAP_INTERFACE)?;
+        let arg = self.matches.value(OPT_EXTCAP_RELOAD_OPTION)?;
+        self.interfaces
+            .iter()
+            .find(|ifc| ifc.get_interface() == interface)?
+            .arg(arg)
+    }
+
+    /// The argument with its previous values and a disabled value telling why
+    fn reload_fallback(&self, arg: &IfArg, note: IfArgVal) -> String {
+        let mut vals = arg.get_vals().to_vec();
+        vals.push(note);
+        let mut targ = arg.clone();
+        targ.reload_option(vals);
+        let mut out = String::new();
+        targ.write_arg(&mut out, self.get_reload_placeholder(), &self.texts);
+        out
+    }
+
+    /// The fifo writer of the pcapng and raw output, with the byte copy of `tee_to`
+    fn fifo_writer(&self, fifo: &Path) -> io::Result<ExtcapWriter> {
+        let writer = create_fifo_writer(fifo, self.sink_options.gzip)?;
+        let options = self.fifo_sink_options();
+        if options.rotation.is_some() {
+            warn!(
+                "[{}] file rotation ignored, the {:?} output is not rotated",
+                self.name, self.output_format
+            );
+        }
+        Ok(match options.tee {
+            Some(path) => ExtcapWriter::EWCustom(Box::new(TeeWriter::new(
+                writer,
+                &path,
+                options.sync_on_close,
+                self.name,
+            ))),
+            None => writer,
+        })
+    }
+
+    /// The configured sink options, the fifo closed by Wireshark trips the stop token
+    fn fifo_sink_options(&self) -> SinkOptions {
+        let tee = match self.matches.value_path(OPT_ALSO_SAVE) {
+            Some(path) => Some(path.to_path_buf()),
+            None => self.sink_options.tee.clone(),
+        };
+        SinkOptions {
+            stop: Some(self.stop.clone()),
+            tee,
+            name: self.name.to_owned(),
+            ..self.sink_options.clone()
+        }
+    }
+
+    /// The values of an argument depending on another one, see `IfArg::depends_on()`
+    fn dependent_vals(&self, ifc: &IFace, arg: &IfArg) -> Option<Vec<IfArgVal>> {
+        let other = arg.get_depends_on()?;
+        let value = self
+            .matches
+            .value(other)
+            .or_else(|| ifc.arg(other).and_then(IfArg::get_default))
+            .unwrap_or_default();
+        debug!(
+            "[{}] reload_option() arg '{}' depends on '{}'='{}'",
+            self.name,
+            arg.get_name(),
+            other,
+            value
+        );
+        arg.dependent_vals(value)
+    }
+
+    fn reload_cache_path(&self, interface: &str, arg: &str) -> PathBuf {
+        let dir = self
+            .reload_cache_dir
+            .clone()
+            .unwrap_or_else(std::env::temp_dir);
+        reload_cache::cache_path(&dir, self.name, interface, arg)
+    }
+
     fn reload_option<T: ExtcapListener>(&mut self, listener: &mut T, ifidx: usize, arg: &str) {
         let ifc = self.get_if(ifidx);
         let aidx = if let Some(aidx) = ifc.get_arg_idx(arg) {
             aidx
         } else {
             warn!(
-                "reload_option() arg '{}' not available for interface '{}'",
+                "[{}] reload_option() arg '{}' not available for interface '{}'",
+                self.name,
                 arg,
                 ifc.get_interface()
             );
             return;
         };
 
-        if let Some(nargs) = listener.reload_option(self, ifc, ifc.get_arg(aidx)) {
+        let number = ifc.get_arg(aidx).get_number();
+        let cache = ifc
+            .get_arg(aidx)
+            .get_reload_cache()
+            .filter(|_| ifc.get_arg(aidx).get_depends_on().is_none())
+            .map(|ttl| (ttl, self.reload_cache_path(ifc.get_interface(), arg)));
+        if let Some((ttl, path)) = &cache {
+            let now = self.sink_options.clock.since_epoch();
+            if listener.force_reload(self, ifc, ifc.get_arg(aidx)) {
+                debug!(
+                    "[{}] reload_option() arg '{}' cache bypassed by the listener",
+                    self.name, arg
+                );
+            } else if let Some(vals) = reload_cache::load(path, now, *ttl) {
+                info!(
+                    "[{}] reload_option() arg '{}' number={} for interface '{}' served {} cached values",
+                    self.name,
+                    arg,
+                    number,
+                    ifc.get_interface(),
+                    vals.len()
+                );
+                self.get_if_mut(ifidx).get_arg_mut(aidx).reload_option(vals);
+                let mut out = String::new();
+                self.get_if(ifidx).get_arg(aidx).write_arg(
+                    &mut out,
+                    self.get_reload_placeholder(),
+                    &self.texts,
+                );
+                self.emit(&out);
+                return;
+            }
+        }
+        let targ = ifc.get_arg(aidx);
+        let task = targ
+            .get_reload_timeout()
+            .and_then(|timeout| Some((listener.reload_task(self, ifc, targ)?, timeout)));
+        let nargs = match task {
+            Some((task, timeout)) => match reload_timeout::run_with_timeout(task, timeout) {
+                Some(nargs) => nargs,
+                None => {
+                    warn!(
+                        "[{}] reload_option() arg '{}' timed out after {:?}",
+                        self.name, arg, timeout
+                    );
+                    let timed_out = IfArgVal::new("timeout")
+                        .display("timed out, try again")
+                        .enabled(false);
+                    self.emit(&self.reload_fallback(targ, timed_out));
+                    return;
+                }
+            },
+            None => listener.try_reload_option(self, ifc, targ),
+        }
+        .map(|nargs| nargs.or_else(|| self.dependent_vals(ifc, targ)));
+        let nargs = match nargs {
+            Ok(nargs) => nargs,
+            Err(e) => {
+                warn!(
+                    "[{}] reload_option() arg '{}' number={} for interface '{}' failed: {}",
+                    self.name,
+                    arg,
+                    number,
+                    ifc.get_interface(),
+                    e
+                );
+                // the previous values stay untouched, only the printed list shows the error
+                self.emit(&self.reload_fallback(ifc.get_arg(aidx), reload_error_val(&e)));
+                return;
+            }
+        };
+        if let Some(nargs) = nargs {
             debug!(
-                "reload_option() arg '{}' for interface '{}' has got {} values",
+                "[{}] reload_option() arg '{}' number={} for interface '{}' has got {} values",
+                self.name,
                 arg,
+                number,
                 ifc.get_interface(),
                 nargs.len()
             );
@@ -656,33 +2298,103 @@ impl<'a> Extcap<'a> {
             arg.reload_option(nargs);
         } else {
             debug!(
-                "reload_option() arg '{}' for interface '{}' nothing has changed",
+                "[{}] reload_option() arg '{}' number={} for interface '{}' nothing has changed",
+                self.name,
                 arg,
+                number,
                 ifc.get_interface()
             );
         };
 
-        self.get_if(ifidx).get_arg(aidx).print_arg();
+        self.keep_reload_number(ifidx, aidx, number);
+        let arg = self.get_if(ifidx).get_arg(aidx);
+        if let Some((_, path)) = cache {
+            let now = self.sink_options.clock.since_epoch();
+            if let Err(e) = reload_cache::store(&path, now, arg.get_vals()) {
+                warn!(
+                    "[{}] reload cache {} can't be written: {}",
+                    self.name,
+                    path.display(),
+                    e
+                );
+            }
+        }
+        let mut out = String::new();
+        arg.write_arg(&mut out, self.get_reload_placeholder(), &self.texts);
+        self.emit(&out);
+    }
+
+    /// Restores the number of the reloaded arg, Wireshark matches the values by the
+    /// number printed by the config step
+    fn keep_reload_number(&mut self, ifidx: usize, aidx: usize, number: usize) {
+        let name = self.name;
+        let arg = self.get_if_mut(ifidx).get_arg_mut(aidx);
+        if arg.get_number() != number {
+            warn!(
+                "[{}] reload_option() arg '{}' number changed from {} to {}, kept {}",
+                name,
+                arg.get_name(),
+                number,
+                arg.get_number(),
+                number
+            );
+            arg.set_number(number);
+        }
     }
 
     fn capture<T: ExtcapListener>(&self, listener: &mut T, ifc: &IFace) -> ExtcapResult<()> {
-        let fifo = self.get_matches().value_of(OPT_FIFO).unwrap();
-        let capture_filter = self.get_matches().value_of(OPT_EXTCAP_CAPTURE_FILTER);
+        let fifo = self.get_fifo()?;
+        let capture_filter = self.matches.value(OPT_EXTCAP_CAPTURE_FILTER);
         debug!(
-            "capture required fifo={} capture_filter={}",
-            fifo,
+            "[{}] capture required fifo={} capture_filter={}",
+            self.name,
+            fifo.display(),
             capture_filter.unwrap_or_default()
         );
 
-        let ph = listener.capture_header(self, ifc);
-        debug!("capture pcap header: {:?}", ph);
-        let pw = create_pcap_writer(fifo, ph)?;
-
-        let res = {
-            debug!("capture starting");
-            listener.capture(self, ifc, pw)
+        let res = match self.output_format {
+            OutputFormat::Pcap if self.defer_fifo => {
+                let factory = SinkFactory::new(fifo, self.fifo_sink_options());
+                debug!("[{}] deferred capture starting", self.name);
+                listener.capture_lazy(self, ifc, factory)
+            }
+            OutputFormat::Pcap => {
+                let ch = listener.try_capture_header(self, ifc)?;
+                debug!("[{}] capture header: {:?}", self.name, ch);
+                let sink = PacketSink::create(fifo, ch, self.fifo_sink_options())?;
+                debug!("[{}] capture starting", self.name);
+                listener.capture2(self, ifc, sink)
+            }
+            OutputFormat::PcapNg => {
+                let idbs = listener.capture_interfaces_ng(self, ifc);
+                debug!("[{}] capture interface descriptions: {:?}", self.name, idbs);
+                let userappl = match &self.version {
+                    Some(version) => format!("{} {}", self.name, version),
+                    None => self.name.to_owned(),
+                };
+                let writer = create_pcapng_writer(
+                    self.fifo_writer(fifo)?,
+                    &idbs,
+                    &self.pcapng_section,
+                    &userappl,
+                    self.sink_options.clock.clone(),
+                )?;
+                debug!("[{}] pcapng capture starting", self.name);
+                listener.capture_ng(self, ifc, writer)
+            }
+            OutputFormat::Raw => {
+                let writer = self.fifo_writer(fifo)?;
+                debug!("[{}] raw capture starting", self.name);
+                listener.capture_raw(self, ifc, writer)
+            }
         };
-        debug!("capture finished: {:?}", res);
+        let res = self.stopped_by_wireshark(res);
+        self.stop.stop();
+        debug!("[{}] capture finished: {:?}", self.name, res);
+        self.log_capture_stats();
+        listener.capture_ended(self, ifc, &res);
+        #[cfg(feature = "standalone")]
+        self.print_standalone_summary();
 
         res
     }
@@ -693,25 +2405,52 @@ impl<'a> Extcap<'a> {
         listener: &mut T,
         ifc: &IFace<'_>,
     ) -> ExtcapResult<()> {
-        let fifo = self.get_matches().value_of(OPT_FIFO).unwrap();
-        let capture_filter = self.get_matches().value_of(OPT_EXTCAP_CAPTURE_FILTER);
+        if self.output_format != OutputFormat::Pcap {
+            return Err(ExtcapError::invalid_config(format!(
+                "{:?} output is not supported by the async capture",
+                self.output_format
+            )));
+        }
+        let fifo = self.get_fifo()?;
+        let capture_filter = self.matches.value(OPT_EXTCAP_CAPTURE_FILTER);
         debug!(
-            "async capture required fifo={} capture_filter={}",
-            fifo,
+            "[{}] async capture required fifo={} capture_filter={}",
+            self.name,
+            fifo.display(),
             capture_filter.unwrap_or_default()
         );
         #[cfg(feature = "ctrl-pipe")]
         let mut control_pipe = {
-            let control_in = self.get_matches().value_of(OPT_EXTCAP_CONTROL_IN);
-            let control_out = self.get_matches().value_of(OPT_EXTCAP_CONTROL_OUT);
+            let (control_in, control_out) = if self.control {
+                (
+                    self.matches.value_path(OPT_EXTCAP_CONTROL_IN),
+                    self.matches.value_path(OPT_EXTCAP_CONTROL_OUT),
+                )
+            } else {
+                (None, None)
+            };
             if let (Some(ctrl_in), Some(ctrl_out)) = (control_in, control_out) {
-                debug!("async capture with control in={} out={}", ctrl_in, ctrl_out);
-                match create_control_pipe(ctrl_in, ctrl_out) {
+                debug!(
+                    "[{}] async capture with control in={} out={}",
+                    self.name,
+                    ctrl_in.display(),
+                    ctrl_out.display()
+                );
+                match create_control_pipe(
+                    ctrl_in,
+                    ctrl_out,
+                    self.ctrl_peer_version.clone(),
+                    self.strict_control_framing,
+                    self.channel_metrics.clone(),
+                    self.pause_control
+                        .map(|ctrl_num| (ctrl_num, self.sink_options.pause.clone())),
+                    self.name,
+                ) {
                     Ok(ctrl_pipe) => Some(ctrl_pipe),
                     Err(e) => {
                         warn!(
-                            "create_control_pipe(ctrl_in={}, ctrl_out={}), failed with error {}",
-                            ctrl_in, ctrl_out, e
+                            "[{}] create_control_pipe(ctrl_in={}, ctrl_out={}), failed with error {}", self.name,
+                            ctrl_in.display(), ctrl_out.display(), e
                         );
                         None
                     }
@@ -721,61 +2460,725 @@ impl<'a> Extcap<'a> {
             }
         };
 
-        let ph = listener.capture_header(self, ifc);
-        debug!("async capture pcap header: {:?}", ph);
-        let pw = create_pcap_writer(fifo, ph)?;
+        let ch = listener.try_capture_header(self, ifc)?;
+        debug!("[{}] async capture header: {:?}", self.name, ch);
+        #[cfg(feature = "ctrl-pipe")]
+        let output = self.create_async_output(fifo, ch).await?;
+        #[cfg(not(feature = "ctrl-pipe"))]
+        let sink = PacketSink::create(fifo, ch, self.fifo_sink_options())?;
 
         #[cfg(feature = "ctrl-pipe")]
         let res = {
-            let ctrl_pipe = control_pipe.as_mut().map(control_pipe::ControlPipe::start);
+            let ignored: Vec<u8> = self
+                .controls
+                .iter()
+                .filter(|c| !c.applies_to(ifc))
+                .map(|c| c.get_number() as u8)
+                .collect();
+            let store = self.control_state.as_deref().map(|path| {
+                let persistent = self
+                    .controls
+                    .iter()
+                    .filter(|c| c.is_persistent() && c.applies_to(ifc))
+                    .map(|c| c.get_number() as u8)
+                    .collect();
+                ControlStore::load(path, persistent)
+            });
+            let mut ctrl_pipe = control_pipe
+                .as_mut()
+                .map(|cp| {
+                    cp.start(
+                        &self.controls,
+                        ignored.clone(),
+                        store.clone(),
+                        self.stop.clone(),
+                        self.sink_options.control_closed.clone(),
+                        !self.keep_capture_on_control_close,
+                    )
+                })
+                .transpose()?;
+            if let Some(pipes) = ctrl_pipe
+                .as_mut()
+                .filter(|_| self.disable_unrelated_controls)
+            {
+                for ctrl_num in ignored {
+                    debug!("[{}] disabling unrelated control {}", self.name, ctrl_num);
+                    pipes
+                        .sender()
+                        .send(ControlMsg::new(ctrl_num, ControlCmd::Disable, &[]))
+                        .await?;
+                }
+            }
             debug!(
-                "async capture starting {} ctrl pipes",
+                "[{}] async capture starting {} ctrl pipes",
+                self.name,
                 if ctrl_pipe.is_some() {
                     "with"
                 } else {
                     "without"
                 }
             );
-            let receiver = listener.capture_async_with_ctrl(self, ifc, ctrl_pipe)?;
+            let ctrl_pipe_started = ctrl_pipe.is_some();
+            let statusbar = match (self.statusbar_stats, &self.sink_options.stats) {
+                (Some(interval), Some(stats)) => ctrl_pipe
+                    .as_mut()
+                    .map(|pipes| (pipes.sender().clone(), stats.clone(), interval)),
+                _ => None,
+            };
+            let capture = match output {
+                AsyncOutput::Sink(sink) => {
+                    let (receiver, task) =
+                        listener.capture_async_task_with_ctrl(self, ifc, ctrl_pipe)?;
+                    self.run_capture(receiver, *sink, task).boxed_local()
+                }
+                #[cfg(feature = "async-writer")]
+                AsyncOutput::Writer(writer) => {
+                    let task = listener.capture_async_writer(self, ifc, ctrl_pipe, *writer)?;
+                    self.run_writer_task(task).boxed_local()
+                }
+            };
             let tsk_ctrl_opt = control_pipe
                 .as_mut()
-                .map(control_pipe::ControlPipe::run_task);
+                .map(control_pipe::ControlPipe::run_task)
+                .transpose()?;
             let tsk_capture = async {
-                let res = capture_async_loop(receiver, pw).await;
+                let res = match statusbar {
+                    Some((sender, stats, interval)) => {
+                        with_statusbar_stats(self.name, capture, sender, stats, interval).await
+                    }
+                    None => capture.await,
+                };
                 if let Some(cp) = control_pipe {
                     cp.stop();
                 }
                 res
             };
-            if let Some(tsk_ctrl) = tsk_ctrl_opt {
+            let res = if let Some(tsk_ctrl) = tsk_ctrl_opt {
                 future::join(tsk_ctrl, tsk_capture).await.1
             } else {
                 tsk_capture.await
+            };
+            if let Some(Err(e)) = store.filter(|_| ctrl_pipe_started).map(|s| s.save()) {
+                warn!("[{}] control state not saved: {}", self.name, e);
+            }
+            if self.sink_options.control_closed.load(Ordering::SeqCst) {
+                if let Some(Ok(mut stats)) = self.sink_options.stats.as_ref().map(|s| s.lock()) {
+                    stats.control_closed = true;
+                }
             }
+            res
         };
 
         #[cfg(not(feature = "ctrl-pipe"))]
         let res = {
-            debug!("async capture starting");
-            let receiver = listener.capture_async(self, ifc)?;
-            capture_async_loop(receiver, pw).await
+            debug!("[{}] async capture starting", self.name);
+            let (receiver, task) = listener.capture_async_task(self, ifc)?;
+            self.run_capture(receiver, sink, task).await
         };
 
-        debug!("async capture finished: {:?}", res);
+        let res = self.stopped_by_wireshark(res);
+        self.stop.stop();
+        debug!("[{}] async capture finished: {:?}", self.name, res);
+        if let Some(metrics) = self.channel_metrics() {
+            info!("[{}] capture finished, {:?}", self.name, metrics);
+        }
+        self.log_capture_stats();
+        listener.capture_ended(self, ifc, &res);
+        #[cfg(feature = "standalone")]
+        self.print_standalone_summary();
 
         res
     }
+
+    /// Creates the fifo sink, or the writer handed to the listener with `async_writer`
+    #[cfg(feature = "ctrl-pipe")]
+    async fn create_async_output(
+        &self,
+        fifo: &Path,
+        ch: CaptureHeader,
+    ) -> ExtcapResult<AsyncOutput> {
+        #[cfg(feature = "async-writer")]
+        if self.async_writer {
+            debug!("[{}] async capture with the listener's writer", self.name);
+            let writer = AsyncPcapWriter::create(fifo, ch, self.fifo_sink_options()).await?;
+            return Ok(AsyncOutput::Writer(Box::new(writer)));
+        }
+        let sink = PacketSink::create(fifo, ch, self.fifo_sink_options())?;
+        Ok(AsyncOutput::Sink(Box::new(sink)))
+    }
+
+    #[cfg(feature = "async-api")]
+    fn packet_counters(&self) -> Option<&ChannelCounters> {
+        self.channel_metrics.as_deref().map(|m| &m.packets)
+    }
+
+    /// Writes the received packets along with the task, within the shutdown timeout if set
+    #[cfg(feature = "async-api")]
+    async fn run_capture(
+        &self,
+        mut receiver: ExtcapReceiver,
+        mut sink: PacketSink,
+        task: Option<CaptureTask>,
+    ) -> ExtcapResult<()> {
+        let timeout = match self.shutdown_timeout {
+            Some(timeout) => timeout,
+            None => {
+                let capture = capture_async_loop(
+                    self.name,
+                    &mut receiver,
+                    &mut sink,
+                    false,
+                    self.packet_counters(),
+                );
+                return capture_with_task(capture, task).await;
+            }
+        };
+        let res = {
+            let capture = capture_async_loop(
+                self.name,
+                &mut receiver,
+                &mut sink,
+                true,
+                self.packet_counters(),
+            );
+            let capture = capture_with_task(capture, task);
+            with_shutdown_timeout(capture, self.stop.stopped(), timeout).await
+        };
+        match res {
+            Some(res) => {
+                if self.stop.is_stopped() {
+                    debug!("[{}] clean shutdown within {:?}", self.name, timeout);
+                }
+                res
+            }
+            None => {
+                warn!(
+                    "[{}] shutdown timeout {:?} passed, capture aborted",
+                    self.name, timeout
+                );
+                // the task has been dropped, write what has been queued
+                while let Some(Some(pkt)) = receiver.next().now_or_never() {
+                    if !sink.is_closed() {
+                        pkt.write_to(&mut sink)?;
+                    }
+                }
+                if !sink.is_closed() {
+                    sink.flush()?;
+                }
+                Err(ExtcapError::shutdown_timeout(timeout))
+            }
+        }
+    }
+
+    /// Runs the listener's writer task, within the shutdown timeout if set
+    #[cfg(feature = "async-writer")]
+    async fn run_writer_task(&self, task: CaptureTask) -> ExtcapResult<()> {
+        let timeout = match self.shutdown_timeout {
+            Some(timeout) => timeout,
+            None => return task.await,
+        };
+        match with_shutdown_timeout(task, self.stop.stopped(), timeout).await {
+            Some(res) => res,
+            None => {
+                warn!(
+                    "[{}] shutdown timeout {:?} passed, capture aborted",
+                    self.name, timeout
+                );
+                Err(ExtcapError::shutdown_timeout(timeout))
+            }
+        }
+    }
+}
+
+/// Where the async capture writes the packets, see `Extcap::async_writer`
+#[cfg(feature = "ctrl-pipe")]
+enum AsyncOutput {
+    Sink(Box<PacketSink>),
+    #[cfg(feature = "async-writer")]
+    Writer(Box<AsyncPcapWriter>),
+}
+
+/// The location of the first run is included in debug builds only
+fn already_run_error(location: &Location<'_>) -> ExtcapError {
+    if cfg!(debug_assertions) {
+        ExtcapError::invalid_state(format!("run() already called at {}", location))
+    } else {
+        ExtcapError::invalid_state("run() already called")
+    }
+}
+
+/// Disabled value showing the error of a reload in the selector
+fn reload_error_val(e: &ExtcapError) -> IfArgVal {
+    IfArgVal::new("error")
+        .display(&sanitize_display(&format!("error: {}", e.get_message())))
+        .enabled(false)
 }
 
 #[cfg(feature = "async-api")]
+/// Writes the received packets, with `grace` the producer may finish after the fifo is closed
 async fn capture_async_loop(
-    mut receiver: ExtcapReceiver,
-    mut pw: PcapWriter<ExtcapWriter>,
+    name: &str,
+    receiver: &mut ExtcapReceiver,
+    sink: &mut PacketSink,
+    grace: bool,
+    counters: Option<&ChannelCounters>,
 ) -> ExtcapResult<()> {
-    debug!("async capture started");
-    while let Some(pkt) = receiver.next().await {
-        debug!("async packet received {:?}", pkt);
-        pw.write_packet(&pkt)?;
+    debug!("[{}] async capture started", name);
+    let mut depth = 0;
+    while let Some(pkt) = next_packet(receiver, sink, counters, &mut depth).await {
+        debug!("[{}] async packet received {:?}", name, pkt);
+        let started = counters.map(|_| Instant::now());
+        let res = pkt.write_to(sink);
+        if let (Some(counters), Some(started)) = (counters, started) {
+            counters.blocked(started.elapsed());
+        }
+        if let Err(e) = res {
+            if grace && sink.is_closed() {
+                debug!("[{}] fifo closed, waiting for the producer", name);
+                while receiver.next().await.is_some() {
+                    if let Some(counters) = counters {
+                        counters.dropped();
+                    }
+                }
+            }
+            return Err(e);
+        }
     }
     Ok(())
 }
+
+#[cfg(feature = "async-api")]
+/// Receives the next packet, `depth` counts the packets received back to back without waiting
+///
+/// The packets buffered by the sink are flushed before waiting.
+async fn next_packet(
+    receiver: &mut ExtcapReceiver,
+    sink: &mut PacketSink,
+    counters: Option<&ChannelCounters>,
+    depth: &mut usize,
+) -> Option<ExtcapPacket> {
+    let pkt = match receiver.next().now_or_never() {
+        Some(pkt) => {
+            *depth += 1;
+            pkt
+        }
+        None => {
+            *depth = 1;
+            sink.flush_idle();
+            receiver.next().await
+        }
+    }?;
+    if let Some(counters) = counters {
+        counters.enqueued();
+        counters.depth(*depth);
+    }
+    Some(pkt)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::process::ExitCode;
+    use std::sync::{Arc, OnceLock};
+
+    use pcap_file::pcap::PcapReader;
+    use pcap_file::DataLink;
+
+    use super::*;
+    use crate::test_support::{self, TempPath};
+
+    #[derive(Default)]
+    struct Failing {
+        update: bool,
+        reload: bool,
+        capture: Option<io::ErrorKind>,
+    }
+
+    impl ExtcapListener for Failing {
+        fn try_update_interfaces(&mut self, _: &mut Extcap) -> ExtcapResult<()> {
+            if self.update {
+                return Err(ExtcapError::user_error("no devices"));
+            }
+            Ok(())
+        }
+
+        fn try_reload_option(
+            &mut self,
+            _: &Extcap,
+            _: &IFace,
+            _: &IfArg,
+        ) -> ExtcapResult<Option<Vec<IfArgVal>>> {
+            if self.reload {
+                return Err(ExtcapError::user_error("scan failed"));
+            }
+            Ok(Some(vec![IfArgVal::new("new")]))
+        }
+
+        fn capture2(&mut self, _: &Extcap, _: &IFace, mut sink: PacketSink) -> ExtcapResult<()> {
+            sink.write_now(b"packet")?;
+            match self.capture {
+                Some(kind) => Err(io::Error::from(kind).into()),
+                None => Ok(()),
+            }
+        }
+    }
+
+    fn extcap() -> Extcap<'static> {
+        let mut extcap = Extcap::new("failing");
+        let mut ifc = IFace::new("if1");
+        let mut arg = IfArg::new_selector("dev").reload(true);
+        arg.add_val(IfArgVal::new("old"));
+        ifc.add_arg(arg);
+        extcap.add_interface(ifc).unwrap();
+        extcap
+    }
+
+    fn exit_code(listener: Failing, args: &[&str]) -> (ExitCode, String) {
+        test_support::run_to_exit_code(extcap(), listener, args)
+    }
+
+    const RELOAD: &[&str] = &[
+        "--extcap-config",
+        "--extcap-interface",
+        "if1",
+        "--extcap-reload-option",
+        "dev",
+    ];
+
+    #[test]
+    fn query_interfaces_failure_exits_non_zero() {
+        let listener = Failing {
+            update: true,
+            ..Default::default()
+        };
+        let (code, out) = exit_code(listener, &["--extcap-interfaces"]);
+        assert_eq!(code, ExitCode::FAILURE);
+        assert!(out.is_empty(), "{}", out);
+        let (code, _) = exit_code(Failing::default(), &["--extcap-interfaces"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn query_dlts_failure_exits_non_zero() {
+        let args = ["--extcap-dlts", "--extcap-interface", "unknown"];
+        let (code, _) = exit_code(Failing::default(), &args);
+        assert_eq!(code, ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn config_failure_exits_non_zero() {
+        let args = ["--extcap-config", "--extcap-interface", "unknown"];
+        let (code, _) = exit_code(Failing::default(), &args);
+        assert_eq!(code, ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn failed_reload_exits_zero_with_previous_values() {
+        let listener = Failing {
+            reload: true,
+            ..Default::default()
+        };
+        let (code, out) = exit_code(listener, RELOAD);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(out.contains("{value=old}"), "{}", out);
+        assert!(out.contains("scan failed}{enabled=false}"), "{}", out);
+    }
+
+    #[test]
+    fn reload_failing_before_the_listener_exits_zero_with_previous_values() {
+        let listener = Failing {
+            update: true,
+            ..Default::default()
+        };
+        let (code, out) = exit_code(listener, RELOAD);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(out.contains("{value=old}"), "{}", out);
+        assert!(out.contains("no devices}{enabled=false}"), "{}", out);
+    }
+
+    #[test]
+    fn capture_stopped_by_wireshark_exits_zero() {
+        let fifo = TempPath::new("stopped.pcap");
+        let listener = Failing {
+            capture: Some(io::ErrorKind::BrokenPipe),
+            ..Default::default()
+        };
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        let (code, _) = exit_code(listener, &args);
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn capture_error_exits_non_zero() {
+        let fifo = TempPath::new("failed.pcap");
+        let listener = Failing {
+            capture: Some(io::ErrorKind::PermissionDenied),
+            ..Default::default()
+        };
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        let (code, _) = exit_code(listener, &args);
+        assert_eq!(code, ExitCode::FAILURE);
+        let (code, _) = exit_code(Failing::default(), &args);
+        assert_eq!(code, ExitCode::SUCCESS);
+    }
+
+    /// Extcap with many interfaces, each with many arguments of its own
+    fn many_args_extcap() -> Extcap<'static> {
+        static NAMES: OnceLock<Vec<(String, Vec<String>)>> = OnceLock::new();
+        let names = NAMES.get_or_init(|| {
+            (0..100)
+                .map(|i| {
+                    let args = (0..30).map(|j| format!("arg{}-{}", i, j)).collect();
+                    (format!("if{}", i), args)
+                })
+                .collect()
+        });
+        let mut extcap = Extcap::new("manyargs");
+        for (name, args) in names {
+            let mut ifc = IFace::new(name);
+            for arg in args {
+                ifc.add_arg(IfArg::new_string(arg).display("Argument"));
+            }
+            extcap.add_interface(ifc).unwrap();
+        }
+        extcap
+    }
+
+    /// Number of the interface argument options registered by the run of the step
+    fn registered_if_args(args: &[&str]) -> usize {
+        let mut extcap = many_args_extcap();
+        let (res, _) = test_support::run_in_place(&mut extcap, Failing::default(), args);
+        assert!(res.is_ok(), "{:?}", res.err());
+        extcap
+            .interfaces
+            .iter()
+            .flat_map(IFace::get_args)
+            .filter(|ifa| extcap.app_args.contains(ifa.get_name()))
+            .count()
+    }
+
+    #[test]
+    fn interface_listing_skips_interface_args() {
+        assert_eq!(registered_if_args(&["--extcap-interfaces"]), 0);
+        let config = ["--extcap-config", "--extcap-interface", "if0"];
+        assert_eq!(registered_if_args(&config), 3000);
+    }
+
+    #[test]
+    fn reload_keeps_the_config_numbers() {
+        fn extcap() -> Extcap<'static> {
+            let mut extcap = Extcap::new("numbered");
+            let mut ifc = IFace::new("if1");
+            ifc.add_arg(IfArg::new_string("name"));
+            ifc.add_arg(IfArg::new_boolflag("flag"));
+            let mut arg = IfArg::new_selector("dev").reload(true);
+            arg.add_val(IfArgVal::new("old"));
+            ifc.add_arg(arg);
+            ifc.config_debug();
+            extcap.add_interface(ifc).unwrap();
+            extcap
+        }
+        let config = ["--extcap-config", "--extcap-interface", "if1"];
+        let (res, out) = test_support::run(extcap(), Failing::default(), &config);
+        assert!(res.is_ok());
+        let number = out
+            .lines()
+            .find(|line| line.contains("{call=--dev}"))
+            .and_then(|line| line.strip_prefix("arg {number="))
+            .and_then(|line| line.split('}').next())
+            .unwrap()
+            .to_owned();
+        assert_eq!(number, "2");
+        let (res, out) = test_support::run(extcap(), Failing::default(), RELOAD);
+        assert!(res.is_ok());
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", out);
+        let arg = format!("arg {{number={}}}{{call=--dev}}", number);
+        assert!(lines[0].starts_with(&arg), "{}", out);
+        let value = format!("value {{arg={}}}{{value=new}}", number);
+        assert!(lines[1].starts_with(&value), "{}", out);
+    }
+
+    #[test]
+    fn changed_reload_number_restored() {
+        let mut extcap = Extcap::new("numbered");
+        let mut ifc = IFace::new("if1");
+        let mut arg = IfArg::new_selector("dev").reload(true);
+        arg.add_val(IfArgVal::new("old"));
+        ifc.add_arg(arg);
+        extcap.add_interface(ifc).unwrap();
+        let number = extcap.get_if(0).get_arg(0).get_number();
+        extcap.get_if_mut(0).get_arg_mut(0).set_number(number + 5);
+        let ((), logs) = test_support::capture_logs(|| extcap.keep_reload_number(0, 0, number));
+        assert!(
+            logs.iter().any(|msg| msg.contains("number changed")),
+            "{:?}",
+            logs
+        );
+        let arg = extcap.get_if(0).get_arg(0);
+        assert_eq!(arg.get_number(), number);
+        let mut out = String::new();
+        arg.write_arg(&mut out, "", &extcap.texts);
+        let value = format!("value {{arg={}}}{{value=old}}", number);
+        assert!(out.contains(&value), "{}", out);
+    }
+
+    /// Listener reloading with the given outcome
+    struct Reload(fn() -> ExtcapResult<Option<Vec<IfArgVal>>>);
+
+    impl ExtcapListener for Reload {
+        fn try_reload_option(
+            &mut self,
+            _: &Extcap,
+            _: &IFace,
+            _: &IfArg,
+        ) -> ExtcapResult<Option<Vec<IfArgVal>>> {
+            (self.0)()
+        }
+    }
+
+    #[test]
+    fn reload_outcome_rendering() {
+        const ARG: &str = "arg {number=0}{call=--dev}{display=dev}{type=selector}{reload=true}\n";
+        const OLD: &str = "value {arg=0}{value=old}{display=old}\n";
+        let cases: [(Reload, String); 3] = [
+            // the previous values are kept
+            (Reload(|| Ok(None)), format!("{}{}", ARG, OLD)),
+            (
+                Reload(|| {
+                    Ok(Some(vec![
+                        IfArgVal::new("a"),
+                        IfArgVal::new("b").display("B"),
+                    ]))
+                }),
+                format!(
+                    "{}value {{arg=0}}{{value=a}}{{display=a}}\n\
+                     value {{arg=0}}{{value=b}}{{display=B}}\n",
+                    ARG
+                ),
+            ),
+            (
+                Reload(|| Err(ExtcapError::user_error("network down"))),
+                format!(
+                    "{}{}value {{arg=0}}{{value=error}}{{display=error: network down}}\
+                     {{enabled=false}}\n",
+                    ARG, OLD
+                ),
+            ),
+        ];
+        for (listener, expected) in cases {
+            let (res, out) = test_support::run(extcap(), listener, RELOAD);
+            assert!(res.is_ok());
+            assert_eq!(out, expected);
+        }
+    }
+
+    #[test]
+    fn emitted_sentences_are_mirrored_to_debug_log() {
+        let mut extcap = Extcap::new("mirrored");
+        let mut ifc = IFace::new("if1").description("First");
+        ifc.add_arg(IfArg::new_string("name"));
+        ifc.config_debug();
+        extcap.add_interface(ifc).unwrap();
+        extcap.add_interface(IFace::new("if2")).unwrap();
+        let args = ["--extcap-interfaces", "--debug"];
+        let ((res, out), logs) =
+            test_support::capture_logs(|| test_support::run(extcap, Failing::default(), &args));
+        assert!(res.is_ok());
+        assert!(!out.is_empty());
+        let mirrored: String = logs
+            .iter()
+            .filter_map(|msg| msg.strip_prefix("[mirrored] >> "))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert_eq!(mirrored, out);
+    }
+
+    #[test]
+    fn emitted_sentences_are_not_mirrored_without_debug() {
+        let (_, logs) = test_support::capture_logs(|| {
+            test_support::run(extcap(), Failing::default(), &["--extcap-interfaces"])
+        });
+        assert!(!logs.iter().any(|msg| msg.contains(">> ")), "{:?}", logs);
+    }
+
+    /// Writes at most 3 bytes at once, every other write and the first flush interrupted
+    struct ShortWrites {
+        out: Arc<std::sync::Mutex<Vec<u8>>>,
+        calls: usize,
+        flush_interrupted: bool,
+    }
+
+    impl Write for ShortWrites {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls.is_multiple_of(2) {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            let len = buf.len().min(3);
+            self.out.lock().unwrap().extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            if !self.flush_interrupted {
+                self.flush_interrupted = true;
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn short_and_interrupted_writes_keep_the_records() {
+        let out = Arc::default();
+        let writer = ShortWrites {
+            out: Arc::clone(&out),
+            calls: 0,
+            flush_interrupted: false,
+        };
+        let header = CaptureHeader::from_data_link(DataLink::ETHERNET);
+        let mut sink = PacketSink::from_writer(writer, header).unwrap();
+        for i in 0..10u8 {
+            sink.write(i.into(), 0, &vec![i; 1 + i as usize * 7], 100)
+                .unwrap();
+        }
+        sink.flush().unwrap();
+        drop(sink);
+
+        let out = out.lock().unwrap();
+        let mut reader = PcapReader::new(&out[..]).unwrap();
+        for i in 0..10u8 {
+            let pkt = reader.next_packet().unwrap().unwrap();
+            assert_eq!(pkt.timestamp.as_secs(), i.into());
+            assert_eq!(pkt.data, vec![i; 1 + i as usize * 7]);
+            assert_eq!(pkt.orig_len, 100);
+        }
+        assert!(reader.next_packet().is_none());
+    }
+
+    #[test]
+    fn single_write_takes_the_whole_buffer() {
+        let out = Arc::default();
+        let mut writer = ExtcapWriter::EWCustom(Box::new(ShortWrites {
+            out: Arc::clone(&out),
+            calls: 0,
+            flush_interrupted: false,
+        }));
+        assert_eq!(writer.write(&[7; 100]).unwrap(), 100);
+        writer.flush().unwrap();
+        assert_eq!(*out.lock().unwrap(), [7; 100]);
+    }
+}
@@ -0,0 +1,189 @@
+#[cfg(feature = "ctrl-pipe")]
+use crate::CtrlPipes;
+use crate::{
+    CaptureHeader, Extcap, ExtcapError, ExtcapListener, ExtcapResult, IFace, IfArg, IfArgVal,
+    LogConfig, PacketSink,
+};
+#[cfg(feature = "async-api")]
+use crate::{CaptureTask, ExtcapReceiver};
+
+/// A trait for Extcap callbacks returning the listener's own error type
+///
+/// The errors are converted into `ExtcapError` once when returned to `Extcap`,
+/// `Box<dyn Error + Send + Sync>` keeps the original error as the source.
+/// Every implementor is an `ExtcapListener`.
+pub trait ExtcapListenerWithError {
+    /// Error returned by the callbacks
+    type Error: Into<ExtcapError>;
+
+    /// Log initialization
+    fn init_logging(&mut self, _extcap: &Extcap, _config: LogConfig) {}
+
+    /// Interfaces update if it depends on passed options
+    fn try_update_interfaces(&mut self, _extcap: &mut Extcap) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Interface config reload required for some argument(s)
+    fn try_reload_option(
+        &mut self,
+        _extcap: &Extcap,
+        _ifc: &IFace,
+        _arg: &IfArg,
+    ) -> Result<Option<Vec<IfArgVal>>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Bypasses the reload cache of the argument, see `IfArg::reload_cache()`
+    fn force_reload(&mut self, _extcap: &Extcap, _ifc: &IFace, _arg: &IfArg) -> bool {
+        false
+    }
+
+    /// Get capture header from listener
+    ///
+    /// Defaults to the DLT of the interface, snaplen 65535 and microsecond timestamps.
+    fn capture_header2(&mut self, _extcap: &Extcap, ifc: &IFace) -> CaptureHeader {
+        CaptureHeader::builder().link(ifc.get_data_link()).build()
+    }
+
+    /// Get capture header from listener or fail the capture before the fifo is opened
+    ///
+    /// Calls `capture_header2` by default.
+    fn try_capture_header(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+    ) -> Result<CaptureHeader, Self::Error> {
+        Ok(self.capture_header2(extcap, ifc))
+    }
+
+    /// Main capture loop
+    fn capture2(
+        &mut self,
+        _extcap: &Extcap,
+        _ifc: &IFace,
+        _sink: PacketSink,
+    ) -> Result<(), Self::Error> {
+        unimplemented!()
+    }
+
+    /// Capture finished, also when it has failed or been aborted
+    fn capture_ended(&mut self, _extcap: &Extcap, _ifc: &IFace, _result: &ExtcapResult<()>) {}
+
+    /// Main async capture loop
+    #[cfg(feature = "async-api")]
+    fn capture_async(
+        &mut self,
+        _extcap: &Extcap,
+        _ifc: &IFace,
+    ) -> Result<ExtcapReceiver, Self::Error> {
+        unimplemented!()
+    }
+
+    /// Main async capture loop with optional `CtrlPipes`
+    #[cfg(feature = "ctrl-pipe")]
+    fn capture_async_with_ctrl(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+        _ctrl_pipes: Option<CtrlPipes>,
+    ) -> Result<ExtcapReceiver, Self::Error> {
+        self.capture_async(extcap, ifc)
+    }
+
+    /// Main async capture loop returning the producer task along with the receiver
+    #[cfg(feature = "async-api")]
+    fn capture_async_task(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+    ) -> Result<(ExtcapReceiver, Option<CaptureTask>), Self::Error> {
+        Ok((self.capture_async(extcap, ifc)?, None))
+    }
+
+    /// Main async capture loop with optional `CtrlPipes` returning the producer task
+    #[cfg(feature = "ctrl-pipe")]
+    fn capture_async_task_with_ctrl(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+        ctrl_pipes: Option<CtrlPipes>,
+    ) -> Result<(ExtcapReceiver, Option<CaptureTask>), Self::Error> {
+        Ok((self.capture_async_with_ctrl(extcap, ifc, ctrl_pipes)?, None))
+    }
+}
+
+impl<T: ExtcapListenerWithError> ExtcapListener for T {
+    fn init_logging(&mut self, extcap: &Extcap, config: LogConfig) {
+        ExtcapListenerWithError::init_logging(self, extcap, config)
+    }
+
+    fn try_update_interfaces(&mut self, extcap: &mut Extcap) -> ExtcapResult<()> {
+        ExtcapListenerWithError::try_update_interfaces(self, extcap).map_err(Into::into)
+    }
+
+    fn try_reload_option(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+        arg: &IfArg,
+    ) -> ExtcapResult<Option<Vec<IfArgVal>>> {
+        ExtcapListenerWithError::try_reload_option(self, extcap, ifc, arg).map_err(Into::into)
+    }
+
+    fn force_reload(&mut self, extcap: &Extcap, ifc: &IFace, arg: &IfArg) -> bool {
+        ExtcapListenerWithError::force_reload(self, extcap, ifc, arg)
+    }
+
+    fn capture_header2(&mut self, extcap: &Extcap, ifc: &IFace) -> CaptureHeader {
+        ExtcapListenerWithError::capture_header2(self, extcap, ifc)
+    }
+
+    fn try_capture_header(&mut self, extcap: &Extcap, ifc: &IFace) -> ExtcapResult<CaptureHeader> {
+        ExtcapListenerWithError::try_capture_header(self, extcap, ifc).map_err(Into::into)
+    }
+
+    fn capture2(&mut self, extcap: &Extcap, ifc: &IFace, sink: PacketSink) -> ExtcapResult<()> {
+        ExtcapListenerWithError::capture2(self, extcap, ifc, sink).map_err(Into::into)
+    }
+
+    fn capture_ended(&mut self, extcap: &Extcap, ifc: &IFace, result: &ExtcapResult<()>) {
+        ExtcapListenerWithError::capture_ended(self, extcap, ifc, result)
+    }
+
+    #[cfg(feature = "async-api")]
+    fn capture_async(&mut self, extcap: &Extcap, ifc: &IFace) -> ExtcapResult<ExtcapReceiver> {
+        ExtcapListenerWithError::capture_async(self, extcap, ifc).map_err(Into::into)
+    }
+
+    #[cfg(feature = "ctrl-pipe")]
+    fn capture_async_with_ctrl(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+        ctrl_pipes: Option<CtrlPipes>,
+    ) -> ExtcapResult<ExtcapReceiver> {
+        ExtcapListenerWithError::capture_async_with_ctrl(self, extcap, ifc, ctrl_pipes)
+            .map_err(Into::into)
+    }
+
+    #[cfg(feature = "async-api")]
+    fn capture_async_task(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+    ) -> ExtcapResult<(ExtcapReceiver, Option<CaptureTask>)> {
+        ExtcapListenerWithError::capture_async_task(self, extcap, ifc).map_err(Into::into)
+    }
+
+    #[cfg(feature = "ctrl-pipe")]
+    fn capture_async_task_with_ctrl(
+        &mut self,
+        extcap: &Extcap,
+        ifc: &IFace,
+        ctrl_pipes: Option<CtrlPipes>,
+    ) -> ExtcapResult<(ExtcapReceiver, Option<CaptureTask>)> {
+        ExtcapListenerWithError::capture_async_task_with_ctrl(self, extcap, ifc, ctrl_pipes)
+            .map_err(Into::into)
+    }
+}
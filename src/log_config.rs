@@ -0,0 +1,61 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::LevelFilter;
+
+use crate::{DebugFile, DebugFileLimits, ExtcapStep};
+
+/// Logging configuration passed to `ExtcapListener::init_logging`
+///
+/// Built from the `--debug` and `--debug-file` options, new fields may be added.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct LogConfig<'a> {
+    /// Debug mode requested
+    pub debug: bool,
+    /// Debug file path
+    pub debug_file: Option<PathBuf>,
+    /// Debug file opened for appending, `None` if not requested or it could not be opened
+    ///
+    /// Size-capped as set by `Extcap::debug_file_limits`.
+    pub opened_debug_file: Option<DebugFile>,
+    /// Current step
+    pub step: &'a ExtcapStep,
+    /// Suggested log level, `Debug` in debug mode and `Warn` otherwise
+    pub level: LevelFilter,
+}
+
+impl<'a> LogConfig<'a> {
+    /// Creates the configuration, the debug file open error is returned separately
+    /// so it can be logged once the logger is initialized
+    pub(crate) fn new(
+        debug: bool,
+        debug_file: Option<&Path>,
+        limits: DebugFileLimits,
+        step: &'a ExtcapStep,
+    ) -> (Self, Option<io::Error>) {
+        let debug_file = debug_file.map(Path::to_path_buf);
+        let (opened_debug_file, open_err) = match &debug_file {
+            Some(path) => match DebugFile::open(path, limits) {
+                Ok(file) => (Some(file), None),
+                Err(e) => (None, Some(e)),
+            },
+            None => (None, None),
+        };
+        let level = if debug {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Warn
+        };
+        (
+            Self {
+                debug,
+                debug_file,
+                opened_debug_file,
+                step,
+                level,
+            },
+            open_err,
+        )
+    }
+}
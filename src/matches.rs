@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(feature = "clap")]
+use clap::{parser::ValueSource, ArgMatches};
+use log::warn;
+
+use crate::arg::normalize_bool;
+
+/// Parsed command line options
+///
+/// Querying an option which has not been registered (or before the command line
+/// has been parsed) behaves as if the option has not been passed.
+#[derive(Debug, Default)]
+pub struct ExtcapMatches {
+    values: HashMap<String, Vec<String>>,
+    os_values: HashMap<String, Vec<OsString>>,
+    #[cfg(feature = "clap")]
+    pub(crate) matches: ArgMatches,
+}
+
+impl ExtcapMatches {
+    #[cfg(not(feature = "clap"))]
+    pub(crate) fn from_values(os_values: HashMap<String, Vec<OsString>>) -> Self {
+        Self {
+            values: lossy_values(&os_values),
+            os_values,
+        }
+    }
+
+    #[cfg(feature = "clap")]
+    pub(crate) fn from_clap(matches: ArgMatches, opts: &[(String, bool)]) -> Self {
+        let os_values = opts
+            .iter()
+            // flags are always set, keep the options passed on the command line only
+            .filter(|(name, _)| matches.value_source(name) == Some(ValueSource::CommandLine))
+            .map(|(name, takes_value)| {
+                let vals = match matches.try_get_raw(name) {
+                    Ok(Some(vals)) if *takes_value => vals.map(OsStr::to_os_string).collect(),
+                    _ => Vec::new(),
+                };
+                (name.clone(), vals)
+            })
+            .collect();
+        Self {
+            values: lossy_values(&os_values),
+            os_values,
+            matches,
+        }
+    }
+
+    /// Sets the option as if it has been passed
+    #[cfg(feature = "standalone")]
+    pub(crate) fn insert(&mut self, name: &str, values: Vec<OsString>) {
+        self.values.insert(
+            name.to_owned(),
+            values
+                .iter()
+                .map(|v| v.to_string_lossy().into_owned())
+                .collect(),
+        );
+        self.os_values.insert(name.to_owned(), values);
+    }
+
+    /// Returns `true` if the option has been passed
+    pub fn is_present(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// Gets the option value, the last one if passed multiple times
+    ///
+    /// Only the options of the interface arguments may be passed multiple times,
+    /// e.g. by Wireshark after a profile migration. A warning is logged then unless
+    /// it's a multicheck argument.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).into_iter().last()
+    }
+
+    /// Gets all the option values in the passed order
+    pub fn values(&self, name: &str) -> Vec<&str> {
+        self.values
+            .get(name)
+            .map(|vals| vals.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Gets the option value as passed, not necessarily valid UTF-8, the last one
+    /// if passed multiple times
+    ///
+    /// `value` and `values` replace the invalid UTF-8 sequences.
+    pub fn value_os(&self, name: &str) -> Option<&OsStr> {
+        self.values_os(name).into_iter().last()
+    }
+
+    /// Gets all the option values as passed
+    pub fn values_os(&self, name: &str) -> Vec<&OsStr> {
+        self.os_values
+            .get(name)
+            .map(|vals| vals.iter().map(OsString::as_os_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Gets the option value as a path
+    pub fn value_path(&self, name: &str) -> Option<&Path> {
+        self.value_os(name).map(Path::new)
+    }
+
+    /// Gets the option value parsed as `T`, an invalid value is logged and ignored
+    pub fn value_parsed<T: FromStr>(&self, name: &str) -> Option<T> {
+        let val = self.value(name)?;
+        match val.parse() {
+            Ok(v) => Some(v),
+            Err(_) => {
+                warn!("invalid value '{}' of option --{}", val, name);
+                None
+            }
+        }
+    }
+
+    /// Gets the option value as a boolean, accepts `true`/`false`, `1`/`0`, `yes`/`no` and `on`/`off`
+    pub fn value_bool(&self, name: &str) -> Option<bool> {
+        self.value(name)
+            .and_then(normalize_bool)
+            .map(|v| v == "true")
+    }
+}
+
+fn lossy_values(os_values: &HashMap<String, Vec<OsString>>) -> HashMap<String, Vec<String>> {
+    os_values
+        .iter()
+        .map(|(name, vals)| {
+            let vals = vals
+                .iter()
+                .map(|v| v.to_string_lossy().into_owned())
+                .collect();
+            (name.clone(), vals)
+        })
+        .collect()
+}
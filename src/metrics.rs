@@ -0,0 +1,131 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "ctrl-pipe")]
+use std::time::Instant;
+
+#[cfg(feature = "ctrl-pipe")]
+use futures::channel::mpsc::{SendError, Sender};
+#[cfg(feature = "ctrl-pipe")]
+use futures::sink::SinkExt;
+
+/// Counters of a channel between the listener and the crate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChannelMetrics {
+    /// Number of items passed through the channel
+    pub enqueued: u64,
+    /// Most items found queued at once
+    pub high_watermark: u64,
+    /// Number of items dropped by the crate
+    pub dropped: u64,
+    /// Total time the sending side was blocked
+    pub blocked: Duration,
+}
+
+/// Metrics of the channels managed by the crate, see `Extcap::collect_channel_metrics`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureChannelMetrics {
+    /// Packets received from the listener
+    ///
+    /// The high watermark counts the packets received back to back without waiting,
+    /// `blocked` is the time spent writing to the fifo, i.e. Wireshark not keeping up.
+    /// The packets discarded after the fifo has been closed are dropped.
+    pub packets: ChannelMetrics,
+    /// Control messages from Wireshark to the listener
+    ///
+    /// `blocked` is the time spent waiting for the listener to make room in the channel,
+    /// the high watermark is not tracked.
+    /// The messages of the controls not relevant to the interface and the ones received
+    /// after the listener dropped the receiver are dropped.
+    pub control_in: ChannelMetrics,
+    /// Control messages from the listener to Wireshark
+    ///
+    /// The high watermark is the largest batch written at once, `blocked` is the time
+    /// the senders waited for room in the channel.
+    pub control_out: ChannelMetrics,
+}
+
+/// Lock-free counters behind `ChannelMetrics`
+#[derive(Debug, Default)]
+pub(crate) struct ChannelCounters {
+    enqueued: AtomicU64,
+    high_watermark: AtomicU64,
+    dropped: AtomicU64,
+    blocked_us: AtomicU64,
+}
+
+impl ChannelCounters {
+    pub(crate) fn enqueued(&self) {
+        self.enqueued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn depth(&self, depth: usize) {
+        self.high_watermark
+            .fetch_max(depth as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn blocked(&self, time: Duration) {
+        self.blocked_us
+            .fetch_add(time.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> ChannelMetrics {
+        ChannelMetrics {
+            enqueued: self.enqueued.load(Ordering::Relaxed),
+            high_watermark: self.high_watermark.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            blocked: Duration::from_micros(self.blocked_us.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Counters of all the channels, shared with the control pipe tasks
+#[derive(Debug, Default)]
+pub(crate) struct MetricsCounters {
+    pub(crate) packets: ChannelCounters,
+    pub(crate) control_in: ChannelCounters,
+    pub(crate) control_out: ChannelCounters,
+}
+
+impl MetricsCounters {
+    pub(crate) fn get(&self) -> CaptureChannelMetrics {
+        CaptureChannelMetrics {
+            packets: self.packets.get(),
+            control_in: self.control_in.get(),
+            control_out: self.control_out.get(),
+        }
+    }
+}
+
+/// `None` when the metrics are not collected
+pub(crate) type SharedMetrics = Option<Arc<MetricsCounters>>;
+
+#[cfg(feature = "ctrl-pipe")]
+/// Sends the item, the time waiting for room in the channel is counted as blocked
+pub(crate) async fn send_counted<T>(
+    sender: &mut Sender<T>,
+    item: T,
+    counters: Option<&ChannelCounters>,
+) -> Result<(), SendError> {
+    let counters = match counters {
+        Some(counters) => counters,
+        None => return sender.send(item).await,
+    };
+    let res = match sender.try_send(item) {
+        Err(e) if e.is_full() => {
+            let started = Instant::now();
+            let res = sender.send(e.into_inner()).await;
+            counters.blocked(started.elapsed());
+            res
+        }
+        res => res.map_err(|e| e.into_send_error()),
+    };
+    if res.is_ok() {
+        counters.enqueued();
+    }
+    res
+}
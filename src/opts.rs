@@ -0,0 +1,70 @@
+//! Command line option names used by Wireshark to invoke extcap utilities
+
+/// `--extcap-version`: Wireshark version, passed along with `--extcap-interfaces`
+pub const OPT_EXTCAP_VERSION: &str = "extcap-version";
+/// `--extcap-interfaces`: list the available interfaces
+pub const OPT_EXTCAP_INTERFACES: &str = "extcap-interfaces";
+/// `--extcap-interface`: the interface the other options refer to
+pub const OPT_EXTCAP_INTERFACE: &str = "extcap-interface";
+/// `--extcap-dlts`: list the DLTs of the interface
+pub const OPT_EXTCAP_DTLS: &str = "extcap-dlts";
+/// `--extcap-config`: list the configuration arguments of the interface
+pub const OPT_EXTCAP_CONFIG: &str = "extcap-config";
+/// `--extcap-reload-option`: reload the values of the given argument, used with `--extcap-config`
+pub const OPT_EXTCAP_RELOAD_OPTION: &str = "extcap-reload-option";
+/// `--capture`: start the capture on the interface
+pub const OPT_CAPTURE: &str = "capture";
+/// `--extcap-capture-filter`: capture filter entered by the user
+pub const OPT_EXTCAP_CAPTURE_FILTER: &str = "extcap-capture-filter";
+/// `--fifo`: file or fifo the captured packets are written to, `-` for stdout
+pub const OPT_FIFO: &str = "fifo";
+/// `--extcap-control-in`: pipe for control messages from the toolbar
+pub const OPT_EXTCAP_CONTROL_IN: &str = "extcap-control-in";
+/// `--extcap-control-out`: pipe for control messages to the toolbar
+pub const OPT_EXTCAP_CONTROL_OUT: &str = "extcap-control-out";
+/// `--extcap-selfcheck`: validate the extcap configuration, handled by the crate
+pub const OPT_EXTCAP_SELFCHECK: &str = "extcap-selfcheck";
+/// `--debug`: print debug messages, an interface argument added by `IFace::config_debug`
+pub const OPT_DEBUG: &str = "debug";
+/// `--debug-file`: file the debug messages are written to, added by `IFace::config_debug`
+pub const OPT_DEBUG_FILE: &str = "debug-file";
+/// `--also-save`: file the capture is copied to, added by `IFace::config_also_save`
+pub const OPT_ALSO_SAVE: &str = "also-save";
+
+/// `--standalone`: run the capture without Wireshark, added by `Extcap::enable_standalone_mode`
+#[cfg(feature = "standalone")]
+pub const OPT_STANDALONE: &str = "standalone";
+/// `--iface`: interface captured in the standalone mode
+#[cfg(feature = "standalone")]
+pub const OPT_STANDALONE_IFACE: &str = "iface";
+/// `--out`: file the standalone capture is written to, `-` for stdout
+#[cfg(feature = "standalone")]
+pub const OPT_STANDALONE_OUT: &str = "out";
+
+/// Options added by the standalone mode
+#[cfg(feature = "standalone")]
+pub(crate) const STANDALONE_OPTIONS: &[&str] =
+    &[OPT_STANDALONE, OPT_STANDALONE_IFACE, OPT_STANDALONE_OUT];
+
+const RESERVED_OPTIONS: &[&str] = &[
+    OPT_EXTCAP_VERSION,
+    OPT_EXTCAP_INTERFACES,
+    OPT_EXTCAP_INTERFACE,
+    OPT_EXTCAP_DTLS,
+    OPT_EXTCAP_CONFIG,
+    OPT_EXTCAP_RELOAD_OPTION,
+    OPT_CAPTURE,
+    OPT_EXTCAP_CAPTURE_FILTER,
+    OPT_FIFO,
+    OPT_EXTCAP_CONTROL_IN,
+    OPT_EXTCAP_CONTROL_OUT,
+    OPT_EXTCAP_SELFCHECK,
+];
+
+/// Returns `true` if the option name (without leading dashes) is used by Wireshark
+/// or handled by the crate itself
+///
+/// `debug` and `debug-file` are not reserved, they are regular interface arguments.
+pub fn is_reserved_option(name: &str) -> bool {
+    RESERVED_OPTIONS.contains(&name)
+}
@@ -0,0 +1,196 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Pace of the packets written to the `PacketSink`, set by `PacketSink::set_pacing`
+///
+/// Meant for replaying a recorded capture: the write blocks until the packet is due.
+/// The schedule is computed from the first paced packet, a late write does not delay
+/// the following ones, so the pace does not drift over a long capture.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Pacing {
+    /// Written as fast as the fifo accepts them
+    #[default]
+    Unpaced,
+    /// Written at the pace of their timestamps, `speedup` times faster
+    Realtime {
+        /// Greater than 1 replays faster, less than 1 slower
+        speedup: f64,
+    },
+    /// At most the given number of packets per second, spread evenly
+    MaxPacketsPerSec(u32),
+}
+
+impl Pacing {
+    pub(crate) fn check(self) -> io::Result<()> {
+        let valid = match self {
+            Pacing::Unpaced => true,
+            Pacing::Realtime { speedup } => speedup.is_finite() && speedup > 0.0,
+            Pacing::MaxPacketsPerSec(rate) => rate > 0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid pacing {:?}", self),
+            ))
+        }
+    }
+}
+
+/// Schedule of the paced packets
+#[derive(Debug)]
+pub(crate) struct Pacer {
+    pacing: Pacing,
+    start: Option<(Instant, Duration)>, // the first packet written and its timestamp
+    packets: u64,
+}
+
+impl Pacer {
+    pub(crate) fn new(pacing: Pacing) -> Self {
+        Self {
+            pacing,
+            start: None,
+            packets: 0,
+        }
+    }
+
+    /// Returns how long to wait before writing the packet with the timestamp
+    pub(crate) fn delay(&mut self, ts: Duration) -> Duration {
+        self.delay_at(Instant::now(), ts)
+    }
+
+    /// Returns how long to wait from `now` before writing the packet with the timestamp
+    fn delay_at(&mut self, now: Instant, ts: Duration) -> Duration {
+        let (started, first_ts) = *self.start.get_or_insert((now, ts));
+        let offset = match self.pacing {
+            Pacing::Unpaced => Duration::ZERO,
+            Pacing::Realtime { speedup } => {
+                Duration::from_secs_f64(ts.saturating_sub(first_ts).as_secs_f64() / speedup)
+            }
+            Pacing::MaxPacketsPerSec(rate) => {
+                Duration::from_secs_f64(self.packets as f64 / f64::from(rate))
+            }
+        };
+        self.packets += 1;
+        (started + offset).saturating_duration_since(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use pcap_file::DataLink;
+
+    use super::*;
+    use crate::capture::SinkOptions;
+    use crate::test_support::TempPath;
+    use crate::{CaptureHeader, PacketSink, StopToken};
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Delays of the packets with the timestamps written at the given times
+    fn delays(pacing: Pacing, writes: &[(u64, u64)]) -> Vec<Duration> {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(pacing);
+        writes
+            .iter()
+            .map(|&(at, ts)| pacer.delay_at(start + ms(at), ms(ts)))
+            .collect()
+    }
+
+    #[test]
+    fn realtime_follows_the_timestamps() {
+        let pacing = Pacing::Realtime { speedup: 1.0 };
+        // the first timestamp is the origin, not the epoch
+        let res = delays(pacing, &[(0, 5000), (0, 5100), (100, 5300), (300, 5300)]);
+        assert_eq!(res, [ms(0), ms(100), ms(200), ms(0)]);
+    }
+
+    #[test]
+    fn realtime_speedup() {
+        let res = delays(
+            Pacing::Realtime { speedup: 4.0 },
+            &[(0, 0), (0, 400), (0, 800)],
+        );
+        assert_eq!(res, [ms(0), ms(100), ms(200)]);
+        let res = delays(Pacing::Realtime { speedup: 0.5 }, &[(0, 0), (0, 400)]);
+        assert_eq!(res, [ms(0), ms(800)]);
+    }
+
+    #[test]
+    fn late_write_does_not_drift() {
+        // the second packet is written 150 ms late, the fourth is still due at 300 ms
+        let res = delays(
+            Pacing::MaxPacketsPerSec(10),
+            &[(0, 0), (250, 0), (250, 0), (250, 0)],
+        );
+        assert_eq!(res, [ms(0), ms(0), ms(0), ms(50)]);
+        // over many packets the schedule stays on the first one
+        let mut pacer = Pacer::new(Pacing::MaxPacketsPerSec(1000));
+        let start = Instant::now();
+        assert_eq!(pacer.delay_at(start, ms(0)), ms(0));
+        for i in 1..10_000u64 {
+            pacer.delay_at(start + ms(i) + Duration::from_micros(300), ms(0));
+        }
+        assert_eq!(pacer.delay_at(start + ms(10_000), ms(0)), ms(0));
+        assert_eq!(pacer.delay_at(start + ms(10_000), ms(0)), ms(1));
+    }
+
+    #[test]
+    fn invalid_pacing_refused() {
+        for pacing in [
+            Pacing::Realtime { speedup: 0.0 },
+            Pacing::Realtime { speedup: -1.0 },
+            Pacing::Realtime { speedup: f64::NAN },
+            Pacing::MaxPacketsPerSec(0),
+        ] {
+            assert!(pacing.check().is_err(), "{:?}", pacing);
+        }
+        assert!(Pacing::Unpaced.check().is_ok());
+    }
+
+    fn sink(fifo: &TempPath, stop: &StopToken) -> PacketSink {
+        let options = SinkOptions {
+            stop: Some(stop.clone()),
+            ..Default::default()
+        };
+        let header = CaptureHeader::from_data_link(DataLink::ETHERNET);
+        PacketSink::create(&fifo.0, header, options).unwrap()
+    }
+
+    #[test]
+    fn packet_rate_paces_the_writes() {
+        let fifo = TempPath::new("rate.pcap");
+        let stop = StopToken::default();
+        let mut sink = sink(&fifo, &stop);
+        sink.set_pacing(Pacing::MaxPacketsPerSec(50)).unwrap();
+        let started = Instant::now();
+        for _ in 0..6 {
+            sink.write(0, 0, &[0], 1).unwrap();
+        }
+        assert!(started.elapsed() >= ms(100), "{:?}", started.elapsed());
+    }
+
+    #[test]
+    fn stop_ends_the_wait() {
+        let fifo = TempPath::new("stop.pcap");
+        let stop = StopToken::default();
+        let mut sink = sink(&fifo, &stop);
+        sink.set_pacing(Pacing::Realtime { speedup: 1.0 }).unwrap();
+        sink.write(0, 0, &[0], 1).unwrap();
+        let stopper = stop.clone();
+        thread::spawn(move || {
+            thread::sleep(ms(100));
+            stopper.stop();
+        });
+        // due in an hour
+        let started = Instant::now();
+        sink.write(3600, 0, &[0], 1).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(sink.is_stopped());
+    }
+}
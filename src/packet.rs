@@ -0,0 +1,113 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use pcap_file::pcap::PcapPacket;
+
+use crate::{CaptureHeader, Clock, ExtcapResult, PacketSink};
+
+/// Direction of the packet
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Direction not known
+    #[default]
+    Unknown,
+    /// Received packet
+    In,
+    /// Sent packet
+    Out,
+}
+
+/// Builder of a captured packet record
+///
+/// Unset fields take the defaults: the time is the current time of the
+/// `Extcap::clock`, `orig_len` is the length of the data. Data longer than the
+/// header snaplen is truncated.
+///
+/// ```no_run
+/// # use extcap::{Direction, ExtcapResult, PacketBuilder, PacketSink};
+/// # fn f(sink: &mut PacketSink, buf: &[u8]) -> ExtcapResult<()> {
+/// PacketBuilder::new()
+///     .data(buf)
+///     .direction(Direction::In)
+///     .write_to(sink)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct PacketBuilder<'a> {
+    time: Option<SystemTime>,
+    data: &'a [u8],
+    orig_len: Option<u32>,
+    direction: Direction,
+    comment: Option<String>,
+}
+
+impl<'a> PacketBuilder<'a> {
+    /// Creates the builder of an empty packet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the packet timestamp
+    pub fn time(mut self, time: SystemTime) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Sets the packet data
+    pub fn data(mut self, data: &'a [u8]) -> Self {
+        self.data = data;
+        self
+    }
+
+    /// Sets the original length of the packet when the data has been truncated
+    pub fn orig_len(mut self, orig_len: u32) -> Self {
+        self.orig_len = Some(orig_len);
+        self
+    }
+
+    /// Sets the packet direction
+    ///
+    /// Not stored in the pcap format.
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Sets the packet comment
+    ///
+    /// Not stored in the pcap format.
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.comment = Some(comment.to_owned());
+        self
+    }
+
+    /// Gets the packet direction
+    pub fn get_direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Gets the packet comment
+    pub fn get_comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    /// Builds the pcap packet for the capture header, the data truncated to its snaplen
+    ///
+    /// For the async-api convert the result `into()` an `ExtcapPacket` before sending it.
+    pub fn build(&self, header: &CaptureHeader, clock: &Clock) -> PcapPacket<'a> {
+        let since_epoch = self
+            .time
+            .unwrap_or_else(|| clock.now())
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let (data, orig_len) =
+            header.truncate(self.data, self.orig_len.unwrap_or(self.data.len() as u32));
+        PcapPacket::new(since_epoch, orig_len, data)
+    }
+
+    /// Writes the packet to the sink
+    pub fn write_to(&self, sink: &mut PacketSink) -> ExtcapResult<()> {
+        let pkt = self.build(sink.get_header(), sink.get_clock());
+        sink.write_packet(&pkt)
+    }
+}
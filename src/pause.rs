@@ -0,0 +1,40 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Pause state of the capture
+///
+/// Cheap to clone, all clones share the state. While paused the packets written to
+/// the `PacketSink` are suppressed, the listener keeps reading its source and the
+/// suppressed packets are counted in `CaptureStats::suppressed`. Flipped by the toolbar
+/// control set by `Extcap::pause_control`, or by the listener itself.
+#[derive(Clone, Debug, Default)]
+pub struct PauseState {
+    paused: Arc<AtomicBool>,
+}
+
+impl PauseState {
+    /// Creates a new instance of `PauseState` which is not paused
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pauses the capture
+    pub fn pause(&self) {
+        self.set_paused(true);
+    }
+
+    /// Resumes the capture
+    pub fn resume(&self) {
+        self.set_paused(false);
+    }
+
+    /// Pauses or resumes the capture, returns `true` if the state has changed
+    pub fn set_paused(&self, paused: bool) -> bool {
+        self.paused.swap(paused, Ordering::SeqCst) != paused
+    }
+
+    /// Checks whether the capture is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+}
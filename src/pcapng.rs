@@ -0,0 +1,1172 @@
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use pcap_file::DataLink;
+
+use crate::{CaptureHeader, Clock, Direction, ExtcapResult, PacketBuilder, TsResolution};
+
+const BLOCK_SHB: u32 = 0x0A0D_0D0A;
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_ISB: u32 = 0x0000_0005;
+const BLOCK_EPB: u32 = 0x0000_0006;
+const BLOCK_DSB: u32 = 0x0000_000A;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const OPT_SHB_OS: u16 = 3;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_DESCRIPTION: u16 = 3;
+const OPT_IF_TSRESOL: u16 = 9;
+const OPT_EPB_FLAGS: u16 = 2;
+const OPT_ISB_STARTTIME: u16 = 2;
+const OPT_ISB_ENDTIME: u16 = 3;
+const OPT_ISB_IFRECV: u16 = 4;
+const OPT_ISB_IFDROP: u16 = 5;
+const OPT_VALUE_MAX: usize = u16::MAX as usize;
+
+/// Format of the capture written to the fifo, see `Extcap::set_output_format`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Legacy pcap written by `ExtcapListener::capture2`
+    #[default]
+    Pcap,
+    /// pcapng written by `ExtcapListener::capture_ng`, supports the packet comments
+    /// and directions and several link types
+    PcapNg,
+    /// Byte stream written by `ExtcapListener::capture_raw`, e.g. relayed from a remote
+    /// capture. The crate writes no header
+    Raw,
+}
+
+/// Secrets type of the TLS key log, `SSLKEYLOGFILE` lines, see `PcapNgWriter::write_secrets`
+pub const SECRETS_TLS_KEY_LOG: u32 = 0x544c_534b;
+/// Secrets type of the WireGuard key log, see `PcapNgWriter::write_secrets`
+pub const SECRETS_WIREGUARD_KEY_LOG: u32 = 0x5747_4b4c;
+
+/// Metadata of the pcapng Section Header Block, see `Extcap::set_pcapng_section`
+///
+/// By default `shb_os` is the OS the extcap is built for, `std::env::consts::OS`,
+/// and `shb_userappl` the name and the version of the `Extcap`, e.g. `mydump 1.2.0`.
+/// Each can be overridden or left out.
+///
+/// ```
+/// # use extcap::SectionMetadata;
+/// let section = SectionMetadata::new().os("Linux 6.1").no_userappl();
+/// assert_eq!(section.get_os(), Some("Linux 6.1"));
+/// assert_eq!(section.get_userappl("mydump 1.2.0"), None);
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SectionMetadata {
+    os: MetadataValue,
+    userappl: MetadataValue,
+}
+
+/// Option of the section header, written with the default value unless set
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+enum MetadataValue {
+    #[default]
+    Default,
+    Value(String),
+    Omitted,
+}
+
+impl MetadataValue {
+    fn resolve<'a>(&'a self, default: &'a str) -> Option<&'a str> {
+        match self {
+            MetadataValue::Default => Some(default),
+            MetadataValue::Value(value) => Some(value),
+            MetadataValue::Omitted => None,
+        }
+    }
+}
+
+impl SectionMetadata {
+    /// Creates the metadata with the defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `shb_os`, e.g. with the OS version
+    pub fn os(mut self, os: &str) -> Self {
+        self.os = MetadataValue::Value(os.to_owned());
+        self
+    }
+
+    /// Leaves `shb_os` out
+    pub fn no_os(mut self) -> Self {
+        self.os = MetadataValue::Omitted;
+        self
+    }
+
+    /// Sets `shb_userappl`
+    pub fn userappl(mut self, userappl: &str) -> Self {
+        self.userappl = MetadataValue::Value(userappl.to_owned());
+        self
+    }
+
+    /// Leaves `shb_userappl` out
+    pub fn no_userappl(mut self) -> Self {
+        self.userappl = MetadataValue::Omitted;
+        self
+    }
+
+    /// Returns `shb_os` written, `None` if left out
+    pub fn get_os(&self) -> Option<&str> {
+        self.os.resolve(std::env::consts::OS)
+    }
+
+    /// Returns `shb_userappl` written with the given default, `None` if left out
+    pub fn get_userappl<'a>(&'a self, default: &'a str) -> Option<&'a str> {
+        self.userappl.resolve(default)
+    }
+}
+
+/// Interface Description Block of the pcapng output
+///
+/// The ones returned by `ExtcapListener::capture_interfaces_ng` are written when
+/// the capture starts, e.g. one per aggregated radio, more can be added by
+/// `PcapNgWriter::add_interface`, e.g. for another link type.
+/// The packets refer to them by the interface id, the index in the order added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceDescription {
+    link_type: u32,
+    snaplen: u32,
+    ts_resolution: TsResolution,
+    name: Option<String>,
+    description: Option<String>,
+}
+
+impl InterfaceDescription {
+    /// Creates the interface for the given link type (DLT), snaplen 65535
+    /// and microsecond timestamps
+    pub fn new(link_type: u32) -> Self {
+        Self::from_header(&CaptureHeader::new(link_type))
+    }
+
+    /// Creates the interface for the given `DataLink`, e.g. `DataLink::USER10`
+    pub fn from_data_link(data_link: DataLink) -> Self {
+        Self::new(data_link.into())
+    }
+
+    /// Creates the interface with the link type, snaplen and timestamp resolution
+    /// of the pcap header
+    pub fn from_header(header: &CaptureHeader) -> Self {
+        Self {
+            link_type: header.link_type,
+            snaplen: header.snaplen,
+            ts_resolution: header.ts_resolution,
+            name: None,
+            description: None,
+        }
+    }
+
+    /// Sets the maximum number of bytes stored per packet, longer packets are truncated
+    pub fn snaplen(mut self, snaplen: u32) -> Self {
+        self.snaplen = snaplen;
+        self
+    }
+
+    /// Uses nanosecond timestamps instead of microsecond ones
+    pub fn nanos(mut self, nanos: bool) -> Self {
+        self.ts_resolution = if nanos {
+            TsResolution::NanoSecond
+        } else {
+            TsResolution::MicroSecond
+        };
+        self
+    }
+
+    /// Sets the interface name, `if_name`
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Sets the interface description, `if_description`
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_owned());
+        self
+    }
+
+    /// Gets the link type (DLT)
+    pub fn get_link_type(&self) -> u32 {
+        self.link_type
+    }
+
+    /// Gets the snaplen
+    pub fn get_snaplen(&self) -> u32 {
+        self.snaplen
+    }
+
+    /// Gets the timestamp resolution
+    pub fn get_ts_resolution(&self) -> TsResolution {
+        self.ts_resolution
+    }
+
+    /// Gets the interface name
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Gets the interface description
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The pcap header with the same link type, snaplen and timestamp resolution
+    fn to_header(&self) -> CaptureHeader {
+        CaptureHeader {
+            link_type: self.link_type,
+            snaplen: self.snaplen,
+            ts_resolution: self.ts_resolution,
+            ..Default::default()
+        }
+    }
+}
+
+/// Writer of the pcapng output, see `ExtcapListener::capture_ng`
+///
+/// The Section Header Block and the first Interface Description Block are written
+/// when the writer is created. The packets are written as Enhanced Packet Blocks,
+/// each one by a single write. An Interface Statistics Block per interface is written
+/// when the writer is dropped, i.e. when `ExtcapListener::capture_ng` returns, with
+/// the counts reported by `set_received_count` and `set_drop_count`.
+pub struct PcapNgWriter<W: Write> {
+    writer: Option<W>,
+    interfaces: Vec<Interface>,
+    clock: Clock,
+}
+
+/// Interface described in the output with its statistics
+struct Interface {
+    header: CaptureHeader,
+    name: Option<String>,
+    started_at: SystemTime,
+    received: Option<u64>,
+    dropped: Option<u64>,
+}
+
+impl<W: Write> PcapNgWriter<W> {
+    /// Creates the writer, writes the section header and the interface as the interface 0
+    ///
+    /// The section header has the default `shb_os` and no `shb_userappl`, see
+    /// `with_section`.
+    pub fn new(writer: W, interface: &InterfaceDescription) -> ExtcapResult<Self> {
+        Self::with_section(writer, interface, &SectionMetadata::new().no_userappl())
+    }
+
+    /// Creates the writer with the metadata of the section header, `shb_userappl`
+    /// is left out unless set
+    pub fn with_section(
+        writer: W,
+        interface: &InterfaceDescription,
+        section: &SectionMetadata,
+    ) -> ExtcapResult<Self> {
+        let userappl = match &section.userappl {
+            MetadataValue::Value(userappl) => Some(userappl.as_str()),
+            _ => None,
+        };
+        let os = section.get_os();
+        Ok(Self::with_clock(
+            writer,
+            interface,
+            os,
+            userappl,
+            Clock::default(),
+        )?)
+    }
+
+    /// Creates the writer with the section header options and the clock of `Extcap`
+    pub(crate) fn with_clock(
+        writer: W,
+        interface: &InterfaceDescription,
+        os: Option<&str>,
+        userappl: Option<&str>,
+        clock: Clock,
+    ) -> io::Result<Self> {
+        let mut ngw = Self {
+            writer: Some(writer),
+            interfaces: Vec::new(),
+            clock,
+        };
+        let mut opts = Vec::new();
+        if let Some(os) = os {
+            put_str_option(&mut opts, OPT_SHB_OS, os);
+        }
+        if let Some(userappl) = userappl {
+            put_str_option(&mut opts, OPT_SHB_USERAPPL, userappl);
+        }
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes()); // major version
+        body.extend_from_slice(&0u16.to_le_bytes()); // minor version
+        body.extend_from_slice(&(-1i64).to_le_bytes()); // section length not known
+        ngw.write_block(BLOCK_SHB, &body, &opts)?;
+        ngw.add_interface(interface)?;
+        Ok(ngw)
+    }
+
+    /// Adds an Interface Description Block, returns its interface id
+    ///
+    /// The ids are assigned in the order added, starting with 0. A name can be used
+    /// by a single interface only.
+    pub fn add_interface(&mut self, interface: &InterfaceDescription) -> io::Result<u32> {
+        let header = interface.to_header();
+        header.check()?;
+        if let Some(name) = interface.get_name() {
+            if self.interface_id(name).is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("interface {} already described", name),
+                ));
+            }
+        }
+        let link_type = u16::try_from(header.link_type).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("link type {:#x} doesn't fit pcapng", header.link_type),
+            )
+        })?;
+        let mut opts = Vec::new();
+        if let Some(name) = interface.get_name() {
+            put_str_option(&mut opts, OPT_IF_NAME, name);
+        }
+        if let Some(description) = interface.get_description() {
+            put_str_option(&mut opts, OPT_IF_DESCRIPTION, description);
+        }
+        if header.ts_resolution == TsResolution::NanoSecond {
+            put_option(&mut opts, OPT_IF_TSRESOL, &[9]);
+        }
+        let mut body = Vec::new();
+        body.extend_from_slice(&link_type.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes()); // reserved
+        body.extend_from_slice(&header.snaplen.to_le_bytes());
+        self.write_block(BLOCK_IDB, &body, &opts)?;
+        self.interfaces.push(Interface {
+            header,
+            name: interface.get_name().map(str::to_owned),
+            started_at: self.clock.now(),
+            received: None,
+            dropped: None,
+        });
+        Ok(self.interfaces.len() as u32 - 1)
+    }
+
+    /// Gets the id of the interface with the name
+    pub fn interface_id(&self, name: &str) -> Option<u32> {
+        self.interfaces
+            .iter()
+            .position(|ifc| ifc.name.as_deref() == Some(name))
+            .map(|id| id as u32)
+    }
+
+    /// Gets the number of interfaces described
+    pub fn interface_count(&self) -> u32 {
+        self.interfaces.len() as u32
+    }
+
+    /// Gets the interface as the pcap header, e.g. for `PacketBuilder::build`
+    pub fn get_interface(&self, interface_id: u32) -> Option<&CaptureHeader> {
+        self.interfaces
+            .get(interface_id as usize)
+            .map(|ifc| &ifc.header)
+    }
+
+    /// Sets the number of packets received by the interface, `isb_ifrecv`
+    ///
+    /// Written in the statistics when the capture ends, see the type documentation.
+    pub fn set_received_count(&mut self, interface_id: u32, received: u64) -> io::Result<()> {
+        self.interface_mut(interface_id)?.received = Some(received);
+        Ok(())
+    }
+
+    /// Sets the number of packets dropped by the interface, `isb_ifdrop`
+    ///
+    /// Shown by Wireshark in the capture statistics, see `set_received_count`.
+    pub fn set_drop_count(&mut self, interface_id: u32, dropped: u64) -> io::Result<()> {
+        self.interface_mut(interface_id)?.dropped = Some(dropped);
+        Ok(())
+    }
+
+    /// Gets the clock configured by `Extcap::clock`
+    pub fn get_clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Writes the packet, the timestamp fraction is in the resolution of the interface
+    ///
+    /// A fraction of a whole second or more fails. Data longer than the interface snaplen
+    /// is truncated.
+    pub fn write(
+        &mut self,
+        interface_id: u32,
+        ts_sec: u32,
+        ts_frac: u32,
+        data: &[u8],
+        orig_len: u32,
+    ) -> io::Result<()> {
+        self.write_ts64(interface_id, ts_sec.into(), ts_frac, data, orig_len)
+    }
+
+    /// Writes the packet with the seconds since the epoch as `u64`
+    ///
+    /// The pcapng timestamp is a 64-bit count of the resolution units, a time past
+    /// it, beyond the year 2554 with nanoseconds, fails. See `write` for the rest.
+    pub fn write_ts64(
+        &mut self,
+        interface_id: u32,
+        ts_sec: u64,
+        ts_frac: u32,
+        data: &[u8],
+        orig_len: u32,
+    ) -> io::Result<()> {
+        self.write_epb(interface_id, ts_sec, ts_frac, data, orig_len, &[])
+    }
+
+    /// Writes the packet with its comment and direction
+    ///
+    /// A comment longer than an option can hold, 65535 bytes, is cut at a character
+    /// boundary.
+    pub fn write_packet(&mut self, interface_id: u32, packet: &PacketBuilder) -> io::Result<()> {
+        let header = *self.interface(interface_id)?;
+        let pkt = packet.build(&header, &self.clock);
+        let mut opts = Vec::new();
+        if let Some(comment) = packet.get_comment() {
+            put_str_option(&mut opts, OPT_COMMENT, comment);
+        }
+        let flags: u32 = match packet.get_direction() {
+            Direction::Unknown => 0,
+            Direction::In => 1,
+            Direction::Out => 2,
+        };
+        if flags != 0 {
+            put_option(&mut opts, OPT_EPB_FLAGS, &flags.to_le_bytes());
+        }
+        self.write_epb(
+            interface_id,
+            pkt.timestamp.as_secs(),
+            header.ts_resolution.fraction(pkt.timestamp),
+            &pkt.data,
+            pkt.orig_len,
+            &opts,
+        )
+    }
+
+    /// Writes the packet captured at the time with the comment shown in the packet details
+    ///
+    /// A shortcut of `write_packet`, see there for the comment limit.
+    pub fn write_with_comment(
+        &mut self,
+        interface_id: u32,
+        time: SystemTime,
+        data: &[u8],
+        comment: &str,
+    ) -> io::Result<()> {
+        let packet = PacketBuilder::new().time(time).data(data).comment(comment);
+        self.write_packet(interface_id, &packet)
+    }
+
+    /// Writes a Decryption Secrets Block, e.g. the TLS session keys
+    ///
+    /// The blocks are written in the order of the calls, Wireshark applies the secrets
+    /// to the packets written after. More blocks can follow during the capture as new
+    /// sessions come up, e.g. one per TLS handshake before its first application data.
+    pub fn write_secrets(&mut self, secrets_type: u32, data: &[u8]) -> io::Result<()> {
+        let len = u32::try_from(data.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "secrets too long for pcapng")
+        })?;
+        let mut body = Vec::with_capacity(8 + data.len() + 3);
+        body.extend_from_slice(&secrets_type.to_le_bytes());
+        body.extend_from_slice(&len.to_le_bytes());
+        body.extend_from_slice(data);
+        body.resize(padded(body.len()), 0);
+        self.write_block(BLOCK_DSB, &body, &[])
+    }
+
+    /// Flushes the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out().flush()
+    }
+
+    /// Gets the underlying writer
+    pub fn get_mut(&mut self) -> &mut W {
+        self.out()
+    }
+
+    /// Consumes the writer, returns the underlying one after writing the statistics
+    pub fn into_writer(mut self) -> W {
+        self.write_statistics();
+        self.writer.take().expect("writer taken")
+    }
+
+    fn out(&mut self) -> &mut W {
+        self.writer.as_mut().expect("writer taken")
+    }
+
+    fn interface(&self, interface_id: u32) -> io::Result<&CaptureHeader> {
+        self.get_interface(interface_id)
+            .ok_or_else(|| not_described(interface_id))
+    }
+
+    fn interface_mut(&mut self, interface_id: u32) -> io::Result<&mut Interface> {
+        self.interfaces
+            .get_mut(interface_id as usize)
+            .ok_or_else(|| not_described(interface_id))
+    }
+
+    /// Writes an Interface Statistics Block per interface, the errors are ignored
+    /// as the fifo may have been closed by Wireshark already
+    fn write_statistics(&mut self) {
+        let ended_at = self.clock.now();
+        let blocks: Vec<_> = self
+            .interfaces
+            .iter()
+            .enumerate()
+            .map(|(id, ifc)| {
+                let ts_resolution = ifc.header.ts_resolution;
+                let mut opts = Vec::new();
+                let started = timestamp(ts_resolution, ifc.started_at);
+                put_option(&mut opts, OPT_ISB_STARTTIME, &started);
+                put_option(
+                    &mut opts,
+                    OPT_ISB_ENDTIME,
+                    &timestamp(ts_resolution, ended_at),
+                );
+                if let Some(received) = ifc.received {
+                    put_option(&mut opts, OPT_ISB_IFRECV, &received.to_le_bytes());
+                }
+                if let Some(dropped) = ifc.dropped {
+                    put_option(&mut opts, OPT_ISB_IFDROP, &dropped.to_le_bytes());
+                }
+                let mut body = Vec::with_capacity(12);
+                body.extend_from_slice(&(id as u32).to_le_bytes());
+                body.extend_from_slice(&timestamp(ts_resolution, ended_at));
+                (body, opts)
+            })
+            .collect();
+        for (body, opts) in blocks {
+            if self.write_block(BLOCK_ISB, &body, &opts).is_err() {
+                return;
+            }
+        }
+        let _ = self.out().flush();
+    }
+
+    fn write_epb(
+        &mut self,
+        interface_id: u32,
+        ts_sec: u64,
+        ts_frac: u32,
+        data: &[u8],
+        orig_len: u32,
+        opts: &[u8],
+    ) -> io::Result<()> {
+        let header = *self.interface(interface_id)?;
+        let ts_resolution = header.ts_resolution;
+        ts_resolution.check_fraction(ts_frac)?;
+        let (data, orig_len) = header.truncate(data, orig_len);
+        let ts = ts_sec
+            .checked_mul(ts_resolution.units_per_second().into())
+            .and_then(|ts| ts.checked_add(ts_frac.into()))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("timestamp {}s out of range of the pcapng timestamp", ts_sec),
+                )
+            })?;
+        let mut body = Vec::with_capacity(20 + data.len() + 3);
+        body.extend_from_slice(&interface_id.to_le_bytes());
+        body.extend_from_slice(&split_timestamp(ts));
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&orig_len.to_le_bytes());
+        body.extend_from_slice(data);
+        body.resize(padded(body.len()), 0);
+        self.write_block(BLOCK_EPB, &body, opts)
+    }
+
+    /// Writes the block by a single write, the options get the end of options appended
+    fn write_block(&mut self, block_type: u32, body: &[u8], opts: &[u8]) -> io::Result<()> {
+        let end = if opts.is_empty() { 0 } else { 4 };
+        let total_len = (12 + body.len() + opts.len() + end) as u32;
+        let mut block = Vec::with_capacity(total_len as usize);
+        block.extend_from_slice(&block_type.to_le_bytes());
+        block.extend_from_slice(&total_len.to_le_bytes());
+        block.extend_from_slice(body);
+        block.extend_from_slice(opts);
+        if end > 0 {
+            block.extend_from_slice(&OPT_END.to_le_bytes());
+            block.extend_from_slice(&0u16.to_le_bytes());
+        }
+        block.extend_from_slice(&total_len.to_le_bytes());
+        self.out().write_all(&block)
+    }
+}
+
+impl<W: Write> Drop for PcapNgWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            self.write_statistics();
+        }
+    }
+}
+
+fn not_described(interface_id: u32) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("interface {} not described", interface_id),
+    )
+}
+
+/// The time in units of the resolution as the high and the low 32 bits
+fn timestamp(ts_resolution: TsResolution, time: SystemTime) -> [u8; 8] {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let units = ts_resolution.units_per_second() as u64;
+    split_timestamp(since_epoch.as_secs() * units + ts_resolution.fraction(since_epoch) as u64)
+}
+
+/// The 64-bit timestamp as the high and the low 32 bits in the little-endian order
+fn split_timestamp(ts: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    bytes[..4].copy_from_slice(&((ts >> 32) as u32).to_le_bytes());
+    bytes[4..].copy_from_slice(&(ts as u32).to_le_bytes());
+    bytes
+}
+
+/// Rounds up to the 32-bit boundary of the pcapng fields
+fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Appends the option with its value padded, longer values are cut
+fn put_option(opts: &mut Vec<u8>, code: u16, value: &[u8]) {
+    let value = &value[..value.len().min(OPT_VALUE_MAX)];
+    opts.extend_from_slice(&code.to_le_bytes());
+    opts.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    opts.extend_from_slice(value);
+    opts.resize(padded(opts.len()), 0);
+}
+
+/// Appends the UTF-8 option, a longer value is cut at a character boundary
+fn put_str_option(opts: &mut Vec<u8>, code: u16, value: &str) {
+    let mut len = value.len().min(OPT_VALUE_MAX);
+    while !value.is_char_boundary(len) {
+        len -= 1;
+    }
+    put_option(opts, code, &value.as_bytes()[..len]);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::test_support::{self, TempPath};
+    use crate::{Extcap, ExtcapListener, ExtcapWriter, IFace};
+
+    /// Block read back from the output
+    #[derive(Debug)]
+    struct Block {
+        block_type: u32,
+        body: Vec<u8>,
+        opts: Vec<(u16, Vec<u8>)>,
+    }
+
+    impl Block {
+        fn opt(&self, code: u16) -> Option<&[u8]> {
+            self.opts
+                .iter()
+                .find(|(c, _)| *c == code)
+                .map(|(_, v)| &v[..])
+        }
+
+        fn str_opt(&self, code: u16) -> Option<&str> {
+            self.opt(code).map(|v| std::str::from_utf8(v).unwrap())
+        }
+
+        fn u32_at(&self, pos: usize) -> u32 {
+            u32::from_le_bytes(self.body[pos..pos + 4].try_into().unwrap())
+        }
+    }
+
+    fn u16_at(data: &[u8], pos: usize) -> u16 {
+        u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap())
+    }
+
+    fn u32_at(data: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+    }
+
+    /// Splits the output to the blocks, checks the lengths and the padding
+    fn read_blocks(data: &[u8]) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let block_type = u32_at(data, pos);
+            let total_len = u32_at(data, pos + 4) as usize;
+            assert_eq!(total_len % 4, 0);
+            assert_eq!(u32_at(data, pos + total_len - 4) as usize, total_len);
+            let content = &data[pos + 8..pos + total_len - 4];
+            let body_len = match block_type {
+                BLOCK_SHB => 16,
+                BLOCK_IDB => 8,
+                BLOCK_EPB => 20 + padded(u32_at(content, 12) as usize),
+                BLOCK_ISB => 12,
+                BLOCK_DSB => 8 + padded(u32_at(content, 4) as usize),
+                _ => panic!("unexpected block {:#x}", block_type),
+            };
+            let mut opts = Vec::new();
+            let mut opt = body_len;
+            while opt < content.len() {
+                let (code, len) = (u16_at(content, opt), u16_at(content, opt + 2) as usize);
+                if code == OPT_END {
+                    assert_eq!(opt + 4, content.len());
+                    break;
+                }
+                opts.push((code, content[opt + 4..opt + 4 + len].to_vec()));
+                opt += 4 + padded(len);
+            }
+            blocks.push(Block {
+                block_type,
+                body: content[..body_len].to_vec(),
+                opts,
+            });
+            pos += total_len;
+        }
+        blocks
+    }
+
+    const LINKTYPE_IEEE802_15_4: u32 = 195;
+    const LINKTYPE_BLUETOOTH_LE_LL: u32 = 251;
+
+    fn ethernet() -> InterfaceDescription {
+        InterfaceDescription::from_data_link(DataLink::ETHERNET)
+    }
+
+    fn packets(blocks: &[Block]) -> Vec<&Block> {
+        blocks
+            .iter()
+            .filter(|b| b.block_type == BLOCK_EPB)
+            .collect()
+    }
+
+    #[test]
+    fn comment_read_back() {
+        let mut writer = PcapNgWriter::new(Vec::new(), &ethernet()).unwrap();
+        let time = UNIX_EPOCH + std::time::Duration::from_micros(1_500_000);
+        writer
+            .write_with_comment(0, time, &[1, 2, 3], "checksum error")
+            .unwrap();
+        writer.write(0, 2, 0, &[4], 1).unwrap();
+        let blocks = read_blocks(&writer.into_writer());
+        let epbs = packets(&blocks);
+        assert_eq!(epbs.len(), 2);
+        assert_eq!(epbs[0].str_opt(OPT_COMMENT), Some("checksum error"));
+        assert_eq!(epbs[0].u32_at(8), 1_500_000); // low 32 bits of the microseconds
+        assert_eq!(&epbs[0].body[20..23], [1, 2, 3]);
+        assert!(epbs[1].opts.is_empty());
+    }
+
+    #[test]
+    fn long_comment_cut_at_a_character() {
+        let mut writer = PcapNgWriter::new(Vec::new(), &ethernet()).unwrap();
+        let comment = "é".repeat(40_000);
+        writer
+            .write_with_comment(0, UNIX_EPOCH, &[0], &comment)
+            .unwrap();
+        let blocks = read_blocks(&writer.into_writer());
+        let cut = packets(&blocks)[0].str_opt(OPT_COMMENT).unwrap();
+        assert_eq!(cut.len(), OPT_VALUE_MAX - 1);
+        assert!(comment.starts_with(cut));
+    }
+
+    #[test]
+    fn direction_written_as_flags() {
+        let mut writer = PcapNgWriter::new(Vec::new(), &ethernet()).unwrap();
+        for direction in [Direction::In, Direction::Out, Direction::Unknown] {
+            let packet = PacketBuilder::new().data(&[0]).direction(direction);
+            writer.write_packet(0, &packet).unwrap();
+        }
+        let blocks = read_blocks(&writer.into_writer());
+        let flags: Vec<_> = packets(&blocks)
+            .iter()
+            .map(|b| b.opt(OPT_EPB_FLAGS).map(|v| u32_at(v, 0)))
+            .collect();
+        assert_eq!(flags, [Some(1), Some(2), None]);
+    }
+
+    #[test]
+    fn interface_ids_in_the_order_added() {
+        let radio = |name: &str| ethernet().name(name);
+        let mut writer = PcapNgWriter::new(Vec::new(), &radio("radio0")).unwrap();
+        assert_eq!(writer.add_interface(&radio("radio1")).unwrap(), 1);
+        let unnamed = InterfaceDescription::new(LINKTYPE_IEEE802_15_4);
+        assert_eq!(writer.add_interface(&unnamed).unwrap(), 2);
+        assert_eq!(writer.interface_count(), 3);
+        assert_eq!(writer.interface_id("radio1"), Some(1));
+        assert_eq!(writer.interface_id("radio2"), None);
+        // a name describes a single interface
+        assert!(writer.add_interface(&radio("radio0")).is_err());
+        assert_eq!(writer.interface_count(), 3);
+        assert!(writer.write(3, 0, 0, &[0], 1).is_err());
+        assert!(writer
+            .add_interface(&InterfaceDescription::new(0x1_0000))
+            .is_err());
+    }
+
+    /// Writes a packet per stream through the stream handles
+    struct Radios;
+
+    impl ExtcapListener for Radios {
+        fn capture_interfaces_ng(&mut self, _: &Extcap, _: &IFace) -> Vec<InterfaceDescription> {
+            vec![
+                ethernet().name("eth").description("Wired"),
+                InterfaceDescription::new(LINKTYPE_IEEE802_15_4).name("zigbee"),
+                InterfaceDescription::new(LINKTYPE_BLUETOOTH_LE_LL).name("ble"),
+            ]
+        }
+
+        fn capture_ng(
+            &mut self,
+            _: &Extcap,
+            _: &IFace,
+            mut writer: PcapNgWriter<ExtcapWriter>,
+        ) -> ExtcapResult<()> {
+            for (name, data) in [("ble", 3), ("eth", 1), ("zigbee", 2), ("ble", 4)] {
+                let id = writer.interface_id(name).unwrap();
+                writer.write(id, 0, 0, &[data], 1)?;
+            }
+            writer.set_drop_count(2, 5)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stream_per_interface() {
+        let fifo = TempPath::new("radios.pcapng");
+        let mut extcap = Extcap::new("radiodump");
+        extcap.add_interface(IFace::new("radios")).unwrap();
+        extcap.set_output_format(OutputFormat::PcapNg);
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "radios",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        let (res, _) = test_support::run(extcap, Radios, &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+
+        let blocks = read_blocks(&std::fs::read(&fifo.0).unwrap());
+        let idbs: Vec<_> = blocks
+            .iter()
+            .filter(|b| b.block_type == BLOCK_IDB)
+            .map(|b| (b.u32_at(0) & 0xFFFF, b.str_opt(OPT_IF_NAME)))
+            .collect();
+        let expected = [
+            (1, Some("eth")),
+            (LINKTYPE_IEEE802_15_4, Some("zigbee")),
+            (LINKTYPE_BLUETOOTH_LE_LL, Some("ble")),
+        ];
+        assert_eq!(idbs, expected);
+        assert_eq!(blocks[1].str_opt(OPT_IF_DESCRIPTION), Some("Wired"));
+        // the interface id and the data of each packet
+        let epbs: Vec<_> = packets(&blocks)
+            .iter()
+            .map(|b| (b.u32_at(0), b.body[20]))
+            .collect();
+        assert_eq!(epbs, [(2, 3), (0, 1), (1, 2), (2, 4)]);
+        let isbs: Vec<_> = blocks
+            .iter()
+            .filter(|b| b.block_type == BLOCK_ISB)
+            .map(|b| (b.u32_at(0), b.opt(OPT_ISB_IFDROP).map(|v| v[0])))
+            .collect();
+        assert_eq!(isbs, [(0, None), (1, None), (2, Some(5))]);
+    }
+
+    /// Writes the headers of the default interface only
+    struct HeadersOnly;
+
+    impl ExtcapListener for HeadersOnly {
+        fn capture_ng(
+            &mut self,
+            _: &Extcap,
+            _: &IFace,
+            _: PcapNgWriter<ExtcapWriter>,
+        ) -> ExtcapResult<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs a capture of `shbdump` 1.2.3, returns the section and the interface headers
+    fn capture_headers(section: Option<SectionMetadata>) -> (Block, Block) {
+        let fifo = TempPath::new("shb.pcapng");
+        let mut extcap = Extcap::new("shbdump");
+        extcap.version("1.2.3");
+        let ifc = IFace::new("usb0").description("USB adapter");
+        extcap.add_interface(ifc).unwrap();
+        extcap.set_output_format(OutputFormat::PcapNg);
+        if let Some(section) = section {
+            extcap.set_pcapng_section(section);
+        }
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "usb0",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        let (res, _) = test_support::run(extcap, HeadersOnly, &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+
+        let mut blocks = read_blocks(&std::fs::read(&fifo.0).unwrap()).into_iter();
+        let (shb, idb) = (blocks.next().unwrap(), blocks.next().unwrap());
+        assert_eq!((shb.block_type, idb.block_type), (BLOCK_SHB, BLOCK_IDB));
+        (shb, idb)
+    }
+
+    #[test]
+    fn section_and_interface_metadata() {
+        let (shb, idb) = capture_headers(None);
+        assert_eq!(shb.str_opt(OPT_SHB_OS), Some(std::env::consts::OS));
+        assert_eq!(shb.str_opt(OPT_SHB_USERAPPL), Some("shbdump 1.2.3"));
+        assert_eq!(idb.str_opt(OPT_IF_NAME), Some("usb0"));
+        assert_eq!(idb.str_opt(OPT_IF_DESCRIPTION), Some("USB adapter"));
+    }
+
+    #[test]
+    fn section_metadata_overridden_or_left_out() {
+        let section = SectionMetadata::new().os("Linux 6.1").userappl("probe");
+        let (shb, _) = capture_headers(Some(section));
+        assert_eq!(shb.str_opt(OPT_SHB_OS), Some("Linux 6.1"));
+        assert_eq!(shb.str_opt(OPT_SHB_USERAPPL), Some("probe"));
+
+        let section = SectionMetadata::new().no_os().no_userappl();
+        let (shb, idb) = capture_headers(Some(section));
+        assert!(shb.opts.is_empty(), "{:?}", shb.opts);
+        assert_eq!(idb.str_opt(OPT_IF_NAME), Some("usb0"));
+
+        // the writer created directly has no application by default
+        let writer = PcapNgWriter::new(Vec::new(), &ethernet()).unwrap();
+        let blocks = read_blocks(&writer.into_writer());
+        assert_eq!(blocks[0].str_opt(OPT_SHB_OS), Some(std::env::consts::OS));
+        assert_eq!(blocks[0].opt(OPT_SHB_USERAPPL), None);
+        let section = SectionMetadata::new().userappl("probe");
+        let writer = PcapNgWriter::with_section(Vec::new(), &ethernet(), &section).unwrap();
+        let blocks = read_blocks(&writer.into_writer());
+        assert_eq!(blocks[0].str_opt(OPT_SHB_USERAPPL), Some("probe"));
+    }
+
+    /// Decryption secrets of a DSB, the type and the data
+    fn secrets(block: &Block) -> (u32, &[u8]) {
+        let len = block.u32_at(4) as usize;
+        (block.u32_at(0), &block.body[8..8 + len])
+    }
+
+    #[test]
+    fn secrets_between_the_packets() {
+        let mut writer = PcapNgWriter::new(Vec::new(), &ethernet()).unwrap();
+        writer.write(0, 0, 0, &[1], 1).unwrap();
+        writer
+            .write_secrets(SECRETS_TLS_KEY_LOG, b"CLIENT_RANDOM 01 02\n")
+            .unwrap();
+        writer.write(0, 0, 0, &[2], 1).unwrap();
+        writer
+            .write_secrets(SECRETS_WIREGUARD_KEY_LOG, b"LOCAL_STATIC_PRIVATE_KEY = x\n")
+            .unwrap();
+        writer.write(0, 0, 0, &[3], 1).unwrap();
+        let blocks = read_blocks(&writer.into_writer());
+        let order: Vec<_> = blocks.iter().map(|b| b.block_type).collect();
+        let expected = [
+            BLOCK_SHB, BLOCK_IDB, BLOCK_EPB, BLOCK_DSB, BLOCK_EPB, BLOCK_DSB, BLOCK_EPB, BLOCK_ISB,
+        ];
+        assert_eq!(order, expected);
+        assert_eq!(
+            secrets(&blocks[3]),
+            (SECRETS_TLS_KEY_LOG, &b"CLIENT_RANDOM 01 02\n"[..])
+        );
+        assert_eq!(
+            secrets(&blocks[5]),
+            (
+                SECRETS_WIREGUARD_KEY_LOG,
+                &b"LOCAL_STATIC_PRIVATE_KEY = x\n"[..]
+            )
+        );
+    }
+
+    /// Sends the session keys before the packets of the session
+    struct TlsProxy;
+
+    impl ExtcapListener for TlsProxy {
+        fn capture_interfaces_ng(&mut self, _: &Extcap, _: &IFace) -> Vec<InterfaceDescription> {
+            vec![ethernet()]
+        }
+
+        fn capture_ng(
+            &mut self,
+            _: &Extcap,
+            _: &IFace,
+            mut writer: PcapNgWriter<ExtcapWriter>,
+        ) -> ExtcapResult<()> {
+            for session in 1..=2u8 {
+                let keys = format!("CLIENT_RANDOM {0:02x} {0:02x}\n", session);
+                writer.write_secrets(SECRETS_TLS_KEY_LOG, keys.as_bytes())?;
+                writer.write(0, 0, 0, &[session; 5], 5)?;
+                writer.write(0, 0, 0, &[session; 7], 7)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn secrets_interleaved_in_the_fifo() {
+        let fifo = TempPath::new("tls.pcapng");
+        let mut extcap = Extcap::new("tlsdump");
+        extcap.add_interface(IFace::new("tls")).unwrap();
+        extcap.set_output_format(OutputFormat::PcapNg);
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "tls",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        let (res, _) = test_support::run(extcap, TlsProxy, &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+
+        let blocks = read_blocks(&std::fs::read(&fifo.0).unwrap());
+        // every session's keys precede its packets
+        let stream: Vec<_> = blocks[2..]
+            .iter()
+            .filter(|b| b.block_type != BLOCK_ISB)
+            .map(|b| match b.block_type {
+                BLOCK_DSB => String::from_utf8_lossy(secrets(b).1).trim_end().to_owned(),
+                _ => format!("packet {}", b.body[20]),
+            })
+            .collect();
+        let expected = [
+            "CLIENT_RANDOM 01 01",
+            "packet 1",
+            "packet 1",
+            "CLIENT_RANDOM 02 02",
+            "packet 2",
+            "packet 2",
+        ];
+        assert_eq!(stream, expected);
+    }
+
+    /// The 64-bit timestamp written as the high and the low 32 bits
+    fn ts_at(data: &[u8], pos: usize) -> u64 {
+        (u32_at(data, pos) as u64) << 32 | u32_at(data, pos + 4) as u64
+    }
+
+    #[test]
+    fn statistics_written_at_the_end() {
+        let nanos = Arc::new(AtomicU64::new(10_000_000_000));
+        let clock = Clock::Manual(nanos.clone());
+        let mut writer =
+            PcapNgWriter::with_clock(Vec::new(), &ethernet(), None, None, clock).unwrap();
+        nanos.store(11_000_000_000, Ordering::SeqCst);
+        writer.add_interface(&ethernet().name("eth1")).unwrap();
+        writer.write(1, 11, 0, &[1], 1).unwrap();
+        writer.set_received_count(1, 1000).unwrap();
+        writer.set_drop_count(1, 7).unwrap();
+        assert!(writer.set_drop_count(2, 1).is_err());
+        nanos.store(12_500_000_000, Ordering::SeqCst);
+
+        let blocks = read_blocks(&writer.into_writer());
+        let isbs: Vec<_> = blocks
+            .iter()
+            .filter(|b| b.block_type == BLOCK_ISB)
+            .collect();
+        assert_eq!(blocks.last().unwrap().block_type, BLOCK_ISB);
+        assert_eq!(isbs.len(), 2);
+        let fields: Vec<_> = isbs
+            .iter()
+            .map(|b| {
+                (
+                    b.u32_at(0),
+                    ts_at(&b.body, 4),
+                    ts_at(b.opt(OPT_ISB_STARTTIME).unwrap(), 0),
+                    ts_at(b.opt(OPT_ISB_ENDTIME).unwrap(), 0),
+                    b.opt(OPT_ISB_IFRECV)
+                        .map(|v| u64::from_le_bytes(v.try_into().unwrap())),
+                    b.opt(OPT_ISB_IFDROP)
+                        .map(|v| u64::from_le_bytes(v.try_into().unwrap())),
+                )
+            })
+            .collect();
+        let expected = [
+            (0, 12_500_000, 10_000_000, 12_500_000, None, None),
+            (1, 12_500_000, 11_000_000, 12_500_000, Some(1000), Some(7)),
+        ];
+        assert_eq!(fields, expected);
+    }
+
+    /// Fails the writes once the limit is reached, like a fifo closed by Wireshark
+    struct ClosedAfter(usize);
+
+    impl Write for ClosedAfter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.len() > self.0 {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            self.0 -= buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn statistics_to_a_closed_fifo_ignored() {
+        let mut writer = PcapNgWriter::new(ClosedAfter(1000), &ethernet()).unwrap();
+        writer.set_drop_count(0, 3).unwrap();
+        writer.write(0, 0, 0, &[0; 2000], 2000).unwrap_err();
+        // neither dropping nor taking the writer fails or panics
+        drop(writer);
+        // room for the headers only, the statistics block not written
+        let header_len = PcapNgWriter::new(Vec::new(), &ethernet())
+            .unwrap()
+            .get_mut()
+            .len();
+        let writer = PcapNgWriter::new(ClosedAfter(header_len + 10), &ethernet()).unwrap();
+        assert_eq!(writer.into_writer().0, 10);
+    }
+
+    #[test]
+    fn timestamps_past_2106_written() {
+        let nanos = ethernet().nanos(true);
+        let mut writer = PcapNgWriter::new(Vec::new(), &ethernet()).unwrap();
+        let id = writer.add_interface(&nanos).unwrap();
+        let past_2106 = u64::from(u32::MAX) + 1;
+        writer.write_ts64(0, past_2106, 5, &[1], 1).unwrap();
+        writer.write_ts64(id, past_2106, 5, &[2], 1).unwrap();
+        // the last second the 64-bit count of nanoseconds holds
+        let last = u64::MAX / 1_000_000_000;
+        writer.write_ts64(id, last, 0, &[3], 1).unwrap();
+        assert!(writer.write_ts64(id, last + 1, 0, &[4], 1).is_err());
+        assert!(writer.write_ts64(0, u64::MAX, 0, &[5], 1).is_err());
+        let time = UNIX_EPOCH + std::time::Duration::new(past_2106, 7);
+        writer
+            .write_packet(id, &PacketBuilder::new().time(time).data(&[6]))
+            .unwrap();
+
+        let blocks = read_blocks(&writer.into_writer());
+        let stamps: Vec<_> = packets(&blocks)
+            .iter()
+            .map(|b| (ts_at(&b.body, 4), b.body[20]))
+            .collect();
+        let expected = [
+            (past_2106 * 1_000_000 + 5, 1),
+            (past_2106 * 1_000_000_000 + 5, 2),
+            (last * 1_000_000_000, 3),
+            (past_2106 * 1_000_000_000 + 7, 6),
+        ];
+        assert_eq!(stamps, expected);
+    }
+}
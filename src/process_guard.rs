@@ -0,0 +1,432 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(target_os = "linux")]
+use std::sync::{mpsc, OnceLock};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::task::{Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use crate::{ExtcapResult, StopToken};
+
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(2);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const STDERR_LINES: usize = 20;
+const STDERR_DRAIN: Duration = Duration::from_millis(200);
+
+/// Helper process tied to the capture, e.g. `ssh` producing the captured data
+///
+/// The child is terminated when the guard is dropped or the bound stop token is tripped.
+/// On Unix it gets `SIGTERM` first and `SIGKILL` after the grace period, on Linux it also
+/// gets `SIGTERM` when the extcap dies without a cleanup. On Windows the child runs in a job
+/// object which is terminated, closing the job by the system kills the child even when
+/// the extcap is killed by Wireshark. The stderr of the child is forwarded to the debug log.
+///
+/// The Linux parent death signal is tied to the thread which forks the child, not to the
+/// process, so the children are forked by a thread living as long as the process; a guard
+/// may be spawned from a short-lived or a tokio blocking thread. On Windows the child is
+/// assigned to the job right after it has been spawned, a process it starts before that
+/// escapes the job and survives the termination.
+///
+/// ```no_run
+/// # use std::process::{Command, Stdio};
+/// # use extcap::{ExtcapResult, ProcessGuard, StopToken};
+/// # fn remote(stop: &StopToken) -> ExtcapResult<()> {
+/// let guard = ProcessGuard::spawn(
+///     Command::new("ssh")
+///         .args(["host", "tcpdump", "-U", "-w", "-"])
+///         .stdout(Stdio::piped()),
+/// )?
+/// .stop_on(stop);
+/// let stdout = guard.take_stdout();
+/// # Ok(())
+/// # }
+/// ```
+pub struct ProcessGuard {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    name: String,
+    child: Mutex<Child>,
+    grace: Mutex<Duration>,
+    terminated: AtomicBool,
+    stderr: Arc<Mutex<VecDeque<String>>>, // the last lines
+    stderr_reader: Mutex<Option<thread::JoinHandle<()>>>,
+    #[cfg(windows)]
+    job: Option<job::Job>,
+}
+
+impl ProcessGuard {
+    /// Spawns the command, its stderr is piped into the debug log
+    pub fn spawn(command: &mut Command) -> ExtcapResult<Self> {
+        let name = Path::new(command.get_program())
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        command.stderr(Stdio::piped());
+        #[cfg(target_os = "linux")]
+        set_parent_death_signal(command);
+        #[cfg(target_os = "linux")]
+        let mut child = spawn_long_lived(command)?;
+        #[cfg(not(target_os = "linux"))]
+        let mut child = command.spawn()?;
+        debug!("[{}] process {} spawned", name, child.id());
+        #[cfg(windows)]
+        let job = job::Job::assign(&child)
+            .map_err(|e| warn!("[{}] job object can't be created: {}", name, e))
+            .ok();
+        let stderr_lines = Arc::new(Mutex::new(VecDeque::new()));
+        let stderr_reader = child.stderr.take().map(|stderr| {
+            let name = name.clone();
+            let lines = stderr_lines.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    debug!("[{}] stderr: {}", name, line);
+                    let mut lines = lines.lock().unwrap_or_else(|e| e.into_inner());
+                    if lines.len() == STDERR_LINES {
+                        lines.pop_front();
+                    }
+                    lines.push_back(line);
+                }
+            })
+        });
+        Ok(Self {
+            inner: Arc::new(Inner {
+                name,
+                child: Mutex::new(child),
+                grace: Mutex::new(DEFAULT_GRACE_PERIOD),
+                terminated: AtomicBool::new(false),
+                stderr: stderr_lines,
+                stderr_reader: Mutex::new(stderr_reader),
+                #[cfg(windows)]
+                job,
+            }),
+        })
+    }
+
+    /// Sets how long the child may take to exit after `SIGTERM` before it is killed
+    ///
+    /// Defaults to 2 s. Not used on Windows, the job is terminated at once.
+    pub fn grace_period(self, grace: Duration) -> Self {
+        *self.inner.lock_grace() = grace;
+        self
+    }
+
+    /// Terminates the child once the stop token is tripped
+    ///
+    /// Use the token of `CaptureContext`, it is tripped also when the capture has finished.
+    pub fn stop_on(self, stop: &StopToken) -> Self {
+        stop.on_stop(Waker::from(Arc::new(StopWaker(Arc::downgrade(
+            &self.inner,
+        )))));
+        self
+    }
+
+    /// Gets the process id of the child
+    pub fn get_id(&self) -> u32 {
+        self.inner.lock_child().id()
+    }
+
+    /// Takes the stdout of the child, piped by the command
+    pub fn take_stdout(&self) -> Option<ChildStdout> {
+        self.inner.lock_child().stdout.take()
+    }
+
+    /// Checks whether the child has exited without waiting
+    pub fn try_wait(&self) -> ExtcapResult<Option<ExitStatus>> {
+        Ok(self.inner.lock_child().try_wait()?)
+    }
+
+    /// Waits for the child to exit
+    pub fn wait(&self) -> ExtcapResult<ExitStatus> {
+        loop {
+            // polled, the stop token may need the child meanwhile
+            if let Some(status) = self.try_wait()? {
+                self.inner.finish_stderr();
+                return Ok(status);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Checks whether the termination has been requested by the guard
+    pub fn is_terminated(&self) -> bool {
+        self.inner.terminated.load(Ordering::SeqCst)
+    }
+
+    /// Gets the last lines written by the child to stderr
+    pub fn get_stderr(&self) -> String {
+        let lines = self.inner.stderr.lock().unwrap_or_else(|e| e.into_inner());
+        lines
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Terminates the child and waits for it, up to the grace period on Unix
+    pub fn terminate(&self) {
+        self.inner.signal();
+        self.inner.wait_or_kill();
+    }
+}
+
+impl Drop for ProcessGuard {
+    fn drop(&mut self) {
+        self.terminate();
+    }
+}
+
+impl Inner {
+    fn lock_child(&self) -> MutexGuard<'_, Child> {
+        self.child.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Gives the stderr reader a moment to catch the last lines of the exited child
+    fn finish_stderr(&self) {
+        let reader = self.stderr_reader.lock().map(|mut r| r.take());
+        if let Ok(Some(reader)) = reader {
+            let deadline = Instant::now() + STDERR_DRAIN;
+            while !reader.is_finished() && Instant::now() < deadline {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    fn lock_grace(&self) -> MutexGuard<'_, Duration> {
+        self.grace.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Asks the child to exit, once
+    fn signal(&self) {
+        if self.terminated.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let mut child = self.lock_child();
+        if let Ok(Some(status)) = child.try_wait() {
+            debug!("[{}] process {} exited: {}", self.name, child.id(), status);
+            return;
+        }
+        debug!("[{}] terminating process {}", self.name, child.id());
+        #[cfg(unix)]
+        {
+            // the child is not reaped yet, so the pid can't have been reused
+            let pid = child.id() as libc::pid_t;
+            if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+                warn!(
+                    "[{}] SIGTERM to process {} failed: {}",
+                    self.name,
+                    pid,
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            if let Err(e) = job.terminate() {
+                warn!("[{}] job object can't be terminated: {}", self.name, e);
+            }
+        }
+    }
+
+    /// Waits for the child to exit, kills it after the grace period
+    fn wait_or_kill(&self) {
+        let deadline = Instant::now() + *self.lock_grace();
+        loop {
+            let mut child = self.lock_child();
+            match child.try_wait() {
+                Ok(Some(_)) | Err(_) => return,
+                Ok(None) if Instant::now() >= deadline => {
+                    warn!("[{}] killing process {}", self.name, child.id());
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return;
+                }
+                Ok(None) => {}
+            }
+            drop(child);
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Terminates the child when the stop token is tripped
+struct StopWaker(Weak<Inner>);
+
+impl Wake for StopWaker {
+    fn wake(self: Arc<Self>) {
+        let inner = match self.0.upgrade() {
+            Some(inner) => inner,
+            None => return,
+        };
+        // the signal is sent at once as the process may exit soon after the stop
+        inner.signal();
+        thread::spawn(move || inner.wait_or_kill());
+    }
+}
+
+/// The child gets `SIGTERM` when the extcap dies, e.g. killed by Wireshark
+///
+/// A child whose parent has died before `prctl` is not spawned.
+#[cfg(target_os = "linux")]
+fn set_parent_death_signal(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    let parent = std::process::id() as libc::pid_t;
+    // SAFETY: prctl and getppid are async-signal-safe, nothing is allocated
+    unsafe {
+        command.pre_exec(move || {
+            if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            if libc::getppid() != parent {
+                return Err(std::io::Error::from_raw_os_error(libc::ESRCH));
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Command to spawn with the channel for the command back and the child
+#[cfg(target_os = "linux")]
+type SpawnRequest = (Command, mpsc::Sender<(Command, std::io::Result<Child>)>);
+
+/// Spawns the command from a thread living as long as the process
+///
+/// `PR_SET_PDEATHSIG` fires when the forking thread exits. The command is moved to
+/// the spawner and back, spawned in place if the spawner thread can't be started.
+#[cfg(target_os = "linux")]
+fn spawn_long_lived(command: &mut Command) -> std::io::Result<Child> {
+    static SPAWNER: OnceLock<Option<mpsc::Sender<SpawnRequest>>> = OnceLock::new();
+    let spawner = SPAWNER.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<SpawnRequest>();
+        let spawned = thread::Builder::new()
+            .name("extcap-spawner".to_owned())
+            .spawn(move || {
+                for (mut command, reply) in rx {
+                    let child = command.spawn();
+                    let _ = reply.send((command, child));
+                }
+            });
+        match spawned {
+            Ok(_) => Some(tx),
+            Err(e) => {
+                warn!("spawner thread can't be started: {}", e);
+                None
+            }
+        }
+    });
+    let spawner = match spawner {
+        Some(spawner) => spawner,
+        None => return command.spawn(),
+    };
+    let placeholder = Command::new(command.get_program());
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let request = (std::mem::replace(command, placeholder), reply_tx);
+    if let Err(mpsc::SendError((owned, _))) = spawner.send(request) {
+        *command = owned;
+        return command.spawn();
+    }
+    let (owned, child) = reply_rx
+        .recv()
+        .map_err(|_| std::io::Error::other("spawner thread has exited"))?;
+    *command = owned;
+    child
+}
+
+#[cfg(windows)]
+mod job {
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+    use std::process::Child;
+    use std::ptr;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    /// Job object killing its processes when the last handle is closed
+    pub(super) struct Job(HANDLE);
+
+    // SAFETY: the job handle may be used from any thread
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        /// Creates a job with the child assigned
+        pub(super) fn assign(child: &Child) -> io::Result<Self> {
+            // SAFETY: the handles are valid, the structure is properly sized
+            unsafe {
+                let handle = CreateJobObjectW(ptr::null(), ptr::null());
+                if handle.is_null() {
+                    return Err(io::Error::last_os_error());
+                }
+                let job = Job(handle);
+                let mut info = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+                info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+                if SetInformationJobObject(
+                    job.0,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const _,
+                    std::mem::size_of_val(&info) as u32,
+                ) == 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+                if AssignProcessToJobObject(job.0, child.as_raw_handle() as HANDLE) == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(job)
+            }
+        }
+
+        pub(super) fn terminate(&self) -> io::Result<()> {
+            // SAFETY: the handle is valid until drop
+            if unsafe { TerminateJobObject(self.0, 1) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: the handle is owned
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn child_outlives_spawning_thread() {
+        let guard = thread::spawn(|| ProcessGuard::spawn(Command::new("sleep").arg("5")))
+            .join()
+            .unwrap()
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert!(guard.try_wait().unwrap().is_none());
+        guard.terminate();
+        assert!(guard.wait().is_ok());
+    }
+
+    #[test]
+    fn command_is_kept_after_spawn() {
+        let mut command = Command::new("true");
+        command.arg("kept");
+        let guard = ProcessGuard::spawn(&mut command).unwrap();
+        assert!(guard.wait().unwrap().success());
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["kept"]);
+    }
+}
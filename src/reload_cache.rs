@@ -0,0 +1,111 @@
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::IfArgVal;
+
+const HEADER: &str = "extcap-reload-cache 1";
+
+/// Path of the cache file of an argument, keyed by the utility, interface and argument names
+pub(crate) fn cache_path(dir: &Path, utility: &str, interface: &str, arg: &str) -> PathBuf {
+    let utility: String = utility
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    dir.join(format!(
+        "extcap-reload-{}-{}-{}.cache",
+        utility, interface, arg
+    ))
+}
+
+/// Loads the cached values stored less than `ttl` before `now` (since the epoch)
+///
+/// A missing, stale or corrupt file means no values.
+pub(crate) fn load(path: &Path, now: Duration, ttl: Duration) -> Option<Vec<IfArgVal>> {
+    let text = fs::read_to_string(path).ok()?;
+    let mut lines = text.lines();
+    if lines.next() != Some(HEADER) {
+        return None;
+    }
+    let stored = Duration::from_secs(lines.next()?.strip_prefix("ts=")?.parse().ok()?);
+    // a timestamp from the future is not trusted
+    if stored > now || now - stored > ttl {
+        return None;
+    }
+    lines.map(parse_val).collect()
+}
+
+/// Stores the values with the `now` timestamp (since the epoch)
+///
+/// The file is replaced by a rename so a concurrent reload never reads a partial file.
+pub(crate) fn store(path: &Path, now: Duration, vals: &[IfArgVal]) -> io::Result<()> {
+    let mut text = format!("{}\nts={}\n", HEADER, now.as_secs());
+    for val in vals {
+        let _ = writeln!(
+            text,
+            "{}\t{}\t{}\t{}",
+            hex(val.get_value()),
+            val.get_display().map_or_else(|| "-".to_owned(), hex),
+            flag(val.get_default()),
+            flag(val.get_enabled())
+        );
+    }
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&tmp, text)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+fn parse_val(line: &str) -> Option<IfArgVal> {
+    let mut fields = line.split('\t');
+    let mut val = IfArgVal::new(unhex(fields.next()?)?);
+    match fields.next()? {
+        "-" => {}
+        display => val = val.display(&unhex(display)?),
+    }
+    if let Some(default) = parse_flag(fields.next()?)? {
+        val = val.default(default);
+    }
+    if let Some(enabled) = parse_flag(fields.next()?)? {
+        val = val.enabled(enabled);
+    }
+    fields.next().is_none().then_some(val)
+}
+
+fn hex(text: &str) -> String {
+    text.bytes().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{:02x}", b);
+        out
+    })
+}
+
+fn unhex(hex: &str) -> Option<String> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+fn flag(flag: Option<bool>) -> &'static str {
+    match flag {
+        None => "-",
+        Some(false) => "0",
+        Some(true) => "1",
+    }
+}
+
+fn parse_flag(flag: &str) -> Option<Option<bool>> {
+    match flag {
+        "-" => Some(None),
+        "0" => Some(Some(false)),
+        "1" => Some(Some(true)),
+        _ => None,
+    }
+}
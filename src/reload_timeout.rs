@@ -0,0 +1,116 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::{ExtcapError, ExtcapResult, IfArgVal};
+
+/// Reload of an argument run on a worker thread, see `ExtcapListener::reload_task`
+///
+/// It owns what it needs, it may outlive the listener once its timeout passes.
+pub type ReloadTask = Box<dyn FnOnce() -> ExtcapResult<Option<Vec<IfArgVal>>> + Send>;
+
+/// Runs the task on a worker thread, `None` if it doesn't return within `timeout`
+///
+/// Arbitrary user code can't be cancelled, a task over the timeout is left running
+/// on its detached thread and its late result is dropped. The thread doesn't keep
+/// the process alive, it ends with the process when `main` returns.
+pub(crate) fn run_with_timeout(
+    task: ReloadTask,
+    timeout: Duration,
+) -> Option<ExtcapResult<Option<Vec<IfArgVal>>>> {
+    let (tx, rx) = mpsc::channel();
+    let spawned = thread::Builder::new()
+        .name("extcap-reload".to_owned())
+        .spawn(move || {
+            // the receiver is gone after the timeout
+            let _ = tx.send(task());
+        });
+    if let Err(e) = spawned {
+        return Some(Err(e.into()));
+    }
+    match rx.recv_timeout(timeout) {
+        Ok(res) => Some(res),
+        Err(mpsc::RecvTimeoutError::Timeout) => None,
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            Some(Err(ExtcapError::user_error("reload task panicked")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::test_support;
+    use crate::{Extcap, ExtcapListener, IFace, IfArg};
+
+    struct SlowReload;
+
+    impl ExtcapListener for SlowReload {
+        fn reload_task(&mut self, _: &Extcap, _: &IFace, _: &IfArg) -> Option<ReloadTask> {
+            Some(Box::new(|| {
+                thread::sleep(Duration::from_secs(5));
+                Ok(Some(vec![IfArgVal::new("late")]))
+            }))
+        }
+    }
+
+    fn slow_extcap() -> Extcap<'static> {
+        let mut extcap = Extcap::new("slowdump");
+        let mut ifc = IFace::new("if1");
+        let mut arg = IfArg::new_selector("dev")
+            .reload(true)
+            .reload_timeout(Duration::from_millis(50));
+        arg.add_val(IfArgVal::new("old"));
+        ifc.add_arg(arg);
+        extcap.add_interface(ifc).unwrap();
+        extcap
+    }
+
+    #[test]
+    fn timed_out_reload_prints_previous_values() {
+        let args = [
+            "--extcap-config",
+            "--extcap-interface",
+            "if1",
+            "--extcap-reload-option",
+            "dev",
+        ];
+        let started = Instant::now();
+        let (res, out) = test_support::run(slow_extcap(), SlowReload, &args);
+        assert!(res.is_ok());
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(out.contains("{value=old}"), "{}", out);
+        assert!(out.contains("{display=timed out, try again}"), "{}", out);
+        assert!(!out.contains("late"), "{}", out);
+    }
+
+    #[test]
+    fn sleeping_task_times_out() {
+        let task: ReloadTask = Box::new(|| {
+            thread::sleep(Duration::from_secs(5));
+            Ok(None)
+        });
+        let started = Instant::now();
+        assert!(run_with_timeout(task, Duration::from_millis(50)).is_none());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn task_in_time_returns_values() {
+        let task: ReloadTask = Box::new(|| Ok(Some(vec![IfArgVal::new("a")])));
+        let vals = run_with_timeout(task, Duration::from_secs(5))
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(vals.len(), 1);
+    }
+
+    #[test]
+    fn panicking_task_fails() {
+        let task: ReloadTask = Box::new(|| panic!("reload"));
+        let res = run_with_timeout(task, Duration::from_secs(5)).unwrap();
+        assert!(res.is_err());
+    }
+}
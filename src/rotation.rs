@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{debug, warn};
+
+use crate::Clock;
+
+/// Ring buffer style rotation of the capture file, like `dumpcap -b`
+///
+/// Applies only when the fifo argument is a regular file, the files are named
+/// after it with a sequence number, e.g. `capture_00001.pcap`. In the rollover mode
+/// the current file keeps the name, a full one is renamed with the next number.
+/// The limits are checked before each packet, a packet is never split between files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RotationPolicy {
+    max_bytes: Option<u64>,
+    max_duration: Option<Duration>,
+    max_files: Option<usize>,
+    rollover: bool,
+}
+
+impl RotationPolicy {
+    /// Creates a policy without any limit
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Switches to the next file once the current one reaches the size
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Switches to the next file once the current one is open for the duration
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Keeps at most the number of files, the oldest ones are removed
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        self.max_files = Some(max_files);
+        self
+    }
+
+    /// Writes to the fifo file itself and renames it once it reaches a limit,
+    /// e.g. to `capture_00001.pcap`, the current file counts to the maximum
+    pub fn rollover(mut self) -> Self {
+        self.rollover = true;
+        self
+    }
+
+    /// Returns the size limit of a file
+    pub fn get_max_bytes(&self) -> Option<u64> {
+        self.max_bytes
+    }
+
+    /// Returns the duration limit of a file
+    pub fn get_max_duration(&self) -> Option<Duration> {
+        self.max_duration
+    }
+
+    /// Returns the maximum number of kept files
+    pub fn get_max_files(&self) -> Option<usize> {
+        self.max_files
+    }
+
+    /// Returns true in the rollover mode
+    pub fn is_rollover(&self) -> bool {
+        self.rollover
+    }
+}
+
+/// Returns true if the fifo argument names a regular file rather than a pipe or stdout
+pub(crate) fn is_regular_file_target(fifo: &Path) -> bool {
+    if fifo == Path::new("-") || is_named_pipe(fifo) {
+        return false;
+    }
+    match fs::metadata(fifo) {
+        Ok(meta) => meta.is_file(),
+        Err(_) => true, // not created by Wireshark
+    }
+}
+
+/// Returns true if the fifo argument names a Windows named pipe, `\\.\pipe\...`
+pub(crate) fn is_named_pipe(fifo: &Path) -> bool {
+    fifo.to_string_lossy()
+        .get(..9)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(r"\\.\pipe\"))
+}
+
+/// Rotation state of the capture files
+pub(crate) struct Rotation {
+    policy: RotationPolicy,
+    template: PathBuf,
+    index: u32,
+    files: VecDeque<PathBuf>,
+    opened: bool,
+    file_started: SystemTime,
+    clock: Clock,
+    name: String,
+}
+
+impl Rotation {
+    pub(crate) fn new(policy: RotationPolicy, fifo: &Path, clock: Clock, name: &str) -> Self {
+        Self {
+            policy,
+            template: fifo.to_path_buf(),
+            index: 0,
+            files: VecDeque::new(),
+            opened: false,
+            file_started: clock.now(),
+            clock,
+            name: name.to_owned(),
+        }
+    }
+
+    /// Returns true if the current file has reached a limit
+    pub(crate) fn is_due(&self, file_bytes: u64) -> bool {
+        self.policy.max_bytes.is_some_and(|max| file_bytes >= max)
+            || self
+                .policy
+                .max_duration
+                .is_some_and(|max| self.clock.elapsed(self.file_started) >= max)
+    }
+
+    /// Returns the path of the next file and removes the files over the limit
+    ///
+    /// In the rollover mode the current file is renamed, it may be still open.
+    pub(crate) fn next_path(&mut self) -> io::Result<PathBuf> {
+        self.file_started = self.clock.now();
+        if !self.policy.rollover {
+            self.index += 1;
+            let path = rotated_path(&self.template, self.index);
+            self.files.push_back(path.clone());
+            self.remove_old(0);
+            return Ok(path);
+        }
+        if self.opened {
+            self.index += 1;
+            let full = rotated_path(&self.template, self.index);
+            debug!("[{}] rollover renaming to {}", self.name, full.display());
+            fs::rename(&self.template, &full)?;
+            self.files.push_back(full);
+            self.remove_old(1);
+        }
+        self.opened = true;
+        Ok(self.template.clone())
+    }
+
+    /// Removes the oldest files over the limit, `current` files aren't on the list
+    fn remove_old(&mut self, current: usize) {
+        let max_files = match self.policy.max_files {
+            Some(max_files) => max_files.max(1),
+            None => return,
+        };
+        while self.files.len() + current > max_files {
+            if let Some(old) = self.files.pop_front() {
+                debug!("[{}] rotation removing {}", self.name, old.display());
+                if let Err(e) = fs::remove_file(&old) {
+                    warn!(
+                        "[{}] rotation can't remove {}: {}",
+                        self.name,
+                        old.display(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn rotated_path(template: &Path, index: u32) -> PathBuf {
+    // capture.pcap.gz becomes capture_00001.pcap.gz
+    if template.extension().is_some_and(|ext| ext == "gz") {
+        let inner = rotated_path(&template.with_extension(""), index);
+        let mut name = inner.into_os_string();
+        name.push(".gz");
+        return PathBuf::from(name);
+    }
+    let stem = template
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match template.extension() {
+        Some(ext) => format!("{}_{:05}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}_{:05}", stem, index),
+    };
+    template.with_file_name(name)
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::control_pipe::{ControlCmd, ControlMsg};
+use crate::{Control, ControlType};
+
+/// Entries of a selector control as known to Wireshark
+#[derive(Debug, Default)]
+struct Selector {
+    values: Vec<Vec<u8>>,
+    selected: Option<Vec<u8>>,
+}
+
+/// How to remove a selector entry, see `SelectorStates::plan_remove`
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RemovePlan {
+    /// Not a tracked entry, the `Remove` is sent as is
+    Untracked,
+    /// The entry is not selected, the `Remove` is enough
+    Remove,
+    /// The selected entry, the returned one is set afterwards
+    RemoveSelected(Vec<u8>),
+    /// The only entry left, not removed
+    Last,
+}
+
+/// Entries and selection of the selector controls, shared by the control pipes
+///
+/// Follows the sent `Add`, `Remove` and `Set` messages and the `Set` messages from
+/// Wireshark, i.e. the selection made by the user.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SelectorStates {
+    selectors: Arc<Mutex<BTreeMap<u8, Selector>>>,
+}
+
+impl SelectorStates {
+    /// Tracks the selectors among the controls, starting with their registered values
+    pub(crate) fn new(controls: &[Control]) -> Self {
+        let selectors = controls
+            .iter()
+            .filter(|c| matches!(c.get_type(), ControlType::Selector))
+            .map(|c| {
+                let values: Vec<Vec<u8>> = c
+                    .get_vals()
+                    .iter()
+                    .map(|v| v.get_value().as_bytes().to_vec())
+                    .collect();
+                let selected = c
+                    .get_vals()
+                    .iter()
+                    .find(|v| v.get_default() == Some(true))
+                    .map(|v| v.get_value())
+                    .or_else(|| c.get_default())
+                    .map(|v| v.as_bytes().to_vec())
+                    .or_else(|| values.first().cloned());
+                (c.get_number() as u8, Selector { values, selected })
+            })
+            .collect();
+        Self {
+            selectors: Arc::new(Mutex::new(selectors)),
+        }
+    }
+
+    /// Updates the entries by a message sent to or received from Wireshark
+    pub(crate) fn record(&self, msg: &ControlMsg) {
+        let mut selectors = match self.selectors.lock() {
+            Ok(selectors) => selectors,
+            Err(_) => return,
+        };
+        let selector = match selectors.get_mut(&msg.get_ctrl_num()) {
+            Some(selector) => selector,
+            None => return,
+        };
+        let data = msg.get_data();
+        match msg.get_command() {
+            ControlCmd::Set => selector.selected = Some(data.to_vec()),
+            ControlCmd::Add => {
+                // the payload is the value optionally followed by NUL and the display string
+                let value = data.split(|b| *b == 0).next().unwrap_or_default();
+                if !selector.values.iter().any(|v| v == value) {
+                    selector.values.push(value.to_vec());
+                }
+            }
+            // an empty payload removes all the entries
+            ControlCmd::Remove if data.is_empty() => {
+                selector.values.clear();
+                selector.selected = None;
+            }
+            ControlCmd::Remove => {
+                selector.values.retain(|v| v != data);
+                if selector.selected.as_deref() == Some(data) {
+                    selector.selected = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Decides how to remove the `value` entry of the selector
+    ///
+    /// The entry following the removed selected one replaces it, or the preceding one
+    /// if it was the last entry.
+    pub(crate) fn plan_remove(&self, ctrl_num: u8, value: &[u8]) -> RemovePlan {
+        let selectors = match self.selectors.lock() {
+            Ok(selectors) => selectors,
+            Err(_) => return RemovePlan::Untracked,
+        };
+        let selector = match selectors.get(&ctrl_num) {
+            Some(selector) => selector,
+            None => return RemovePlan::Untracked,
+        };
+        let pos = match selector.values.iter().position(|v| v == value) {
+            Some(pos) => pos,
+            None => return RemovePlan::Untracked,
+        };
+        if selector.values.len() == 1 {
+            return RemovePlan::Last;
+        }
+        if selector.selected.as_deref() != Some(value) {
+            return RemovePlan::Remove;
+        }
+        let next = match selector.values.get(pos + 1) {
+            Some(next) => next,
+            None => &selector.values[pos - 1],
+        };
+        RemovePlan::RemoveSelected(next.clone())
+    }
+}
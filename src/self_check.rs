@@ -0,0 +1,83 @@
+use std::fmt;
+
+/// Severity of a self-check issue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Likely a mistake, Wireshark works anyway
+    Warning,
+    /// Wireshark can't use the configuration as intended
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Issue found by `Extcap::self_check`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    severity: Severity,
+    message: String,
+}
+
+impl Issue {
+    pub(crate) fn error<T: ToString>(message: T) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn warning<T: ToString>(message: T) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.to_string(),
+        }
+    }
+
+    /// Gets the severity
+    pub fn get_severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Gets the message
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns `true` for an error-level issue
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Reports a user-facing string breaking the sentence syntax (`{`, `}` and control characters)
+pub(crate) fn check_text(issues: &mut Vec<Issue>, what: &str, text: Option<&str>) {
+    if let Some(text) = text.filter(|t| t.chars().any(|c| c.is_control() || matches!(c, '{' | '}')))
+    {
+        issues.push(Issue::error(format!(
+            "{}: '{}' contains '{{', '}}' or control characters",
+            what,
+            text.escape_debug()
+        )));
+    }
+}
+
+/// Returns `true` for a help URI with a scheme Wireshark opens
+pub(crate) fn is_help_uri(uri: &str) -> bool {
+    ["http://", "https://", "file://"].iter().any(|scheme| {
+        uri.get(..scheme.len())
+            .is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+    })
+}
@@ -0,0 +1,7 @@
+//! Ready-made capture sources
+//!
+//! Each source provides the `IfArg`s configuring it and spawns the producer
+//! of the captured data.
+
+#[cfg(feature = "ssh")]
+pub mod ssh;
@@ -0,0 +1,428 @@
+//! Remote capture over the system ssh client, as done by Wireshark's `sshdump`
+//!
+//! The remote command writes pcap data to its stdout, e.g. `tcpdump -U -w -`,
+//! which is read by [`SshCaptureSource`] and written to the [`PacketSink`].
+//!
+//! ```no_run
+//! use extcap::sources::ssh::{ssh_args, SshCaptureSource, SshOptions};
+//! use extcap::{Extcap, ExtcapListener, ExtcapResult, IFace, PacketSink};
+//!
+//! struct RemoteDump;
+//!
+//! impl ExtcapListener for RemoteDump {
+//!     fn capture2(&mut self, extcap: &Extcap, _ifc: &IFace, mut sink: PacketSink) -> ExtcapResult<()> {
+//!         let opts = SshOptions::from_matches(extcap.matches())?;
+//!         let stop = extcap.capture_context().map(|c| c.get_stop_token().clone());
+//!         let mut source = SshCaptureSource::spawn(&opts)?;
+//!         if let Some(stop) = &stop {
+//!             source = source.stop_on(stop);
+//!         }
+//!         source.copy_to(&mut sink)
+//!     }
+//! }
+//!
+//! fn main() -> ExtcapResult<()> {
+//!     let mut extcap = Extcap::new("remotedump");
+//!     let mut ifc = IFace::new("remote");
+//!     ssh_args().into_iter().for_each(|arg| ifc.add_arg(arg));
+//!     extcap.add_interface(ifc)?;
+//!     extcap.run(RemoteDump)
+//! }
+//! ```
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{debug, warn};
+use pcap_file::pcap::PcapReader;
+
+use crate::{
+    CaptureHeader, ExtcapError, ExtcapMatches, ExtcapResult, IfArg, IfArgVal, PacketSink,
+    ProcessGuard, StopToken,
+};
+
+/// `--remote-host`: the remote host name or address
+pub const OPT_SSH_HOST: &str = "remote-host";
+/// `--remote-port`: the SSH port
+pub const OPT_SSH_PORT: &str = "remote-port";
+/// `--remote-username`: the remote user
+pub const OPT_SSH_USER: &str = "remote-username";
+/// `--remote-password`: the password or the passphrase of the key
+pub const OPT_SSH_PASSWORD: &str = "remote-password";
+/// `--sshkey`: the private key file
+pub const OPT_SSH_KEY: &str = "sshkey";
+/// `--remote-host-key-checking`: `StrictHostKeyChecking` of ssh
+pub const OPT_SSH_HOST_KEY_CHECKING: &str = "remote-host-key-checking";
+/// `--remote-capture-command`: the remote command writing pcap data to stdout
+pub const OPT_SSH_COMMAND: &str = "remote-capture-command";
+
+const DEFAULT_PORT: u16 = 22;
+const DEFAULT_COMMAND: &str = "tcpdump -U -w - -i any";
+const PASSWORD_ENV: &str = "EXTCAP_SSH_PASSWORD";
+
+/// Arguments configuring [`SshOptions`], to be added to an interface
+pub fn ssh_args() -> Vec<IfArg<'static>> {
+    let mut host_key_checking = IfArg::new_selector(OPT_SSH_HOST_KEY_CHECKING)
+        .display("Host key checking")
+        .tooltip("StrictHostKeyChecking of ssh, there is no prompt for unknown hosts")
+        .group("Server");
+    for hkc in [
+        HostKeyChecking::Yes,
+        HostKeyChecking::AcceptNew,
+        HostKeyChecking::No,
+    ] {
+        host_key_checking.add_val(
+            IfArgVal::new(hkc.as_str())
+                .display(hkc.display())
+                .default(hkc == HostKeyChecking::default()),
+        );
+    }
+    vec![
+        IfArg::new_string(OPT_SSH_HOST)
+            .display("Remote SSH server address")
+            .tooltip("The remote SSH host, a name or an IP address")
+            .group("Server"),
+        IfArg::new_unsigned(OPT_SSH_PORT)
+            .display("Remote SSH server port")
+            .default(&DEFAULT_PORT)
+            .range(&"1,65535")
+            .group("Server"),
+        host_key_checking,
+        IfArg::new_string(OPT_SSH_USER)
+            .display("Remote SSH server username")
+            .tooltip("The remote user, the ssh default when empty")
+            .group("Authentication"),
+        IfArg::new_password(OPT_SSH_PASSWORD)
+            .display("Remote SSH server password")
+            .tooltip("The password or the passphrase of the key, keys or the agent when empty")
+            .group("Authentication"),
+        IfArg::new_fileselect(OPT_SSH_KEY)
+            .display("Path to SSH private key")
+            .mustexist(true)
+            .group("Authentication"),
+        IfArg::new_string(OPT_SSH_COMMAND)
+            .display("Remote capture command")
+            .default(&DEFAULT_COMMAND)
+            .tooltip("The command writing pcap data to stdout")
+            .group("Capture"),
+    ]
+}
+
+/// `StrictHostKeyChecking` of ssh
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyChecking {
+    /// Unknown and changed host keys are refused
+    #[default]
+    Yes,
+    /// Unknown host keys are added, changed ones are refused
+    AcceptNew,
+    /// Host keys are not checked
+    No,
+}
+
+impl HostKeyChecking {
+    /// Gets the ssh option value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HostKeyChecking::Yes => "yes",
+            HostKeyChecking::AcceptNew => "accept-new",
+            HostKeyChecking::No => "no",
+        }
+    }
+
+    fn display(&self) -> &'static str {
+        match self {
+            HostKeyChecking::Yes => "Known hosts only",
+            HostKeyChecking::AcceptNew => "Accept new hosts",
+            HostKeyChecking::No => "No checking (insecure)",
+        }
+    }
+
+    fn parse(val: &str) -> Option<Self> {
+        match val {
+            "yes" => Some(HostKeyChecking::Yes),
+            "accept-new" => Some(HostKeyChecking::AcceptNew),
+            "no" => Some(HostKeyChecking::No),
+            _ => None,
+        }
+    }
+}
+
+/// Options of the ssh connection and the remote command
+#[derive(Debug, Clone)]
+pub struct SshOptions {
+    host: String,
+    port: u16,
+    user: Option<String>,
+    password: Option<String>,
+    identity: Option<PathBuf>,
+    host_key_checking: HostKeyChecking,
+    command: String,
+    program: PathBuf,
+}
+
+impl SshOptions {
+    /// Creates the options for the host with the defaults
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_owned(),
+            port: DEFAULT_PORT,
+            user: None,
+            password: None,
+            identity: None,
+            host_key_checking: HostKeyChecking::default(),
+            command: DEFAULT_COMMAND.to_owned(),
+            program: PathBuf::from("ssh"),
+        }
+    }
+
+    /// Creates the options from the values of the [`ssh_args`] arguments
+    ///
+    /// Fails with a user error when the host is missing or a value is invalid.
+    pub fn from_matches(matches: &ExtcapMatches) -> ExtcapResult<Self> {
+        let non_empty = |name| matches.value(name).filter(|v| !v.trim().is_empty());
+        let host = non_empty(OPT_SSH_HOST)
+            .ok_or_else(|| ExtcapError::user_error("Remote SSH server address is missing"))?;
+        let mut opts = Self::new(host.trim());
+        if let Some(port) = non_empty(OPT_SSH_PORT) {
+            opts.port =
+                port.trim().parse().ok().filter(|p| *p > 0).ok_or_else(|| {
+                    ExtcapError::user_error(format!("Invalid SSH port '{}'", port))
+                })?;
+        }
+        if let Some(hkc) = non_empty(OPT_SSH_HOST_KEY_CHECKING) {
+            opts.host_key_checking = HostKeyChecking::parse(hkc.trim()).ok_or_else(|| {
+                ExtcapError::user_error(format!("Invalid host key checking '{}'", hkc))
+            })?;
+        }
+        opts.user = non_empty(OPT_SSH_USER).map(|u| u.trim().to_owned());
+        opts.password = non_empty(OPT_SSH_PASSWORD).map(str::to_owned);
+        opts.identity = matches
+            .value_path(OPT_SSH_KEY)
+            .filter(|p| !p.as_os_str().is_empty())
+            .map(Path::to_path_buf);
+        if let Some(command) = non_empty(OPT_SSH_COMMAND) {
+            opts.command = command.to_owned();
+        }
+        Ok(opts)
+    }
+
+    /// Gets the host
+    pub fn get_host(&self) -> &str {
+        &self.host
+    }
+
+    /// Sets the port, 22 by default
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Sets the remote user
+    pub fn user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_owned());
+        self
+    }
+
+    /// Sets the password or the passphrase of the key, passed to ssh via `SSH_ASKPASS`
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_owned());
+        self
+    }
+
+    /// Sets the private key file
+    pub fn identity(mut self, identity: &Path) -> Self {
+        self.identity = Some(identity.to_path_buf());
+        self
+    }
+
+    /// Sets `StrictHostKeyChecking`, `yes` by default
+    pub fn host_key_checking(mut self, host_key_checking: HostKeyChecking) -> Self {
+        self.host_key_checking = host_key_checking;
+        self
+    }
+
+    /// Sets the remote command writing pcap data to stdout, `tcpdump -U -w - -i any` by default
+    pub fn command(mut self, command: &str) -> Self {
+        self.command = command.to_owned();
+        self
+    }
+
+    /// Sets the ssh client program, `ssh` from `PATH` by default
+    pub fn program(mut self, program: &Path) -> Self {
+        self.program = program.to_path_buf();
+        self
+    }
+
+    fn build_command(&self) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.arg("-T").args(["-p", &self.port.to_string()]).args([
+            "-o",
+            &format!("StrictHostKeyChecking={}", self.host_key_checking.as_str()),
+        ]);
+        if self.password.is_none() {
+            // no prompt can be answered
+            cmd.args(["-o", "BatchMode=yes"]);
+        }
+        if let Some(user) = &self.user {
+            cmd.args(["-l", user]);
+        }
+        if let Some(identity) = &self.identity {
+            cmd.arg("-i").arg(identity);
+        }
+        cmd.arg("--")
+            .arg(&self.host)
+            .arg(&self.command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped());
+        cmd
+    }
+}
+
+/// Running ssh client, its stdout is the pcap data written by the remote command
+pub struct SshCaptureSource {
+    guard: ProcessGuard,
+    stdout: ChildStdout,
+    _askpass: Option<Askpass>,
+}
+
+impl SshCaptureSource {
+    /// Spawns the ssh client under a [`ProcessGuard`]
+    pub fn spawn(opts: &SshOptions) -> ExtcapResult<Self> {
+        let mut cmd = opts.build_command();
+        let askpass = match &opts.password {
+            Some(password) => {
+                let askpass = Askpass::create()?;
+                cmd.env("SSH_ASKPASS", &askpass.path)
+                    .env("SSH_ASKPASS_REQUIRE", "force")
+                    .env(PASSWORD_ENV, password);
+                if std::env::var_os("DISPLAY").is_none() {
+                    // required by older ssh clients to use SSH_ASKPASS
+                    cmd.env("DISPLAY", ":0");
+                }
+                Some(askpass)
+            }
+            None => None,
+        };
+        debug!("ssh spawning {:?} {}", opts.program, opts.host);
+        let guard = ProcessGuard::spawn(&mut cmd)?;
+        let stdout = guard
+            .take_stdout()
+            .ok_or_else(|| ExtcapError::invalid_state("ssh stdout not piped"))?;
+        Ok(Self {
+            guard,
+            stdout,
+            _askpass: askpass,
+        })
+    }
+
+    /// Terminates ssh once the stop token is tripped
+    pub fn stop_on(mut self, stop: &StopToken) -> Self {
+        self.guard = self.guard.stop_on(stop);
+        self
+    }
+
+    /// Gets the guard of the ssh process
+    pub fn get_guard(&self) -> &ProcessGuard {
+        &self.guard
+    }
+
+    /// Writes the received packets to the sink until ssh exits
+    ///
+    /// The timestamps are converted to the resolution of the sink, the link type
+    /// of the sink should match the remote one. See [`SshCaptureSource::finish`] for the errors.
+    pub fn copy_to(mut self, sink: &mut PacketSink) -> ExtcapResult<()> {
+        let mut reader = match PcapReader::new(&mut self) {
+            Ok(reader) => reader,
+            Err(e) => {
+                self.finish()?;
+                return Err(ExtcapError::user_error(format!(
+                    "Remote command output is not pcap: {}",
+                    e
+                )));
+            }
+        };
+        let remote = CaptureHeader::from_pcap_header(&reader.header());
+        let local = *sink.get_header();
+        if remote.link_type != local.link_type {
+            warn!(
+                "ssh remote link type {} differs from the capture one {}",
+                remote.link_type, local.link_type
+            );
+        }
+        while let Some(pkt) = reader.next_packet() {
+            let pkt = match pkt {
+                Ok(pkt) => pkt,
+                Err(e) => {
+                    debug!("ssh pcap stream ended: {:?}", e);
+                    break;
+                }
+            };
+            sink.write_packet(&pkt)?;
+        }
+        sink.flush()?;
+        self.finish()
+    }
+
+    /// Waits for ssh to exit
+    ///
+    /// A non-zero exit code is a user error with the last lines of the ssh stderr,
+    /// unless the termination has been requested by the guard.
+    pub fn finish(self) -> ExtcapResult<()> {
+        let status = self.guard.wait()?;
+        debug!("ssh exited: {}", status);
+        if status.success() || self.guard.is_terminated() {
+            return Ok(());
+        }
+        let stderr = self.guard.get_stderr();
+        Err(ExtcapError::user_error(if stderr.is_empty() {
+            format!("ssh failed: {}", status)
+        } else {
+            format!("ssh failed: {}: {}", status, stderr)
+        }))
+    }
+}
+
+impl Read for SshCaptureSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+/// Script answering the `SSH_ASKPASS` prompt with the password from the environment
+struct Askpass {
+    path: PathBuf,
+}
+
+impl Askpass {
+    fn create() -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "extcap-askpass-{}-{}.{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            if cfg!(windows) { "cmd" } else { "sh" }
+        ));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o700);
+        let mut file = options.open(&path)?;
+        // the password itself is never written to the disk
+        if cfg!(windows) {
+            write!(file, "@echo off\r\necho %{}%\r\n", PASSWORD_ENV)?;
+        } else {
+            write!(file, "#!/bin/sh\nprintf '%s\\n' \"${}\"\n", PASSWORD_ENV)?;
+        }
+        Ok(Self { path })
+    }
+}
+
+impl Drop for Askpass {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
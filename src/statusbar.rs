@@ -0,0 +1,192 @@
+use std::future::Future;
+use std::pin::pin;
+use std::thread;
+use std::time::Duration;
+
+use futures::channel::mpsc::{self, Receiver};
+use futures::future::{self, Either};
+use futures::stream::StreamExt;
+use log::debug;
+
+use crate::capture::SharedStats;
+use crate::{CaptureStats, ControlSender};
+
+/// Runs the capture sending its statistics to the Wireshark status bar every `interval`,
+/// the final summary is sent when the capture finishes
+pub(crate) async fn with_statusbar_stats<F>(
+    name: &str,
+    capture: F,
+    mut sender: ControlSender,
+    stats: SharedStats,
+    interval: Duration,
+) -> F::Output
+where
+    F: Future,
+{
+    let mut capture = pin!(capture);
+    let mut ticks = ticker(interval);
+    loop {
+        match future::select(capture.as_mut(), ticks.next()).await {
+            Either::Left((res, _)) => {
+                let msg = format!("{}: capture finished, {}", name, format_stats(&stats));
+                if let Err(e) = sender.statusbar_message(&msg).await {
+                    debug!("[{}] statusbar stats not sent: {}", name, e);
+                }
+                return res;
+            }
+            Either::Right(_) => {
+                let msg = format!("{}: {}", name, format_stats(&stats));
+                if let Err(e) = sender.statusbar_message(&msg).await {
+                    debug!("[{}] statusbar stats not sent: {}", name, e);
+                }
+            }
+        }
+    }
+}
+
+/// Ticks every `interval` until the receiver is dropped, a thread is used as the timer
+fn ticker(interval: Duration) -> Receiver<()> {
+    let (mut tx, rx) = mpsc::channel(0);
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        if let Err(e) = tx.try_send(()) {
+            if e.is_disconnected() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+fn format_stats(stats: &SharedStats) -> String {
+    let stats = stats.lock().map(|s| *s).unwrap_or_default();
+    format!("{} pkts, {}", stats.packets, format_bytes(&stats))
+}
+
+fn format_bytes(stats: &CaptureStats) -> String {
+    let bytes = stats.bytes as f64;
+    match stats.bytes {
+        0..=999 => format!("{} B", stats.bytes),
+        1_000..=999_999 => format!("{:.1} kB", bytes / 1e3),
+        1_000_000..=999_999_999 => format!("{:.1} MB", bytes / 1e6),
+        _ => format!("{:.1} GB", bytes / 1e9),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::test_support::{self, read_ctrl_msgs, write_ctrl_msgs, TempPath};
+    use crate::{
+        ButtonRole, Control, ControlCmd, ControlMsg, CtrlPipes, Extcap, ExtcapListener,
+        ExtcapPacket, ExtcapReceiver, ExtcapResult, IFace,
+    };
+
+    /// Sends a packet every 120 ms, doesn't touch the control pipes
+    struct SlowPackets;
+
+    impl ExtcapListener for SlowPackets {
+        fn capture_async_with_ctrl(
+            &mut self,
+            _: &Extcap,
+            _: &IFace,
+            _: Option<CtrlPipes>,
+        ) -> ExtcapResult<ExtcapReceiver> {
+            let (mut sender, receiver) = mpsc::channel(3);
+            thread::spawn(move || {
+                for _ in 0..3 {
+                    thread::sleep(Duration::from_millis(120));
+                    let pkt = ExtcapPacket::new(Duration::ZERO, 4, vec![1, 2, 3, 4]);
+                    if sender.try_send(pkt).is_err() {
+                        break;
+                    }
+                }
+            });
+            Ok(receiver)
+        }
+    }
+
+    /// Captures with the given statistics interval, returns the status bar messages sent
+    async fn statusbar_messages(dir: &Path, interval: Option<Duration>) -> Vec<String> {
+        let (ctrl_in, ctrl_out, fifo) = (dir.join("in"), dir.join("out"), dir.join("fifo"));
+        write_ctrl_msgs(
+            &ctrl_in,
+            vec![ControlMsg::new(0, ControlCmd::Initialized, &[])],
+        );
+        let mut extcap = Extcap::new("statusbar");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        extcap
+            .add_control(Control::new_button(ButtonRole::Control).display("Clear"))
+            .unwrap();
+        if let Some(interval) = interval {
+            extcap.auto_statusbar_stats_interval(interval);
+        }
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.to_str().unwrap(),
+            "--extcap-control-in",
+            ctrl_in.to_str().unwrap(),
+            "--extcap-control-out",
+            ctrl_out.to_str().unwrap(),
+        ];
+        let (res, _) = test_support::run_async(extcap, SlowPackets, &args).await;
+        assert!(res.is_ok(), "{:?}", res.err());
+        read_ctrl_msgs(&ctrl_out)
+            .iter()
+            .filter(|m| matches!(m.get_command(), ControlCmd::StatusbarMessage))
+            .map(|m| String::from_utf8_lossy(m.get_data()).into_owned())
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn periodic_messages_and_final_summary() {
+        let dir = TempPath::new("statusbar");
+        let msgs =
+            statusbar_messages(dir.0.parent().unwrap(), Some(Duration::from_millis(50))).await;
+        let (last, periodic) = msgs.split_last().expect("status bar messages");
+        assert!(periodic.len() >= 3, "{:?}", msgs);
+        for msg in periodic {
+            assert!(
+                msg.starts_with("statusbar: ") && msg.ends_with(" B"),
+                "{}",
+                msg
+            );
+            assert!(!msg.contains("finished"), "{}", msg);
+        }
+        // the counters only grow
+        let packets = |msg: &str| -> u64 {
+            let count = msg.split(": ").last().unwrap().split(' ').next().unwrap();
+            count.parse().unwrap()
+        };
+        let counts: Vec<u64> = periodic.iter().map(|m| packets(m)).collect();
+        assert!(counts.windows(2).all(|w| w[0] <= w[1]), "{:?}", msgs);
+        // pcap header 24 B, each packet 16 B record header and 4 B data
+        assert_eq!(last, "statusbar: capture finished, 3 pkts, 84 B");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn nothing_sent_when_disabled() {
+        let dir = TempPath::new("statusbar");
+        let msgs = statusbar_messages(dir.0.parent().unwrap(), None).await;
+        assert!(msgs.is_empty(), "{:?}", msgs);
+    }
+
+    #[test]
+    fn bytes_in_units() {
+        let bytes = |bytes| {
+            format_bytes(&CaptureStats {
+                bytes,
+                ..Default::default()
+            })
+        };
+        assert_eq!(bytes(999), "999 B");
+        assert_eq!(bytes(1_000), "1.0 kB");
+        assert_eq!(bytes(3_200_000), "3.2 MB");
+        assert_eq!(bytes(12_345_678_901), "12.3 GB");
+    }
+}
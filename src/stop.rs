@@ -0,0 +1,309 @@
+use std::future::{poll_fn, Future};
+use std::io::{self, IsTerminal, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+#[cfg(windows)]
+use std::sync::{Once, Weak};
+use std::task::{Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+/// Capture stop token
+///
+/// Cheap to clone, all clones share the same state. It is tripped by the crate
+/// when the capture has finished, so helper threads and tasks can finish too.
+/// Tripped before, it requests the capture to stop, see `Extcap::shutdown_timeout`.
+///
+/// Wireshark stops a capture by closing the fifo and the control pipes, the crate trips
+/// the token when it detects it: a write to the fifo fails with `EPIPE` on Unix or
+/// `ERROR_BROKEN_PIPE`/`ERROR_NO_DATA` on Windows, or the incoming control pipe is closed.
+/// On Unix Wireshark also sends `SIGTERM`, handled with `Extcap::stop_on_signal`. On Windows
+/// the process is killed when it does not exit in time, so the cleanup must be done
+/// within the shutdown timeout. On Windows the crate registers a console control handler
+/// for the token of every `Extcap`, Ctrl-C, Ctrl-Break and closing the console trip it.
+/// In the standalone mode Ctrl-C trips the token on every platform.
+///
+/// Neither is seen when Wireshark or dumpcap dies abruptly before closing the fifo,
+/// `Extcap::stop_on_stdin_eof` covers it on every platform: the stdin of the process
+/// reaches EOF once its parent is gone.
+#[derive(Clone, Debug, Default)]
+pub struct StopToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    stopped: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+    blocked: (Mutex<()>, Condvar), // threads blocked in wait_timeout
+}
+
+/// Checks whether the reading end of a pipe has been closed
+pub(crate) fn is_pipe_closed(e: &io::Error) -> bool {
+    // ERROR_BROKEN_PIPE and ERROR_NO_DATA
+    #[cfg(windows)]
+    if matches!(e.raw_os_error(), Some(109 | 232)) {
+        return true;
+    }
+    e.kind() == io::ErrorKind::BrokenPipe
+}
+
+/// Maps the closed pipe to `io::ErrorKind::BrokenPipe`, e.g. `ERROR_NO_DATA` on Windows
+pub(crate) fn broken_pipe(e: io::Error) -> io::Error {
+    if e.kind() != io::ErrorKind::BrokenPipe && is_pipe_closed(&e) {
+        io::Error::new(io::ErrorKind::BrokenPipe, e)
+    } else {
+        e
+    }
+}
+
+/// Trips `stop` on `SIGINT`, `SIGTERM` or `SIGHUP`, on Windows on Ctrl-C, Ctrl-Break
+/// or closing the console
+#[cfg(feature = "signals")]
+pub(crate) fn stop_on_signal(name: &str, stop: StopToken) {
+    let owned = name.to_owned();
+    if let Err(e) = ctrlc::set_handler(move || {
+        debug!("[{}] signal received, stopping the capture", owned);
+        stop.stop();
+    }) {
+        warn!("[{}] signal handler can't be set: {}", name, e);
+    }
+}
+
+/// Tokens tripped by the console control handler
+#[cfg(windows)]
+static CONSOLE_TOKENS: Mutex<Vec<Weak<Inner>>> = Mutex::new(Vec::new());
+
+/// Time the handler keeps the process alive after the console has been closed
+#[cfg(windows)]
+const CONSOLE_CLOSE_WAIT: Duration = Duration::from_secs(5);
+
+/// Trips `stop` on Ctrl-C, Ctrl-Break or closing the console
+///
+/// The first event trips the live tokens, the next one gets the default handling which
+/// terminates the process, so a run not watching the token can still be interrupted.
+/// The system terminates the process once the handler of closing the console returns,
+/// the handler waits meanwhile so the capture can finish its cleanup.
+#[cfg(windows)]
+pub(crate) fn stop_on_console_ctrl(stop: &StopToken) {
+    use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+    static REGISTERED: Once = Once::new();
+    if let Ok(mut tokens) = CONSOLE_TOKENS.lock() {
+        tokens.retain(|t| t.strong_count() > 0);
+        tokens.push(Arc::downgrade(&stop.inner));
+    }
+    REGISTERED.call_once(|| {
+        // SAFETY: the handler is a plain function living as long as the process
+        if unsafe { SetConsoleCtrlHandler(Some(console_ctrl_handler), 1) } == 0 {
+            warn!(
+                "console control handler can't be set: {}",
+                io::Error::last_os_error()
+            );
+        }
+    });
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn console_ctrl_handler(event: u32) -> i32 {
+    use windows_sys::Win32::System::Console::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+    let tokens: Vec<StopToken> = match CONSOLE_TOKENS.lock() {
+        Ok(tokens) => tokens
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|inner| StopToken { inner })
+            .collect(),
+        Err(_) => return 0,
+    };
+    if tokens.iter().all(StopToken::is_stopped) {
+        return 0;
+    }
+    debug!("console control event {}, stopping the capture", event);
+    tokens.iter().for_each(StopToken::stop);
+    if !matches!(event, CTRL_C_EVENT | CTRL_BREAK_EVENT) {
+        thread::sleep(CONSOLE_CLOSE_WAIT);
+    }
+    1
+}
+
+/// Trips `stop` once stdin reaches EOF or fails, the read data is discarded
+///
+/// Not started for a terminal, a manual run would stop at once or never.
+pub(crate) fn stop_on_stdin_eof(name: &str, stop: StopToken) {
+    if io::stdin().is_terminal() {
+        debug!("[{}] stdin is a terminal, not watched", name);
+        return;
+    }
+    let thread_name = name.to_owned();
+    let spawned = thread::Builder::new()
+        .name("extcap-stdin-watch".to_owned())
+        .spawn(move || {
+            let mut buf = [0u8; 1];
+            let mut stdin = io::stdin();
+            loop {
+                match stdin.read(&mut buf) {
+                    Ok(0) => {
+                        debug!("[{}] stdin closed, stopping the capture", thread_name);
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        debug!(
+                            "[{}] stdin failed, stopping the capture: {}",
+                            thread_name, e
+                        );
+                        break;
+                    }
+                }
+            }
+            stop.stop();
+        });
+    if let Err(e) = spawned {
+        warn!("[{}] stdin watcher can't be started: {}", name, e);
+    }
+}
+
+impl StopToken {
+    /// Creates a new instance of `StopToken` which is not stopped
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trips the token
+    pub fn stop(&self) {
+        if self.inner.stopped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        if let Ok(mut wakers) = self.inner.wakers.lock() {
+            wakers.drain(..).for_each(Waker::wake);
+        }
+        let (lock, blocked) = &self.inner.blocked;
+        let _guard = lock.lock();
+        blocked.notify_all();
+    }
+
+    /// Checks whether the token has been tripped
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Wakes `waker` once the token is tripped, at once if it already is
+    #[cfg(feature = "process-guard")]
+    pub(crate) fn on_stop(&self, waker: Waker) {
+        if let Ok(mut wakers) = self.inner.wakers.lock() {
+            if !self.is_stopped() {
+                wakers.push(waker);
+                return;
+            }
+        }
+        waker.wake();
+    }
+
+    /// Blocks the thread until the token is tripped or the timeout passes,
+    /// returns `true` if tripped
+    pub(crate) fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let (lock, blocked) = &self.inner.blocked;
+        let Ok(mut guard) = lock.lock() else {
+            thread::sleep(timeout);
+            return self.is_stopped();
+        };
+        while !self.is_stopped() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return false;
+            }
+            guard = match blocked.wait_timeout(guard, left) {
+                Ok((guard, _)) => guard,
+                Err(_) => return self.is_stopped(),
+            };
+        }
+        true
+    }
+
+    /// Waits until the token is tripped
+    pub fn stopped(&self) -> impl Future<Output = ()> + Send + 'static {
+        let inner = self.inner.clone();
+        poll_fn(move |cx| {
+            if inner.stopped.load(Ordering::SeqCst) {
+                return Poll::Ready(());
+            }
+            if let Ok(mut wakers) = inner.wakers.lock() {
+                if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+            }
+            // tripped meanwhile, the waker may have been missed
+            if inner.stopped.load(Ordering::SeqCst) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn closed_pipe_is_detected() {
+        let (reader, mut writer) = io::pipe().unwrap();
+        drop(reader);
+        let e = writer.write_all(b"x").unwrap_err();
+        assert!(is_pipe_closed(&e));
+        assert_eq!(broken_pipe(e).kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn other_errors_are_kept() {
+        let e = broken_pipe(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(!is_pipe_closed(&e));
+        assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn closed_named_pipe_is_detected() {
+        use std::fs::OpenOptions;
+        use std::iter;
+        use std::os::windows::ffi::OsStrExt;
+        use std::ptr;
+
+        use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+        use windows_sys::Win32::Storage::FileSystem::PIPE_ACCESS_INBOUND;
+        use windows_sys::Win32::System::Pipes::{CreateNamedPipeW, PIPE_TYPE_BYTE, PIPE_WAIT};
+
+        let path = format!(r"\\.\pipe\extcap-stop-test-{}", std::process::id());
+        let wide: Vec<u16> = std::ffi::OsStr::new(&path)
+            .encode_wide()
+            .chain(iter::once(0))
+            .collect();
+        // SAFETY: the name is a valid wide C string
+        let server = unsafe {
+            CreateNamedPipeW(
+                wide.as_ptr(),
+                PIPE_ACCESS_INBOUND,
+                PIPE_TYPE_BYTE | PIPE_WAIT,
+                1,
+                4096,
+                4096,
+                0,
+                ptr::null(),
+            )
+        };
+        assert_ne!(server, INVALID_HANDLE_VALUE);
+        let mut client = OpenOptions::new().write(true).open(&path).unwrap();
+        client.write_all(b"x").unwrap();
+        // SAFETY: the handle is valid and not used afterwards
+        unsafe { CloseHandle(server) };
+        let e = client.write_all(b"x").unwrap_err();
+        assert!(is_pipe_closed(&e), "{:?}", e);
+        assert_eq!(broken_pipe(e).kind(), io::ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,345 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{debug, warn};
+
+use crate::capture::{
+    create_file, is_gzip_target, record_header, write_pcap_header, write_record, PCAP_HEADER_LEN,
+    PCAP_RECORD_HEADER_LEN,
+};
+use crate::rotation::Rotation;
+use crate::{CaptureHeader, Clock, ExtcapWriter, RotationPolicy};
+
+/// Copy of the capture written to a local file, see `Extcap::tee_to`
+///
+/// A failure disables the copy, it never fails the capture.
+pub(crate) struct Tee {
+    header: CaptureHeader,
+    writer: ExtcapWriter,
+    rotation: Option<Rotation>,
+    file_bytes: u64,
+    sync: bool,
+    name: String,
+}
+
+impl Tee {
+    /// Creates the copy, `None` if the file can't be created
+    pub(crate) fn create(
+        path: &Path,
+        header: &CaptureHeader,
+        rotation: Option<RotationPolicy>,
+        clock: Clock,
+        sync: bool,
+        name: &str,
+    ) -> Option<Self> {
+        let mut rotation = rotation.map(|policy| Rotation::new(policy, path, clock, name));
+        let opened = match rotation.as_mut() {
+            Some(rot) => rot.next_path(),
+            None => Ok(path.to_path_buf()),
+        }
+        .and_then(|path| Ok((open(&path, header, sync)?, path)));
+        match opened {
+            Ok((writer, path)) => {
+                debug!("[{}] tee writing {}", name, path.display());
+                Some(Self {
+                    header: *header,
+                    writer,
+                    rotation,
+                    file_bytes: PCAP_HEADER_LEN,
+                    sync,
+                    name: name.to_owned(),
+                })
+            }
+            Err(e) => {
+                warn!("[{}] tee to {} disabled: {}", name, path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Writes the packet, `false` if it failed and the copy is to be disabled
+    pub(crate) fn write(&mut self, ts_sec: u32, ts_frac: u32, data: &[u8], orig_len: u32) -> bool {
+        let fields = [ts_sec, ts_frac, data.len() as u32, orig_len];
+        let record = record_header(self.header.endianness, fields);
+        let res = self
+            .rotate()
+            .and_then(|_| write_record(&mut self.writer, &record, data));
+        if let Err(e) = res {
+            warn!("[{}] tee write failed, disabled: {}", self.name, e);
+            return false;
+        }
+        self.file_bytes += PCAP_RECORD_HEADER_LEN + data.len() as u64;
+        true
+    }
+
+    /// Flushes the file, `false` if it failed and the copy is to be disabled
+    pub(crate) fn flush(&mut self) -> bool {
+        if let Err(e) = self.writer.flush() {
+            warn!("[{}] tee flush failed, disabled: {}", self.name, e);
+            return false;
+        }
+        true
+    }
+
+    /// Flushes the file, syncs it to the disk if configured
+    pub(crate) fn close(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        if self.sync {
+            self.writer.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let path: PathBuf = match self.rotation.as_mut() {
+            Some(rot) if rot.is_due(self.file_bytes) => rot.next_path()?,
+            _ => return Ok(()),
+        };
+        debug!("[{}] tee rotation writing {}", self.name, path.display());
+        self.close()?;
+        self.writer = open(&path, &self.header, self.sync)?;
+        self.file_bytes = PCAP_HEADER_LEN;
+        Ok(())
+    }
+}
+
+/// Byte copy of the pcapng or raw output, see `Extcap::tee_to`
+///
+/// A failure disables the copy, it never fails the capture.
+pub(crate) struct TeeWriter {
+    writer: ExtcapWriter,
+    copy: Option<ExtcapWriter>,
+    sync: bool,
+    name: String,
+}
+
+impl TeeWriter {
+    /// Creates the copy of all the bytes written to `writer`, none if the file can't be created
+    pub(crate) fn new(writer: ExtcapWriter, path: &Path, sync: bool, name: &str) -> Self {
+        let compressed = is_gzip_target(path, false).then(Arc::default);
+        let copy = match create_file(path, compressed.as_ref(), sync) {
+            Ok(copy) => {
+                debug!("[{}] tee writing {}", name, path.display());
+                Some(copy)
+            }
+            Err(e) => {
+                warn!("[{}] tee to {} disabled: {}", name, path.display(), e);
+                None
+            }
+        };
+        Self {
+            writer,
+            copy,
+            sync,
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl Write for TeeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.writer.write(buf)?;
+        if let Some(Err(e)) = self.copy.as_mut().map(|copy| copy.write_all(&buf[..len])) {
+            warn!("[{}] tee write failed, disabled: {}", self.name, e);
+            self.copy = None;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(Err(e)) = self.copy.as_mut().map(Write::flush) {
+            warn!("[{}] tee flush failed, disabled: {}", self.name, e);
+            self.copy = None;
+        }
+        self.writer.flush()
+    }
+}
+
+impl Drop for TeeWriter {
+    fn drop(&mut self) {
+        if let Some(copy) = self.copy.as_mut() {
+            let sync = self.sync;
+            let res = copy
+                .flush()
+                .and_then(|_| if sync { copy.sync_all() } else { Ok(()) });
+            if let Err(e) = res {
+                debug!("[{}] capture copy not flushed on drop: {}", self.name, e);
+            }
+        }
+    }
+}
+
+/// Creates the file, gzip compressed for a `.gz` file
+fn open(path: &Path, header: &CaptureHeader, sync: bool) -> io::Result<ExtcapWriter> {
+    let compressed = is_gzip_target(path, false).then(Arc::default);
+    write_pcap_header(create_file(path, compressed.as_ref(), sync)?, header)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pcap_file::DataLink;
+
+    use super::*;
+    use crate::test_support::{self, TempPath};
+    use crate::{
+        Extcap, ExtcapListener, ExtcapResult, IFace, InterfaceDescription, OutputFormat,
+        PacketSink, PcapNgWriter,
+    };
+
+    /// Writes the same packets in every output format
+    struct Packets;
+
+    impl ExtcapListener for Packets {
+        fn capture2(&mut self, _: &Extcap, _: &IFace, mut sink: PacketSink) -> ExtcapResult<()> {
+            for i in 1..=3u8 {
+                sink.write(i.into(), 0, &vec![i; i as usize * 10], i as u32 * 10)?;
+            }
+            Ok(())
+        }
+
+        fn capture_interfaces_ng(&mut self, _: &Extcap, _: &IFace) -> Vec<InterfaceDescription> {
+            vec![InterfaceDescription::from_data_link(DataLink::ETHERNET)]
+        }
+
+        fn capture_ng(
+            &mut self,
+            _: &Extcap,
+            _: &IFace,
+            mut writer: PcapNgWriter<ExtcapWriter>,
+        ) -> ExtcapResult<()> {
+            for i in 1..=3u8 {
+                writer.write(0, i.into(), 0, &vec![i; i as usize * 10], i as u32 * 10)?;
+            }
+            Ok(())
+        }
+
+        fn capture_raw(
+            &mut self,
+            _: &Extcap,
+            _: &IFace,
+            mut writer: ExtcapWriter,
+        ) -> ExtcapResult<()> {
+            for i in 1..=3u8 {
+                writer.write_all(&vec![i; i as usize * 10])?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Runs the capture teed to `copy`, returns the fifo bytes
+    fn capture_teed(format: OutputFormat, copy: &Path) -> Vec<u8> {
+        let fifo = TempPath::new("fifo");
+        let mut extcap = Extcap::new("teedump");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        extcap.set_output_format(format);
+        extcap.tee_to(copy);
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.as_str(),
+        ];
+        let (res, _) = test_support::run(extcap, Packets, &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+        fs::read(&fifo.0).unwrap()
+    }
+
+    #[test]
+    fn pcap_copy_identical() {
+        let copy = TempPath::new("copy.pcap");
+        let fifo = capture_teed(OutputFormat::Pcap, &copy.0);
+        assert_eq!(fifo.len(), 24 + 3 * 16 + 60);
+        assert_eq!(fs::read(&copy.0).unwrap(), fifo);
+    }
+
+    #[test]
+    fn pcapng_copy_identical() {
+        let copy = TempPath::new("copy.pcapng");
+        let fifo = capture_teed(OutputFormat::PcapNg, &copy.0);
+        assert!(!fifo.is_empty());
+        assert_eq!(fs::read(&copy.0).unwrap(), fifo);
+    }
+
+    #[test]
+    fn raw_copy_identical() {
+        let copy = TempPath::new("copy.raw");
+        let fifo = capture_teed(OutputFormat::Raw, &copy.0);
+        assert_eq!(fifo.len(), 60);
+        assert_eq!(fs::read(&copy.0).unwrap(), fifo);
+    }
+
+    #[test]
+    fn failed_copy_keeps_the_capture() {
+        let dir = TempPath::new("missing");
+        let copy = dir.0.join("copy.pcap");
+        let (fifo, logs) = test_support::capture_logs(|| capture_teed(OutputFormat::Pcap, &copy));
+        assert_eq!(fifo.len(), 24 + 3 * 16 + 60);
+        let disabled = logs
+            .iter()
+            .any(|l| l.starts_with("[teedump] tee to") && l.contains("disabled:"));
+        assert!(disabled, "{:?}", logs);
+    }
+
+    /// Runs the capture to `fifo.pcap` teed to `copy.pcap` with the rotation, returns
+    /// the files and the rotation log lines
+    fn capture_rotated(format: OutputFormat, dir: &TempPath) -> (Vec<String>, Vec<String>) {
+        let mut extcap = Extcap::new("teedump");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        extcap.set_output_format(format);
+        extcap.tee_to(&dir.0.join("copy.pcap"));
+        extcap.file_rotation(RotationPolicy::new().max_bytes(40));
+        let fifo = dir.0.join("fifo.pcap");
+        let args = [
+            "--capture",
+            "--extcap-interface",
+            "if1",
+            "--fifo",
+            fifo.to_str().unwrap(),
+        ];
+        let ((res, _), logs) =
+            test_support::capture_logs(|| test_support::run(extcap, Packets, &args));
+        assert!(res.is_ok(), "{:?}", res.err());
+        let mut files: Vec<_> = fs::read_dir(&dir.0)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        let logs = logs
+            .into_iter()
+            .filter(|l| l.contains("rotation"))
+            .collect();
+        (files, logs)
+    }
+
+    #[test]
+    fn rotation_applies_to_the_copy_only() {
+        let dir = TempPath::new("rotated");
+        fs::create_dir(&dir.0).unwrap();
+        let (files, logs) = capture_rotated(OutputFormat::Pcap, &dir);
+        let expected = [
+            "copy_00001.pcap",
+            "copy_00002.pcap",
+            "copy_00003.pcap",
+            "fifo.pcap",
+        ];
+        assert_eq!(files, expected);
+        assert_eq!(logs[0], "[teedump] file rotation applied to the copy only");
+        // the fifo got all the packets in a single file
+        let fifo = fs::read(dir.0.join("fifo.pcap")).unwrap();
+        assert_eq!(fifo.len(), 24 + 3 * 16 + 60);
+    }
+
+    #[test]
+    fn byte_copy_not_rotated() {
+        let dir = TempPath::new("rotated");
+        fs::create_dir(&dir.0).unwrap();
+        let (files, logs) = capture_rotated(OutputFormat::Raw, &dir);
+        assert_eq!(files, ["copy.pcap", "fifo.pcap"]);
+        let ignored = "[teedump] file rotation ignored, the Raw output is not rotated";
+        assert_eq!(logs, [ignored]);
+    }
+}
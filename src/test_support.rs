@@ -0,0 +1,181 @@
+//! Runs `Extcap` in the unit tests with a given command line, collecting the output
+
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::{Extcap, ExtcapListener, ExtcapResult};
+
+/// Command line and stdout of the instance run by a test
+#[derive(Default)]
+pub(crate) struct TestIo {
+    pub(crate) args: Option<Vec<OsString>>,
+    out: Option<Arc<Mutex<String>>>,
+}
+
+impl TestIo {
+    /// Collects the output, `false` outside a test run
+    pub(crate) fn capture(&self, out: &str) -> bool {
+        match &self.out {
+            Some(buf) => {
+                buf.lock().unwrap().push_str(out);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Sets the command line, the program name is prepended, returns the output buffer
+fn prepare(extcap: &mut Extcap, args: &[&str]) -> Arc<Mutex<String>> {
+    let mut argv = vec![OsString::from(extcap.name)];
+    argv.extend(args.iter().map(OsString::from));
+    let out = Arc::new(Mutex::new(String::new()));
+    extcap.test_io = TestIo {
+        args: Some(argv),
+        out: Some(out.clone()),
+    };
+    out
+}
+
+fn take(out: Arc<Mutex<String>>) -> String {
+    let out = out.lock().unwrap();
+    out.clone()
+}
+
+/// Runs the instance like `Extcap::run`, returns the result and the output
+pub(crate) fn run<T: ExtcapListener>(
+    mut extcap: Extcap,
+    listener: T,
+    args: &[&str],
+) -> (ExtcapResult<()>, String) {
+    let out = prepare(&mut extcap, args);
+    let res = extcap.run(listener);
+    (res, take(out))
+}
+
+/// Runs the instance like `Extcap::run` keeping it, e.g. for checking its state
+pub(crate) fn run_in_place<T: ExtcapListener>(
+    extcap: &mut Extcap,
+    mut listener: T,
+    args: &[&str],
+) -> (ExtcapResult<()>, String) {
+    let out = prepare(extcap, args);
+    let res = extcap.run_steps(&mut listener);
+    (res, take(out))
+}
+
+/// Runs the instance like `Extcap::run_to_exit_code`, returns the code and the output
+pub(crate) fn run_to_exit_code<T: ExtcapListener>(
+    mut extcap: Extcap,
+    listener: T,
+    args: &[&str],
+) -> (ExitCode, String) {
+    let out = prepare(&mut extcap, args);
+    let code = extcap.run_to_exit_code(listener);
+    (code, take(out))
+}
+
+/// Runs the instance like `Extcap::run_async`, returns the result and the output
+#[cfg(feature = "ctrl-pipe")]
+pub(crate) async fn run_async<T: ExtcapListener>(
+    mut extcap: Extcap<'_>,
+    listener: T,
+    args: &[&str],
+) -> (ExtcapResult<()>, String) {
+    let out = prepare(&mut extcap, args);
+    let res = extcap.run_async(listener).await;
+    (res, take(out))
+}
+
+/// Writes the control messages Wireshark sends to the file standing in for the pipe
+#[cfg(feature = "ctrl-pipe")]
+pub(crate) fn write_ctrl_msgs(path: &std::path::Path, msgs: Vec<crate::ControlMsg>) {
+    use tokio_util::codec::Encoder;
+    let mut codec = crate::control_pipe_runtime::ControlMsgCodec::new("test", true);
+    let mut buf = bytes::BytesMut::new();
+    for msg in msgs {
+        codec.encode(msg, &mut buf).unwrap();
+    }
+    std::fs::write(path, &buf).unwrap();
+}
+
+/// Reads the control messages sent to the file standing in for the pipe
+#[cfg(feature = "ctrl-pipe")]
+pub(crate) fn read_ctrl_msgs(path: &std::path::Path) -> Vec<crate::ControlMsg> {
+    use tokio_util::codec::Decoder;
+    let mut codec = crate::control_pipe_runtime::ControlMsgCodec::new("test", true);
+    let mut buf = bytes::BytesMut::from(&std::fs::read(path).unwrap()[..]);
+    let mut msgs = Vec::new();
+    while let Some(msg) = codec.decode(&mut buf).unwrap() {
+        msgs.push(msg);
+    }
+    msgs
+}
+
+/// Path in a temp directory of its own, removed when dropped
+pub(crate) struct TempPath(pub(crate) PathBuf);
+
+impl TempPath {
+    pub(crate) fn new(name: &str) -> Self {
+        static SEQ: AtomicUsize = AtomicUsize::new(0);
+        let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("extcap-test-{}-{}", std::process::id(), seq));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir.join(name))
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if let Some(dir) = self.0.parent() {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+thread_local! {
+    static LOGS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Collects the log messages of the thread capturing them
+struct TestLogger;
+
+impl Log for TestLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        LOGS.with(|logs| {
+            if let Some(logs) = logs.borrow_mut().as_mut() {
+                logs.push(record.args().to_string());
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Runs `f`, returns its result and the messages it has logged on this thread
+pub(crate) fn capture_logs<R>(f: impl FnOnce() -> R) -> (R, Vec<String>) {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        if log::set_logger(&TestLogger).is_ok() {
+            log::set_max_level(LevelFilter::Trace);
+        }
+    });
+    LOGS.with(|logs| *logs.borrow_mut() = Some(Vec::new()));
+    let res = f();
+    let logs = LOGS.with(|logs| logs.borrow_mut().take().unwrap_or_default());
+    (res, logs)
+}
@@ -0,0 +1,165 @@
+//! Utilities for testing extcap utilities built with this crate
+//!
+//! The parser follows the sentence syntax Wireshark expects, e.g.
+//! `arg {number=0}{call=--port}{display=Port}{type=integer}`.
+//! An attribute value ends with `}` followed by `{` or the end of the line,
+//! so values can contain braces (e.g. regular expression quantifiers) but not `}{`.
+
+#[cfg(feature = "ctrl-pipe")]
+use futures::channel::mpsc::{self, Receiver};
+#[cfg(feature = "ctrl-pipe")]
+use futures::{future::FutureExt, stream::StreamExt};
+
+#[cfg(feature = "ctrl-pipe")]
+use crate::{ControlMsg, ControlSender, CtrlPipes};
+use crate::{ExtcapError, ExtcapResult};
+
+#[cfg(feature = "testing-cli")]
+pub mod cli;
+mod traffic_gen;
+pub use self::traffic_gen::{PacketSizes, TrafficGen, TrafficPackets, TrafficRate, TrafficReport};
+
+/// Capacity of the mock outgoing control pipe
+#[cfg(feature = "ctrl-pipe")]
+const MOCK_OUTBOX_LEN: usize = 1024;
+
+/// Sentence types with their required attributes
+const SENTENCES: &[(&str, &[&str])] = &[
+    ("extcap", &["version"]),
+    ("interface", &["value"]),
+    ("dlt", &["number", "name"]),
+    ("arg", &["number", "call", "display", "type"]),
+    ("value", &["value", "display"]),
+    ("control", &["number", "type"]),
+];
+
+/// Parsed extcap sentence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sentence {
+    kind: String,
+    attrs: Vec<(String, String)>,
+}
+
+impl Sentence {
+    /// Gets the sentence type, e.g. `arg`
+    pub fn get_kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Gets the attribute value
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Iterates over the attributes in the emitted order
+    pub fn attrs(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.attrs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// Parses a single sentence, the trailing newline is optional
+pub fn parse_sentence(line: &str) -> ExtcapResult<Sentence> {
+    let err = |msg: &str| ExtcapError::invalid_sentence(format!("{}: {}", msg, line));
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    if line.contains(['\n', '\r']) {
+        return Err(err("line break inside the sentence"));
+    }
+    let (kind, mut rest) = line
+        .split_once(' ')
+        .ok_or_else(|| err("missing attributes"))?;
+    let required = SENTENCES
+        .iter()
+        .find(|(k, _)| *k == kind)
+        .map(|(_, required)| *required)
+        .ok_or_else(|| err("unknown sentence type"))?;
+
+    let mut attrs: Vec<(String, String)> = Vec::new();
+    while !rest.is_empty() {
+        let attr = rest
+            .strip_prefix('{')
+            .ok_or_else(|| err("attribute not starting with '{'"))?;
+        let (key, attr) = attr
+            .split_once('=')
+            .ok_or_else(|| err("attribute without '='"))?;
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(err("invalid attribute name"));
+        }
+        let (value, next) = match attr.find("}{") {
+            Some(end) => (&attr[..end], &attr[end + 1..]),
+            None => (
+                attr.strip_suffix('}')
+                    .ok_or_else(|| err("attribute not ending with '}'"))?,
+                "",
+            ),
+        };
+        if attrs.iter().any(|(k, _)| k == key) {
+            return Err(err("duplicate attribute"));
+        }
+        attrs.push((key.to_owned(), value.to_owned()));
+        rest = next;
+    }
+
+    let sentence = Sentence {
+        kind: kind.to_owned(),
+        attrs,
+    };
+    if let Some(key) = required.iter().find(|k| sentence.get(k).is_none()) {
+        return Err(err(&format!("missing required attribute '{}'", key)));
+    }
+    if kind == "value" && sentence.get("arg").is_none() && sentence.get("control").is_none() {
+        return Err(err("missing required attribute 'arg' or 'control'"));
+    }
+    Ok(sentence)
+}
+
+/// Parses the sentences, one per line
+pub fn parse_sentences(out: &str) -> ExtcapResult<Vec<Sentence>> {
+    out.lines().map(parse_sentence).collect()
+}
+
+/// Messages sent through mock control pipes, see `mock_ctrl_pipes`
+#[cfg(feature = "ctrl-pipe")]
+pub struct CtrlOutbox {
+    receiver: Receiver<ControlMsg>,
+}
+
+#[cfg(feature = "ctrl-pipe")]
+impl CtrlOutbox {
+    /// Takes the messages sent so far without waiting
+    pub fn take(&mut self) -> Vec<ControlMsg> {
+        let mut msgs = Vec::new();
+        while let Some(Some(msg)) = self.receiver.next().now_or_never() {
+            msgs.push(msg);
+        }
+        msgs
+    }
+
+    /// Waits for the next message, `None` once all the senders have been dropped
+    pub async fn next(&mut self) -> Option<ControlMsg> {
+        self.receiver.next().await
+    }
+}
+
+/// Creates in-memory control pipes as passed to `capture_async_with_ctrl`
+///
+/// The `incoming` messages are delivered as if sent by Wireshark, then the incoming
+/// pipe is closed. Everything sent to the `controls` registered controls is collected
+/// in the returned `CtrlOutbox`, up to 1024 messages not taken yet. The pipes don't
+/// depend on any async runtime.
+#[cfg(feature = "ctrl-pipe")]
+pub fn mock_ctrl_pipes(incoming: Vec<ControlMsg>, controls: usize) -> (CtrlPipes, CtrlOutbox) {
+    let (mut snd_in, rcv_in) = mpsc::channel(incoming.len());
+    for msg in incoming {
+        snd_in
+            .try_send(msg)
+            .expect("mock control pipe sized for all the incoming messages");
+    }
+    let (snd_out, rcv_out) = mpsc::channel(MOCK_OUTBOX_LEN);
+    (
+        CtrlPipes::new(rcv_in, ControlSender::new(snd_out, controls)),
+        CtrlOutbox { receiver: rcv_out },
+    )
+}
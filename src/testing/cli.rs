@@ -0,0 +1,850 @@
+//! Black-box helpers running the compiled extcap binary the way Wireshark does
+//!
+//! The printed sentences are checked by `parse_sentence`, the parser verifying the output
+//! of the debug builds. The capture runs with a temporary fifo, a named pipe on Windows,
+//! and optionally with the control pipes.
+//!
+//! ```no_run
+//! # use std::process::Command;
+//! # use extcap::testing::cli::{assert_interfaces, spawn_capture};
+//! # fn main() -> extcap::ExtcapResult<()> {
+//! // env!("CARGO_BIN_EXE_myextcap") in an integration test
+//! let bin = "target/debug/myextcap";
+//! let out = Command::new(bin).arg("--extcap-interfaces").output()?;
+//! let ifaces = assert_interfaces(&String::from_utf8_lossy(&out.stdout));
+//! assert_eq!(ifaces[0].get_value(), "myif");
+//!
+//! let mut session = spawn_capture(bin, "myif", &["--delay", "1"])?;
+//! assert_eq!(session.header()?.link_type, 147);
+//! let packets = session.packets(10)?;
+//! let out = session.stop()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ffi::OsStr;
+#[cfg(feature = "ctrl-pipe")]
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "ctrl-pipe")]
+use bytes::BytesMut;
+use pcap_file::pcap::{PcapPacket, PcapReader};
+#[cfg(feature = "ctrl-pipe")]
+use tokio_util::codec::{Decoder, Encoder};
+
+#[cfg(feature = "ctrl-pipe")]
+use crate::control_pipe_runtime::ControlMsgCodec;
+use crate::testing::{parse_sentences, Sentence};
+use crate::CaptureHeader;
+#[cfg(feature = "ctrl-pipe")]
+use crate::ControlMsg;
+use crate::ExtcapResult;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Distinguishes the pipes of the sessions of one test process
+static SESSION_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// Interface listed by `--extcap-interfaces`, see `assert_interfaces`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedInterface {
+    sentence: Sentence,
+}
+
+impl ParsedInterface {
+    /// Gets the interface name passed back as `--extcap-interface`
+    pub fn get_value(&self) -> &str {
+        self.sentence.get("value").unwrap_or_default()
+    }
+
+    /// Gets the display string
+    pub fn get_display(&self) -> Option<&str> {
+        self.sentence.get("display")
+    }
+
+    /// Gets the whole `interface` sentence
+    pub fn get_sentence(&self) -> &Sentence {
+        &self.sentence
+    }
+}
+
+/// Value of a selector-like argument, see `ParsedArg::get_values`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedValue {
+    sentence: Sentence,
+}
+
+impl ParsedValue {
+    /// Gets the value passed to the argument
+    pub fn get_value(&self) -> &str {
+        self.sentence.get("value").unwrap_or_default()
+    }
+
+    /// Gets the display string
+    pub fn get_display(&self) -> &str {
+        self.sentence.get("display").unwrap_or_default()
+    }
+
+    /// Returns `true` for the value selected by default
+    pub fn is_default(&self) -> bool {
+        self.sentence.get("default") == Some("true")
+    }
+
+    /// Gets the whole `value` sentence
+    pub fn get_sentence(&self) -> &Sentence {
+        &self.sentence
+    }
+}
+
+/// Argument listed by `--extcap-config`, see `assert_config`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedArg {
+    number: u32,
+    sentence: Sentence,
+    values: Vec<ParsedValue>,
+}
+
+impl ParsedArg {
+    /// Gets the argument number
+    pub fn get_number(&self) -> u32 {
+        self.number
+    }
+
+    /// Gets the option, e.g. `--port`
+    pub fn get_call(&self) -> &str {
+        self.sentence.get("call").unwrap_or_default()
+    }
+
+    /// Gets the display string
+    pub fn get_display(&self) -> &str {
+        self.sentence.get("display").unwrap_or_default()
+    }
+
+    /// Gets the argument type, e.g. `integer`
+    pub fn get_type(&self) -> &str {
+        self.sentence.get("type").unwrap_or_default()
+    }
+
+    /// Gets the attribute value, e.g. `default`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.sentence.get(key)
+    }
+
+    /// Gets the values listed for the argument, in the printed order
+    pub fn get_values(&self) -> &[ParsedValue] {
+        &self.values
+    }
+
+    /// Gets the whole `arg` sentence
+    pub fn get_sentence(&self) -> &Sentence {
+        &self.sentence
+    }
+}
+
+/// Parses the output of `--extcap-interfaces`, panics when it isn't valid
+///
+/// Only the `extcap`, `interface`, `control` and control `value` sentences are accepted,
+/// the interface names must be unique.
+#[track_caller]
+pub fn assert_interfaces(output: &str) -> Vec<ParsedInterface> {
+    let mut ifaces: Vec<ParsedInterface> = Vec::new();
+    for sentence in assert_sentences(output) {
+        match sentence.get_kind() {
+            "interface" => {
+                let iface = ParsedInterface { sentence };
+                assert!(
+                    ifaces.iter().all(|i| i.get_value() != iface.get_value()),
+                    "duplicate interface '{}' in:\n{}",
+                    iface.get_value(),
+                    output
+                );
+                ifaces.push(iface);
+            }
+            "extcap" | "control" => {}
+            "value" if sentence.get("control").is_some() => {}
+            kind => panic!("unexpected '{}' sentence in:\n{}", kind, output),
+        }
+    }
+    ifaces
+}
+
+/// Parses the output of `--extcap-config`, panics when it isn't valid
+///
+/// Only the `arg` and `value` sentences are accepted, the argument numbers must be unique
+/// and every value must follow the argument it belongs to.
+#[track_caller]
+pub fn assert_config(output: &str) -> Vec<ParsedArg> {
+    let mut args: Vec<ParsedArg> = Vec::new();
+    for sentence in assert_sentences(output) {
+        match sentence.get_kind() {
+            "arg" => {
+                let number = assert_number(&sentence, "number", output);
+                assert!(
+                    args.iter().all(|a| a.number != number),
+                    "duplicate arg number {} in:\n{}",
+                    number,
+                    output
+                );
+                args.push(ParsedArg {
+                    number,
+                    sentence,
+                    values: Vec::new(),
+                });
+            }
+            "value" if sentence.get("arg").is_some() => {
+                let number = assert_number(&sentence, "arg", output);
+                let arg = args
+                    .iter_mut()
+                    .find(|a| a.number == number)
+                    .unwrap_or_else(|| {
+                        panic!("value of the unknown arg {} in:\n{}", number, output)
+                    });
+                arg.values.push(ParsedValue { sentence });
+            }
+            kind => panic!("unexpected '{}' sentence in:\n{}", kind, output),
+        }
+    }
+    args
+}
+
+#[track_caller]
+fn assert_sentences(output: &str) -> Vec<Sentence> {
+    parse_sentences(output).unwrap_or_else(|e| panic!("{} in:\n{}", e, output))
+}
+
+#[track_caller]
+fn assert_number(sentence: &Sentence, key: &str, output: &str) -> u32 {
+    let value = sentence.get(key).unwrap_or_default();
+    value
+        .parse()
+        .unwrap_or_else(|_| panic!("{} '{}' not a number in:\n{}", key, value, output))
+}
+
+/// Starts the capture as Wireshark does, without the control pipes
+///
+/// Runs `binary --capture --extcap-interface <iface> --fifo <fifo>` followed by `args`,
+/// the fifo is created in the temporary directory and removed with the session.
+pub fn spawn_capture<S: AsRef<OsStr>>(
+    binary: impl AsRef<Path>,
+    iface: &str,
+    args: &[S],
+) -> ExtcapResult<CaptureSession> {
+    CaptureSession::spawn(binary.as_ref(), iface, args, false)
+}
+
+/// Starts the capture as Wireshark does, with the control pipes
+///
+/// Adds `--extcap-control-in` and `--extcap-control-out`, the messages are exchanged
+/// by `CaptureSession::send_control` and `CaptureSession::next_control`.
+#[cfg(feature = "ctrl-pipe")]
+pub fn spawn_capture_with_ctrl<S: AsRef<OsStr>>(
+    binary: impl AsRef<Path>,
+    iface: &str,
+    args: &[S],
+) -> ExtcapResult<CaptureSession> {
+    CaptureSession::spawn(binary.as_ref(), iface, args, true)
+}
+
+enum CaptureEvent {
+    Header(CaptureHeader),
+    Packet(PcapPacket<'static>),
+    End(Option<String>), // the reading error
+}
+
+#[cfg(feature = "ctrl-pipe")]
+enum CtrlEvent {
+    Msg(ControlMsg),
+    End(Option<String>),
+}
+
+/// Running capture started by `spawn_capture`
+///
+/// Every wait is limited by the timeout, 5 s by default, a timeout fails with
+/// `io::ErrorKind::TimedOut`. The plugin is killed when the session is dropped.
+pub struct CaptureSession {
+    child: Child,
+    timeout: Duration,
+    dir: Option<PathBuf>,
+    pipes: Vec<(PathBuf, bool)>, // opened by the helper threads, inbound
+    capture: Receiver<CaptureEvent>,
+    header: Option<CaptureHeader>,
+    ended: Option<Option<String>>,
+    output: Vec<JoinHandle<Vec<u8>>>, // stdout and stderr
+    #[cfg(feature = "ctrl-pipe")]
+    ctrl: Option<CtrlSession>,
+}
+
+#[cfg(feature = "ctrl-pipe")]
+struct CtrlSession {
+    writer: Receiver<io::Result<File>>,
+    pipe_in: Option<File>,
+    msgs: Receiver<CtrlEvent>,
+    closed: bool,
+}
+
+impl CaptureSession {
+    fn spawn<S: AsRef<OsStr>>(
+        binary: &Path,
+        iface: &str,
+        args: &[S],
+        control: bool,
+    ) -> ExtcapResult<Self> {
+        let seq = SESSION_SEQ.fetch_add(1, Ordering::SeqCst);
+        let prefix = format!("extcap-test-{}-{}", std::process::id(), seq);
+        let dir = pipe::temp_dir(&prefix)?;
+
+        let fifo = pipe::Pipe::create(dir.as_deref(), &prefix, "fifo", true)?;
+        let mut command = Command::new(binary);
+        command
+            .args(["--capture", "--extcap-interface", iface, "--fifo"])
+            .arg(fifo.get_path());
+        #[cfg_attr(not(feature = "ctrl-pipe"), allow(unused_mut))]
+        let mut pipes = vec![(fifo.get_path().to_owned(), true)];
+        #[cfg(feature = "ctrl-pipe")]
+        let ctrl_pipes = if control {
+            let pipe_in = pipe::Pipe::create(dir.as_deref(), &prefix, "ctrl-in", false)?;
+            let pipe_out = pipe::Pipe::create(dir.as_deref(), &prefix, "ctrl-out", true)?;
+            command
+                .arg("--extcap-control-in")
+                .arg(pipe_in.get_path())
+                .arg("--extcap-control-out")
+                .arg(pipe_out.get_path());
+            pipes.push((pipe_in.get_path().to_owned(), false));
+            pipes.push((pipe_out.get_path().to_owned(), true));
+            Some((pipe_in, pipe_out))
+        } else {
+            None
+        };
+        #[cfg(not(feature = "ctrl-pipe"))]
+        let _ = control;
+        let mut child = command
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let output = [
+            child
+                .stdout
+                .take()
+                .map(|r| Box::new(r) as Box<dyn Read + Send>),
+            child
+                .stderr
+                .take()
+                .map(|r| Box::new(r) as Box<dyn Read + Send>),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|mut r| {
+            thread::spawn(move || {
+                let mut buf = Vec::new();
+                let _ = r.read_to_end(&mut buf);
+                buf
+            })
+        })
+        .collect();
+
+        let (snd, capture) = mpsc::channel();
+        thread::spawn(move || read_capture(fifo, snd));
+        Ok(Self {
+            child,
+            timeout: DEFAULT_TIMEOUT,
+            dir,
+            pipes,
+            capture,
+            header: None,
+            ended: None,
+            output,
+            #[cfg(feature = "ctrl-pipe")]
+            ctrl: ctrl_pipes.map(|(pipe_in, pipe_out)| CtrlSession::start(pipe_in, pipe_out)),
+        })
+    }
+
+    /// Sets the timeout of the waits
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Gets the process id of the plugin
+    pub fn get_id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Waits for the pcap header written by the plugin
+    pub fn header(&mut self) -> ExtcapResult<CaptureHeader> {
+        while self.header.is_none() {
+            if self.next_event("pcap header")?.is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "capture ended without the pcap header",
+                )
+                .into());
+            }
+        }
+        Ok(self.header.unwrap_or_default())
+    }
+
+    /// Waits for the next packet, `None` once the plugin has closed the fifo
+    ///
+    /// A malformed pcap stream fails, also every call after it.
+    pub fn next_packet(&mut self) -> ExtcapResult<Option<PcapPacket<'static>>> {
+        loop {
+            match self.next_event("packet")? {
+                Some(CaptureEvent::Packet(packet)) => return Ok(Some(packet)),
+                Some(_) => {}
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Waits for `count` packets, fails when the capture ends before
+    pub fn packets(&mut self, count: usize) -> ExtcapResult<Vec<PcapPacket<'static>>> {
+        let mut packets = Vec::with_capacity(count);
+        while packets.len() < count {
+            match self.next_packet()? {
+                Some(packet) => packets.push(packet),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("capture ended after {} of {} packets", packets.len(), count),
+                    )
+                    .into())
+                }
+            }
+        }
+        Ok(packets)
+    }
+
+    /// Gets the next event, `None` at the end of the capture
+    fn next_event(&mut self, what: &str) -> ExtcapResult<Option<CaptureEvent>> {
+        if let Some(ended) = &self.ended {
+            return match ended {
+                Some(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e.clone()).into()),
+                None => Ok(None),
+            };
+        }
+        let event = match self.capture.recv_timeout(self.timeout) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => return Err(timed_out(what, self.timeout)),
+            Err(RecvTimeoutError::Disconnected) => CaptureEvent::End(None),
+        };
+        match &event {
+            CaptureEvent::Header(header) => self.header = Some(*header),
+            CaptureEvent::End(e) => {
+                self.ended = Some(e.clone());
+                return self.next_event(what);
+            }
+            CaptureEvent::Packet(_) => {}
+        }
+        Ok(Some(event))
+    }
+
+    /// Sends the message as Wireshark does
+    ///
+    /// The first message waits for the plugin to open its incoming control pipe.
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn send_control(&mut self, msg: ControlMsg) -> ExtcapResult<()> {
+        self.send_controls([msg])
+    }
+
+    /// Sends the scripted messages in one write
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn send_controls<I: IntoIterator<Item = ControlMsg>>(
+        &mut self,
+        msgs: I,
+    ) -> ExtcapResult<()> {
+        use std::io::Write;
+
+        let timeout = self.timeout;
+        let ctrl = self.ctrl_session()?;
+        if ctrl.pipe_in.is_none() {
+            let file = match ctrl.writer.recv_timeout(timeout) {
+                Ok(file) => file?,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(timed_out("incoming control pipe opened", timeout))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::from(io::ErrorKind::BrokenPipe).into())
+                }
+            };
+            ctrl.pipe_in = Some(file);
+        }
+        let mut codec = ControlMsgCodec::new("testing", true);
+        let mut buf = BytesMut::new();
+        for msg in msgs {
+            codec.encode(msg, &mut buf)?;
+        }
+        if let Some(pipe_in) = &mut ctrl.pipe_in {
+            pipe_in.write_all(&buf)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the next message sent by the plugin, `None` once it has closed the pipe
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn next_control(&mut self) -> ExtcapResult<Option<ControlMsg>> {
+        let timeout = self.timeout;
+        let ctrl = self.ctrl_session()?;
+        if ctrl.closed {
+            return Ok(None);
+        }
+        match ctrl.msgs.recv_timeout(timeout) {
+            Ok(CtrlEvent::Msg(msg)) => Ok(Some(msg)),
+            Ok(CtrlEvent::End(e)) => {
+                ctrl.closed = true;
+                match e {
+                    Some(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e).into()),
+                    None => Ok(None),
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => Err(timed_out("control message", timeout)),
+            Err(RecvTimeoutError::Disconnected) => {
+                ctrl.closed = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Closes the incoming control pipe, as Wireshark does when the capture is stopped
+    #[cfg(feature = "ctrl-pipe")]
+    pub fn close_control(&mut self) -> ExtcapResult<()> {
+        let ctrl = self.ctrl_session()?;
+        ctrl.pipe_in = None;
+        Ok(())
+    }
+
+    #[cfg(feature = "ctrl-pipe")]
+    fn ctrl_session(&mut self) -> ExtcapResult<&mut CtrlSession> {
+        self.ctrl.as_mut().ok_or_else(|| {
+            crate::ExtcapError::invalid_state("capture spawned without the control pipes")
+        })
+    }
+
+    /// Waits for the plugin to exit on its own and collects its output
+    pub fn wait(mut self) -> ExtcapResult<Output> {
+        let deadline = Instant::now() + self.timeout;
+        while self.child.try_wait()?.is_none() {
+            if Instant::now() >= deadline {
+                return Err(timed_out("plugin exit", self.timeout));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        self.finish()
+    }
+
+    /// Stops the capture as Wireshark does and collects the output of the plugin
+    ///
+    /// On Unix the plugin gets `SIGTERM` and is killed when it doesn't exit within
+    /// the timeout, on Windows it is killed at once.
+    pub fn stop(mut self) -> ExtcapResult<Output> {
+        #[cfg(feature = "ctrl-pipe")]
+        if let Some(ctrl) = &mut self.ctrl {
+            ctrl.pipe_in = None;
+        }
+        if self.child.try_wait()?.is_none() {
+            #[cfg(unix)]
+            {
+                // the child is not reaped yet, so the pid can't have been reused
+                let pid = self.child.id() as libc::pid_t;
+                if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+                    return Err(io::Error::last_os_error().into());
+                }
+                let deadline = Instant::now() + self.timeout;
+                while self.child.try_wait()?.is_none() && Instant::now() < deadline {
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+            if self.child.try_wait()?.is_none() {
+                self.child.kill()?;
+            }
+        }
+        self.finish()
+    }
+
+    /// Collects the output of the exited plugin
+    fn finish(&mut self) -> ExtcapResult<Output> {
+        let status = self.child.wait()?;
+        let mut output = std::mem::take(&mut self.output)
+            .into_iter()
+            .map(|h| h.join().unwrap_or_default());
+        Ok(Output {
+            status,
+            stdout: output.next().unwrap_or_default(),
+            stderr: output.next().unwrap_or_default(),
+        })
+    }
+}
+
+impl Drop for CaptureSession {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+        // the helper threads may still wait for the plugin to open the pipes
+        for (path, inbound) in &self.pipes {
+            pipe::unblock(path, *inbound);
+        }
+        if let Some(dir) = &self.dir {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+#[cfg(feature = "ctrl-pipe")]
+impl CtrlSession {
+    fn start(pipe_in: pipe::Pipe, pipe_out: pipe::Pipe) -> Self {
+        let (snd_writer, writer) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = snd_writer.send(pipe_in.connect());
+        });
+        let (snd, msgs) = mpsc::channel();
+        thread::spawn(move || read_control(pipe_out, snd));
+        Self {
+            writer,
+            pipe_in: None,
+            msgs,
+            closed: false,
+        }
+    }
+}
+
+fn timed_out(what: &str, timeout: Duration) -> crate::ExtcapError {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("no {} within {:?}", what, timeout),
+    )
+    .into()
+}
+
+/// Reads the pcap stream written by the plugin
+fn read_capture(fifo: pipe::Pipe, events: Sender<CaptureEvent>) {
+    let end = |e: Option<String>| {
+        let _ = events.send(CaptureEvent::End(e));
+    };
+    let file = match fifo.connect() {
+        Ok(file) => file,
+        Err(e) => return end(Some(e.to_string())),
+    };
+    let mut reader = match PcapReader::new(BufReader::new(file)) {
+        Ok(reader) => reader,
+        Err(e) => return end(Some(format!("pcap header: {}", e))),
+    };
+    let header = CaptureHeader::from_pcap_header(&reader.header());
+    if events.send(CaptureEvent::Header(header)).is_err() {
+        return;
+    }
+    while let Some(packet) = reader.next_packet() {
+        match packet {
+            Ok(packet) => {
+                if events
+                    .send(CaptureEvent::Packet(packet.into_owned()))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            Err(e) => return end(Some(format!("pcap packet: {}", e))),
+        }
+    }
+    end(None)
+}
+
+/// Reads the control messages sent by the plugin
+#[cfg(feature = "ctrl-pipe")]
+fn read_control(pipe_out: pipe::Pipe, events: Sender<CtrlEvent>) {
+    let end = |e: Option<String>| {
+        let _ = events.send(CtrlEvent::End(e));
+    };
+    let mut file = match pipe_out.connect() {
+        Ok(file) => file,
+        Err(e) => return end(Some(e.to_string())),
+    };
+    let mut codec = ControlMsgCodec::new("testing", true);
+    let mut buf = BytesMut::new();
+    let mut chunk = [0; 4096];
+    loop {
+        loop {
+            match codec.decode(&mut buf) {
+                Ok(Some(msg)) => {
+                    if events.send(CtrlEvent::Msg(msg)).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => return end(Some(e.to_string())),
+            }
+        }
+        match file.read(&mut chunk) {
+            Ok(0) if buf.is_empty() => return end(None),
+            Ok(0) => return end(Some(format!("{} bytes of a partial message", buf.len()))),
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return end(Some(e.to_string())),
+        }
+    }
+}
+
+#[cfg(unix)]
+mod pipe {
+    use std::ffi::CString;
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::path::{Path, PathBuf};
+
+    /// Fifo opened by the plugin, `inbound` when the test reads it
+    pub(super) struct Pipe {
+        path: PathBuf,
+        inbound: bool,
+    }
+
+    /// Creates the directory of the fifos
+    pub(super) fn temp_dir(prefix: &str) -> io::Result<Option<PathBuf>> {
+        let dir = std::env::temp_dir().join(prefix);
+        std::fs::create_dir(&dir)?;
+        Ok(Some(dir))
+    }
+
+    impl Pipe {
+        pub(super) fn create(
+            dir: Option<&Path>,
+            _prefix: &str,
+            name: &str,
+            inbound: bool,
+        ) -> io::Result<Self> {
+            let path = dir.unwrap_or(Path::new("")).join(name);
+            let cpath = CString::new(path.as_os_str().as_bytes())?;
+            // SAFETY: the path is a valid C string
+            if unsafe { libc::mkfifo(cpath.as_ptr(), 0o600) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { path, inbound })
+        }
+
+        pub(super) fn get_path(&self) -> &Path {
+            &self.path
+        }
+
+        /// Opens the fifo, blocks until the plugin opens the other end
+        pub(super) fn connect(self) -> io::Result<File> {
+            OpenOptions::new()
+                .read(self.inbound)
+                .write(!self.inbound)
+                .open(&self.path)
+        }
+    }
+
+    /// Releases `connect` waiting for the plugin by opening the other end
+    pub(super) fn unblock(path: &Path, inbound: bool) {
+        let _ = OpenOptions::new()
+            .read(!inbound)
+            .write(inbound)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path);
+    }
+}
+
+#[cfg(windows)]
+mod pipe {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::iter;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, RawHandle};
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+
+    use windows_sys::Win32::Foundation::{ERROR_PIPE_CONNECTED, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_INBOUND, PIPE_ACCESS_OUTBOUND,
+    };
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE, PIPE_WAIT,
+    };
+
+    const PIPE_BUFFER: u32 = 65536;
+
+    /// Named pipe opened by the plugin
+    pub(super) struct Pipe {
+        path: PathBuf,
+        server: File,
+    }
+
+    /// Named pipes don't need a directory
+    pub(super) fn temp_dir(_prefix: &str) -> io::Result<Option<PathBuf>> {
+        Ok(None)
+    }
+
+    impl Pipe {
+        pub(super) fn create(
+            _dir: Option<&Path>,
+            prefix: &str,
+            name: &str,
+            inbound: bool,
+        ) -> io::Result<Self> {
+            let path = PathBuf::from(format!(r"\\.\pipe\{}-{}", prefix, name));
+            let wide: Vec<u16> = path
+                .as_os_str()
+                .encode_wide()
+                .chain(iter::once(0))
+                .collect();
+            let access = if inbound {
+                PIPE_ACCESS_INBOUND
+            } else {
+                PIPE_ACCESS_OUTBOUND
+            };
+            // SAFETY: the name is a valid wide C string
+            let handle = unsafe {
+                CreateNamedPipeW(
+                    wide.as_ptr(),
+                    access | FILE_FLAG_FIRST_PIPE_INSTANCE,
+                    PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+                    1,
+                    PIPE_BUFFER,
+                    PIPE_BUFFER,
+                    0,
+                    ptr::null(),
+                )
+            };
+            if handle == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: the handle is valid and owned from now on
+            let server = unsafe { File::from_raw_handle(handle as RawHandle) };
+            Ok(Self { path, server })
+        }
+
+        pub(super) fn get_path(&self) -> &Path {
+            &self.path
+        }
+
+        /// Waits for the plugin to open the pipe
+        pub(super) fn connect(self) -> io::Result<File> {
+            // SAFETY: the handle is a valid pipe server handle
+            if unsafe { ConnectNamedPipe(self.server.as_raw_handle() as HANDLE, ptr::null_mut()) }
+                == 0
+            {
+                let e = io::Error::last_os_error();
+                if e.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+                    return Err(e);
+                }
+            }
+            Ok(self.server)
+        }
+    }
+
+    /// Releases `connect` waiting for the plugin by opening the pipe as a client
+    pub(super) fn unblock(path: &Path, inbound: bool) {
+        let _ = OpenOptions::new().read(!inbound).write(inbound).open(path);
+    }
+}
@@ -0,0 +1,329 @@
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{ExtcapResult, PacketBuilder, PacketSink, StopToken};
+
+/// Packet size distribution of `TrafficGen`
+#[derive(Debug, Clone)]
+pub enum PacketSizes {
+    /// Every packet has the same size
+    Fixed(usize),
+    /// Sizes uniformly distributed between the bounds, both inclusive
+    Uniform(usize, usize),
+    /// Sizes picked by their relative weights, e.g. the simple IMIX
+    /// `vec![(64, 7), (594, 4), (1518, 1)]`
+    Weighted(Vec<(usize, u32)>),
+}
+
+/// Requested rate of `TrafficGen`, a rate which is not positive means `Unlimited`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrafficRate {
+    /// As fast as the packets can be written
+    Unlimited,
+    /// Packets per second
+    Pps(f64),
+    /// Megabits of packet data per second
+    Mbps(f64),
+}
+
+/// Reproducible synthetic traffic for load testing
+///
+/// The packets are generated from a seeded pseudo-random sequence, the same
+/// configuration gives the same packets. Without a pattern the payload is
+/// pseudo-random too. Writing stops after `count` packets, after `duration`,
+/// or when the `until` token is stopped, whatever comes first; none of them
+/// means the packets never end.
+///
+/// `run` writes into a `PacketSink`, e.g. in `capture2` or into the sink of
+/// `PacketSink::from_writer` to measure the write path, `packets` gives the
+/// paced packet data for any other source, e.g. a thread feeding `capture_async`.
+///
+/// ```
+/// # use extcap::testing::{PacketSizes, TrafficGen, TrafficRate};
+/// # use extcap::{CaptureHeader, PacketSink};
+/// # fn main() -> extcap::ExtcapResult<()> {
+/// let mut sink = PacketSink::from_writer(Vec::new(), CaptureHeader::new(147))?;
+/// let report = TrafficGen::new()
+///     .sizes(PacketSizes::Uniform(60, 1500))
+///     .rate(TrafficRate::Unlimited)
+///     .count(100)
+///     .run(&mut sink)?;
+/// assert_eq!(report.get_packets(), 100);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TrafficGen {
+    sizes: PacketSizes,
+    rate: TrafficRate,
+    count: Option<u64>,
+    duration: Option<Duration>,
+    pattern: Option<Vec<u8>>,
+    seed: u64,
+    until: Option<StopToken>,
+}
+
+impl Default for TrafficGen {
+    fn default() -> Self {
+        Self {
+            sizes: PacketSizes::Fixed(64),
+            rate: TrafficRate::Unlimited,
+            count: None,
+            duration: None,
+            pattern: None,
+            seed: 0,
+            until: None,
+        }
+    }
+}
+
+impl TrafficGen {
+    /// Creates the generator of unlimited 64 bytes packets without a limit
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the packet size distribution
+    pub fn sizes(mut self, sizes: PacketSizes) -> Self {
+        self.sizes = sizes;
+        self
+    }
+
+    /// Sets the requested rate
+    pub fn rate(mut self, rate: TrafficRate) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Stops after the number of packets
+    pub fn count(mut self, count: u64) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    /// Stops after the duration
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Fills the payload with the repeated pattern instead of pseudo-random bytes
+    pub fn pattern(mut self, pattern: &[u8]) -> Self {
+        self.pattern = Some(pattern.to_vec()).filter(|p| !p.is_empty());
+        self
+    }
+
+    /// Sets the seed of the pseudo-random sizes and payload, 0 by default
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Stops when the token is stopped, e.g. the one of the capture context
+    pub fn until(mut self, stop: &StopToken) -> Self {
+        self.until = Some(stop.clone());
+        self
+    }
+
+    /// Iterates over the packet data, each returned when it's due by the rate
+    pub fn packets(&self) -> TrafficPackets {
+        TrafficPackets {
+            gen: self.clone(),
+            rng: SplitMix64(self.seed),
+            started: Instant::now(),
+            packets: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Writes the packets to the sink, returns the achieved rate
+    pub fn run(&self, sink: &mut PacketSink) -> ExtcapResult<TrafficReport> {
+        let mut packets = self.packets();
+        for data in packets.by_ref() {
+            PacketBuilder::new().data(&data).write_to(sink)?;
+        }
+        Ok(packets.report())
+    }
+}
+
+/// Packet data of `TrafficGen::packets`
+#[derive(Debug)]
+pub struct TrafficPackets {
+    gen: TrafficGen,
+    rng: SplitMix64,
+    started: Instant,
+    packets: u64,
+    bytes: u64,
+}
+
+impl TrafficPackets {
+    /// The rate achieved by the packets returned so far
+    pub fn report(&self) -> TrafficReport {
+        TrafficReport {
+            packets: self.packets,
+            bytes: self.bytes,
+            elapsed: self.started.elapsed(),
+            requested: self.gen.rate,
+        }
+    }
+
+    fn next_size(&mut self) -> usize {
+        match &self.gen.sizes {
+            PacketSizes::Fixed(size) => *size,
+            PacketSizes::Uniform(min, max) => {
+                let (min, max) = (*min.min(max), *min.max(max));
+                min + self.rng.below((max - min) as u64 + 1) as usize
+            }
+            PacketSizes::Weighted(sizes) => {
+                let total: u64 = sizes.iter().map(|(_, w)| u64::from(*w)).sum();
+                let mut pick = self.rng.below(total.max(1));
+                sizes
+                    .iter()
+                    .find(|(_, w)| {
+                        let found = pick < u64::from(*w);
+                        pick = pick.saturating_sub(u64::from(*w));
+                        found
+                    })
+                    .map(|(size, _)| *size)
+                    .unwrap_or_default()
+            }
+        }
+    }
+
+    /// Time since the start when the next packet is due
+    fn next_due(&self) -> Option<Duration> {
+        let secs = match self.gen.rate {
+            TrafficRate::Unlimited => return None,
+            TrafficRate::Pps(pps) => self.packets as f64 / pps,
+            TrafficRate::Mbps(mbps) => (self.bytes * 8) as f64 / (mbps * 1_000_000.0),
+        };
+        // not positive rates give infinite or NaN
+        Duration::try_from_secs_f64(secs).ok()
+    }
+}
+
+impl Iterator for TrafficPackets {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.gen.count.is_some_and(|count| self.packets >= count)
+            || self
+                .gen
+                .duration
+                .is_some_and(|d| self.started.elapsed() >= d)
+            || self.gen.until.as_ref().is_some_and(StopToken::is_stopped)
+        {
+            return None;
+        }
+        if let Some(wait) = self
+            .next_due()
+            .and_then(|due| due.checked_sub(self.started.elapsed()))
+        {
+            thread::sleep(wait);
+        }
+        let size = self.next_size();
+        let data = match &self.gen.pattern {
+            Some(pattern) => pattern.iter().copied().cycle().take(size).collect(),
+            None => (0..size).map(|_| self.rng.next() as u8).collect(),
+        };
+        self.packets += 1;
+        self.bytes += size as u64;
+        Some(data)
+    }
+}
+
+/// Achieved rate of `TrafficGen`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrafficReport {
+    packets: u64,
+    bytes: u64,
+    elapsed: Duration,
+    requested: TrafficRate,
+}
+
+impl TrafficReport {
+    /// Gets the number of packets
+    pub fn get_packets(&self) -> u64 {
+        self.packets
+    }
+
+    /// Gets the number of bytes of the packet data
+    pub fn get_bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Gets the time taken
+    pub fn get_elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Gets the requested rate
+    pub fn get_requested(&self) -> TrafficRate {
+        self.requested
+    }
+
+    /// Achieved packets per second
+    pub fn achieved_pps(&self) -> f64 {
+        self.packets as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Achieved megabits of packet data per second
+    pub fn achieved_mbps(&self) -> f64 {
+        (self.bytes * 8) as f64 / 1_000_000.0 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Achieved rate relative to the requested one, `None` for an unlimited rate
+    ///
+    /// Somewhat below 1.0 means the write path keeps up, e.g. `assert!(ratio > 0.95)`
+    /// catches a regression of a benchmark run locally.
+    pub fn achieved_ratio(&self) -> Option<f64> {
+        match self.requested {
+            TrafficRate::Pps(pps) if pps > 0.0 => Some(self.achieved_pps() / pps),
+            TrafficRate::Mbps(mbps) if mbps > 0.0 => Some(self.achieved_mbps() / mbps),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TrafficReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} packets, {} bytes in {:.3}s: {:.1} pps, {:.2} Mbps",
+            self.packets,
+            self.bytes,
+            self.elapsed.as_secs_f64(),
+            self.achieved_pps(),
+            self.achieved_mbps()
+        )?;
+        match (self.requested, self.achieved_ratio()) {
+            (TrafficRate::Pps(pps), Some(ratio)) => {
+                write!(f, " ({:.1}% of {} pps)", ratio * 100.0, pps)
+            }
+            (TrafficRate::Mbps(mbps), Some(ratio)) => {
+                write!(f, " ({:.1}% of {} Mbps)", ratio * 100.0, mbps)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Small seeded generator, the sequence must not change between versions
+#[derive(Debug)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform enough below `n`, `n` is not 0
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
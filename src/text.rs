@@ -0,0 +1,82 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use crate::arg::sanitize_display;
+use crate::write_opt_value;
+
+/// Kind of a user-facing string, see `TextKey`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextKind {
+    /// Display string of an interface, DLT, argument, value or control
+    Display,
+    /// Tooltip of an argument or control
+    Tooltip,
+    /// Placeholder of an argument or control
+    Placeholder,
+    /// Group of an argument
+    Group,
+}
+
+/// User-facing string passed to the text resolver, see `Extcap::set_text_resolver`
+///
+/// The text set by the builders is the key, gettext style: the English text itself,
+/// or a symbolic key like `band.tooltip` if the resolver covers every language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextKey<'t> {
+    kind: TextKind,
+    text: &'t str,
+}
+
+impl<'t> TextKey<'t> {
+    /// Gets the kind of the string
+    pub fn get_kind(&self) -> TextKind {
+        self.kind
+    }
+
+    /// Gets the text set by the builder
+    pub fn get_text(&self) -> &'t str {
+        self.text
+    }
+}
+
+type Resolver = Arc<dyn Fn(&TextKey) -> Option<String> + Send + Sync>;
+
+/// Text resolver consulted when the sentences are printed
+#[derive(Clone, Default)]
+pub(crate) struct Texts {
+    resolver: Option<Resolver>,
+}
+
+impl Texts {
+    pub(crate) fn new<F>(resolver: F) -> Self
+    where
+        F: Fn(&TextKey) -> Option<String> + Send + Sync + 'static,
+    {
+        Self {
+            resolver: Some(Arc::new(resolver)),
+        }
+    }
+
+    /// The resolved text, braces and control characters of a resolved one are replaced
+    pub(crate) fn resolve<'t>(&self, kind: TextKind, text: &'t str) -> Cow<'t, str> {
+        match self
+            .resolver
+            .as_ref()
+            .and_then(|resolver| resolver(&TextKey { kind, text }))
+        {
+            Some(resolved) => Cow::Owned(sanitize_display(&resolved)),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    /// Writes the optional attribute with the resolved text
+    pub(crate) fn write_opt(
+        &self,
+        out: &mut String,
+        name: &str,
+        kind: TextKind,
+        text: Option<&str>,
+    ) {
+        write_opt_value(out, name, &text.map(|text| self.resolve(kind, text)));
+    }
+}
@@ -0,0 +1,135 @@
+/// Policy applied when a minimum Wireshark version is required
+/// but no version has been passed via `--extcap-version`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingWsVersion {
+    /// Proceed silently
+    #[default]
+    Proceed,
+    /// Proceed with a warning logged
+    Warn,
+}
+
+/// Parses the major and minor numbers of a version string like `3.4.2`
+pub(crate) fn parse_major_minor(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim().split('.').map(|p| {
+        p.chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>()
+            .parse::<u32>()
+            .ok()
+    });
+    let major = parts.next().flatten()?;
+    let minor = parts.next().flatten().unwrap_or(0);
+    Some((major, minor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, TempPath};
+    use crate::{Extcap, ExtcapListener, ExtcapResult, IFace, PacketSink};
+
+    struct Listener;
+
+    impl ExtcapListener for Listener {
+        fn capture2(&mut self, _: &Extcap, _: &IFace, _: PacketSink) -> ExtcapResult<()> {
+            Ok(())
+        }
+    }
+
+    fn extcap(policy: MissingWsVersion) -> Extcap<'static> {
+        let mut extcap = Extcap::new("versioned");
+        extcap.add_interface(IFace::new("if1")).unwrap();
+        extcap.require_ws_version(3, 4);
+        extcap.missing_ws_version(policy);
+        extcap
+    }
+
+    #[test]
+    fn major_minor_parsed() {
+        let cases = [
+            ("3.4.2", Some((3, 4))),
+            ("4.2.0rc1", Some((4, 2))),
+            ("3.6", Some((3, 6))),
+            ("4", Some((4, 0))),
+            (" 3.10.1 ", Some((3, 10))),
+            ("v3.4", None),
+            ("", None),
+        ];
+        for (version, expected) in cases {
+            assert_eq!(parse_major_minor(version), expected, "{}", version);
+        }
+    }
+
+    #[test]
+    fn steps_with_old_new_and_absent_version() {
+        let fifo = TempPath::new("versioned.pcap");
+        let steps: [&[&str]; 4] = [
+            &["--extcap-interfaces"],
+            &["--extcap-dlts", "--extcap-interface", "if1"],
+            &["--extcap-config", "--extcap-interface", "if1"],
+            &[
+                "--capture",
+                "--extcap-interface",
+                "if1",
+                "--fifo",
+                fifo.as_str(),
+            ],
+        ];
+        for step in steps {
+            for version in [Some("3.2.1"), Some("3.4.0"), Some("4.2.0"), None] {
+                let mut args = step.to_vec();
+                let version_arg = version.map(|v| format!("--extcap-version={}", v));
+                args.extend(version_arg.as_deref());
+                let (res, out) =
+                    test_support::run(extcap(MissingWsVersion::Proceed), Listener, &args);
+                let old = version == Some("3.2.1");
+                let listing = step[0] == "--extcap-interfaces";
+                let unsupported = "interface {value=versioned-unsupported}\
+                     {display=versioned requires Wireshark >= 3.4 (running 3.2.1)}\n";
+                match (old, listing) {
+                    (true, true) => {
+                        assert!(res.is_ok(), "{:?}", args);
+                        assert!(out.ends_with(unsupported), "{:?}: {}", args, out);
+                        assert!(!out.contains("{value=if1}"), "{:?}: {}", args, out);
+                    }
+                    (true, false) => {
+                        let e = res.unwrap_err();
+                        assert!(e.get_message().contains(">= 3.4"), "{:?}: {}", args, e);
+                        assert!(out.is_empty(), "{:?}: {}", args, out);
+                    }
+                    (false, _) => {
+                        assert!(res.is_ok(), "{:?}: {:?}", args, res.err());
+                        assert!(!out.contains("unsupported"), "{:?}: {}", args, out);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reported_version_sanitized_in_the_listing() {
+        let args = ["--extcap-interfaces", "--extcap-version=3.2}{display=x\n"];
+        let (res, out) = test_support::run(extcap(MissingWsVersion::Proceed), Listener, &args);
+        assert!(res.is_ok(), "{:?}", res.err());
+        let unsupported = "interface {value=versioned-unsupported}\
+             {display=versioned requires Wireshark >= 3.4 (running 3.2)(display=x )}\n";
+        assert!(out.ends_with(unsupported), "{}", out);
+    }
+
+    #[test]
+    fn absent_version_warned_by_policy() {
+        for (policy, warned) in [
+            (MissingWsVersion::Proceed, false),
+            (MissingWsVersion::Warn, true),
+        ] {
+            let ((res, out), logs) = test_support::capture_logs(|| {
+                test_support::run(extcap(policy), Listener, &["--extcap-interfaces"])
+            });
+            assert!(res.is_ok());
+            assert!(out.contains("{value=if1}"), "{}", out);
+            let warning = logs.iter().any(|msg| msg.contains("version not provided"));
+            assert_eq!(warning, warned, "{:?}", logs);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Allocations of the packets written to the sink, counted by a global allocator
+//! of this test binary
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io;
+use std::time::Duration;
+
+use extcap::pcap_file::DataLink;
+use extcap::{CaptureHeader, ExtcapPacket, PacketPool, PacketSink};
+
+/// Counts the allocations of the thread, the other tests run on their own threads
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCS: Cell<usize> = const { Cell::new(0) };
+}
+
+// SAFETY: delegates to the system allocator
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+const PACKETS: usize = 100_000;
+
+fn allocs() -> usize {
+    ALLOCS.with(Cell::get)
+}
+
+/// Sends the packets made by `make` through the sink, returns the allocations
+fn allocs_per_capture(mut make: impl FnMut(&[u8]) -> ExtcapPacket) -> usize {
+    let header = CaptureHeader::from_data_link(DataLink::ETHERNET);
+    let mut sink = PacketSink::from_writer(io::sink(), header).unwrap();
+    let frame = [0x55; 64];
+    let before = allocs();
+    for _ in 0..PACKETS {
+        let pkt = make(&frame);
+        sink.write(0, 0, &pkt.data, pkt.orig_len).unwrap();
+    }
+    allocs() - before
+}
+
+#[test]
+fn pooled_packets_not_allocated() {
+    let owned = allocs_per_capture(|frame| ExtcapPacket::new(Duration::ZERO, 64, frame.to_vec()));
+    assert!(owned >= PACKETS, "{}", owned);
+    let mut pool = PacketPool::new(64 * 1024);
+    let pooled =
+        allocs_per_capture(|frame| ExtcapPacket::new(Duration::ZERO, 64, pool.copy(frame)));
+    // one allocation per pool of 1024 frames
+    assert!(pooled * 100 < PACKETS, "{}", pooled);
+}
+
+#[test]
+fn checked_out_buffers_reused() {
+    let mut pool = PacketPool::new(1024);
+    drop(pool.checkout(100));
+    // the dropped buffers give their allocation back
+    let before = allocs();
+    for _ in 0..100 {
+        let mut buf = pool.checkout(100);
+        buf.truncate(10);
+        drop(ExtcapPacket::new(Duration::ZERO, 10, buf));
+    }
+    assert_eq!(allocs() - before, 0);
+    // a buffer still alive is not overwritten
+    let kept = pool.copy(&[1; 1000]);
+    let before = allocs();
+    let mut next = pool.checkout(1000);
+    next.fill(2);
+    assert!(allocs() > before);
+    assert_eq!(kept, vec![1; 1000]);
+}